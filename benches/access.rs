@@ -0,0 +1,64 @@
+//! Measures reading values through `get` and `ind`, on units holding a single
+//! value and on units holding a list. The lookup cache is always hit, so this
+//! is the cost of borrowing the unit and reaching the value inside it.
+//!
+//! Run with `cargo bench --bench access`.
+use restor::{make_storage, DynamicStorage, MutexStorage, RwLockStorage};
+use std::hint::black_box;
+use std::time::Instant;
+
+const ROUNDS: usize = 2_000_000;
+const SAMPLES: usize = 7;
+
+/// Reports the fastest of several samples, as the slower ones are mostly noise
+fn time<F: FnMut() -> usize>(name: &str, mut f: F) {
+    let mut best = f64::MAX;
+    for _ in 0..SAMPLES {
+        let start = Instant::now();
+        let mut sum = 0;
+        for _ in 0..ROUNDS {
+            sum += f();
+        }
+        let elapsed = start.elapsed();
+        black_box(sum);
+        best = best.min(elapsed.as_nanos() as f64 / ROUNDS as f64);
+    }
+    println!("{:<40} {:>8.2} ns/iter", name, best);
+}
+
+fn main() {
+    let one = make_storage!(DynamicStorage: usize);
+    one.insert(1usize).unwrap();
+    let many = make_storage!(DynamicStorage: usize);
+    many.insert_many(vec![1usize, 2, 3]).unwrap();
+    time("DynamicStorage: get on One", || {
+        *one.get::<usize>().unwrap()
+    });
+    time("DynamicStorage: ind on One", || {
+        *one.ind::<usize>(0).unwrap()
+    });
+    time("DynamicStorage: ind on Many", || {
+        *many.ind::<usize>(1).unwrap()
+    });
+    time("DynamicStorage: get_mut on One", || {
+        *one.get_mut::<usize>().unwrap()
+    });
+
+    let one = make_storage!(RwLockStorage: usize);
+    one.insert(1usize).unwrap();
+    let many = make_storage!(RwLockStorage: usize);
+    many.insert_many(vec![1usize, 2, 3]).unwrap();
+    time("RwLockStorage: get on One", || *one.get::<usize>().unwrap());
+    time("RwLockStorage: ind on One", || {
+        *one.ind::<usize>(0).unwrap()
+    });
+    time("RwLockStorage: ind on Many", || {
+        *many.ind::<usize>(1).unwrap()
+    });
+
+    let one = make_storage!(MutexStorage: usize);
+    one.insert(1usize).unwrap();
+    time("MutexStorage: get_mut on One", || {
+        *one.get_mut::<usize>().unwrap()
+    });
+}
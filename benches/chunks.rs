@@ -0,0 +1,79 @@
+//! Measures mutating every value of a large unit, comparing going through the
+//! values in order on the calling thread with `try_for_each_mut`, to handing
+//! chunks of them to scoped threads with `scope_chunks_mut`.
+//!
+//! Run with `cargo bench --bench chunks`.
+use restor::{make_storage, MutexStorage, RwLockStorage};
+use std::hint::black_box;
+use std::num::NonZeroUsize;
+use std::time::Instant;
+
+const ROUNDS: usize = 50;
+const VALUES: usize = 1 << 20;
+
+/// Enough work per value for the threads to pay off.
+fn step(x: &mut f64) {
+    for _ in 0..16 {
+        *x = (*x * 1.000_001 + 0.5).sqrt();
+    }
+}
+
+fn time<F: FnMut()>(name: &str, mut f: F) {
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        f();
+    }
+    let elapsed = start.elapsed();
+    println!(
+        "{:<52} {:>8.2} ms/iter",
+        name,
+        elapsed.as_secs_f64() * 1000.0 / ROUNDS as f64
+    );
+}
+
+fn main() {
+    let threads = std::thread::available_parallelism().map_or(1, NonZeroUsize::get);
+    println!("{} threads available", threads);
+
+    let storage = make_storage!(RwLockStorage: f64);
+    storage.insert_many(vec![1.0f64; VALUES]).unwrap();
+    time("RwLockStorage: try_for_each_mut", || {
+        storage
+            .try_for_each_mut(|x: &mut f64| {
+                step(x);
+                Ok::<_, ()>(())
+            })
+            .unwrap()
+    });
+    for chunk_size in [1 << 10, 1 << 14, 1 << 18] {
+        time(
+            &format!("RwLockStorage: scope_chunks_mut ({} per chunk)", chunk_size),
+            || {
+                storage
+                    .scope_chunks_mut(chunk_size, |_, chunk: &mut [f64]| {
+                        chunk.iter_mut().for_each(step)
+                    })
+                    .unwrap()
+            },
+        );
+    }
+
+    let storage = make_storage!(MutexStorage: f64);
+    storage.insert_many(vec![1.0f64; VALUES]).unwrap();
+    time("MutexStorage: try_for_each_mut", || {
+        storage
+            .try_for_each_mut(|x: &mut f64| {
+                step(x);
+                Ok::<_, ()>(())
+            })
+            .unwrap()
+    });
+    time("MutexStorage: scope_chunks_mut (16384 per chunk)", || {
+        storage
+            .scope_chunks_mut(1 << 14, |_, chunk: &mut [f64]| {
+                chunk.iter_mut().for_each(step)
+            })
+            .unwrap()
+    });
+    black_box(storage.extract_many::<f64>().unwrap());
+}
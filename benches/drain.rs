@@ -0,0 +1,54 @@
+//! Measures draining a unit which is refilled every round, as with a queue of
+//! events handled once per frame, comparing `extract_many`, which allocates a
+//! new slice every time, with `extract_many_into`, which reuses a buffer.
+//!
+//! Run with `cargo bench --bench drain`.
+use restor::{make_storage, DynamicStorage, RwLockStorage};
+use std::hint::black_box;
+use std::time::Instant;
+
+const ROUNDS: usize = 100_000;
+const EVENTS: usize = 64;
+
+fn time<F: FnMut() -> usize>(name: &str, mut f: F) {
+    let start = Instant::now();
+    let mut sum = 0;
+    for _ in 0..ROUNDS {
+        sum += f();
+    }
+    let elapsed = start.elapsed();
+    black_box(sum);
+    println!(
+        "{:<48} {:>8.2} ns/iter",
+        name,
+        elapsed.as_nanos() as f64 / ROUNDS as f64
+    );
+}
+
+fn main() {
+    let storage = make_storage!(DynamicStorage: usize);
+    time("DynamicStorage: insert + extract_many", || {
+        (0..EVENTS).for_each(|i| storage.insert(i).unwrap());
+        storage.extract_many::<usize>().unwrap().len()
+    });
+    let mut buf = Vec::<usize>::new();
+    time("DynamicStorage: insert + extract_many_into", || {
+        (0..EVENTS).for_each(|i| storage.insert(i).unwrap());
+        let moved = storage.extract_many_into(&mut buf).unwrap();
+        buf.clear();
+        moved
+    });
+
+    let storage = make_storage!(RwLockStorage: usize);
+    time("RwLockStorage: insert + extract_many", || {
+        (0..EVENTS).for_each(|i| storage.insert(i).unwrap());
+        storage.extract_many::<usize>().unwrap().len()
+    });
+    let mut buf = Vec::<usize>::new();
+    time("RwLockStorage: insert + extract_many_into", || {
+        (0..EVENTS).for_each(|i| storage.insert(i).unwrap());
+        let moved = storage.extract_many_into(&mut buf).unwrap();
+        buf.clear();
+        moved
+    });
+}
@@ -0,0 +1,112 @@
+//! Measures simulated frames which fill a unit and then empty it, counting the
+//! allocations made once the frames reach a steady state. A unit which was
+//! given a buffer with `allocate_for_with_vec`, or which rotates buffers with
+//! `swap_backing_vec`, shouldn't allocate at all.
+//!
+//! Run with `cargo bench --bench frames`.
+use restor::{make_storage, DynamicStorage, RwLockStorage};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+const FRAMES: usize = 100_000;
+const WARMUP: usize = 16;
+const VALUES: usize = 64;
+
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+fn time<F: FnMut() -> usize>(name: &str, mut f: F) {
+    for _ in 0..WARMUP {
+        black_box(f());
+    }
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    let mut sum = 0;
+    for _ in 0..FRAMES {
+        sum += f();
+    }
+    let elapsed = start.elapsed();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations;
+    black_box(sum);
+    println!(
+        "{:<52} {:>8.2} ns/frame {:>6.2} allocs/frame",
+        name,
+        elapsed.as_nanos() as f64 / FRAMES as f64,
+        allocations as f64 / FRAMES as f64
+    );
+}
+
+fn main() {
+    let storage = make_storage!(DynamicStorage: usize);
+    time("DynamicStorage: insert + extract_many", || {
+        (0..VALUES).for_each(|i| storage.insert(i).unwrap());
+        storage.extract_many::<usize>().unwrap().len()
+    });
+
+    let mut storage = DynamicStorage::new();
+    storage.allocate_for_with_vec::<usize>(Vec::with_capacity(VALUES));
+    time("DynamicStorage: insert + extract", || {
+        (0..VALUES).for_each(|i| storage.insert(i).unwrap());
+        (0..VALUES)
+            .map(|_| storage.extract_ind::<usize>(0).unwrap())
+            .sum()
+    });
+    let mut spare = Vec::<usize>::with_capacity(VALUES);
+    time("DynamicStorage: insert + swap_backing_vec", || {
+        (0..VALUES).for_each(|i| storage.insert(i).unwrap());
+        let mut values = storage
+            .swap_backing_vec(std::mem::take(&mut spare))
+            .unwrap();
+        let moved = values.len();
+        values.clear();
+        spare = values;
+        moved
+    });
+
+    let storage = make_storage!(RwLockStorage: usize);
+    time("RwLockStorage: insert + extract_many", || {
+        (0..VALUES).for_each(|i| storage.insert(i).unwrap());
+        storage.extract_many::<usize>().unwrap().len()
+    });
+
+    let mut storage = RwLockStorage::new();
+    storage.allocate_for_with_vec::<usize>(Vec::with_capacity(VALUES));
+    let mut buf = Vec::<usize>::with_capacity(VALUES);
+    time("RwLockStorage: insert + extract_many_into", || {
+        (0..VALUES).for_each(|i| storage.insert(i).unwrap());
+        let moved = storage.extract_many_into(&mut buf).unwrap();
+        buf.clear();
+        moved
+    });
+    let mut spare = Vec::<usize>::with_capacity(VALUES);
+    time("RwLockStorage: insert + swap_backing_vec", || {
+        (0..VALUES).for_each(|i| storage.insert(i).unwrap());
+        let mut values = storage
+            .swap_backing_vec(std::mem::take(&mut spare))
+            .unwrap();
+        let moved = values.len();
+        values.clear();
+        spare = values;
+        moved
+    });
+}
@@ -0,0 +1,56 @@
+//! Measures the cost of looking up units in a storage, comparing repeatedly
+//! fetching one type (which hits the lookup cache) with alternating between
+//! two types (which misses it every time).
+//!
+//! Run with `cargo bench --bench lookup`.
+use restor::{make_storage, DynamicStorage, RwLockStorage};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const ITERATIONS: u32 = 1_000_000;
+
+fn time<F: FnMut()>(name: &str, mut f: F) {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        f();
+    }
+    let elapsed: Duration = start.elapsed();
+    println!(
+        "{:<32} {:>8.2} ns/iter",
+        name,
+        elapsed.as_nanos() as f64 / f64::from(ITERATIONS)
+    );
+}
+
+fn main() {
+    let storage = make_storage!(DynamicStorage: usize, isize, u8, u16, u32, u64, String);
+    storage.insert(0usize).unwrap();
+    storage.insert(0isize).unwrap();
+    time("DynamicStorage: same type", || {
+        black_box(*storage.get::<usize>().unwrap());
+    });
+    let mut flip = false;
+    time("DynamicStorage: alternating", || {
+        flip = !flip;
+        if flip {
+            black_box(*storage.get::<usize>().unwrap());
+        } else {
+            black_box(*storage.get::<isize>().unwrap());
+        }
+    });
+
+    let storage = make_storage!(RwLockStorage: usize, isize, u8, u16, u32, u64, String);
+    storage.insert(0usize).unwrap();
+    storage.insert(0isize).unwrap();
+    time("RwLockStorage: same type", || {
+        black_box(*storage.get::<usize>().unwrap());
+    });
+    time("RwLockStorage: alternating", || {
+        flip = !flip;
+        if flip {
+            black_box(*storage.get::<usize>().unwrap());
+        } else {
+            black_box(*storage.get::<isize>().unwrap());
+        }
+    });
+}
@@ -0,0 +1,56 @@
+//! Measures polling a unit which is locked, where every attempt fails with
+//! `BorrowedIncompatibly`. The errors of the accessors are built without
+//! allocating, which the last case compares against by wrapping each one in
+//! a `Context`.
+//!
+//! Run with `cargo bench --bench polling`.
+use restor::{make_storage, DynamicStorage, ErrorKind, MutexStorage, RwLockStorage};
+use std::hint::black_box;
+use std::time::Instant;
+
+const ROUNDS: usize = 1_000_000;
+
+fn time<F: FnMut() -> usize>(name: &str, mut f: F) {
+    let start = Instant::now();
+    let mut sum = 0;
+    for _ in 0..ROUNDS {
+        sum += f();
+    }
+    let elapsed = start.elapsed();
+    black_box(sum);
+    println!(
+        "{:<40} {:>8.2} ns/iter",
+        name,
+        elapsed.as_nanos() as f64 / ROUNDS as f64
+    );
+}
+
+fn main() {
+    let storage = make_storage!(DynamicStorage: usize);
+    storage.insert(0usize).unwrap();
+    let _lock = storage.get_mut::<usize>().unwrap();
+    time("DynamicStorage: get on locked", || {
+        storage.get::<usize>().is_err() as usize
+    });
+    time("DynamicStorage: get_mut on locked", || {
+        (storage.get_mut::<usize>().unwrap_err().kind() == ErrorKind::BorrowedIncompatibly) as usize
+    });
+
+    let storage = make_storage!(MutexStorage: usize);
+    storage.insert(0usize).unwrap();
+    let _lock = storage.get_mut::<usize>().unwrap();
+    time("MutexStorage: get_mut on locked", || {
+        storage.get_mut::<usize>().is_err() as usize
+    });
+
+    let storage = make_storage!(RwLockStorage: usize);
+    storage.insert(0usize).unwrap();
+    let _lock = storage.get_mut::<usize>().unwrap();
+    time("RwLockStorage: get on locked", || {
+        storage.get::<usize>().is_err() as usize
+    });
+    time("RwLockStorage: get on locked + context", || {
+        let err = storage.get::<usize>().unwrap_err().context("polling");
+        (black_box(err).kind() == ErrorKind::BorrowedIncompatibly) as usize
+    });
+}
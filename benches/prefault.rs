@@ -0,0 +1,51 @@
+//! Measures the first frame spent filling and reading a 100MB unit, with and
+//! without faulting in its memory ahead of time through `warm_capacity` and
+//! `prefault`. Every round uses a new storage, so that the memory is fresh.
+//!
+//! Run with `cargo bench --bench prefault`.
+use restor::{make_storage, DynamicStorage};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const ROUNDS: u32 = 5;
+const VALUES: u64 = 100 * 1024 * 1024 / 8;
+
+fn timed<R, F: FnOnce() -> R>(f: F) -> Duration {
+    let start = Instant::now();
+    black_box(f());
+    start.elapsed()
+}
+
+fn report(name: &str, total: Duration) {
+    println!(
+        "{:<48} {:>8.2} ms/round",
+        name,
+        total.as_secs_f64() * 1000.0 / ROUNDS as f64
+    );
+}
+
+fn main() {
+    let (mut fill, mut sum) = (Duration::ZERO, Duration::ZERO);
+    let (mut warm, mut warm_fill) = (Duration::ZERO, Duration::ZERO);
+    let (mut prefault, mut prefault_sum) = (Duration::ZERO, Duration::ZERO);
+    let total = |storage: &DynamicStorage| {
+        storage.run_for::<u64, u64, _>(|x| Some(x.unwrap().iter().sum()))
+    };
+    for _ in 0..ROUNDS {
+        let storage = make_storage!(DynamicStorage: u64);
+        fill += timed(|| storage.insert_iter(0..VALUES).unwrap());
+        sum += timed(|| total(&storage));
+
+        let storage = make_storage!(DynamicStorage: u64);
+        warm += timed(|| storage.warm_capacity::<u64>(VALUES as usize).unwrap());
+        warm_fill += timed(|| storage.insert_iter(0..VALUES).unwrap());
+        prefault += timed(|| storage.prefault::<u64>().unwrap());
+        prefault_sum += timed(|| total(&storage));
+    }
+    report("cold: insert_iter", fill);
+    report("cold: first sum", sum);
+    report("warm_capacity", warm);
+    report("warmed: insert_iter", warm_fill);
+    report("prefault", prefault);
+    report("prefaulted: first sum", prefault_sum);
+}
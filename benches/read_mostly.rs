@@ -0,0 +1,55 @@
+//! Compares reading a value through the `RwLock` of a unit against reading a
+//! value allocated with `allocate_read_mostly_for`, from several threads.
+//!
+//! Run with `cargo bench --bench read_mostly`.
+use restor::RwLockStorage;
+use std::hint::black_box;
+use std::sync::Arc;
+use std::thread::spawn;
+use std::time::Instant;
+
+const THREADS: usize = 8;
+const READS: usize = 1_000_000;
+
+fn time<F: Fn(&RwLockStorage) -> usize + Copy + Send + 'static>(
+    name: &str,
+    storage: &Arc<RwLockStorage>,
+    f: F,
+) {
+    let start = Instant::now();
+    let threads = (0..THREADS)
+        .map(|_| {
+            let storage = storage.clone();
+            spawn(move || {
+                let mut sum = 0;
+                for _ in 0..READS {
+                    sum += f(&storage);
+                }
+                black_box(sum);
+            })
+        })
+        .collect::<Vec<_>>();
+    threads.into_iter().for_each(|t| t.join().unwrap());
+    let elapsed = start.elapsed();
+    println!(
+        "{:<40} {:>8.2} ns/read",
+        name,
+        elapsed.as_nanos() as f64 / READS as f64
+    );
+}
+
+fn main() {
+    let mut storage = RwLockStorage::new();
+    storage.allocate_for::<usize>();
+    storage.allocate_read_mostly_for::<isize>();
+    storage.insert(1usize).unwrap();
+    storage.store(1isize).unwrap();
+    let storage = Arc::new(storage);
+
+    time("RwLockStorage: get, 8 threads", &storage, |x| {
+        *x.get::<usize>().unwrap()
+    });
+    time("RwLockStorage: load, 8 threads", &storage, |x| {
+        *x.load::<isize>().unwrap() as usize
+    });
+}
@@ -0,0 +1,48 @@
+//! Measures units holding only a couple of values, where the allocation of the
+//! list backing `Many` dominates.
+//!
+//! Run with `cargo bench --bench small`.
+use restor::{make_storage, DynamicStorage, RwLockStorage};
+use std::hint::black_box;
+use std::time::Instant;
+
+const ROUNDS: usize = 100_000;
+
+fn time<F: FnMut() -> usize>(name: &str, mut f: F) {
+    let start = Instant::now();
+    let mut sum = 0;
+    for _ in 0..ROUNDS {
+        sum += f();
+    }
+    let elapsed = start.elapsed();
+    black_box(sum);
+    println!(
+        "{:<40} {:>8.2} ns/iter",
+        name,
+        elapsed.as_nanos() as f64 / ROUNDS as f64
+    );
+}
+
+fn main() {
+    let storage = make_storage!(DynamicStorage: usize);
+    time("DynamicStorage: insert 2, extract_many", || {
+        storage.insert(1usize).unwrap();
+        storage.insert(2usize).unwrap();
+        storage.extract_many::<usize>().unwrap().len()
+    });
+    storage.insert_many(vec![1usize, 2]).unwrap();
+    time("DynamicStorage: ind of 2", || {
+        *storage.ind::<usize>(0).unwrap() + *storage.ind::<usize>(1).unwrap()
+    });
+
+    let storage = make_storage!(RwLockStorage: usize);
+    time("RwLockStorage: insert 2, extract_many", || {
+        storage.insert(1usize).unwrap();
+        storage.insert(2usize).unwrap();
+        storage.extract_many::<usize>().unwrap().len()
+    });
+    storage.insert_many(vec![1usize, 2]).unwrap();
+    time("RwLockStorage: ind of 2", || {
+        *storage.ind::<usize>(0).unwrap() + *storage.ind::<usize>(1).unwrap()
+    });
+}
@@ -0,0 +1,76 @@
+//! Measures accessing units through a `TypeToken`, which indexes the units of
+//! the storage directly, against looking them up by `TypeId`, both when the
+//! same type is accessed over and over (which hits the lookup cache) and when
+//! going through several types in turn (which misses it every time).
+//!
+//! Run with `cargo bench --bench tokens`.
+use restor::{DynamicStorage, RwLockStorage};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const ITERATIONS: u32 = 1_000_000;
+
+fn time<F: FnMut()>(name: &str, mut f: F) {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        f();
+    }
+    let elapsed: Duration = start.elapsed();
+    println!(
+        "{:<40} {:>8.2} ns/iter",
+        name,
+        elapsed.as_nanos() as f64 / f64::from(ITERATIONS)
+    );
+}
+
+fn main() {
+    let mut storage = DynamicStorage::new();
+    let a = storage.register_token::<u8>();
+    let b = storage.register_token::<u16>();
+    let c = storage.register_token::<u32>();
+    let d = storage.register_token::<u64>();
+    storage.insert(0u8).unwrap();
+    storage.insert(0u16).unwrap();
+    storage.insert(0u32).unwrap();
+    storage.insert(0u64).unwrap();
+    time("DynamicStorage: same type, by TypeId", || {
+        black_box(*storage.get::<u64>().unwrap());
+    });
+    time("DynamicStorage: same type, by token", || {
+        black_box(*storage.get_by_token(d).unwrap());
+    });
+    time("DynamicStorage: four types, by TypeId", || {
+        black_box(*storage.get::<u8>().unwrap());
+        black_box(*storage.get::<u16>().unwrap());
+        black_box(*storage.get::<u32>().unwrap());
+        black_box(*storage.get::<u64>().unwrap());
+    });
+    time("DynamicStorage: four types, by token", || {
+        black_box(*storage.get_by_token(a).unwrap());
+        black_box(*storage.get_by_token(b).unwrap());
+        black_box(*storage.get_by_token(c).unwrap());
+        black_box(*storage.get_by_token(d).unwrap());
+    });
+
+    let mut storage = RwLockStorage::new();
+    let a = storage.register_token::<u8>();
+    let b = storage.register_token::<u16>();
+    let c = storage.register_token::<u32>();
+    let d = storage.register_token::<u64>();
+    storage.insert(0u8).unwrap();
+    storage.insert(0u16).unwrap();
+    storage.insert(0u32).unwrap();
+    storage.insert(0u64).unwrap();
+    time("RwLockStorage: four types, by TypeId", || {
+        black_box(*storage.get::<u8>().unwrap());
+        black_box(*storage.get::<u16>().unwrap());
+        black_box(*storage.get::<u32>().unwrap());
+        black_box(*storage.get::<u64>().unwrap());
+    });
+    time("RwLockStorage: four types, by token", || {
+        black_box(*storage.get_by_token(a).unwrap());
+        black_box(*storage.get_by_token(b).unwrap());
+        black_box(*storage.get_by_token(c).unwrap());
+        black_box(*storage.get_by_token(d).unwrap());
+    });
+}
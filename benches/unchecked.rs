@@ -0,0 +1,61 @@
+//! Compares the checked accessors against their unchecked counterparts.
+//!
+//! Run with `cargo bench --bench unchecked`.
+use restor::{make_storage, DynamicStorage, RwLockStorage};
+use std::hint::black_box;
+use std::time::Instant;
+
+const LEN: usize = 1024;
+const ROUNDS: usize = 1000;
+
+fn time<F: FnMut(usize) -> usize>(name: &str, mut f: F) {
+    let start = Instant::now();
+    let mut sum = 0;
+    for _ in 0..ROUNDS {
+        for i in 0..LEN {
+            sum += f(i);
+        }
+    }
+    let elapsed = start.elapsed();
+    black_box(sum);
+    println!(
+        "{:<44} {:>8.2} ns/iter",
+        name,
+        elapsed.as_nanos() as f64 / (LEN * ROUNDS) as f64
+    );
+}
+
+fn main() {
+    let storage = make_storage!(DynamicStorage: usize);
+    storage.insert_many((0..LEN).collect::<Vec<_>>()).unwrap();
+    time("DynamicStorage: ind", |i| *storage.ind::<usize>(i).unwrap());
+    time("DynamicStorage: ind_unchecked", |i| unsafe {
+        *storage.ind_unchecked::<usize>(i).unwrap()
+    });
+
+    let storage = make_storage!(RwLockStorage: usize);
+    storage.insert_many((0..LEN).collect::<Vec<_>>()).unwrap();
+    time("RwLockStorage: ind", |i| *storage.ind::<usize>(i).unwrap());
+    time("RwLockStorage: ind_unchecked", |i| unsafe {
+        *storage.ind_unchecked::<usize>(i).unwrap()
+    });
+
+    let storage = make_storage!(DynamicStorage: usize, u32);
+    storage.insert(1usize).unwrap();
+    storage.insert(1u32).unwrap();
+    // Alternating between types misses the cache of the most recent lookup
+    time("DynamicStorage: get, alternating", |i| {
+        if i % 2 == 0 {
+            *storage.get::<usize>().unwrap()
+        } else {
+            *storage.get::<u32>().unwrap() as usize
+        }
+    });
+    time("DynamicStorage: get_unchecked, alternating", |i| unsafe {
+        if i % 2 == 0 {
+            *storage.get_unchecked::<usize>().unwrap()
+        } else {
+            *storage.get_unchecked::<u32>().unwrap() as usize
+        }
+    });
+}
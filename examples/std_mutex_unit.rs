@@ -0,0 +1,302 @@
+//! A storage whose units keep their values behind a `std::sync::Mutex`, as an
+//! example of implementing `Unit` outside of this crate.
+//!
+//! A std `MutexGuard` can't be mapped to a part of what it guards, so the
+//! guards handed out by the unit hold the whole lock alongside which value was
+//! selected, and the guards handed out by the storage hold those in turn.
+//!
+use restor::backend::{conformance, DynamicResult, ErrorDesc, Map, MapMut, StorageUnit, Unit};
+use restor::{BlackBox, UnitState};
+use std::any::{Any, TypeId};
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard, TryLockError};
+
+type StdMutexStorage = BlackBox<
+    dyn for<'a> Unit<
+            'a,
+            Borrowed = StdGuard<'a>,
+            MutBorrowed = StdGuard<'a>,
+            Owned = Box<dyn Any + Send>,
+        > + Send,
+>;
+
+#[derive(Clone, Copy)]
+enum At {
+    One,
+    Ind(usize),
+    FromEnd(usize),
+    Storage,
+}
+
+/// A locked unit and the value selected in it, erasing the stored type.
+trait Selected {
+    fn get(&self) -> &(dyn Any + Send);
+    fn get_mut(&mut self) -> &mut (dyn Any + Send);
+}
+
+struct Selection<'a, T: 'static> {
+    lock: MutexGuard<'a, StorageUnit<T>>,
+    at: At,
+}
+
+// The selection was checked when the lock was taken, and can't have changed
+// since, so the `expect`s below never fail.
+impl<T: 'static + Send> Selected for Selection<'_, T> {
+    fn get(&self) -> &(dyn Any + Send) {
+        match self.at {
+            At::One => self.lock.one().expect("Checked when locked"),
+            At::Ind(ind) => self.lock.ind(ind).expect("Checked when locked"),
+            At::FromEnd(back) => self.lock.ind_from_end(back).expect("Checked when locked"),
+            At::Storage => &*self.lock,
+        }
+    }
+    fn get_mut(&mut self) -> &mut (dyn Any + Send) {
+        match self.at {
+            At::One => self.lock.one_mut().expect("Checked when locked"),
+            At::Ind(ind) => self.lock.ind_mut(ind).expect("Checked when locked"),
+            At::FromEnd(back) => self
+                .lock
+                .ind_from_end_mut(back)
+                .expect("Checked when locked"),
+            At::Storage => &mut *self.lock,
+        }
+    }
+}
+
+pub struct StdGuard<'a>(Box<dyn Selected + 'a>);
+
+impl Deref for StdGuard<'_> {
+    type Target = dyn Any + Send;
+    fn deref(&self) -> &(dyn Any + Send) {
+        self.0.get()
+    }
+}
+
+impl DerefMut for StdGuard<'_> {
+    fn deref_mut(&mut self) -> &mut (dyn Any + Send) {
+        self.0.get_mut()
+    }
+}
+
+pub struct StdReadGuard<'a, O: ?Sized> {
+    guard: StdGuard<'a>,
+    f: for<'b> fn(&'b (dyn Any + Send)) -> &'b O,
+}
+
+impl<O: ?Sized> Deref for StdReadGuard<'_, O> {
+    type Target = O;
+    fn deref(&self) -> &O {
+        (self.f)(&*self.guard)
+    }
+}
+
+#[cfg(feature = "instrument")]
+impl<O: ?Sized> restor::backend::Tracked for StdReadGuard<'_, O> {}
+
+impl<'a, O: 'static + ?Sized> Map<dyn Any + Send, O> for StdGuard<'a> {
+    type Output = StdReadGuard<'a, O>;
+    type Func = for<'b> fn(&'b (dyn Any + Send)) -> &'b O;
+    fn map(self, f: Self::Func) -> StdReadGuard<'a, O> {
+        StdReadGuard { guard: self, f }
+    }
+}
+
+pub struct StdWriteGuard<'a, O> {
+    guard: StdGuard<'a>,
+    f: for<'b> fn(&'b mut (dyn Any + Send)) -> &'b mut O,
+}
+
+impl<O: 'static> Deref for StdWriteGuard<'_, O> {
+    type Target = O;
+    fn deref(&self) -> &O {
+        self.guard
+            .downcast_ref()
+            .expect("The storage only maps to the stored type")
+    }
+}
+
+impl<O: 'static> DerefMut for StdWriteGuard<'_, O> {
+    fn deref_mut(&mut self) -> &mut O {
+        (self.f)(&mut *self.guard)
+    }
+}
+
+impl<O: 'static> restor::backend::Poisoning for StdWriteGuard<'_, O> {}
+
+#[cfg(feature = "instrument")]
+impl<O: 'static> restor::backend::Tracked for StdWriteGuard<'_, O> {}
+
+impl<'a, O: 'static> MapMut<dyn Any + Send, O> for StdGuard<'a> {
+    type Output = StdWriteGuard<'a, O>;
+    type Func = for<'b> fn(&'b mut (dyn Any + Send)) -> &'b mut O;
+    fn map(self, f: Self::Func) -> StdWriteGuard<'a, O> {
+        StdWriteGuard { guard: self, f }
+    }
+}
+
+pub struct StdMutexUnit<T: 'static> {
+    inner: Mutex<StorageUnit<T>>,
+    version: AtomicU64,
+}
+
+impl<T: 'static> Default for StdMutexUnit<T> {
+    fn default() -> Self {
+        Self {
+            inner: Mutex::new(StorageUnit::new()),
+            version: AtomicU64::new(0),
+        }
+    }
+}
+
+impl<T: 'static + Send> StdMutexUnit<T> {
+    fn lock(&self) -> DynamicResult<MutexGuard<'_, StorageUnit<T>>> {
+        match self.inner.try_lock() {
+            Ok(guard) => Ok(guard),
+            // Like the `parking_lot` locks of the provided units, this doesn't poison
+            Err(TryLockError::Poisoned(poisoned)) => Ok(poisoned.into_inner()),
+            Err(TryLockError::WouldBlock) => Err(ErrorDesc::BorrowedIncompatibly),
+        }
+    }
+
+    fn bump(&self) {
+        self.version.fetch_add(1, Ordering::AcqRel);
+    }
+
+    fn select(&self, at: At, write: bool) -> DynamicResult<StdGuard<'_>> {
+        let lock = self.lock()?;
+        match at {
+            At::One => drop(lock.one()?),
+            At::Ind(ind) => drop(lock.ind(ind)?),
+            At::FromEnd(back) => drop(lock.ind_from_end(back)?),
+            At::Storage => {}
+        }
+        if write {
+            self.bump();
+        }
+        Ok(StdGuard(Box::new(Selection { lock, at })))
+    }
+
+    fn extract_with<R: 'static + Send>(
+        &self,
+        f: impl FnOnce(&mut StorageUnit<T>) -> DynamicResult<R>,
+    ) -> DynamicResult<Box<dyn Any + Send>> {
+        let value = f(&mut *self.lock()?)?;
+        self.bump();
+        Ok(Box::new(value))
+    }
+}
+
+// Every guard hands out the value it selected in a `StorageUnit<T>`, or the
+// `StorageUnit<T>` itself, and `id` is the `TypeId` of `T`.
+unsafe impl<'a, T: 'static + Send> Unit<'a> for StdMutexUnit<T> {
+    type Borrowed = StdGuard<'a>;
+    type MutBorrowed = StdGuard<'a>;
+    type Owned = Box<dyn Any + Send>;
+
+    fn one(&'a self) -> DynamicResult<StdGuard<'a>> {
+        self.select(At::One, false)
+    }
+    fn one_mut(&'a self) -> DynamicResult<StdGuard<'a>> {
+        self.select(At::One, true)
+    }
+
+    fn ind(&'a self, ind: usize) -> DynamicResult<StdGuard<'a>> {
+        self.select(At::Ind(ind), false)
+    }
+    fn ind_mut(&'a self, ind: usize) -> DynamicResult<StdGuard<'a>> {
+        self.select(At::Ind(ind), true)
+    }
+    fn ind_from_end(&'a self, back: usize) -> DynamicResult<StdGuard<'a>> {
+        self.select(At::FromEnd(back), false)
+    }
+    fn ind_from_end_mut(&'a self, back: usize) -> DynamicResult<StdGuard<'a>> {
+        self.select(At::FromEnd(back), true)
+    }
+
+    fn extract(&self) -> DynamicResult<Box<dyn Any + Send>> {
+        self.extract_with(|x| x.extract_one())
+    }
+    fn extract_ind(&self, ind: usize) -> DynamicResult<Box<dyn Any + Send>> {
+        self.extract_with(|x| x.extract_ind(ind))
+    }
+    fn extract_many(&self) -> DynamicResult<Box<dyn Any + Send>> {
+        self.extract_with(|x| x.extract_many_boxed())
+    }
+
+    fn insert_any(&self, new: Box<dyn Any + Send>) -> Option<(Box<dyn Any + Send>, ErrorDesc)> {
+        let mut lock = match self.lock() {
+            Ok(lock) => lock,
+            Err(e) => return Some((new, e)),
+        };
+        let new = match new.downcast::<T>() {
+            Ok(one) => {
+                lock.insert(*one);
+                self.bump();
+                return None;
+            }
+            Err(new) => new,
+        };
+        match new.downcast::<Vec<T>>() {
+            Ok(many) => {
+                lock.insert_many(*many);
+                self.bump();
+                None
+            }
+            Err(new) => Some((new, ErrorDesc::NoMatchingType)),
+        }
+    }
+
+    unsafe fn run_for(&self, func: (TypeId, (*const (), *const ()))) -> Option<Box<dyn Any>> {
+        let values = self.lock().ok()?;
+        restor::backend::run_for_values(func, values.many())
+    }
+
+    fn storage(&'a self) -> DynamicResult<StdGuard<'a>> {
+        self.select(At::Storage, false)
+    }
+    fn storage_mut(&'a self) -> DynamicResult<StdGuard<'a>> {
+        self.select(At::Storage, true)
+    }
+
+    fn id(&self) -> TypeId {
+        TypeId::of::<T>()
+    }
+    fn type_name(&self) -> &'static str {
+        std::any::type_name::<T>()
+    }
+    fn state(&self) -> Option<UnitState> {
+        self.lock().ok().map(|x| x.state())
+    }
+    fn is_borrowed(&self) -> bool {
+        self.lock().is_err()
+    }
+    fn version(&self) -> u64 {
+        self.version.load(Ordering::Acquire)
+    }
+}
+
+fn main() {
+    conformance::run::<StdMutexUnit<u32>>();
+
+    let mut storage = StdMutexStorage::new();
+    storage
+        .allocate_unit_for::<String>(Box::new(StdMutexUnit::<String>::default()))
+        .unwrap();
+    storage
+        .allocate_unit_for::<usize>(Box::new(StdMutexUnit::<usize>::default()))
+        .unwrap();
+    storage.insert(String::from("abc")).unwrap();
+    storage.insert_many(vec![1usize, 2, 3]).unwrap();
+
+    let mut string = storage.get_mut::<String>().unwrap();
+    string.push_str("def");
+    assert!(storage.get::<String>().is_err());
+    drop(string);
+    *storage.ind_mut::<usize>(1).unwrap() *= 10;
+
+    assert_eq!(&*storage.get::<String>().unwrap(), "abcdef");
+    assert_eq!(*storage.ind::<usize>(1).unwrap(), 20);
+    assert_eq!(&*storage.extract_many::<usize>().unwrap(), &[1, 20, 3]);
+    println!("A storage over std::sync::Mutex passed the conformance checks");
+}
@@ -0,0 +1,343 @@
+//! Checks that an implementation of `Unit` behaves as a `BlackBox` expects.
+//!
+//! `run` drives a unit storing `u32`s through every operation of `Unit` in
+//! every state, comparing each outcome with that of the same operation on a
+//! plain `StorageUnit<u32>`, and then checks how the unit behaves while it is
+//! borrowed. It panics with a description of the first difference it finds,
+//! and so is meant to be called from a test:
+//!
+//! ```
+//! # fn main() {
+//! use restor::backend::{conformance, RwLockUnit, StorageUnit};
+//! conformance::run::<RwLockUnit<StorageUnit<u32>>>();
+//! # }
+//! ```
+//!
+//! Passing these checks doesn't prove that an implementation upholds the safety
+//! requirements of `Unit`, but values of the wrong type and guards which don't
+//! exclude each other are caught.
+//!
+use super::{DynamicResult, ErrorDesc, StorageUnit, Unit};
+use crate::BlackBox;
+use std::any::{type_name, Any, TypeId};
+
+///
+/// Runs every check on units of type `X`, which must store `u32`s and start
+/// out with no values when created with `Default`.
+///
+/// # Panics
+/// Panics in the case that `X` doesn't behave as expected.
+///
+pub fn run<X>()
+where
+    X: 'static + Default + for<'a> Unit<'a, Owned = Box<dyn Any + Send>>,
+{
+    check_identity::<X>();
+    check_states::<X>();
+    check_borrows::<X>();
+    check_storage::<X>();
+}
+
+/// The operations which are compared against those of a `StorageUnit<u32>`.
+#[derive(Debug, Clone)]
+enum Op {
+    One,
+    OneMut,
+    Ind(usize),
+    IndMut(usize),
+    FromEnd(usize),
+    FromEndMut(usize),
+    Extract,
+    ExtractInd(usize),
+    ExtractMany,
+    Insert(u32),
+    InsertMany(Vec<u32>),
+    InsertOther,
+    Storage,
+    StorageMut,
+}
+
+impl Op {
+    fn is_mut(&self) -> bool {
+        !matches!(self, Op::One | Op::Ind(_) | Op::FromEnd(_) | Op::Storage)
+    }
+
+    fn all() -> Vec<Op> {
+        let mut ops = vec![
+            Op::One,
+            Op::OneMut,
+            Op::Extract,
+            Op::ExtractMany,
+            Op::Insert(7),
+            Op::InsertMany(vec![8, 9]),
+            Op::InsertMany(vec![]),
+            Op::InsertOther,
+            Op::Storage,
+            Op::StorageMut,
+        ];
+        for i in 0..4 {
+            ops.push(Op::Ind(i));
+            ops.push(Op::IndMut(i));
+            ops.push(Op::FromEnd(i));
+            ops.push(Op::FromEndMut(i));
+            ops.push(Op::ExtractInd(i));
+        }
+        ops
+    }
+}
+
+/// The values inserted one at a time to reach each state: none, one and many.
+const STATES: &[&[u32]] = &[&[], &[1], &[1, 2], &[1, 2, 3]];
+
+fn filled<X>(values: &[u32]) -> X
+where
+    X: Default + for<'a> Unit<'a, Owned = Box<dyn Any + Send>>,
+{
+    let unit = X::default();
+    for &value in values {
+        if let Some((_, e)) = unit.insert_any(Box::new(value)) {
+            panic!("Inserting {} into the unit failed with {:?}", value, e);
+        }
+    }
+    unit
+}
+
+fn bump(x: &mut u32) -> Vec<u32> {
+    *x += 100;
+    vec![*x]
+}
+
+fn on_model(model: &mut StorageUnit<u32>, op: &Op) -> DynamicResult<Vec<u32>> {
+    match op {
+        Op::One => model.one().map(|x| vec![*x]),
+        Op::OneMut => model.one_mut().map(bump),
+        Op::Ind(i) => model.ind(*i).map(|x| vec![*x]),
+        Op::IndMut(i) => model.ind_mut(*i).map(bump),
+        Op::FromEnd(i) => model.ind_from_end(*i).map(|x| vec![*x]),
+        Op::FromEndMut(i) => model.ind_from_end_mut(*i).map(bump),
+        Op::Extract => model.extract_one().map(|x| vec![x]),
+        Op::ExtractInd(i) => model.extract_ind(*i).map(|x| vec![x]),
+        Op::ExtractMany => model.extract_many_boxed().map(Vec::from),
+        Op::Insert(x) => {
+            model.insert(*x);
+            Ok(vec![])
+        }
+        Op::InsertMany(x) => {
+            model.insert_many(x.clone());
+            Ok(vec![])
+        }
+        Op::InsertOther => Err(ErrorDesc::NoMatchingType),
+        Op::Storage | Op::StorageMut => Ok(model.as_slice().to_vec()),
+    }
+}
+
+fn downcast<'v, T: 'static>(x: &'v (dyn Any + Send), op: &Op) -> &'v T {
+    x.downcast_ref().unwrap_or_else(|| {
+        panic!(
+            "`{:?}` handed out a value which isn't a `{}`",
+            op,
+            type_name::<T>()
+        )
+    })
+}
+
+fn owned<T: 'static>(x: Box<dyn Any + Send>, op: &Op) -> T {
+    match x.downcast() {
+        Ok(x) => *x,
+        Err(_) => panic!(
+            "`{:?}` moved out a value which isn't a `{}`",
+            op,
+            type_name::<T>()
+        ),
+    }
+}
+
+fn on_unit<X>(unit: &X, op: &Op) -> DynamicResult<Vec<u32>>
+where
+    X: for<'a> Unit<'a, Owned = Box<dyn Any + Send>>,
+{
+    let read = |x: &(dyn Any + Send)| vec![*downcast::<u32>(x, op)];
+    let write = |x: &mut (dyn Any + Send)| {
+        let x = x
+            .downcast_mut::<u32>()
+            .unwrap_or_else(|| panic!("`{:?}` handed out a value which isn't a `u32`", op));
+        bump(x)
+    };
+    let insert = |new: Box<dyn Any + Send>| match unit.insert_any(new) {
+        None => Ok(vec![]),
+        Some((_, e)) => Err(e),
+    };
+    let storage = |x: &(dyn Any + Send)| downcast::<StorageUnit<u32>>(x, op).as_slice().to_vec();
+    match op {
+        Op::One => unit.one().map(|x| read(&*x)),
+        Op::OneMut => unit.one_mut().map(|mut x| write(&mut *x)),
+        Op::Ind(i) => unit.ind(*i).map(|x| read(&*x)),
+        Op::IndMut(i) => unit.ind_mut(*i).map(|mut x| write(&mut *x)),
+        Op::FromEnd(i) => unit.ind_from_end(*i).map(|x| read(&*x)),
+        Op::FromEndMut(i) => unit.ind_from_end_mut(*i).map(|mut x| write(&mut *x)),
+        Op::Extract => unit.extract().map(|x| vec![owned::<u32>(x, op)]),
+        Op::ExtractInd(i) => unit.extract_ind(*i).map(|x| vec![owned::<u32>(x, op)]),
+        Op::ExtractMany => unit
+            .extract_many()
+            .map(|x| owned::<Box<[u32]>>(x, op).into_vec()),
+        Op::Insert(x) => insert(Box::new(*x)),
+        Op::InsertMany(x) => insert(Box::new(x.clone())),
+        Op::InsertOther => insert(Box::new("not a u32")),
+        Op::Storage => unit.storage().map(|x| storage(&*x)),
+        Op::StorageMut => unit.storage_mut().map(|x| storage(&*x)),
+    }
+}
+
+fn is_conflict(result: &DynamicResult<Vec<u32>>) -> bool {
+    matches!(
+        result,
+        Err(ErrorDesc::BorrowedIncompatibly) | Err(ErrorDesc::RecursiveBorrow)
+    )
+}
+
+fn check_identity<X>()
+where
+    X: Default + for<'a> Unit<'a, Owned = Box<dyn Any + Send>>,
+{
+    let unit = X::default();
+    assert_eq!(
+        unit.id(),
+        TypeId::of::<u32>(),
+        "`id` must be the `TypeId` of the stored type, `u32`"
+    );
+    assert_eq!(unit.type_name(), type_name::<u32>());
+    assert_eq!(unit.state(), Some(StorageUnit::<u32>::new().state()));
+    assert!(!unit.is_borrowed(), "A new unit is borrowed");
+}
+
+fn check_states<X>()
+where
+    X: Default + for<'a> Unit<'a, Owned = Box<dyn Any + Send>>,
+{
+    for &values in STATES {
+        for op in Op::all() {
+            let unit = filled::<X>(values);
+            let mut model = StorageUnit::new();
+            model.insert_many(values.to_vec());
+            if values.len() == 1 {
+                model = StorageUnit::One(values[0]);
+            }
+            let before = unit.version();
+
+            let found = on_unit(&unit, &op);
+            let expected = on_model(&mut model, &op);
+            assert_eq!(found, expected, "`{:?}` on a unit holding {:?}", op, values);
+            assert!(
+                !unit.is_borrowed(),
+                "`{:?}` on a unit holding {:?} left it borrowed",
+                op,
+                values
+            );
+            assert_eq!(
+                unit.state(),
+                Some(model.state()),
+                "The state after `{:?}` on a unit holding {:?}",
+                op,
+                values
+            );
+            if let Some(capacity) = unit.capacity() {
+                assert!(
+                    capacity >= model.len(),
+                    "The capacity after `{:?}` on a unit holding {:?} is {}",
+                    op,
+                    values,
+                    capacity
+                );
+            }
+            assert_eq!(
+                on_unit(&unit, &Op::Storage),
+                Ok(model.as_slice().to_vec()),
+                "The values after `{:?}` on a unit holding {:?}",
+                op,
+                values
+            );
+
+            let after = unit.version();
+            if !op.is_mut() {
+                assert_eq!(after, before, "`{:?}` changed the version", op);
+            } else if found.is_ok() {
+                assert!(after > before, "`{:?}` didn't change the version", op);
+            }
+        }
+    }
+}
+
+fn check_borrows<X>()
+where
+    X: Default + for<'a> Unit<'a, Owned = Box<dyn Any + Send>>,
+{
+    let unit = filled::<X>(&[1]);
+    {
+        let _guard = unit.one_mut().expect("`one_mut` on a single value failed");
+        assert!(unit.is_borrowed(), "`is_borrowed` while borrowed mutably");
+        assert_eq!(unit.state(), None, "`state` while borrowed mutably");
+        assert_eq!(unit.capacity(), None, "`capacity` while borrowed mutably");
+        assert_eq!(
+            unit.approx_bytes(),
+            None,
+            "`approx_bytes` while borrowed mutably"
+        );
+        for op in Op::all() {
+            let found = on_unit(&unit, &op);
+            assert!(
+                is_conflict(&found),
+                "`{:?}` while the unit is borrowed mutably returned {:?}",
+                op,
+                found
+            );
+        }
+    }
+    assert!(
+        !unit.is_borrowed(),
+        "Dropping a guard didn't release the unit"
+    );
+
+    let _guard = unit.one().expect("`one` on a single value failed");
+    assert!(unit.is_borrowed(), "`is_borrowed` while borrowed immutably");
+    for op in Op::all() {
+        let found = on_unit(&unit, &op);
+        if op.is_mut() {
+            assert!(
+                is_conflict(&found),
+                "`{:?}` while the unit is borrowed immutably returned {:?}",
+                op,
+                found
+            );
+        } else {
+            // Units whose every borrow is exclusive may refuse these as well
+            let expected = on_model(&mut StorageUnit::One(1), &op);
+            assert!(
+                found == expected || is_conflict(&found),
+                "`{:?}` while the unit is borrowed immutably returned {:?}",
+                op,
+                found
+            );
+        }
+    }
+}
+
+fn check_storage<X>()
+where
+    X: 'static + Default + for<'a> Unit<'a, Owned = Box<dyn Any + Send>>,
+{
+    let mut storage = BlackBox::<X>::new();
+    assert_eq!(
+        storage.allocate_unit_for::<u64>(Box::new(X::default())),
+        Err(ErrorDesc::NoMatchingType)
+    );
+    storage
+        .allocate_unit_for::<u32>(Box::new(X::default()))
+        .expect("A unit storing `u32`s couldn't be allocated for `u32`");
+    storage.insert_many(vec![1u32, 2, 3]).unwrap();
+    let sum = storage.run_for::<u32, u32, _>(|x| x.ok().map(|x| x.iter().sum()));
+    assert_eq!(sum, Some(6), "`run_for` on a unit holding [1, 2, 3]");
+    assert_eq!(
+        storage.extract_many::<u32>().map(Vec::from),
+        Ok(vec![1, 2, 3])
+    );
+}
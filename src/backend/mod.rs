@@ -0,0 +1,35 @@
+//! Everything needed to store values behind locks other than those of the
+//! storages provided by this crate.
+//!
+//! A storage is a `BlackBox<U>`, where `U` is usually a trait object over the
+//! `Unit` trait, naming the guards handed out by its units. Implementing a new
+//! kind of unit takes the following:
+//!  1. A type implementing `Unit`, which holds the `StorageUnit<T>` of a single
+//!     type `T` behind some lock. Implementing `Unit` is `unsafe`, as the storage
+//!     relies on what the unit hands out being of type `T`, see the `# Safety`
+//!     section of `Unit`.
+//!  2. Guard types for `Unit::Borrowed` and `Unit::MutBorrowed`, implementing
+//!     `Map` and `MapMut` respectively, which turn them into guards on a `T`.
+//!     The guards resulting from `MapMut` must also implement `Poisoning`, and
+//!     with the `instrument` feature, all of them must implement `Tracked`.
+//!     Both can be implemented with their default methods.
+//!  3. A storage type, which is a `BlackBox` of the trait object over `Unit`
+//!     with those guard types, and in which units are allocated with
+//!     `BlackBox::allocate_unit_for`.
+//!
+//! Only the storages provided by this crate are `Sync`, so a storage of other
+//! units can't be shared between threads, even if its units can.
+//!
+//! `conformance::run` then checks that a unit behaves as the storage expects
+//! in every state, and `examples/std_mutex_unit.rs` is a complete backend
+//! based on `std::sync::Mutex`.
+//!
+pub mod conformance;
+
+#[cfg(feature = "instrument")]
+pub use crate::black_box::Tracked;
+pub use crate::black_box::{
+    run_for_values, CowUnit, DirtyUnit, DynamicResult, ErrorDesc, Map, MapMut, Poisoning,
+    RefCellUnit, StorageUnit, Unit, UnitError, UnitState, ValueChange,
+};
+pub use crate::concurrent_black_box::{MutexUnit, RwLockUnit};
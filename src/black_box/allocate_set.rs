@@ -0,0 +1,151 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::marker::PhantomData;
+
+use crate::{DynamicStorage, MutexStorage, RwLockStorage};
+
+mod sealed {
+    pub trait Sealed<S: ?Sized> {}
+}
+
+///
+/// A set of types which can be allocated together in a storage of type `S`
+/// through `BlackBox::allocate_all`, or checked to be allocated through
+/// `BlackBox::assert_allocated`.
+///
+/// This is implemented for tuples of up to twelve types, with the bounds on
+/// each element being those of the `allocate_for` of the storage:
+///  - `DynamicStorage` and `MutexStorage` require `T: 'static + Send`
+///  - `RwLockStorage` requires `T: 'static + Send + Sync`, as the readers
+///    of a unit may be on different threads
+///
+/// A tuple of sets is distinguished from a tuple of plain types by wrapping it
+/// in `Sets`, which is what allows sets to be composed from other sets.
+///
+/// This trait is sealed and cannot be implemented outside of this crate.
+///
+pub trait AllocateSet<S: ?Sized>: sealed::Sealed<S> {
+    /// Allocates a unit for every type in the set
+    fn allocate(storage: &mut S);
+    /// Pushes the name of every type in the set which has no unit in `storage`
+    fn missing(storage: &S, names: &mut Vec<&'static str>);
+}
+
+///
+/// The types of a set which have no unit in a storage, as returned by
+/// `BlackBox::assert_allocated`.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MissingTypes {
+    names: Vec<&'static str>,
+}
+
+impl MissingTypes {
+    pub(crate) fn new(names: Vec<&'static str>) -> Self {
+        Self { names }
+    }
+
+    ///
+    /// The names of the missing types, in the order they appear in the set.
+    ///
+    pub fn names(&self) -> &[&'static str] {
+        &self.names
+    }
+}
+
+impl Display for MissingTypes {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "No unit is allocated for ")?;
+        for (i, name) in self.names.iter().enumerate() {
+            if i != 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "`{}`", name)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for MissingTypes {}
+
+///
+/// Composes a tuple of `AllocateSet`s into a single set.
+///
+/// # Example
+/// ```
+/// # use restor::{RwLockStorage, Sets};
+/// # fn main() {
+/// type CoreSet = (usize, isize, String);
+/// type NumericSet = (u8, u16, f32);
+///
+/// let mut storage = RwLockStorage::new();
+/// storage.allocate_all::<Sets<(CoreSet, NumericSet, Sets<((bool,), (char,))>)>>();
+/// storage.insert(0u8).unwrap();
+/// storage.insert(String::new()).unwrap();
+/// storage.insert('a').unwrap();
+/// # }
+/// ```
+///
+/// `Sets` is never constructed, and cannot itself be allocated as the element
+/// of a set:
+/// ```compile_fail
+/// # use restor::{RwLockStorage, Sets};
+/// # fn main() {
+/// let mut storage = RwLockStorage::new();
+/// storage.allocate_all::<(usize, Sets<((isize,),)>)>();
+/// # }
+/// ```
+///
+pub struct Sets<T>(PhantomData<*const T>);
+
+macro_rules! impl_allocate_set {
+    ($storage:ty, $bound:ident, $($name:ident),*) => {
+        impl<$($name: 'static + Send + $bound),*> sealed::Sealed<$storage> for ($($name,)*) {}
+        impl<$($name: 'static + Send + $bound),*> AllocateSet<$storage> for ($($name,)*) {
+            fn allocate(storage: &mut $storage) {
+                $(storage.allocate_for::<$name>();)*
+            }
+            fn missing(storage: &$storage, names: &mut Vec<&'static str>) {
+                $(if !storage.has_unit::<$name>() {
+                    names.push(std::any::type_name::<$name>());
+                })*
+            }
+        }
+    };
+}
+
+macro_rules! impl_sets {
+    ($($name:ident),*) => {
+        impl<S: ?Sized, $($name: AllocateSet<S>),*> sealed::Sealed<S> for Sets<($($name,)*)> {}
+        impl<S: ?Sized, $($name: AllocateSet<S>),*> AllocateSet<S> for Sets<($($name,)*)> {
+            fn allocate(storage: &mut S) {
+                $($name::allocate(storage);)*
+            }
+            fn missing(storage: &S, names: &mut Vec<&'static str>) {
+                $($name::missing(storage, names);)*
+            }
+        }
+    };
+}
+
+macro_rules! impl_all {
+    ($($name:ident),*) => {
+        impl_allocate_set!(DynamicStorage, Send, $($name),*);
+        impl_allocate_set!(MutexStorage, Send, $($name),*);
+        impl_allocate_set!(RwLockStorage, Sync, $($name),*);
+        impl_sets!($($name),*);
+    };
+}
+
+impl_all!(A);
+impl_all!(A, B);
+impl_all!(A, B, C);
+impl_all!(A, B, C, D);
+impl_all!(A, B, C, D, E);
+impl_all!(A, B, C, D, E, F);
+impl_all!(A, B, C, D, E, F, G);
+impl_all!(A, B, C, D, E, F, G, H);
+impl_all!(A, B, C, D, E, F, G, H, I);
+impl_all!(A, B, C, D, E, F, G, H, I, J);
+impl_all!(A, B, C, D, E, F, G, H, I, J, K);
+impl_all!(A, B, C, D, E, F, G, H, I, J, K, L);
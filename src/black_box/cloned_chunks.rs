@@ -0,0 +1,80 @@
+use std::any::Any;
+use std::marker::PhantomData;
+
+use super::{BlackBox, DynamicResult, StorageUnit, Unit};
+
+///
+/// An iterator over clones of the values of `T` in a storage, taken a chunk
+/// at a time, as returned by `BlackBox::cloned_iter_chunked`.
+///
+/// Each chunk is cloned under its own borrow of the unit, so values may be
+/// inserted or extracted in between chunks. The next chunk always starts at
+/// the index after the end of the previous one, so removing a value before
+/// that index makes a value be skipped, and inserting one before it makes a
+/// value be seen twice.
+///
+/// A chunk which can't be cloned, such as when the unit is borrowed mutably,
+/// is yielded as an `Err`, after which the iterator ends.
+///
+pub struct ClonedChunks<'a, U: ?Sized, T> {
+    storage: &'a BlackBox<U>,
+    chunk: usize,
+    position: usize,
+    done: bool,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<'a, U: ?Sized, T> ClonedChunks<'a, U, T> {
+    pub(crate) fn new(storage: &'a BlackBox<U>, chunk: usize) -> Self {
+        assert!(chunk != 0, "chunk size must be non-zero");
+        Self {
+            storage,
+            chunk,
+            position: 0,
+            done: false,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<'a, U, T> Iterator for ClonedChunks<'a, U, T>
+where
+    U: ?Sized + for<'b> Unit<'b, Owned = Box<dyn Any + Send>>,
+    T: 'static + Send + Clone,
+{
+    type Item = DynamicResult<Vec<T>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let (position, chunk) = (self.position, self.chunk);
+        let values = self.storage.with_storage(|unit: &StorageUnit<T>| {
+            let values = unit.as_slice();
+            let start = position.min(values.len());
+            let end = (position + chunk).min(values.len());
+            values[start..end].to_vec()
+        });
+        match values {
+            Ok(ref values) if values.is_empty() => {
+                self.done = true;
+                None
+            }
+            Ok(values) => {
+                self.position += values.len();
+                Some(Ok(values))
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+impl<'a, U, T> std::iter::FusedIterator for ClonedChunks<'a, U, T>
+where
+    U: ?Sized + for<'b> Unit<'b, Owned = Box<dyn Any + Send>>,
+    T: 'static + Send + Clone,
+{
+}
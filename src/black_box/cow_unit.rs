@@ -0,0 +1,200 @@
+use std::any::{Any, TypeId};
+use std::cell::{Cell, Ref, RefCell, RefMut};
+use std::rc::Rc;
+
+use super::*;
+use crate::black_box::unit::ErrorDesc::BorrowedIncompatibly;
+
+///
+/// A unit like `RefCellUnit` whose values can be shared with the units of forks
+/// of the storage, as allocated by `DynamicStorage::allocate_clonable_for`.
+///
+/// Forking the unit only shares its values, and whichever of the units sharing
+/// them is borrowed mutably first clones them for itself, so each unit keeps
+/// seeing the values as they were when it was forked.
+///
+pub struct CowUnit<T> {
+    inner: RefCell<Rc<T>>,
+    version: Cell<u64>,
+}
+
+impl<T> CowUnit<T> {
+    pub fn new(data: T) -> Self {
+        Self {
+            inner: RefCell::new(Rc::new(data)),
+            version: Cell::new(0),
+        }
+    }
+
+    /// Marks the unit as modified, which is done whenever it is borrowed mutably.
+    #[inline]
+    fn bump(&self) {
+        self.version.set(self.version.get() + 1);
+    }
+
+    /// Borrows the values mutably, cloning them first in the case that they are shared.
+    #[inline]
+    fn borrow_mut(&self) -> DynamicResult<RefMut<'_, T>>
+    where
+        T: Clone,
+    {
+        let shared = self
+            .inner
+            .try_borrow_mut()
+            .map_err(|_| BorrowedIncompatibly)?;
+        Ok(RefMut::map(shared, Rc::make_mut))
+    }
+}
+
+impl<T: Default> Default for CowUnit<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+// Mutable borrows which fail still clone shared values, as whether they fail is
+// only known once the values are borrowed.
+unsafe impl<'a, T: 'static + Send + Clone> Unit<'a> for CowUnit<StorageUnit<T>> {
+    type Borrowed = Ref<'a, dyn Any + Send>;
+    type MutBorrowed = RefMut<'a, dyn Any + Send>;
+    type Owned = Box<dyn Any + Send>;
+    fn one(&'a self) -> DynamicResult<Ref<'a, dyn Any + Send>> {
+        let nx = self.inner.try_borrow().map_err(|_| BorrowedIncompatibly)?;
+        Ref::filter_map(nx, |nx| nx.one().ok().map(|x| x as &(dyn Any + Send)))
+            .map_err(|nx| nx.one().err().unwrap())
+    }
+    fn one_mut(&'a self) -> DynamicResult<RefMut<'a, dyn Any + Send>> {
+        match RefMut::filter_map(self.borrow_mut()?, |nx| {
+            nx.one_mut().ok().map(|x| x as &mut (dyn Any + Send))
+        }) {
+            Ok(x) => {
+                self.bump();
+                Ok(x)
+            }
+            Err(nx) => Err(nx.one().err().unwrap()),
+        }
+    }
+
+    fn ind(&'a self, ind: usize) -> DynamicResult<Ref<'a, dyn Any + Send>> {
+        let nx = self.inner.try_borrow().map_err(|_| BorrowedIncompatibly)?;
+        Ref::filter_map(nx, |nx| nx.ind(ind).ok().map(|x| x as &(dyn Any + Send)))
+            .map_err(|nx| nx.ind(ind).err().unwrap())
+    }
+    fn ind_mut(&'a self, ind: usize) -> DynamicResult<RefMut<'a, dyn Any + Send>> {
+        match RefMut::filter_map(self.borrow_mut()?, |nx| {
+            nx.ind_mut(ind).ok().map(|x| x as &mut (dyn Any + Send))
+        }) {
+            Ok(x) => {
+                self.bump();
+                Ok(x)
+            }
+            Err(nx) => Err(nx.ind(ind).err().unwrap()),
+        }
+    }
+    fn ind_from_end(&'a self, back: usize) -> DynamicResult<Ref<'a, dyn Any + Send>> {
+        let nx = self.inner.try_borrow().map_err(|_| BorrowedIncompatibly)?;
+        Ref::filter_map(nx, |nx| {
+            nx.ind_from_end(back).ok().map(|x| x as &(dyn Any + Send))
+        })
+        .map_err(|nx| nx.ind_from_end(back).err().unwrap())
+    }
+    fn ind_from_end_mut(&'a self, back: usize) -> DynamicResult<RefMut<'a, dyn Any + Send>> {
+        match RefMut::filter_map(self.borrow_mut()?, |nx| {
+            nx.ind_from_end_mut(back)
+                .ok()
+                .map(|x| x as &mut (dyn Any + Send))
+        }) {
+            Ok(x) => {
+                self.bump();
+                Ok(x)
+            }
+            Err(nx) => Err(nx.ind_from_end(back).err().unwrap()),
+        }
+    }
+
+    fn extract(&self) -> DynamicResult<Box<dyn Any + Send>> {
+        let value = self.borrow_mut()?.extract_one()?;
+        self.bump();
+        Ok(Box::new(value))
+    }
+    fn extract_ind(&self, ind: usize) -> DynamicResult<Box<dyn Any + Send>> {
+        let value = self.borrow_mut()?.extract_ind(ind)?;
+        self.bump();
+        Ok(Box::new(value))
+    }
+    fn extract_many(&self) -> DynamicResult<Box<dyn Any + Send>> {
+        let values = self.borrow_mut()?.extract_many_boxed()?;
+        self.bump();
+        Ok(Box::new(values))
+    }
+
+    fn insert_any(&self, new: Box<dyn Any + Send>) -> Option<(Box<dyn Any + Send>, ErrorDesc)> {
+        let mut shared = match self.inner.try_borrow_mut() {
+            Ok(x) => x,
+            Err(_) => return Some((new, BorrowedIncompatibly)),
+        };
+        // The values are only cloned once the new value is known to fit
+        if !new.is::<T>() && !new.is::<Vec<T>>() {
+            return Some((new, ErrorDesc::NoMatchingType));
+        }
+        let x = Rc::make_mut(&mut *shared);
+        match new.downcast::<T>() {
+            Ok(new) => x.insert(*new),
+            Err(new) => x.insert_many(*new.downcast::<Vec<T>>().unwrap()),
+        }
+        self.bump();
+        None
+    }
+
+    fn storage(&'a self) -> DynamicResult<Ref<'a, dyn Any + Send>> {
+        self.inner
+            .try_borrow()
+            .ok()
+            .map(|x| Ref::map::<dyn Any + Send, _>(x, |z| &**z))
+            .ok_or(BorrowedIncompatibly)
+    }
+    fn storage_mut(&'a self) -> DynamicResult<RefMut<'a, dyn Any + Send>> {
+        let storage = self.borrow_mut()?;
+        self.bump();
+        Ok(RefMut::map::<dyn Any + Send, _>(storage, |z| &mut *z))
+    }
+
+    unsafe fn run_for(&self, func: (TypeId, (*const (), *const ()))) -> Option<Box<dyn Any>> {
+        let values = self.inner.try_borrow().ok()?;
+        run_for_values(func, values.many())
+    }
+
+    fn id(&self) -> TypeId {
+        TypeId::of::<T>()
+    }
+    fn type_name(&self) -> &'static str {
+        std::any::type_name::<T>()
+    }
+    fn state(&self) -> Option<UnitState> {
+        self.inner.try_borrow().ok().map(|x| x.state())
+    }
+    fn is_borrowed(&self) -> bool {
+        self.inner.try_borrow_mut().is_err()
+    }
+    fn capacity(&self) -> Option<usize> {
+        self.inner.try_borrow().ok().map(|x| x.capacity())
+    }
+    fn approx_bytes(&self) -> Option<usize> {
+        self.inner.try_borrow().ok().map(|x| x.approx_bytes())
+    }
+    fn version(&self) -> u64 {
+        self.version.get()
+    }
+    fn fork(&self) -> DynamicResult<Box<dyn Any>> {
+        let shared = self
+            .inner
+            .try_borrow()
+            .map_err(|_| BorrowedIncompatibly)?
+            .clone();
+        let unit: Box<RefCellUnitTrait> = Box::new(CowUnit {
+            inner: RefCell::new(shared),
+            version: Cell::new(self.version.get()),
+        });
+        Ok(Box::new(unit))
+    }
+}
@@ -0,0 +1,197 @@
+//! Iteration over the values of a unit across many borrows, see `Cursor`.
+use super::{BlackBox, DynamicResult, ErrorDesc, StorageUnit, Unit};
+use std::any::{Any, TypeId};
+use std::fmt::{Debug, Formatter};
+use std::marker::PhantomData;
+use std::sync::atomic::Ordering;
+
+///
+/// A position in the values of `T`, which is kept between borrows of the
+/// unit, so that many values can be read a chunk at a time without holding
+/// the unit in between, as created by `BlackBox::cursor`.
+///
+/// Each chunk borrows the unit immutably, and continues after the last value
+/// read by the previous one, as long as no value was removed since:
+///
+/// - Inserting, through any of the `insert` methods, only appends, so the
+///   values inserted are read once the cursor reaches them.
+/// - Changing values in place, through `get_mut`, `ind_mut`,
+///   `try_for_each_mut`, `map_in_place` or `compose`, keeps every value where
+///   it is. The values which weren't read yet are read as they are then.
+/// - Reserving or shrinking the capacity of the unit changes nothing.
+///
+/// Anything else invalidates the cursor, after which every chunk returns
+/// `ErrorDesc::Invalidated` until the cursor is restarted. That is:
+///
+/// - Extracting any value, whether it was read already or not, including the
+///   values displaced by `insert_bounded` and `insert_many_bounded`.
+/// - Clearing the unit, through `extract_many`, `extract_any_count`,
+///   `extract_many_into`, `swap_backing_vec`, or by inserting into a poisoned
+///   unit.
+/// - `apply`, which can change the values in any way.
+/// - A mutation of the unit which panicked, and may have lost a value.
+/// - Removing the unit, even if it is allocated again afterwards.
+///
+/// # Example
+/// ```
+/// # fn main() {
+/// use restor::{make_storage, ErrorDesc, RwLockStorage};
+///
+/// let storage = make_storage!(RwLockStorage: usize);
+/// storage.insert_many((0..5usize).collect()).unwrap();
+/// let mut cursor = storage.cursor::<usize>().unwrap();
+/// assert_eq!(cursor.next_chunk(&storage, 3), Ok(vec![0, 1, 2]));
+///
+/// // Appending keeps the cursor where it was
+/// storage.insert(5usize).unwrap();
+/// assert_eq!(cursor.next_chunk(&storage, 10), Ok(vec![3, 4, 5]));
+/// assert_eq!(cursor.next_chunk(&storage, 10), Ok(vec![]));
+///
+/// // Removing a value doesn't
+/// storage.extract_ind::<usize>(0).unwrap();
+/// assert_eq!(cursor.next_chunk(&storage, 10), Err(ErrorDesc::Invalidated));
+/// cursor.restart(&storage).unwrap();
+/// assert_eq!(cursor.next_chunk(&storage, 2), Ok(vec![1, 2]));
+/// # }
+/// ```
+///
+pub struct Cursor<T> {
+    /// The index of the next value to read.
+    position: usize,
+    /// The number of times values were removed from the unit, or could have
+    /// been reordered, when the cursor was created. Unlike the version of the
+    /// unit, this isn't changed by inserting, and keeps counting when the unit
+    /// is allocated again, see `BlackBox::reshaped`.
+    reshapes: u64,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Clone for Cursor<T> {
+    fn clone(&self) -> Self {
+        Self {
+            position: self.position,
+            reshapes: self.reshapes,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T> Debug for Cursor<T> {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        f.debug_struct("Cursor")
+            .field("type", &std::any::type_name::<T>())
+            .field("position", &self.position)
+            .finish()
+    }
+}
+
+impl<T: 'static + Send> Cursor<T> {
+    ///
+    /// The index of the next value the cursor reads, which is the number of
+    /// values it read since it was created or restarted.
+    ///
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    ///
+    /// Clones up to `n` of the values after the cursor, moving it past them.
+    /// This returns fewer values once the cursor reaches the end of the unit,
+    /// and none at the end, where it picks up the values inserted later on.
+    ///
+    pub fn next_chunk<U>(&mut self, storage: &BlackBox<U>, n: usize) -> DynamicResult<Vec<T>>
+    where
+        T: Clone,
+        U: ?Sized + for<'a> Unit<'a, Owned = Box<dyn Any + Send>>,
+    {
+        let mut chunk = Vec::new();
+        self.visit_chunk(storage, n, |value| chunk.push(value.clone()))?;
+        Ok(chunk)
+    }
+
+    ///
+    /// Runs `f` on up to `n` of the values after the cursor while holding an
+    /// immutable borrow on the unit, moving the cursor past them, and returns
+    /// how many values `f` was run on.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::{make_storage, DynamicStorage};
+    ///
+    /// let storage = make_storage!(DynamicStorage: String);
+    /// storage
+    ///     .insert_many(vec![String::from("a"), String::from("b"), String::from("c")])
+    ///     .unwrap();
+    /// let mut cursor = storage.cursor::<String>().unwrap();
+    /// let mut joined = String::new();
+    /// while cursor.visit_chunk(&storage, 2, |x| joined.push_str(x)).unwrap() > 0 {
+    ///     joined.push('|');
+    /// }
+    /// assert_eq!(joined, "ab|c|");
+    /// # }
+    /// ```
+    ///
+    pub fn visit_chunk<U, F>(
+        &mut self,
+        storage: &BlackBox<U>,
+        n: usize,
+        mut f: F,
+    ) -> DynamicResult<usize>
+    where
+        U: ?Sized + for<'a> Unit<'a, Owned = Box<dyn Any + Send>>,
+        F: FnMut(&T),
+    {
+        storage
+            .with_storage(|unit: &StorageUnit<T>| {
+                let values = unit.as_slice();
+                if storage.reshapes_of::<T>() != self.reshapes || self.position > values.len() {
+                    return Err(ErrorDesc::Invalidated);
+                }
+                let start = self.position;
+                let end = values.len().min(start.saturating_add(n));
+                values[start..end].iter().for_each(&mut f);
+                self.position = end;
+                Ok(end - start)
+            })
+            .and_then(|x| x)
+    }
+
+    ///
+    /// Moves the cursor back to the first value, so that it can be used again
+    /// after being invalidated.
+    ///
+    pub fn restart<U>(&mut self, storage: &BlackBox<U>) -> DynamicResult<()>
+    where
+        U: ?Sized + for<'a> Unit<'a, Owned = Box<dyn Any + Send>>,
+    {
+        *self = storage.cursor()?;
+        Ok(())
+    }
+}
+
+impl<U: ?Sized + for<'a> Unit<'a, Owned = Box<dyn Any + Send>>> BlackBox<U> {
+    ///
+    /// Creates a cursor on the first value of `T`, which reads the values of
+    /// the unit a chunk at a time, see `Cursor`. This fails in the case that
+    /// the unit couldn't be borrowed immutably.
+    ///
+    pub fn cursor<T: 'static + Send>(&self) -> DynamicResult<Cursor<T>> {
+        self.with_storage(|_: &StorageUnit<T>| Cursor {
+            position: 0,
+            reshapes: self.reshapes_of::<T>(),
+            _marker: PhantomData,
+        })
+    }
+
+    ///
+    /// Internal function. Returns how many times values of `T` were removed or
+    /// could have been reordered. The unit should be borrowed for this to be
+    /// accurate, see `reshaped`.
+    ///
+    fn reshapes_of<T: 'static + Send>(&self) -> u64 {
+        self.reshapes
+            .get(&TypeId::of::<T>())
+            .map_or(0, |reshapes| reshapes.load(Ordering::Acquire))
+    }
+}
@@ -0,0 +1,314 @@
+use std::any::{Any, TypeId};
+use std::cell::{Cell, Ref, RefCell, RefMut};
+
+use super::*;
+use crate::black_box::unit::ErrorDesc::BorrowedIncompatibly;
+
+///
+/// The indices of the values of a unit which were borrowed mutably since they
+/// were last taken, kept as one bit per index.
+///
+#[derive(Default)]
+struct DirtySet {
+    words: Vec<u64>,
+    /// Set when the whole unit was borrowed mutably, in which case every index
+    /// is dirty, including those of values added through that borrow.
+    all: bool,
+}
+
+impl DirtySet {
+    fn mark(&mut self, ind: usize) {
+        if self.all {
+            return;
+        }
+        let word = ind / 64;
+        if word >= self.words.len() {
+            self.words.resize(word + 1, 0);
+        }
+        self.words[word] |= 1 << (ind % 64);
+    }
+
+    fn mark_range(&mut self, start: usize, end: usize) {
+        for ind in start..end {
+            self.mark(ind);
+        }
+    }
+
+    fn mark_all(&mut self) {
+        self.all = true;
+        self.words.clear();
+    }
+
+    /// Removes the bit for `ind`, moving those of the indices after it down by
+    /// one, as the values are when one is removed from the middle of a list.
+    fn remove(&mut self, ind: usize) {
+        let (word, bit) = (ind / 64, ind % 64);
+        if self.all || word >= self.words.len() {
+            return;
+        }
+        let value = self.words[word];
+        let below = value & ((1 << bit) - 1);
+        let above = (value >> bit) >> 1;
+        self.words[word] = below | (above << bit);
+        for i in word + 1..self.words.len() {
+            self.words[i - 1] |= (self.words[i] & 1) << 63;
+            self.words[i] >>= 1;
+        }
+    }
+
+    fn clear(&mut self) {
+        self.all = false;
+        self.words.clear();
+    }
+
+    /// Returns the dirty indices below `len` in ascending order, and clears them.
+    fn take(&mut self, len: usize) -> Vec<usize> {
+        let dirty = if self.all {
+            (0..len).collect()
+        } else {
+            self.words
+                .iter()
+                .enumerate()
+                .flat_map(|(i, &word)| {
+                    (0..64)
+                        .filter(move |bit| word & (1 << bit) != 0)
+                        .map(move |bit| i * 64 + bit)
+                })
+                .take_while(|&ind| ind < len)
+                .collect()
+        };
+        self.clear();
+        dirty
+    }
+}
+
+///
+/// A unit like `RefCellUnit` which also keeps track of which of its values
+/// were borrowed mutably, as allocated by `DynamicStorage::allocate_tracked_for`.
+///
+/// A value is marked as dirty when it is inserted, and when a mutable borrow of
+/// it is handed out, whether or not it is then changed. Borrowing the whole
+/// unit mutably, as done by `apply` or `get_disjoint_ind_mut`, marks every
+/// value, except for the methods of `BlackBox` which report the values they
+/// insert and extract, see `Unit::record_change`. Removing a value moves the
+/// marks of those after it along with them.
+/// The dirty indices are taken with `BlackBox::take_dirty`.
+///
+pub struct DirtyUnit<T> {
+    inner: RefCell<T>,
+    dirty: RefCell<DirtySet>,
+    /// Set by `expect_changes`, for the next `storage_mut` not to mark every value.
+    expecting: Cell<bool>,
+    version: Cell<u64>,
+}
+
+impl<T> DirtyUnit<T> {
+    pub fn new(data: T) -> Self {
+        Self {
+            inner: RefCell::new(data),
+            dirty: RefCell::new(DirtySet::default()),
+            expecting: Cell::new(false),
+            version: Cell::new(0),
+        }
+    }
+
+    /// Marks the unit as modified, which is done whenever it is borrowed mutably.
+    #[inline]
+    fn bump(&self) {
+        self.version.set(self.version.get() + 1);
+    }
+}
+
+impl<T: Default> Default for DirtyUnit<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+unsafe impl<'a, T: 'static + Send> Unit<'a> for DirtyUnit<StorageUnit<T>> {
+    type Borrowed = Ref<'a, dyn Any + Send>;
+    type MutBorrowed = RefMut<'a, dyn Any + Send>;
+    type Owned = Box<dyn Any + Send>;
+    fn one(&'a self) -> DynamicResult<Ref<'a, dyn Any + Send>> {
+        let nx = self.inner.try_borrow().map_err(|_| BorrowedIncompatibly)?;
+        Ref::filter_map(nx, |nx| nx.one().ok().map(|x| x as &(dyn Any + Send)))
+            .map_err(|nx| nx.one().err().unwrap())
+    }
+    fn one_mut(&'a self) -> DynamicResult<RefMut<'a, dyn Any + Send>> {
+        let nx = self
+            .inner
+            .try_borrow_mut()
+            .map_err(|_| BorrowedIncompatibly)?;
+        match RefMut::filter_map(nx, |nx| {
+            nx.one_mut().ok().map(|x| x as &mut (dyn Any + Send))
+        }) {
+            Ok(x) => {
+                self.bump();
+                self.dirty.borrow_mut().mark(0);
+                Ok(x)
+            }
+            Err(nx) => Err(nx.one().err().unwrap()),
+        }
+    }
+
+    fn ind(&'a self, ind: usize) -> DynamicResult<Ref<'a, dyn Any + Send>> {
+        let nx = self.inner.try_borrow().map_err(|_| BorrowedIncompatibly)?;
+        Ref::filter_map(nx, |nx| nx.ind(ind).ok().map(|x| x as &(dyn Any + Send)))
+            .map_err(|nx| nx.ind(ind).err().unwrap())
+    }
+    fn ind_mut(&'a self, ind: usize) -> DynamicResult<RefMut<'a, dyn Any + Send>> {
+        let nx = self
+            .inner
+            .try_borrow_mut()
+            .map_err(|_| BorrowedIncompatibly)?;
+        match RefMut::filter_map(nx, |nx| {
+            nx.ind_mut(ind).ok().map(|x| x as &mut (dyn Any + Send))
+        }) {
+            Ok(x) => {
+                self.bump();
+                self.dirty.borrow_mut().mark(ind);
+                Ok(x)
+            }
+            Err(nx) => Err(nx.ind(ind).err().unwrap()),
+        }
+    }
+    fn ind_from_end(&'a self, back: usize) -> DynamicResult<Ref<'a, dyn Any + Send>> {
+        let nx = self.inner.try_borrow().map_err(|_| BorrowedIncompatibly)?;
+        Ref::filter_map(nx, |nx| {
+            nx.ind_from_end(back).ok().map(|x| x as &(dyn Any + Send))
+        })
+        .map_err(|nx| nx.ind_from_end(back).err().unwrap())
+    }
+    fn ind_from_end_mut(&'a self, back: usize) -> DynamicResult<RefMut<'a, dyn Any + Send>> {
+        let nx = self
+            .inner
+            .try_borrow_mut()
+            .map_err(|_| BorrowedIncompatibly)?;
+        let len = nx.len();
+        match RefMut::filter_map(nx, |nx| {
+            nx.ind_from_end_mut(back)
+                .ok()
+                .map(|x| x as &mut (dyn Any + Send))
+        }) {
+            Ok(x) => {
+                self.bump();
+                self.dirty.borrow_mut().mark(len - 1 - back);
+                Ok(x)
+            }
+            Err(nx) => Err(nx.ind_from_end(back).err().unwrap()),
+        }
+    }
+
+    fn extract(&self) -> DynamicResult<Box<dyn Any + Send>> {
+        let mut borrowed = self
+            .inner
+            .try_borrow_mut()
+            .map_err(|_| BorrowedIncompatibly)?;
+        // This takes the first of many values
+        let value = borrowed.extract_one()?;
+        self.bump();
+        self.dirty.borrow_mut().remove(0);
+        Ok(Box::new(value))
+    }
+    fn extract_ind(&self, ind: usize) -> DynamicResult<Box<dyn Any + Send>> {
+        let mut borrowed = self
+            .inner
+            .try_borrow_mut()
+            .map_err(|_| BorrowedIncompatibly)?;
+        let value = borrowed.extract_ind(ind)?;
+        self.bump();
+        self.dirty.borrow_mut().remove(ind);
+        Ok(Box::new(value))
+    }
+    fn extract_many(&self) -> DynamicResult<Box<dyn Any + Send>> {
+        let values = self
+            .inner
+            .try_borrow_mut()
+            .map_err(|_| BorrowedIncompatibly)?
+            .extract_many_boxed()?;
+        self.bump();
+        self.dirty.borrow_mut().clear();
+        Ok(Box::new(values))
+    }
+
+    fn insert_any(&self, new: Box<dyn Any + Send>) -> Option<(Box<dyn Any + Send>, ErrorDesc)> {
+        let mut x = match self.inner.try_borrow_mut() {
+            Ok(x) => x,
+            Err(_) => return Some((new, BorrowedIncompatibly)),
+        };
+        let start = x.len();
+        match new.downcast::<T>() {
+            Ok(new) => x.insert(*new),
+            Err(new) => match new.downcast::<Vec<T>>() {
+                Ok(new) => x.insert_many(*new),
+                Err(new) => return Some((new, ErrorDesc::NoMatchingType)),
+            },
+        }
+        self.bump();
+        self.dirty.borrow_mut().mark_range(start, x.len());
+        None
+    }
+
+    fn storage(&'a self) -> DynamicResult<Ref<'a, dyn Any + Send>> {
+        self.inner
+            .try_borrow()
+            .ok()
+            .map(|x| Ref::map::<dyn Any + Send, _>(x, |z| z))
+            .ok_or(BorrowedIncompatibly)
+    }
+    fn storage_mut(&'a self) -> DynamicResult<RefMut<'a, dyn Any + Send>> {
+        let expecting = self.expecting.replace(false);
+        let storage = self
+            .inner
+            .try_borrow_mut()
+            .map_err(|_| BorrowedIncompatibly)?;
+        self.bump();
+        if !expecting {
+            self.dirty.borrow_mut().mark_all();
+        }
+        Ok(RefMut::map::<dyn Any + Send, _>(storage, |z| &mut *z))
+    }
+
+    unsafe fn run_for(&self, func: (TypeId, (*const (), *const ()))) -> Option<Box<dyn Any>> {
+        let values = self.inner.try_borrow().ok()?;
+        run_for_values(func, values.many())
+    }
+
+    fn id(&self) -> TypeId {
+        TypeId::of::<T>()
+    }
+    fn type_name(&self) -> &'static str {
+        std::any::type_name::<T>()
+    }
+    fn state(&self) -> Option<UnitState> {
+        self.inner.try_borrow().ok().map(|x| x.state())
+    }
+    fn is_borrowed(&self) -> bool {
+        self.inner.try_borrow_mut().is_err()
+    }
+    fn capacity(&self) -> Option<usize> {
+        self.inner.try_borrow().ok().map(|x| x.capacity())
+    }
+    fn approx_bytes(&self) -> Option<usize> {
+        self.inner.try_borrow().ok().map(|x| x.approx_bytes())
+    }
+    fn version(&self) -> u64 {
+        self.version.get()
+    }
+    fn expect_changes(&self) {
+        self.expecting.set(true);
+    }
+    fn record_change(&self, change: ValueChange) {
+        let mut dirty = self.dirty.borrow_mut();
+        match change {
+            ValueChange::Inserted { index } => dirty.mark(index),
+            ValueChange::Extracted { index } => dirty.remove(index),
+            ValueChange::Cleared => dirty.clear(),
+        }
+    }
+    fn take_dirty(&self) -> DynamicResult<Vec<usize>> {
+        // A value may still be changed through a mutable borrow which is alive
+        let values = self.inner.try_borrow().map_err(|_| BorrowedIncompatibly)?;
+        Ok(self.dirty.borrow_mut().take(values.len()))
+    }
+}
@@ -0,0 +1,64 @@
+//! Change notifications for units, only compiled with the `events` feature.
+use super::{BlackBox, ErrorDesc, Unit};
+use std::any::{Any, TypeId};
+use std::sync::mpsc::{channel, Receiver};
+
+///
+/// Describes a change made to the unit for the type identified by `id`, as
+/// received through `BlackBox::subscribe`.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageEvent {
+    /// A value was inserted, and now lives at `index`.
+    Inserted { id: TypeId, index: usize },
+    /// The value at `index` was removed.
+    Extracted { id: TypeId, index: usize },
+    /// Every value was removed.
+    Cleared { id: TypeId },
+}
+
+impl<U: ?Sized + for<'a> Unit<'a, Owned = Box<dyn Any + Send>>> BlackBox<U> {
+    ///
+    /// Subscribes to changes to the unit for `T`. Every insertion and extraction
+    /// made through the storage is then sent to the returned `Receiver`, in the
+    /// order in which they happened.
+    ///
+    /// Sending never blocks the storage, and events for receivers which have
+    /// been dropped are discarded. This returns an `Err` in the case that there
+    /// is no unit for `T`.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::{DynamicStorage, StorageEvent};
+    /// use std::any::TypeId;
+    /// let mut storage = DynamicStorage::new();
+    /// storage.allocate_for::<usize>();
+    /// let events = storage.subscribe::<usize>().unwrap();
+    /// storage.insert(3usize).unwrap();
+    /// let id = TypeId::of::<usize>();
+    /// assert_eq!(events.try_recv(), Ok(StorageEvent::Inserted { id, index: 0 }));
+    /// # }
+    /// ```
+    ///
+    pub fn subscribe<T: 'static + Send>(&mut self) -> Result<Receiver<StorageEvent>, ErrorDesc> {
+        self.unit_get::<T>()?;
+        let (sender, receiver) = channel();
+        self.subscribers
+            .entry(TypeId::of::<T>())
+            .or_default()
+            .push(sender);
+        Ok(receiver)
+    }
+
+    ///
+    /// Internal function. Sends `event` to every subscriber of the unit for `T`.
+    ///
+    pub(crate) fn notify<T: 'static>(&self, event: StorageEvent) {
+        if let Some(subscribers) = self.subscribers.get(&TypeId::of::<T>()) {
+            for subscriber in subscribers {
+                let _ = subscriber.send(event);
+            }
+        }
+    }
+}
@@ -0,0 +1,209 @@
+//! Finalizers run on values as they are removed from a storage, see
+//! `BlackBox::on_remove`.
+use super::{BlackBox, DynamicResult, ErrorDesc, Unit};
+use std::any::{Any, TypeId};
+use std::cell::RefCell;
+use std::sync::Arc;
+
+type FinalizerFn<U> = dyn Fn(&mut (dyn Any + Send), &BlackBox<U>) + Send + Sync;
+
+///
+/// The finalizer registered for a type with `BlackBox::on_remove`.
+///
+pub(crate) struct Finalizer<U: ?Sized> {
+    /// Finalizes a single value of the type.
+    run: Arc<FinalizerFn<U>>,
+    /// Extracts and finalizes every value of the type. This is a plain function
+    /// so that `Drop for BlackBox` can call it without knowing the type.
+    pub(crate) drain: fn(&BlackBox<U>),
+}
+
+impl<U: ?Sized> Clone for Finalizer<U> {
+    fn clone(&self) -> Self {
+        Self {
+            run: Arc::clone(&self.run),
+            drain: self.drain,
+        }
+    }
+}
+
+thread_local! {
+    /// The addresses of the storages which are running a finalizer on this thread.
+    static FINALIZING: RefCell<Vec<usize>> = const { RefCell::new(Vec::new()) };
+}
+
+///
+/// Marks a storage as running a finalizer on the current thread until dropped,
+/// which also happens when the finalizer panics.
+///
+struct Finalizing(usize);
+
+impl Finalizing {
+    fn enter<U: ?Sized>(storage: &BlackBox<U>) -> Self {
+        let address = storage as *const BlackBox<U> as *const () as usize;
+        FINALIZING.with(|f| f.borrow_mut().push(address));
+        Finalizing(address)
+    }
+}
+
+impl Drop for Finalizing {
+    fn drop(&mut self) {
+        FINALIZING.with(|f| {
+            let mut f = f.borrow_mut();
+            let index = f.iter().rposition(|&address| address == self.0).unwrap();
+            f.remove(index);
+        });
+    }
+}
+
+impl<U: ?Sized> BlackBox<U> {
+    ///
+    /// Internal function. Returns `ErrorDesc::BorrowedIncompatibly` in the case
+    /// that this thread is running a finalizer of this storage, which may not
+    /// change the values of any unit.
+    ///
+    #[inline]
+    pub(crate) fn check_finalizing(&self) -> DynamicResult<()> {
+        if self.finalizers.is_empty() {
+            return Ok(());
+        }
+        let address = self as *const Self as *const () as usize;
+        if FINALIZING.with(|f| f.borrow().contains(&address)) {
+            Err(ErrorDesc::BorrowedIncompatibly)
+        } else {
+            Ok(())
+        }
+    }
+
+    ///
+    /// Internal function. Runs the finalizer registered for the type of
+    /// `value`, which is identified by `id`, if there is one.
+    ///
+    pub(crate) fn finalize_any(&self, id: TypeId, value: &mut (dyn Any + Send)) {
+        if let Some(finalizer) = self.finalizers.get(&id) {
+            let _finalizing = Finalizing::enter(self);
+            (finalizer.run)(value, self);
+        }
+    }
+
+    ///
+    /// Internal function. Runs the finalizer registered for `T` on `value`, if
+    /// there is one, and returns it.
+    ///
+    #[inline]
+    pub(crate) fn finalized<T: 'static + Send>(&self, mut value: T) -> T {
+        self.finalize(std::slice::from_mut(&mut value));
+        value
+    }
+
+    ///
+    /// Internal function. Runs the finalizer registered for `T` on each of
+    /// `values`, if there is one.
+    ///
+    #[inline]
+    pub(crate) fn finalize<T: 'static + Send>(&self, values: &mut [T]) {
+        if self.finalizers.is_empty() {
+            return;
+        }
+        for value in values {
+            self.finalize_any(TypeId::of::<T>(), value);
+        }
+    }
+}
+
+impl<U: ?Sized + for<'a> Unit<'a, Owned = Box<dyn Any + Send>>> BlackBox<U> {
+    ///
+    /// Registers `f` to be run on every value of `T` as it leaves the storage,
+    /// replacing the finalizer registered for `T` before. This is meant for
+    /// values owning resources which can't be released in `Drop`, because doing
+    /// so takes another value of the storage, such as the device which
+    /// allocated a buffer.
+    ///
+    /// The finalizer is run exactly once on each value removed by:
+    ///
+    /// - `extract`, `extract_ind`, `extract_many`, `extract_any_count`,
+    ///   `extract_many_into`, `extract_one_loose`, `extract_any_named`,
+    ///   `remove_where` and `swap_remove_where`, right before the value is
+    ///   handed back.
+    /// - `swap_backing_vec`, on the values of the replaced buffer.
+    /// - `insert_bounded` and `insert_many_bounded`, on the values they displace
+    ///   with `OverflowPolicy::DropOldest`.
+    /// - `remove_types` and `retain_types`, on the values of the removed units.
+    /// - Dropping the storage, before any of the units is dropped. The values
+    ///   of each type are finalized in the order the finalizers were
+    ///   registered in.
+    ///
+    /// Values which are replaced or removed through `apply`, `map_in_place`,
+    /// `compose` and the like, or which are discarded because their unit was
+    /// poisoned, are not finalized.
+    ///
+    /// # Re-entrancy
+    /// The finalizer runs after the value was taken out of its unit, and once
+    /// the unit is no longer borrowed, so it can read any value of the
+    /// storage, including other values of `T`. It may not change any of them
+    /// though: calls on the storage which would borrow a unit mutably, insert
+    /// or extract return `ErrorDesc::BorrowedIncompatibly` from the thread
+    /// running the finalizer.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::{make_storage, ErrorDesc, RwLockStorage};
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// use std::sync::Arc;
+    ///
+    /// #[derive(Debug)]
+    /// struct Device {
+    ///     live_buffers: Arc<AtomicUsize>,
+    /// }
+    /// #[derive(Debug)]
+    /// struct Buffer(u32);
+    ///
+    /// let live_buffers = Arc::new(AtomicUsize::new(0));
+    /// let mut storage = make_storage!(RwLockStorage: Device, Buffer);
+    /// storage.insert(Device { live_buffers: live_buffers.clone() }).unwrap();
+    /// storage.on_remove::<Buffer, _>(|_buffer, storage| {
+    ///     let device = storage.get::<Device>().unwrap();
+    ///     device.live_buffers.fetch_sub(1, Ordering::Relaxed);
+    ///     // Reading is fine, but nothing can be changed from here
+    ///     assert_eq!(
+    ///         storage.insert(Buffer(0)).map_err(|(_, e)| e),
+    ///         Err(ErrorDesc::BorrowedIncompatibly)
+    ///     );
+    /// });
+    /// for i in 0..3 {
+    ///     storage.insert(Buffer(i)).unwrap();
+    ///     live_buffers.fetch_add(1, Ordering::Relaxed);
+    /// }
+    /// storage.extract_ind::<Buffer>(0).unwrap();
+    /// assert_eq!(live_buffers.load(Ordering::Relaxed), 2);
+    /// drop(storage);
+    /// assert_eq!(live_buffers.load(Ordering::Relaxed), 0);
+    /// # }
+    /// ```
+    ///
+    pub fn on_remove<T: 'static + Send, F: Fn(&mut T, &Self) + Send + Sync + 'static>(
+        &mut self,
+        f: F,
+    ) {
+        let run = move |value: &mut (dyn Any + Send), storage: &Self| {
+            f(value.downcast_mut::<T>().unwrap(), storage)
+        };
+        self.finalizers.insert(
+            TypeId::of::<T>(),
+            Finalizer {
+                run: Arc::new(run),
+                drain: Self::finalize_all::<T>,
+            },
+        );
+    }
+
+    ///
+    /// Internal function. Extracts every value of `T` and finalizes them, as is
+    /// done before a unit is removed or the storage is dropped.
+    ///
+    fn finalize_all<T: 'static + Send>(&self) {
+        // This fails for a poisoned unit, whose values are dropped as they are
+        let _ = self.extract_any_count::<T>();
+    }
+}
@@ -0,0 +1,386 @@
+use parking_lot::{MappedMutexGuard, MappedRwLockReadGuard, MappedRwLockWriteGuard};
+use std::cell::{Ref, RefMut};
+use std::fmt::{Debug, Display, Formatter, Result as FmtResult};
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::AtomicBool;
+#[cfg(feature = "poison")]
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+#[cfg(feature = "instrument")]
+use super::instrument::BorrowTicket;
+#[cfg(feature = "tracing")]
+use super::trace;
+#[cfg(feature = "tracing")]
+use std::time::Instant;
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+///
+/// The interior mutability type behind a storage, which decides the guards
+/// wrapped by `StorageReadGuard` and `StorageWriteGuard`.
+///
+/// This trait is sealed and cannot be implemented outside of this crate.
+///
+pub trait Backend: sealed::Sealed {
+    /// The guard of a shared borrow
+    type Read<'a, T: ?Sized + 'a>: Deref<Target = T>;
+    /// The guard of an exclusive borrow
+    type Write<'a, T: ?Sized + 'a>: DerefMut<Target = T>;
+}
+
+/// The backend of `DynamicStorage`, based on `RefCell`s.
+pub enum RefCellBackend {}
+
+/// The backend of `MutexStorage`, based on `Mutex`es. Every borrow is exclusive.
+pub enum MutexBackend {}
+
+/// The backend of `RwLockStorage`, based on `RwLock`s.
+pub enum RwLockBackend {}
+
+impl sealed::Sealed for RefCellBackend {}
+impl sealed::Sealed for MutexBackend {}
+impl sealed::Sealed for RwLockBackend {}
+
+///
+/// Implemented by the guards handed out by a storage, allowing an instrumented
+/// storage to keep track of them, see `BlackBox::new_instrumented`.
+///
+/// The guards of units implemented outside of this crate can implement this
+/// with the default method, in which case they are not tracked.
+///
+#[cfg(feature = "instrument")]
+pub trait Tracked {
+    #[doc(hidden)]
+    fn track(&mut self, ticket: BorrowTicket) {
+        drop(ticket);
+    }
+}
+
+///
+/// Implemented by the guards handed out by a storage, allowing a poisoning
+/// storage to poison a unit when a guard on it is dropped while panicking, see
+/// `BlackBox::new_poisoning`.
+///
+/// The guards of units implemented outside of this crate can implement this
+/// with the default method, in which case they do not poison.
+///
+pub trait Poisoning {
+    ///
+    /// # Safety
+    /// `flag` must outlive the guard.
+    ///
+    #[doc(hidden)]
+    unsafe fn poison_on_panic(&mut self, flag: &AtomicBool) {
+        let _ = flag;
+    }
+}
+
+impl Backend for RefCellBackend {
+    type Read<'a, T: ?Sized + 'a> = Ref<'a, T>;
+    type Write<'a, T: ?Sized + 'a> = RefMut<'a, T>;
+}
+
+impl Backend for MutexBackend {
+    type Read<'a, T: ?Sized + 'a> = MappedMutexGuard<'a, T>;
+    type Write<'a, T: ?Sized + 'a> = MappedMutexGuard<'a, T>;
+}
+
+impl Backend for RwLockBackend {
+    type Read<'a, T: ?Sized + 'a> = MappedRwLockReadGuard<'a, T>;
+    type Write<'a, T: ?Sized + 'a> = MappedRwLockWriteGuard<'a, T>;
+}
+
+///
+/// An immutable lock on a value in a storage, as returned by `get` and `ind`.
+///
+/// Whether a guard may cross threads depends only on the backend and on `T`:
+///  - The guards of `DynamicStorage` are neither `Send` nor `Sync`
+///  - The guards of `MutexStorage` and `RwLockStorage` are `Sync` when `T: Sync`,
+///    as they only hand out `&T`, but must be released on the thread which took
+///    them, so they are not `Send`
+///
+/// # Example
+/// ```
+/// # fn main() {
+/// use restor::{RwLockStorage, make_storage};
+/// let storage = make_storage!(RwLockStorage: String);
+/// storage.insert(String::from("abc")).unwrap();
+/// let guard = storage.get::<String>().unwrap();
+/// let guard = &guard;
+/// std::thread::scope(|s| {
+///     s.spawn(move || assert_eq!(&**guard, "abc"));
+/// });
+/// # }
+/// ```
+///
+/// No guard may be moved to another thread, whatever its backend:
+/// ```compile_fail
+/// # fn main() {
+/// use restor::{RwLockStorage, make_storage};
+/// fn assert_send<T: Send>(_: &T) {}
+/// let storage = make_storage!(RwLockStorage: String);
+/// storage.insert(String::from("abc")).unwrap();
+/// assert_send(&storage.get::<String>().unwrap());
+/// # }
+/// ```
+///
+#[cfg_attr(
+    not(any(feature = "instrument", feature = "tracing")),
+    repr(transparent)
+)]
+pub struct StorageReadGuard<'a, T: ?Sized + 'a, B: Backend> {
+    inner: B::Read<'a, T>,
+    /// When the guard was taken, if it is timed, see `set_hold_warning`
+    #[cfg(feature = "tracing")]
+    held_since: Option<Instant>,
+    /// Unregisters the guard once `inner` has been released
+    #[cfg(feature = "instrument")]
+    ticket: Option<BorrowTicket>,
+    /// Opts out of the auto traits, which are instead implemented per backend
+    _marker: PhantomData<*const ()>,
+}
+
+///
+/// A mutable lock on a value in a storage, as returned by `get_mut`, `ind_mut`
+/// and `insert_and_get_mut`.
+///
+/// The guards of `MutexStorage` and `RwLockStorage` are `Sync` when `T: Sync`,
+/// while those of `DynamicStorage` are not. No guard is `Send`. See
+/// `StorageReadGuard`.
+///
+/// With the `poison` feature, in a storage created with `BlackBox::new_poisoning`,
+/// a guard dropped while panicking poisons its unit.
+///
+/// ```compile_fail
+/// # fn main() {
+/// use restor::{DynamicStorage, make_storage};
+/// fn assert_sync<T: Sync>(_: &T) {}
+/// let storage = make_storage!(DynamicStorage: String);
+/// storage.insert(String::from("abc")).unwrap();
+/// assert_sync(&storage.get_mut::<String>().unwrap());
+/// # }
+/// ```
+///
+#[cfg_attr(
+    not(any(feature = "instrument", feature = "tracing", feature = "poison")),
+    repr(transparent)
+)]
+pub struct StorageWriteGuard<'a, T: ?Sized + 'a, B: Backend> {
+    inner: B::Write<'a, T>,
+    /// Set when the guard is dropped while panicking, see `Poisoning`
+    #[cfg(feature = "poison")]
+    poison: Option<&'a AtomicBool>,
+    /// When the guard was taken, if it is timed, see `set_hold_warning`
+    #[cfg(feature = "tracing")]
+    held_since: Option<Instant>,
+    #[cfg(feature = "instrument")]
+    ticket: Option<BorrowTicket>,
+    _marker: PhantomData<*const ()>,
+}
+
+///
+/// A mutable lock on one of several values of a unit, as returned by
+/// `BlackBox::get_disjoint_ind_mut`.
+///
+/// The guards returned together share the lock `G` on the unit, which is only
+/// released once every one of them has been dropped. Each guard hands out its
+/// own value, and no two of them point to the same one.
+///
+pub struct ElemGuardMut<T, G> {
+    /// Keeps the unit borrowed, and is never dereferenced
+    _shared: Arc<G>,
+    value: *mut T,
+}
+
+impl<T, G> ElemGuardMut<T, G> {
+    ///
+    /// Internal function. `value` must point to a value behind `shared` which
+    /// no other guard points to, and which stays in place as long as `shared`
+    /// is held.
+    ///
+    #[inline]
+    pub(crate) unsafe fn new(shared: Arc<G>, value: *mut T) -> Self {
+        Self {
+            _shared: shared,
+            value,
+        }
+    }
+}
+
+impl<T, G> Deref for ElemGuardMut<T, G> {
+    type Target = T;
+    #[inline]
+    fn deref(&self) -> &T {
+        unsafe { &*self.value }
+    }
+}
+
+impl<T, G> DerefMut for ElemGuardMut<T, G> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.value }
+    }
+}
+
+impl<T: Debug, G> Debug for ElemGuardMut<T, G> {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        Debug::fmt(&**self, f)
+    }
+}
+
+impl<T: Display, G> Display for ElemGuardMut<T, G> {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        Display::fmt(&**self, f)
+    }
+}
+
+impl<'a, T: ?Sized + 'a, B: Backend> StorageReadGuard<'a, T, B> {
+    #[inline]
+    pub(crate) fn new(inner: B::Read<'a, T>) -> Self {
+        Self {
+            inner,
+            #[cfg(feature = "tracing")]
+            held_since: trace::hold_started(),
+            #[cfg(feature = "instrument")]
+            ticket: None,
+            _marker: PhantomData,
+        }
+    }
+}
+
+#[cfg(feature = "instrument")]
+impl<'a, T: ?Sized + 'a, B: Backend> Tracked for StorageReadGuard<'a, T, B> {
+    fn track(&mut self, ticket: BorrowTicket) {
+        self.ticket = Some(ticket);
+    }
+}
+
+impl<'a, T: ?Sized + 'a, B: Backend> StorageWriteGuard<'a, T, B> {
+    #[inline]
+    pub(crate) fn new(inner: B::Write<'a, T>) -> Self {
+        Self {
+            inner,
+            #[cfg(feature = "poison")]
+            poison: None,
+            #[cfg(feature = "tracing")]
+            held_since: trace::hold_started(),
+            #[cfg(feature = "instrument")]
+            ticket: None,
+            _marker: PhantomData,
+        }
+    }
+}
+
+#[cfg(feature = "instrument")]
+impl<'a, T: ?Sized + 'a, B: Backend> Tracked for StorageWriteGuard<'a, T, B> {
+    fn track(&mut self, ticket: BorrowTicket) {
+        self.ticket = Some(ticket);
+    }
+}
+
+#[cfg(not(feature = "poison"))]
+impl<'a, T: ?Sized + 'a, B: Backend> Poisoning for StorageWriteGuard<'a, T, B> {}
+
+#[cfg(feature = "poison")]
+impl<'a, T: ?Sized + 'a, B: Backend> Poisoning for StorageWriteGuard<'a, T, B> {
+    unsafe fn poison_on_panic(&mut self, flag: &AtomicBool) {
+        self.poison = Some(&*(flag as *const AtomicBool));
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl<'a, T: ?Sized + 'a, B: Backend> Drop for StorageReadGuard<'a, T, B> {
+    fn drop(&mut self) {
+        trace::hold_ended::<T>("read", self.held_since);
+    }
+}
+
+#[cfg(any(feature = "tracing", feature = "poison"))]
+impl<'a, T: ?Sized + 'a, B: Backend> Drop for StorageWriteGuard<'a, T, B> {
+    fn drop(&mut self) {
+        #[cfg(feature = "tracing")]
+        trace::hold_ended::<T>("write", self.held_since);
+        // This runs before `inner` is released, so no one can observe the value
+        // before the unit is poisoned
+        #[cfg(feature = "poison")]
+        if let Some(flag) = self.poison {
+            if std::thread::panicking() {
+                flag.store(true, Ordering::Release);
+            }
+        }
+    }
+}
+
+impl<'a, T: ?Sized + 'a, B: Backend> Deref for StorageReadGuard<'a, T, B> {
+    type Target = T;
+    #[inline]
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<'a, T: ?Sized + 'a, B: Backend> Deref for StorageWriteGuard<'a, T, B> {
+    type Target = T;
+    #[inline]
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<'a, T: ?Sized + 'a, B: Backend> DerefMut for StorageWriteGuard<'a, T, B> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+}
+
+impl<'a, T: ?Sized + Debug + 'a, B: Backend> Debug for StorageReadGuard<'a, T, B> {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        Debug::fmt(&**self, f)
+    }
+}
+
+impl<'a, T: ?Sized + Debug + 'a, B: Backend> Debug for StorageWriteGuard<'a, T, B> {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        Debug::fmt(&**self, f)
+    }
+}
+
+impl<'a, T: ?Sized + Display + 'a, B: Backend> Display for StorageReadGuard<'a, T, B> {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        Display::fmt(&**self, f)
+    }
+}
+
+impl<'a, T: ?Sized + Display + 'a, B: Backend> Display for StorageWriteGuard<'a, T, B> {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        Display::fmt(&**self, f)
+    }
+}
+
+// `lock_api` marks the guards of this version of `parking_lot` as `GuardNoSend`, as
+// a lock may keep per thread bookkeeping (e.g. for `deadlock_detection`), so the
+// guards stay on the thread which took them. Sharing one only hands out `&T`.
+unsafe impl<'a, T: ?Sized + Sync + 'a> Sync for StorageReadGuard<'a, T, MutexBackend> {}
+unsafe impl<'a, T: ?Sized + Sync + 'a> Sync for StorageReadGuard<'a, T, RwLockBackend> {}
+unsafe impl<'a, T: ?Sized + Sync + 'a> Sync for StorageWriteGuard<'a, T, MutexBackend> {}
+unsafe impl<'a, T: ?Sized + Sync + 'a> Sync for StorageWriteGuard<'a, T, RwLockBackend> {}
+
+// Each guard behaves as a `&mut T` to its own value, and dropping the last of
+// them may release the shared lock on any thread.
+unsafe impl<T: Send, G: Send + Sync> Send for ElemGuardMut<T, G> {}
+unsafe impl<T: Sync, G: Sync> Sync for ElemGuardMut<T, G> {}
+
+/// Checks the auto traits of the guards at compile time
+const _: fn() = || {
+    fn sync<T: Sync>() {}
+    sync::<StorageReadGuard<'static, String, RwLockBackend>>();
+    sync::<StorageReadGuard<'static, String, MutexBackend>>();
+    sync::<StorageWriteGuard<'static, String, RwLockBackend>>();
+    sync::<StorageWriteGuard<'static, String, MutexBackend>>();
+    sync::<ElemGuardMut<String, StorageWriteGuard<'static, (), RwLockBackend>>>();
+};
@@ -0,0 +1,59 @@
+use std::ops::Deref;
+
+///
+/// Extension methods shared by every guard returned by the storages, so
+/// that they can be used without naming the guard type of a particular
+/// storage. Both methods consume the guard, which releases the borrow or
+/// lock as soon as they return.
+///
+/// # Example
+/// ```
+/// # fn main() {
+/// use restor::prelude::*;
+/// use std::any::Any;
+///
+/// fn snapshot<U>(storage: &BlackBox<U>) -> (String, usize)
+/// where
+///     U: ?Sized + for<'a> Unit<'a, Owned = Box<dyn Any + Send>>,
+///     for<'a> <U as Unit<'a>>::Borrowed: Map<
+///         dyn Any + Send,
+///         String,
+///         Func = for<'b> fn(&'b (dyn Any + Send)) -> &'b String,
+///     >,
+/// {
+///     let name = storage.get::<String>().unwrap();
+///     let len = name.map_owned(|x| x.len());
+///     (storage.get::<String>().unwrap().cloned(), len)
+/// }
+///
+/// let dynamic = make_storage!(DynamicStorage: String);
+/// dynamic.insert("dynamic".to_string()).unwrap();
+/// assert_eq!(snapshot(&dynamic), ("dynamic".to_string(), 7));
+///
+/// let rwlock = make_storage!(RwLockStorage: String);
+/// rwlock.insert("rwlock".to_string()).unwrap();
+/// assert_eq!(snapshot(&rwlock), ("rwlock".to_string(), 6));
+/// # }
+/// ```
+///
+pub trait GuardExt: Deref + Sized {
+    ///
+    /// Clones the value behind the guard, and then releases the guard.
+    ///
+    fn cloned(self) -> Self::Target
+    where
+        Self::Target: Clone,
+    {
+        self.map_owned(Clone::clone)
+    }
+
+    ///
+    /// Runs `f` on the value behind the guard and returns its result,
+    /// releasing the guard afterwards.
+    ///
+    fn map_owned<R, F: FnOnce(&Self::Target) -> R>(self, f: F) -> R {
+        f(&*self)
+    }
+}
+
+impl<G: Deref> GuardExt for G {}
@@ -0,0 +1,130 @@
+use std::any::Any;
+use std::fmt::{Debug, Formatter, Result as FmtResult};
+use std::ops::Deref;
+use std::sync::{Arc, Weak};
+
+use super::{BlackBox, DynamicResult, ErrorDesc, StorageUnit, Unit};
+use crate::RwLockStorage;
+
+///
+/// A shared, owning reference to a storage, which can be downgraded into a
+/// `StorageWeakHandle` that doesn't keep the storage alive.
+///
+/// Values stored in a storage should refer back to it through weak handles,
+/// as a `StorageHandle` stored in its own storage would keep it alive forever.
+///
+/// # Example
+/// ```
+/// # fn main() {
+/// use restor::{RwLockStorage, StorageHandle, StorageUnit, StorageWeakHandle};
+///
+/// #[derive(Debug)]
+/// struct Subsystem {
+///     storage: StorageWeakHandle,
+/// }
+///
+/// let mut storage = RwLockStorage::new();
+/// storage.allocate_for::<usize>();
+/// storage.allocate_for::<Subsystem>();
+/// let storage = StorageHandle::new(storage);
+/// storage.insert(3usize).unwrap();
+/// storage
+///     .insert(Subsystem {
+///         storage: storage.downgrade(),
+///     })
+///     .unwrap();
+///
+/// let subsystem = storage.get::<Subsystem>().unwrap();
+/// assert_eq!(subsystem.storage.with(|x: &StorageUnit<usize>| x.len()), Ok(1));
+/// # }
+/// ```
+///
+pub struct StorageHandle<S = RwLockStorage>(Arc<S>);
+
+///
+/// A weak reference to a storage, created with `StorageHandle::downgrade`.
+///
+pub struct StorageWeakHandle<S = RwLockStorage>(Weak<S>);
+
+impl<S> StorageHandle<S> {
+    /// Moves `storage` behind a new handle
+    pub fn new(storage: S) -> Self {
+        StorageHandle(Arc::new(storage))
+    }
+
+    ///
+    /// Creates a weak handle to the same storage.
+    ///
+    pub fn downgrade(&self) -> StorageWeakHandle<S> {
+        StorageWeakHandle(Arc::downgrade(&self.0))
+    }
+
+    ///
+    /// Returns the `Arc` which owns the storage.
+    ///
+    pub fn as_arc(&self) -> &Arc<S> {
+        &self.0
+    }
+}
+
+impl<S> StorageWeakHandle<S> {
+    ///
+    /// Returns a handle to the storage, or `None` in the case that it was
+    /// already dropped.
+    ///
+    pub fn upgrade(&self) -> Option<StorageHandle<S>> {
+        self.0.upgrade().map(StorageHandle)
+    }
+}
+
+impl<U: ?Sized + for<'a> Unit<'a, Owned = Box<dyn Any + Send>>> StorageWeakHandle<BlackBox<U>> {
+    ///
+    /// Runs `f` on the values of `T` while holding an immutable borrow on them,
+    /// keeping the storage alive until `f` returns. This returns
+    /// `ErrorDesc::StorageDropped` in the case that the storage was dropped.
+    ///
+    pub fn with<T: 'static + Send, R, F: FnOnce(&StorageUnit<T>) -> R>(
+        &self,
+        f: F,
+    ) -> DynamicResult<R> {
+        let storage = self.upgrade().ok_or(ErrorDesc::StorageDropped)?;
+        storage.with_storage(f)
+    }
+}
+
+impl<S> Deref for StorageHandle<S> {
+    type Target = S;
+    fn deref(&self) -> &S {
+        &self.0
+    }
+}
+
+impl<S> Clone for StorageHandle<S> {
+    fn clone(&self) -> Self {
+        StorageHandle(self.0.clone())
+    }
+}
+
+impl<S> Clone for StorageWeakHandle<S> {
+    fn clone(&self) -> Self {
+        StorageWeakHandle(self.0.clone())
+    }
+}
+
+impl<S> From<Arc<S>> for StorageHandle<S> {
+    fn from(storage: Arc<S>) -> Self {
+        StorageHandle(storage)
+    }
+}
+
+impl<S> Debug for StorageHandle<S> {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        f.debug_struct("StorageHandle").finish_non_exhaustive()
+    }
+}
+
+impl<S> Debug for StorageWeakHandle<S> {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        f.debug_struct("StorageWeakHandle").finish_non_exhaustive()
+    }
+}
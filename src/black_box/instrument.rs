@@ -0,0 +1,169 @@
+//! Tracking of the guards which are still alive, only compiled with the
+//! `instrument` feature.
+use super::{BlackBox, Unit};
+use parking_lot::Mutex;
+use std::any::Any;
+use std::backtrace::Backtrace;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+///
+/// Whether a guard is an immutable borrow, as returned by `get` and `ind`, or a
+/// mutable one, as returned by `get_mut` and `ind_mut`.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BorrowKind {
+    Read,
+    Write,
+}
+
+///
+/// A guard which was still alive when `BlackBox::outstanding_borrows` was called.
+///
+#[derive(Debug, Clone)]
+pub struct BorrowReport {
+    /// The name of the type borrowed through the guard.
+    pub type_name: &'static str,
+    /// Whether the guard is a mutable borrow.
+    pub kind: BorrowKind,
+    /// How long ago the guard was handed out.
+    pub age: Duration,
+    /// Where the guard was handed out. This is only captured in debug builds.
+    pub backtrace: Option<Arc<Backtrace>>,
+}
+
+struct Borrow {
+    type_name: &'static str,
+    kind: BorrowKind,
+    since: Instant,
+    backtrace: Option<Arc<Backtrace>>,
+}
+
+///
+/// The guards of an instrumented storage which are still alive. Guards are
+/// registered after their borrow succeeds, and unregistered after it is
+/// released, so the lock on the registry is never held while acquiring one on
+/// a unit.
+///
+#[derive(Default)]
+pub(crate) struct BorrowRegistry {
+    next: AtomicU64,
+    live: Mutex<HashMap<u64, Borrow>>,
+}
+
+impl BorrowRegistry {
+    pub(crate) fn register(
+        self: &Arc<Self>,
+        type_name: &'static str,
+        kind: BorrowKind,
+    ) -> BorrowTicket {
+        let id = self.next.fetch_add(1, Ordering::Relaxed);
+        #[cfg(debug_assertions)]
+        let backtrace = Some(Arc::new(Backtrace::force_capture()));
+        #[cfg(not(debug_assertions))]
+        let backtrace = None;
+        self.live.lock().insert(
+            id,
+            Borrow {
+                type_name,
+                kind,
+                since: Instant::now(),
+                backtrace,
+            },
+        );
+        BorrowTicket {
+            registry: self.clone(),
+            id,
+        }
+    }
+}
+
+///
+/// The registration of a guard, which is held by the guard and unregisters it
+/// when dropped.
+///
+#[doc(hidden)]
+pub struct BorrowTicket {
+    registry: Arc<BorrowRegistry>,
+    id: u64,
+}
+
+impl Drop for BorrowTicket {
+    fn drop(&mut self) {
+        self.registry.live.lock().remove(&self.id);
+    }
+}
+
+impl<U: ?Sized + for<'a> Unit<'a, Owned = Box<dyn Any + Send>>> BlackBox<U> {
+    ///
+    /// Creates a storage which keeps track of every guard it hands out, so
+    /// that those which are held for too long can be found with
+    /// `outstanding_borrows`. In debug builds, the backtrace of where each guard
+    /// was handed out is captured as well, which makes every borrow much slower.
+    ///
+    /// Storages created otherwise track nothing, and without the `instrument`
+    /// feature, guards don't carry anything to be tracked with.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::{BorrowKind, RwLockStorage};
+    /// let mut storage = RwLockStorage::new_instrumented();
+    /// storage.allocate_for::<String>();
+    /// storage.insert(String::from("abc")).unwrap();
+    /// let guard = storage.get_mut::<String>().unwrap();
+    /// let report = storage.outstanding_borrows();
+    /// assert_eq!(report.len(), 1);
+    /// assert_eq!(report[0].kind, BorrowKind::Write);
+    /// assert!(report[0].type_name.contains("String"));
+    /// drop(guard);
+    /// assert!(storage.outstanding_borrows().is_empty());
+    /// # }
+    /// ```
+    ///
+    pub fn new_instrumented() -> Self {
+        let mut storage = Self::new();
+        storage.borrows = Some(Arc::default());
+        storage
+    }
+
+    ///
+    /// Returns the guards handed out by this storage which are still alive,
+    /// oldest first. This is always empty for a storage which wasn't created
+    /// with `new_instrumented`.
+    ///
+    pub fn outstanding_borrows(&self) -> Vec<BorrowReport> {
+        let registry = match &self.borrows {
+            Some(registry) => registry,
+            None => return Vec::new(),
+        };
+        let now = Instant::now();
+        let mut reports = registry
+            .live
+            .lock()
+            .values()
+            .map(|borrow| BorrowReport {
+                type_name: borrow.type_name,
+                kind: borrow.kind,
+                age: now.saturating_duration_since(borrow.since),
+                backtrace: borrow.backtrace.clone(),
+            })
+            .collect::<Vec<_>>();
+        reports.sort_by_key(|report| std::cmp::Reverse(report.age));
+        reports
+    }
+
+    ///
+    /// Internal function. Registers `guard` as a borrow of `T`, if this storage
+    /// is instrumented.
+    ///
+    #[inline]
+    pub(crate) fn track<T: 'static, G: super::Tracked>(&self, mut guard: G, kind: BorrowKind) -> G {
+        if let Some(registry) = &self.borrows {
+            guard.track(registry.register(std::any::type_name::<T>(), kind));
+        }
+        guard
+    }
+}
@@ -1,109 +1,333 @@
+//! The storage itself, and the path taken to reach a value in it.
+//!
+//! Reading a value through `get` or `ind` goes through the following steps:
+//!  1. The unit for `T` is looked up, which is usually a hit in the single entry
+//!     lookup cache rather than a `HashMap` lookup, see `unit_lookup`.
+//!  2. The unit is borrowed through its `Unit` implementation, which is the only
+//!     dynamic call, and which reaches the value with a single match on the
+//!     `StorageUnit`, mapping the guard in the same step, see `Ref::filter_map`.
+//!  3. The `dyn Any` in the guard is cast to `T` without checking its type.
+//!
+//! The last step relies on the following invariant: the unit stored under
+//! `TypeId::of::<T>()` always holds a `StorageUnit<T>`, and so the values it hands
+//! out are always `T`s. Units are only ever created by `allocate_unit`, keyed by
+//! the type they are created for, and the lookup cache is checked against the id
+//! of the unit before being used, so this holds as long as those two do. It is
+//! still asserted in debug builds.
+//!
+//! There is no separate layout for units holding a single value, as the tag of a
+//! `StorageUnit` lives in a niche of its `Vec`, so `One` is already stored inline,
+//! and reaching it costs a single comparison. What remains is dominated by the
+//! borrow itself, which for `RwLockStorage` and `MutexStorage` is an atomic
+//! operation on acquiring and on releasing it. See `benches/access.rs`.
 use parking_lot::{MappedMutexGuard, MappedRwLockReadGuard, MappedRwLockWriteGuard};
 use std::any::{Any, TypeId};
 use std::cell::{Ref, RefMut};
 use std::collections::HashMap;
 use std::ops::{Deref, DerefMut};
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicPtr, AtomicU64, Ordering};
+use std::sync::Arc;
 
 mod unit;
 
-pub use crate::black_box::unit::{DynamicResult, ErrorDesc, StorageUnit, Unit, UnitError};
-use crate::concurrent_black_box::{MutexUnit, RwLockUnit};
+pub use crate::black_box::unit::{
+    run_for_values, DynamicResult, ErrorDesc, ErrorKind, Fairness, IntegrityError, StorageUnit,
+    TryError, Unit, UnitError, UnitState, ValueChange,
+};
+use crate::concurrent_black_box::{MutexUnit, ReadMostly, RwLockUnit};
+
+mod allocate_set;
+pub use crate::black_box::allocate_set::{AllocateSet, MissingTypes, Sets};
+
+mod guard;
+pub use crate::black_box::guard::{
+    Backend, ElemGuardMut, MutexBackend, RefCellBackend, RwLockBackend, StorageReadGuard,
+    StorageWriteGuard,
+};
+
+pub use crate::black_box::guard::Poisoning;
+#[cfg(feature = "instrument")]
+pub use crate::black_box::guard::Tracked;
+
+mod guard_ext;
+pub use crate::black_box::guard_ext::GuardExt;
+
+mod handle;
+pub use crate::black_box::handle::{StorageHandle, StorageWeakHandle};
+
+mod cloned_chunks;
+pub use crate::black_box::cloned_chunks::ClonedChunks;
+
+mod summary;
+pub use crate::black_box::summary::{StorageSummary, SummaryEntry, UnitStats};
+
+mod cow_unit;
+pub use crate::black_box::cow_unit::CowUnit;
+
+mod dirty_unit;
+pub use crate::black_box::dirty_unit::DirtyUnit;
+
+mod poll;
+pub use crate::black_box::poll::{PollResult, RetryToken};
 
 mod refcell_unit;
+mod type_map;
+use crate::black_box::type_map::TypeMap;
+#[cfg(feature = "tracing")]
+mod trace;
+#[cfg(feature = "tracing")]
+pub use crate::black_box::trace::set_hold_warning;
+
+#[cfg(feature = "events")]
+mod events;
+#[cfg(feature = "events")]
+pub use crate::black_box::events::StorageEvent;
+
+#[cfg(feature = "instrument")]
+mod instrument;
+#[cfg(feature = "instrument")]
+pub use crate::black_box::instrument::{BorrowKind, BorrowReport};
+
+/// Reports a change made to the values of the unit for `T` to the unit, see
+/// `Unit::record_change`, and sends it as a `StorageEvent` to the subscribers of
+/// the unit when the `events` feature is enabled.
+#[cfg(feature = "events")]
+macro_rules! notify {
+    ($storage:expr, $t:ty, $kind:ident { $($field:ident: $value:expr),* }) => {{
+        $storage.record_change::<$t>(ValueChange::$kind { $($field: $value),* });
+        $storage.notify::<$t>(StorageEvent::$kind {
+            id: TypeId::of::<$t>(),
+            $($field: $value),*
+        })
+    }};
+}
+
+#[cfg(not(feature = "events"))]
+macro_rules! notify {
+    ($storage:expr, $t:ty, $kind:ident { $($field:ident: $value:expr),* }) => {
+        $storage.record_change::<$t>(ValueChange::$kind { $($field: $value),* })
+    };
+}
+
+/// Reports the outcome of an operation when the `tracing` feature is enabled, and
+/// otherwise evaluates to the result untouched. The four argument form is for results
+/// which hand the rejected value back alongside the error.
+#[cfg(feature = "tracing")]
+macro_rules! traced {
+    ($op:expr, $t:ty, $result:expr) => {{
+        let result = $result;
+        trace::record::<$t>($op, result.as_ref().err());
+        result
+    }};
+    ($op:expr, $t:ty, $result:expr, rejected) => {{
+        let result = $result;
+        trace::record::<$t>($op, result.as_ref().err().map(|(_, e)| e));
+        result
+    }};
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! traced {
+    ($op:expr, $t:ty, $result:expr) => {
+        $result
+    };
+    ($op:expr, $t:ty, $result:expr, rejected) => {
+        $result
+    };
+}
+
+/// Registers a guard on a `T` with an instrumented storage when the `instrument`
+/// feature is enabled, and otherwise returns it as is.
+#[cfg(feature = "instrument")]
+macro_rules! tracked {
+    ($storage:expr, $t:ty, $kind:ident, $guard:expr) => {
+        $storage.track::<$t, _>($guard, BorrowKind::$kind)
+    };
+}
+
+#[cfg(not(feature = "instrument"))]
+macro_rules! tracked {
+    ($storage:expr, $t:ty, $kind:ident, $guard:expr) => {
+        $guard
+    };
+}
+
+mod finalize;
+
+mod transaction;
+pub use crate::black_box::transaction::Transaction;
+
+mod cursor;
+pub use crate::black_box::cursor::Cursor;
+
+mod singleton;
+pub use crate::black_box::singleton::SingletonStorage;
+
+mod view;
+pub use crate::black_box::view::{SharedView, StorageView};
+
+mod par_chunks;
+
+mod token;
+pub use crate::black_box::token::TypeToken;
+
+mod profile;
+pub use crate::black_box::profile::{CapacityEntry, CapacityProfile};
+
+mod spare;
+use crate::black_box::spare::{AnySpare, BufferedUnit, Spare};
+
+#[cfg(feature = "pod")]
+mod pod;
+#[cfg(feature = "pod")]
+pub use crate::black_box::pod::{Pod, PodError};
 
 pub use crate::black_box::refcell_unit::*;
 
 pub type RefCellUnitTrait = dyn for<'a> Unit<
     'a,
-    Borrowed = Ref<'a, (dyn Any + Send)>,
-    MutBorrowed = RefMut<'a, (dyn Any + Send)>,
-    Owned = Box<(dyn Any + Send)>,
+    Borrowed = Ref<'a, dyn Any + Send>,
+    MutBorrowed = RefMut<'a, dyn Any + Send>,
+    Owned = Box<dyn Any + Send>,
 >;
 pub type MutexUnitTrait = dyn for<'a> Unit<
     'a,
-    Borrowed = MappedMutexGuard<'a, (dyn Any + Send)>,
-    MutBorrowed = MappedMutexGuard<'a, (dyn Any + Send)>,
-    Owned = Box<(dyn Any + Send)>,
+    Borrowed = MappedMutexGuard<'a, dyn Any + Send>,
+    MutBorrowed = MappedMutexGuard<'a, dyn Any + Send>,
+    Owned = Box<dyn Any + Send>,
 >;
-pub type RwLockUnitTrait = for<'a> Unit<
+pub type RwLockUnitTrait = dyn for<'a> Unit<
     'a,
-    Borrowed = MappedRwLockReadGuard<'a, (dyn Any + Send)>,
-    MutBorrowed = MappedRwLockWriteGuard<'a, (dyn Any + Send)>,
-    Owned = Box<(dyn Any + Send)>,
+    Borrowed = MappedRwLockReadGuard<'a, dyn Any + Send>,
+    MutBorrowed = MappedRwLockWriteGuard<'a, dyn Any + Send>,
+    Owned = Box<dyn Any + Send>,
 >;
 
+/// Checks that the type erased values of every storage may be sent between threads
+const _: fn() = || {
+    fn send<T: Send>() {}
+    send::<<RefCellUnitTrait as Unit<'static>>::Owned>();
+    send::<<MutexUnitTrait as Unit<'static>>::Owned>();
+    send::<<RwLockUnitTrait as Unit<'static>>::Owned>();
+};
+
 /// A trait forcing the implementor to implement a `map` function
 /// this is used to genericize over `MappedMutexGuard`,
-/// `MappedRwLockReadGuard` and `Ref`
+/// `MappedRwLockReadGuard` and `Ref`, wrapping the result in a
+/// `StorageReadGuard`
 pub trait Map<I: ?Sized, O: ?Sized>: Deref<Target = I> + Sized {
+    #[cfg(not(feature = "instrument"))]
     type Output: Deref<Target = O>;
+    #[cfg(feature = "instrument")]
+    type Output: Deref<Target = O> + Tracked;
     type Func: Sized + 'static;
     fn map(self, f: Self::Func) -> Self::Output;
 }
 
 impl<'a, I: 'static + ?Sized, O: 'static + ?Sized> Map<I, O> for Ref<'a, I> {
-    type Output = Ref<'a, O>;
+    type Output = StorageReadGuard<'a, O, RefCellBackend>;
     type Func = for<'b> fn(&'b I) -> &'b O;
-    fn map(self, f: Self::Func) -> Ref<'a, O> {
-        Ref::map(self, f)
+    fn map(self, f: Self::Func) -> StorageReadGuard<'a, O, RefCellBackend> {
+        StorageReadGuard::new(Ref::map(self, f))
     }
 }
 
 impl<'a, I: 'static + Send + ?Sized, O: 'static + Send + ?Sized> Map<I, O>
     for MappedMutexGuard<'a, I>
 {
-    type Output = MappedMutexGuard<'a, O>;
+    type Output = StorageReadGuard<'a, O, MutexBackend>;
     type Func = for<'b> fn(&'b mut I) -> &'b mut O;
-    fn map(self, f: Self::Func) -> MappedMutexGuard<'a, O> {
-        MappedMutexGuard::map(self, f)
+    fn map(self, f: Self::Func) -> StorageReadGuard<'a, O, MutexBackend> {
+        StorageReadGuard::new(MappedMutexGuard::map(self, f))
     }
 }
 
 impl<'a, I: 'static + Send + ?Sized, O: 'static + Send + ?Sized> Map<I, O>
     for MappedRwLockReadGuard<'a, I>
 {
-    type Output = MappedRwLockReadGuard<'a, O>;
+    type Output = StorageReadGuard<'a, O, RwLockBackend>;
     type Func = for<'b> fn(&'b I) -> &'b O;
-    fn map(self, f: Self::Func) -> MappedRwLockReadGuard<'a, O> {
-        MappedRwLockReadGuard::map(self, f)
+    fn map(self, f: Self::Func) -> StorageReadGuard<'a, O, RwLockBackend> {
+        StorageReadGuard::new(MappedRwLockReadGuard::map(self, f))
     }
 }
 /// A trait forcing the implementor to implement a `map` method
 /// this is used to genericize over `MappedMutexGuard` and
-/// `MappedRwLockWriteGuard` and `RefMut`
+/// `MappedRwLockWriteGuard` and `RefMut`, wrapping the result in a
+/// `StorageWriteGuard`
 pub trait MapMut<I: ?Sized, O: ?Sized>: Deref<Target = I> + Sized + DerefMut {
-    type Output: Deref<Target = O> + DerefMut;
+    #[cfg(not(feature = "instrument"))]
+    type Output: Deref<Target = O> + DerefMut + Poisoning;
+    #[cfg(feature = "instrument")]
+    type Output: Deref<Target = O> + DerefMut + Poisoning + Tracked;
     type Func: Sized + 'static;
     fn map(self, f: Self::Func) -> Self::Output;
 }
 
 impl<'a, I: 'static + ?Sized, O: 'static + ?Sized> MapMut<I, O> for RefMut<'a, I> {
-    type Output = RefMut<'a, O>;
+    type Output = StorageWriteGuard<'a, O, RefCellBackend>;
     type Func = for<'b> fn(&'b mut I) -> &'b mut O;
-    fn map(self, f: Self::Func) -> RefMut<'a, O> {
-        RefMut::map(self, f)
+    fn map(self, f: Self::Func) -> StorageWriteGuard<'a, O, RefCellBackend> {
+        StorageWriteGuard::new(RefMut::map(self, f))
     }
 }
 
 impl<'a, I: 'static + Send + ?Sized, O: 'static + Send + ?Sized> MapMut<I, O>
     for MappedRwLockWriteGuard<'a, I>
 {
-    type Output = MappedRwLockWriteGuard<'a, O>;
+    type Output = StorageWriteGuard<'a, O, RwLockBackend>;
     type Func = for<'b> fn(&'b mut I) -> &'b mut O;
-    fn map(self, f: Self::Func) -> MappedRwLockWriteGuard<'a, O> {
-        MappedRwLockWriteGuard::map(self, f)
+    fn map(self, f: Self::Func) -> StorageWriteGuard<'a, O, RwLockBackend> {
+        StorageWriteGuard::new(MappedRwLockWriteGuard::map(self, f))
     }
 }
 
 impl<'a, I: 'static + Send + ?Sized, O: 'static + Send + ?Sized> MapMut<I, O>
     for MappedMutexGuard<'a, I>
 {
-    type Output = MappedMutexGuard<'a, O>;
+    type Output = StorageWriteGuard<'a, O, MutexBackend>;
     type Func = for<'b> fn(&'b mut I) -> &'b mut O;
-    fn map(self, f: Self::Func) -> MappedMutexGuard<'a, O> {
-        MappedMutexGuard::map(self, f)
+    fn map(self, f: Self::Func) -> StorageWriteGuard<'a, O, MutexBackend> {
+        StorageWriteGuard::new(MappedMutexGuard::map(self, f))
     }
 }
+
+///
+/// Decides what `get` and `get_mut` do when the unit holds many values, see
+/// `BlackBox::with_policy` and `BlackBox::get_with_policy`.
+///
+/// # Tradeoff
+/// `Strict` keeps a single value and a list of values apart: code written for
+/// a single value fails loudly with `UnitError::IsNotOne` once something else
+/// starts inserting more of them, rather than quietly working on one of many.
+/// `FirstOnMany` instead lets code which only cares about *a* value keep
+/// working whether there are one or many, at the cost of no longer noticing
+/// when there are many. Either way, `ind` and `ind_mut` are unaffected, and an
+/// empty unit is still reported as `IsNotOne`.
+///
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum GetPolicy {
+    /// `get` and `get_mut` only return a single value, see `BlackBox::get`.
+    #[default]
+    Strict,
+    /// `get` and `get_mut` return the first value when there are many of them.
+    FirstOnMany,
+}
+///
+/// Decides what `BlackBox::insert_bounded` and `BlackBox::insert_many_bounded`
+/// do with a value which would take a unit past its capacity.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OverflowPolicy {
+    /// The value is handed back with `UnitError::Full`, and nothing changes.
+    Reject,
+    /// The oldest values are removed to make room for the new one.
+    DropOldest,
+    /// The new value is not inserted, and handed back.
+    DropNewest,
+}
+
 ///
 /// The base structure for this library, contains all of the
 /// dynamically typed storage units
@@ -113,477 +337,3723 @@ impl<'a, I: 'static + Send + ?Sized, O: 'static + Send + ?Sized> MapMut<I, O>
 /// with the type alias at the root of this library:
 ///
 /// * `DynamicStorage`:
-/// Based on `RefCell`s, for its interior mutability.
-/// This is _NOT_ `Send`, but it is faster, because it
-/// does not use atomic operations.
+///   Based on `RefCell`s, for its interior mutability.
+///   This is _NOT_ `Send`, but it is faster, because it
+///   does not use atomic operations.
 /// * `MutexStorage`:
-/// Uses a `Mutex` for `Send` capabilites, and interior mutability
-/// This only exposes mutable getter methods, as there is only
-/// a `&mut` api available for a `MappedMutexGuard`
+///   Uses a `Mutex` for `Send` capabilites, and interior mutability
+///   This only exposes mutable getter methods, as there is only
+///   a `&mut` api available for a `MappedMutexGuard`
 /// * `RwLockStorage`:
-/// This exposes the same api as a `RefCell` but is atomically guarded
-/// and therefore guarantees a safe `Send`, while allowing multiple
-/// readers.
+///   This exposes the same api as a `RefCell` but is atomically guarded
+///   and therefore guarantees a safe `Send`, while allowing multiple
+///   readers.
 ///
 /// The type parameter `U` is the `Unit` that is going to be used to store
 /// the data that is placed into it. This type parameter should, once
 /// again be avoided by the user, and should instead use the
 /// type definitions that are noted above.
 ///
+/// Units are kept in the order they were allocated in, and anything going
+/// through every unit of the storage, such as `retain_types`, `validate` or
+/// `fork`, does so in that order, which is the same from run to run. Removing
+/// a unit leaves the others in their order, and allocating it again puts it
+/// last. The values of a unit are kept in the order they were inserted in.
+///
+/// # Receivers
+/// The methods which only go through the units already allocated take
+/// `&self`: inserting, extracting, borrowing and changing values, which is
+/// checked while running, by the interior mutability of each unit. The methods
+/// which change which units there are, or how the storage treats them, take
+/// `&mut self`: allocating and removing units, registering names, tokens,
+/// finalizers and subscribers, and applying a capacity profile. The map of
+/// units is read without a lock by every access, and the most recently used
+/// unit is cached as a pointer to its slot in the map, so changing the map
+/// must not overlap any access, which `&mut self` has checked when compiling.
+/// The guards handed out don't depend on this, as they point into the units
+/// themselves, which are boxed and stay in place when the map changes.
+///
+/// This is the same for every flavor. A storage which is shared between
+/// threads through an `Arc` is set up before it is shared, or through
+/// `Arc::get_mut` while the `Arc` isn't cloned yet, and the `&self` methods
+/// are then called on the `Arc` directly, which derefs to the storage. There
+/// is no way yet to allocate a unit in a storage which is already shared.
+///
 #[derive(Default)]
 pub struct BlackBox<U: ?Sized> {
-    data: HashMap<TypeId, Box<U>>,
+    data: TypeMap<Box<U>>,
+    /// The most recently looked up unit. This points into `data`, and is therefore
+    /// cleared whenever `data` is accessed mutably through `BlackBox::data_mut`.
+    cache: AtomicPtr<Box<U>>,
+    /// The names which units can be looked up by, see `BlackBox::has_unit_named`.
+    names: HashMap<String, TypeId>,
+    /// The receivers of changes to each unit, see `BlackBox::subscribe`.
+    #[cfg(feature = "events")]
+    subscribers: HashMap<TypeId, Vec<std::sync::mpsc::Sender<StorageEvent>>>,
+    /// The poison flag of each unit, or `None` if this storage doesn't poison,
+    /// see `BlackBox::new_poisoning`.
+    #[cfg(feature = "poison")]
+    poisoned: Option<TypeMap<AtomicBool>>,
+    /// Whether units keep track of the thread holding them, see
+    /// `MutexStorage::new_debug`.
+    track_holders: bool,
+    /// The `ReadMostly<T>` for each type allocated through `allocate_read_mostly_for`,
+    /// along with the name of `T`.
+    read_mostly: TypeMap<(&'static str, Box<dyn Any + Send + Sync>)>,
+    /// What `get` and `get_mut` do when there are many values, see `BlackBox::with_policy`.
+    policy: GetPolicy,
+    /// The guards which are still alive, or `None` if this storage isn't
+    /// instrumented, see `BlackBox::new_instrumented`.
+    #[cfg(feature = "instrument")]
+    borrows: Option<Arc<instrument::BorrowRegistry>>,
+    /// The finalizer of each type which has one, see `BlackBox::on_remove`.
+    finalizers: TypeMap<finalize::Finalizer<U>>,
+    /// The number of times values of each type were removed or could have been
+    /// reordered, see `Cursor`. These are kept when a unit is removed, so that
+    /// the cursors over it are invalidated once it is allocated again.
+    reshapes: TypeMap<AtomicU64>,
+    /// The tag and size of each type allocated through `allocate_pod_for`.
+    #[cfg(feature = "pod")]
+    pods: TypeMap<pod::PodUnit<U>>,
+    /// The list kept aside for the unit of each type while it has no values or
+    /// a single one, see `BlackBox::allocate_for_with_vec`.
+    spares: TypeMap<Box<dyn AnySpare>>,
+    /// Tells this storage apart from every other one, so that the tokens
+    /// registered with it aren't used on another, see `TypeToken`.
+    id: u64,
+}
+
+impl<U: ?Sized> Drop for BlackBox<U> {
+    fn drop(&mut self) {
+        // The values are finalized while every unit is still there for the
+        // finalizers to look at.
+        for finalizer in self.finalizers.values() {
+            (finalizer.drain)(self);
+        }
+    }
+}
+
+///
+/// Poisons a unit if it is dropped while panicking. This is armed around
+/// mutations of a unit, and should be dropped before the borrow on the unit
+/// is released, so that no one can observe the unit before it is poisoned.
+///
+struct PoisonOnPanic<'a>(Option<&'a AtomicBool>);
+
+impl Drop for PoisonOnPanic<'_> {
+    fn drop(&mut self) {
+        if let Some(flag) = self.0 {
+            if std::thread::panicking() {
+                flag.store(true, Ordering::Release);
+            }
+        }
+    }
+}
+
+///
+/// Invalidates the cursors over a unit if it is dropped while panicking, as
+/// a mutation which panics may have lost values. Like `PoisonOnPanic`, this
+/// should be dropped before the borrow on the unit is released.
+///
+struct ReshapeOnPanic<'a>(Option<&'a AtomicU64>);
+
+impl Drop for ReshapeOnPanic<'_> {
+    fn drop(&mut self) {
+        if let Some(reshapes) = self.0 {
+            if std::thread::panicking() {
+                reshapes.fetch_add(1, Ordering::AcqRel);
+            }
+        }
+    }
 }
 
 type Borrowed<'a, T> = <T as Unit<'a>>::Borrowed;
+
+/// Downcasts `x` without checking its type, which must be checked beforehand.
+#[inline]
+fn downcast_ref_unchecked<T: 'static>(x: &(dyn Any + Send)) -> &T {
+    debug_assert!(x.is::<T>());
+    unsafe { &*(x as *const (dyn Any + Send) as *const T) }
+}
+
+/// Downcasts `x` without checking its type, which must be checked beforehand.
+#[inline]
+fn downcast_mut_unchecked<T: 'static>(x: &mut (dyn Any + Send)) -> &mut T {
+    debug_assert!(x.is::<T>());
+    unsafe { &mut *(x as *mut (dyn Any + Send) as *mut T) }
+}
+
 type MutBorrowed<'a, T> = <T as Unit<'a>>::MutBorrowed;
+type MappedMut<'a, U, T> = <MutBorrowed<'a, U> as MapMut<dyn Any + Send, T>>::Output;
+type Mapped<'a, U, T> = <Borrowed<'a, U> as Map<dyn Any + Send, T>>::Output;
+type DisjointMut<'a, U, T> = ElemGuardMut<T, MappedMut<'a, U, StorageUnit<T>>>;
 
-impl<U: ?Sized + for<'a> Unit<'a, Owned = Box<(dyn Any + Send)>>> BlackBox<U> {
+/// The number of times `BlackBox::compose` tries to write back a new value.
+pub const COMPOSE_ATTEMPTS: usize = 16;
+
+/// Returns the last value of the `StorageUnit<T>` in `x`, which must not be empty.
+fn last_mut<T: 'static + Send>(x: &mut (dyn Any + Send)) -> &mut T {
+    let unit = x.downcast_mut::<StorageUnit<T>>().unwrap();
+    let last = unit.len() - 1;
+    unit.ind_mut(last).unwrap()
+}
+
+/// Returns the first value of the `StorageUnit<T>` in `x`, which must not be empty.
+fn first_mut<T: 'static + Send>(x: &mut (dyn Any + Send)) -> &mut T {
+    x.downcast_mut::<StorageUnit<T>>()
+        .unwrap()
+        .ind_mut(0)
+        .unwrap()
+}
+
+impl<U: ?Sized + for<'a> Unit<'a, Owned = Box<dyn Any + Send>>> BlackBox<U> {
     ///
     /// A default implementation of `BlackBox`
     ///
     pub fn new() -> Self {
         Self {
-            data: HashMap::new(),
+            data: TypeMap::new(),
+            cache: AtomicPtr::new(ptr::null_mut()),
+            names: HashMap::new(),
+            #[cfg(feature = "events")]
+            subscribers: HashMap::new(),
+            #[cfg(feature = "poison")]
+            poisoned: None,
+            track_holders: false,
+            read_mostly: TypeMap::new(),
+            policy: GetPolicy::Strict,
+            finalizers: TypeMap::new(),
+            reshapes: TypeMap::new(),
+            spares: TypeMap::new(),
+            #[cfg(feature = "pod")]
+            pods: TypeMap::new(),
+            #[cfg(feature = "instrument")]
+            borrows: None,
+            id: token::next_storage_id(),
         }
     }
 
     ///
-    /// Checks if there is an allocated unit for
-    /// the type parameter in the internal hashmap.
+    /// Creates a storage whose `get` and `get_mut` follow `policy` when a unit
+    /// holds many values. See `GetPolicy` for the tradeoff between policies.
     ///
     /// # Example
     /// ```
     /// # fn main() {
-    /// use restor::DynamicStorage;
-    /// let mut storage = DynamicStorage::new();
-    /// assert!(!storage.has_unit::<usize>());
+    /// use restor::{GetPolicy, RwLockStorage};
+    /// let mut storage = RwLockStorage::with_policy(GetPolicy::FirstOnMany);
     /// storage.allocate_for::<usize>();
-    /// assert!(storage.has_unit::<usize>());
+    /// storage.insert_many(vec![1usize, 2, 3]).unwrap();
+    /// assert_eq!(*storage.get::<usize>().unwrap(), 1);
     /// # }
     /// ```
-    #[inline]
-    pub fn has_unit<T: 'static + Send>(&self) -> bool {
-        self.data.contains_key(&TypeId::of::<T>())
+    ///
+    pub fn with_policy(policy: GetPolicy) -> Self {
+        let mut storage = Self::new();
+        storage.policy = policy;
+        storage
     }
 
     ///
-    /// Inserts a value into the storage and returns it in the case
-    /// that it's impossible to insert or it is already borrowed.
+    /// Returns the policy followed by `get` and `get_mut`.
     ///
-    /// This appends to a list of values in the case that there is
-    /// one or values of the type in the internal storage.
+    pub fn policy(&self) -> GetPolicy {
+        self.policy
+    }
+
+    ///
+    /// Changes the policy followed by `get` and `get_mut`, see `with_policy`.
+    ///
+    pub fn set_policy(&mut self, policy: GetPolicy) {
+        self.policy = policy;
+    }
+
+    ///
+    /// Creates a storage which poisons a unit when a closure mutating it panics,
+    /// such as the one passed to `map_in_place`, or when a guard returned by
+    /// `get_mut`, `ind_mut` and the like is dropped while panicking. All accesses
+    /// to a poisoned unit return `ErrorDesc::Poisoned` until `clear_poison` is
+    /// called, or until its contents are replaced by `insert` or `insert_many`.
     ///
     /// # Example
     /// ```
     /// # fn main() {
-    /// use restor::{DynamicStorage, ErrorDesc};
-    /// let mut storage = DynamicStorage::new();
-    /// assert_eq!(storage.insert(0usize), Err((0usize, ErrorDesc::NoAllocatedUnit)));
-    /// storage.allocate_for::<usize>();
-    /// storage.insert(0usize).unwrap();
-    /// # }
-    /// ```
+    /// use restor::{ErrorDesc, RwLockStorage};
+    /// use std::panic::{catch_unwind, AssertUnwindSafe};
     ///
-    /// # Example 2: Appending
-    /// ```
-    /// # fn main() {
-    /// use restor::{DynamicStorage, ErrorDesc};
-    /// let mut storage = DynamicStorage::new();
+    /// let mut storage = RwLockStorage::new_poisoning();
     /// storage.allocate_for::<usize>();
-    /// storage.insert(0usize).unwrap();
-    /// storage.insert(1usize).unwrap();
-    /// storage.insert(2usize).unwrap();
-    /// storage.run_for::<usize, (), _>(|x| {
-    ///     assert_eq!(x.unwrap().len(), 3);
-    ///     None
-    /// });
+    /// storage.insert_many(vec![1usize, 2, 3]).unwrap();
+    /// let result = catch_unwind(AssertUnwindSafe(|| {
+    ///     storage.map_in_place::<usize, _>(|x| if x == 2 { panic!("Interrupted") } else { x * 10 })
+    /// }));
+    /// assert!(result.is_err());
+    /// assert_eq!(storage.ind::<usize>(0).err(), Some(ErrorDesc::Poisoned));
+    /// storage.clear_poison::<usize>().unwrap();
+    /// assert_eq!(*storage.ind::<usize>(0).unwrap(), 10);
     /// # }
     /// ```
     ///
-    /// ## Note
-    /// This returns a `Result<(), (T, ErrorDesc)>` for ease of use, with calling `.unwrap()`.
+    #[cfg(feature = "poison")]
+    pub fn new_poisoning() -> Self {
+        let mut storage = Self::new();
+        storage.poisoned = Some(TypeMap::new());
+        storage
+    }
+
     ///
-    pub fn insert<T: 'static + Send>(&self, data: T) -> Result<(), (T, ErrorDesc)> {
-        let entry = self.data.get(&TypeId::of::<T>());
-        match entry {
-            Some(x) => match x.insert_any(Box::new(data)) {
-                Some((x, e)) => Err((*x.downcast().unwrap(), e)),
-                None => Ok(()),
-            },
-            None => Err((data, ErrorDesc::NoAllocatedUnit)),
+    /// Clears the poison of the unit for `T`, see `new_poisoning`. This is a
+    /// no-op for a storage which doesn't poison.
+    ///
+    #[cfg(feature = "poison")]
+    pub fn clear_poison<T: 'static + Send>(&self) -> DynamicResult<()> {
+        self.unit_lookup::<T>()?;
+        if let Some(flag) = self.poison_flag(TypeId::of::<T>()) {
+            flag.store(false, Ordering::Release);
         }
+        Ok(())
     }
 
     ///
-    /// Sibling to `insert`, this inserts many values at the same time and returns them
-    /// in the case of an error. This will append to a pre-exisiting dataset if there
-    /// is one present, or a single value, if possible.
+    /// Internal function. Returns the poison flag of the unit for `id`, if
+    /// this storage poisons.
     ///
-    /// # Example
-    /// ```
-    /// # fn main() {
-    /// use restor::{DynamicStorage, ErrorDesc};
-    /// let mut storage = DynamicStorage::new();
-    /// assert_eq!(storage.insert_many(vec![0usize, 1, 2, 3]), Err((vec![0usize, 1, 2, 3], ErrorDesc::NoAllocatedUnit)));
-    /// storage.allocate_for::<usize>();
-    /// storage.insert_many(vec![0usize, 1, 2, 3]).unwrap();
-    /// storage.insert_many(vec![4usize, 5, 6, 7]).unwrap();
-    /// storage.run_for::<usize, (), _>(|x| {
-    ///     assert_eq!(x.unwrap(), &[0usize, 1, 2, 3, 4, 5, 6, 7]);
-    ///     None
-    /// });
-    /// # }
-    /// ```
+    #[cfg(feature = "poison")]
+    #[inline]
+    fn poison_flag(&self, id: TypeId) -> Option<&AtomicBool> {
+        self.poisoned.as_ref().and_then(|flags| flags.get(&id))
+    }
+
+    #[cfg(not(feature = "poison"))]
+    #[inline(always)]
+    fn poison_flag(&self, _id: TypeId) -> Option<&AtomicBool> {
+        None
+    }
+
     ///
-    /// ## Note
-    /// This returns the `Vec` passed to it in the case of an erroneous attempt
-    /// at inserting into the storage.
+    /// Internal function. Arms `guard` to poison the unit for `T` if it is
+    /// dropped while panicking, if this storage poisons. `guard` must borrow
+    /// from `self`, so that it doesn't outlive the flag.
     ///
-    pub fn insert_many<T: 'static + Send>(&self, data: Vec<T>) -> Result<(), (Vec<T>, ErrorDesc)> {
-        if let Some(unit) = self.data.get(&TypeId::of::<T>()) {
-            if let Some((ret, e)) = unit.insert_any(Box::new(data)) {
-                Err((*ret.downcast().unwrap(), e))
-            } else {
-                Ok(())
-            }
-        } else {
-            Err((data, ErrorDesc::NoAllocatedUnit))
+    #[inline]
+    fn poisoning<T: 'static, G: Poisoning>(&self, mut guard: G) -> G {
+        if let Some(flag) = self.poison_flag(TypeId::of::<T>()) {
+            // The flags are only removed through `&mut self`, which can't be
+            // taken while `guard` borrows from `self`
+            unsafe { guard.poison_on_panic(flag) };
         }
+        guard
     }
 
     ///
-    /// Internal function. Returns a reference to the `Unit` for `T`
+    /// Internal function. Returns an `Err` if the unit for `id` is poisoned.
     ///
     #[inline]
-    fn unit_get<T: 'static + Send>(&self) -> DynamicResult<&U> {
-        self.data
-            .get(&TypeId::of::<T>())
-            .map(|x| &**x)
-            .ok_or(ErrorDesc::NoAllocatedUnit)
+    fn check_poison(&self, id: TypeId) -> DynamicResult<()> {
+        match self.poison_flag(id) {
+            Some(flag) if flag.load(Ordering::Acquire) => Err(ErrorDesc::Poisoned),
+            _ => Ok(()),
+        }
     }
 
     ///
-    /// Returns a mutable lock on a value of type `T`.
-    /// This will return:
-    ///
-    /// - A [`RefMut<'a, T>`] in the case of `DynamicStorage`
-    /// - A [`MappedMutexGuard<'a, T>`] in the case of `MutexStorage`
-    /// - A [`MappedRwLockWriteGuard<'a, T>`] in the case of `RwLockStorage`
-    ///
-    /// In the case that there is no unit, or that the data is incompatibly borrowed,
-    /// an `Err` value will be returned, containing a description of the error in the
-    /// enum variant name. This will also return an `Err` variant if more than one `T`
-    /// are stored in the storage.
+    /// Allocates a unit for every type in the set `Ts`, which is a tuple of
+    /// types or a `Sets` of other sets. The types need to satisfy the bounds
+    /// that this storage has on its units, so a type that isn't `Sync` can't
+    /// be allocated in a `RwLockStorage`.
     ///
     /// # Example
     /// ```
+    /// # use restor::{DynamicStorage, Sets};
     /// # fn main() {
-    /// use restor::DynamicStorage;
-    ///
+    /// type CoreSet = (usize, String);
     /// let mut storage = DynamicStorage::new();
-    /// storage.allocate_for::<String>();
-    /// storage.insert(String::new());
-    ///
-    /// let mut lock = storage.get_mut::<String>().unwrap();
-    /// lock.push_str("Abc");
-    /// drop(lock);
-    ///
-    /// let lock = storage.get::<String>().unwrap();
-    /// assert_eq!(&*lock, "Abc");
-    ///
+    /// storage.allocate_all::<Sets<(CoreSet, (isize,))>>();
+    /// storage.insert(0usize).unwrap();
+    /// storage.insert(String::new()).unwrap();
+    /// storage.insert(0isize).unwrap();
     /// # }
     /// ```
     ///
-    /// [`MappedMutexGuard<'a, T>`]: https://docs.rs/parking_lot/0.8.0/parking_lot/type.MappedMutexGuard.html
-    /// [`MappedRwLockWriteGuard<'a, T>`]: https://docs.rs/parking_lot/0.8.0/parking_lot/type.MappedRwLockWriteGuard.html
-    /// [`RefMut<'a, T>`]: https://doc.rust-lang.org/std/cell/struct.RefMut.html
+    /// ```compile_fail
+    /// # use restor::RwLockStorage;
+    /// # use std::rc::Rc;
+    /// # fn main() {
+    /// let mut storage = RwLockStorage::new();
+    /// storage.allocate_all::<(usize, Rc<usize>)>();
+    /// # }
+    /// ```
     ///
-    #[inline]
-    pub fn get_mut<'a, T: 'static + Send>(
-        &'a self,
-    ) -> DynamicResult<<MutBorrowed<'a, U> as MapMut<(dyn Any + Send), T>>::Output>
-    where
-        MutBorrowed<'a, U>: MapMut<(dyn Any + Send), T, Func = fn(&mut (dyn Any + Send)) -> &mut T>,
-    {
-        Ok(self
-            .unit_get::<T>()?
-            .one_mut()?
-            .map(|x| x.downcast_mut().unwrap()))
+    pub fn allocate_all<Ts: AllocateSet<Self>>(&mut self) {
+        Ts::allocate(self);
     }
 
     ///
-    /// Returns a mutable lock to the element at a given index.
-    /// This will return the only element available if there is
-    /// only one element in the storage, and the index specified
-    /// is 0.
-    ///
-    /// This will return the same lock types as `BlackBox::get_mut()`
+    /// Checks that there is a unit for every type in the set `Ts`, which is
+    /// given like to `allocate_all`, so that a storage missing a unit is caught
+    /// when it is set up rather than by whichever access first needs the unit.
+    /// This returns the names of the types which have no unit otherwise.
     ///
     /// # Example
     /// ```
     /// # fn main() {
-    /// use restor::DynamicStorage;
-    /// let mut storage = DynamicStorage::new();
-    /// storage.allocate_for::<String>();
-    /// storage.insert(String::new());
-    /// {
-    ///     let mut lock = storage.ind_mut::<String>(0).unwrap();
-    ///     lock.push_str("abc");
-    /// }
-    /// storage.insert(String::new());
-    /// storage.ind_mut::<String>(0).unwrap().push_str("def");
-    /// assert_eq!(
-    ///		&storage.run_for::<String, String, _>(|x| {
-    /// 		let x = x.unwrap();
-    ///         Some(x[0].clone() + &x[1])
-    /// 	}).unwrap(),
-    ///		"abcdef"
-    /// );
+    /// use restor::{make_storage, DynamicStorage};
+    /// let storage = make_storage!(DynamicStorage: usize, String);
+    /// assert!(storage.assert_allocated::<(usize, String)>().is_ok());
+    ///
+    /// let missing = storage.assert_allocated::<(usize, u8, bool)>().unwrap_err();
+    /// assert_eq!(missing.names(), &["u8", "bool"]);
+    /// assert_eq!(missing.to_string(), "No unit is allocated for `u8`, `bool`");
     /// # }
     /// ```
     ///
-    #[inline]
-    pub fn ind_mut<'a, T: 'static + Send>(
-        &'a self,
-        ind: usize,
-    ) -> DynamicResult<<MutBorrowed<'a, U> as MapMut<(dyn Any + Send), T>>::Output>
-    where
-        MutBorrowed<'a, U>: MapMut<(dyn Any + Send), T, Func = fn(&mut (dyn Any + Send)) -> &mut T>,
-    {
-        Ok(self
-            .unit_get::<T>()?
-            .ind_mut(ind)?
-            .map(|x| x.downcast_mut().unwrap()))
+    pub fn assert_allocated<Ts: AllocateSet<Self>>(&self) -> Result<(), MissingTypes> {
+        let mut names = Vec::new();
+        Ts::missing(self, &mut names);
+        if names.is_empty() {
+            Ok(())
+        } else {
+            Err(MissingTypes::new(names))
+        }
     }
 
     ///
-    /// Retrieves an owned `T` from the storage.
+    /// Like `assert_allocated`, but panics with the names of the types which
+    /// have no unit.
     ///
-    /// Returns an `Err`or in the case that it is impossible to retrieve one.
+    /// # Panics
+    /// In the case that any type in `Ts` has no unit.
+    ///
+    pub fn expect_allocated<Ts: AllocateSet<Self>>(&self) {
+        if let Err(missing) = self.assert_allocated::<Ts>() {
+            panic!("{}", missing);
+        }
+    }
+
+    ///
+    /// Returns the `TypeId` of every unit which isn't for one of `expected`,
+    /// in the order the units were allocated in. This finds the units which
+    /// were allocated but aren't used, the other way around from
+    /// `assert_allocated`.
     ///
     /// # Example
     /// ```
     /// # fn main() {
-    ///	use restor::DynamicStorage;
-    /// let mut storage = DynamicStorage::new();
-    /// storage.allocate_for::<String>();
-    /// storage.insert(String::new()).unwrap();
-    /// let my_str: String = storage.extract::<String>().unwrap();
-    /// assert_eq!(my_str, String::new());
+    /// use restor::{make_storage, DynamicStorage};
+    /// use std::any::TypeId;
+    /// let storage = make_storage!(DynamicStorage: usize, String, u8);
+    /// let extra = storage.extra_types(&[TypeId::of::<usize>()]);
+    /// assert_eq!(extra, vec![TypeId::of::<String>(), TypeId::of::<u8>()]);
     /// # }
     /// ```
     ///
-    #[inline]
-    pub fn extract<T: 'static + Send>(&self) -> DynamicResult<T> {
-        Ok(*self.unit_get::<T>()?.extract()?.downcast().unwrap())
+    pub fn extra_types(&self, expected: &[TypeId]) -> Vec<TypeId> {
+        self.data
+            .keys()
+            .filter(|id| !expected.contains(id))
+            .copied()
+            .collect()
     }
 
     ///
-    /// Extracts multiple values and returns them in the form of
-    /// a `Box<[T]>` which can be turned into a `Vec<T>`.
+    /// Internal function. Allocates a unit for `T` using `make` if there isn't
+    /// one already, registering it under the name of `T`. The name is skipped
+    /// in the case that another type already uses it.
     ///
-    /// # Example
+    fn allocate_unit<T: 'static + Send, F: FnOnce() -> Box<U>>(&mut self, make: F) {
+        self.data_mut().get_or_insert_with(TypeId::of::<T>(), make);
+        self.reshapes
+            .get_or_insert_with(TypeId::of::<T>(), AtomicU64::default);
+        self.spares
+            .get_or_insert_with(TypeId::of::<T>(), || Box::new(Spare::<T>::new()));
+        #[cfg(feature = "poison")]
+        if let Some(flags) = &mut self.poisoned {
+            flags.get_or_insert_with(TypeId::of::<T>(), AtomicBool::default);
+        }
+        if self.register_name::<T>(std::any::type_name::<T>()).is_err() {
+            // Another type was given this name through `allocate_named_for`
+            #[cfg(feature = "tracing")]
+            trace::name_skipped::<T>();
+        }
+    }
+
+    ///
+    /// Allocates `unit` as the unit for `T`, unless there already is one. This
+    /// is how units implemented outside of this crate are added to a storage,
+    /// see the `backend` module. This returns `ErrorDesc::NoMatchingType` in the
+    /// case that `unit` doesn't store `T`s, as given by `Unit::id`.
     ///
+    /// # Example
     /// ```
     /// # fn main() {
-    /// use restor::{DynamicStorage, make_storage};
-    /// let storage = make_storage!(DynamicStorage: usize);
-    /// storage.insert_many(vec![1usize, 2, 3, 4]).unwrap();
-    /// let v: Vec<usize> = storage.extract_many::<usize>().unwrap().into();
-    /// assert_eq!(v, vec![1usize, 2, 3, 4]);
+    /// use restor::{RwLockStorage, RwLockUnit, StorageUnit};
+    /// let mut storage = RwLockStorage::new();
+    /// let unit = RwLockUnit::new(StorageUnit::<usize>::new());
+    /// storage.allocate_unit_for::<usize>(Box::new(unit)).unwrap();
+    /// storage.insert(3usize).unwrap();
+    /// assert_eq!(*storage.get::<usize>().unwrap(), 3);
     /// # }
     /// ```
     ///
-    #[inline]
-    pub fn extract_many<T: 'static + Send>(&self) -> DynamicResult<Box<[T]>> {
-        Ok(*self.unit_get::<T>()?.extract_many()?.downcast().unwrap())
+    pub fn allocate_unit_for<T: 'static + Send>(&mut self, unit: Box<U>) -> DynamicResult<()> {
+        if unit.id() != TypeId::of::<T>() {
+            return Err(ErrorDesc::NoMatchingType);
+        }
+        self.allocate_unit::<T, _>(|| unit);
+        Ok(())
     }
 
     ///
-    /// Gets an immutable lock on the single value variant of storage.
+    /// Internal function. Registers `name` as referring to the unit for `T`.
+    ///
+    fn register_name<T: 'static + Send>(&mut self, name: &str) -> DynamicResult<()> {
+        match self.names.get(name) {
+            Some(&id) if id != TypeId::of::<T>() => Err(ErrorDesc::NameCollision),
+            Some(_) => Ok(()),
+            None => {
+                self.names.insert(name.to_string(), TypeId::of::<T>());
+                Ok(())
+            }
+        }
+    }
+
+    ///
+    /// Internal function. The error for there being no unit for `id`, which
+    /// distinguishes types allocated through `allocate_read_mostly_for`.
+    ///
+    #[cold]
+    fn missing_unit(&self, id: TypeId) -> ErrorDesc {
+        if self.read_mostly.contains_key(&id) {
+            ErrorDesc::UnsupportedOperation
+        } else {
+            ErrorDesc::NoAllocatedUnit
+        }
+    }
+
+    ///
+    /// Internal function. Returns the `ReadMostly` allocated for `T`.
+    ///
+    #[inline]
+    fn read_mostly_get<T: 'static + Send + Sync>(&self) -> DynamicResult<&ReadMostly<T>> {
+        match self.read_mostly.get(&TypeId::of::<T>()) {
+            Some((_, x)) => Ok(x.downcast_ref().unwrap()),
+            None if self.has_unit::<T>() => Err(ErrorDesc::UnsupportedOperation),
+            None => Err(ErrorDesc::NoAllocatedUnit),
+        }
+    }
+
+    ///
+    /// Internal function. Returns the unit registered under `name`, whether
+    /// or not it is poisoned.
+    ///
+    fn unit_get_named(&self, name: &str) -> DynamicResult<&U> {
+        let id = self.names.get(name).ok_or(ErrorDesc::NoAllocatedUnit)?;
+        let unit = self.data.get(id).ok_or_else(|| self.missing_unit(*id))?;
+        Ok(&**unit)
+    }
+
+    ///
+    /// Checks if there is a unit registered under `name`. Units are registered
+    /// under the name given by `std::any::type_name` when they are allocated,
+    /// and can be given other names with `allocate_named_for`.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::DynamicStorage;
+    /// let mut storage = DynamicStorage::new();
+    /// storage.allocate_for::<usize>();
+    /// storage.allocate_named_for::<String>("name").unwrap();
+    /// assert!(storage.has_unit_named("usize"));
+    /// assert!(storage.has_unit_named("name"));
+    /// assert!(!storage.has_unit_named("isize"));
+    /// # }
+    /// ```
+    ///
+    pub fn has_unit_named(&self, name: &str) -> bool {
+        self.unit_get_named(name).is_ok()
+    }
+
+    ///
+    /// Inserts a type erased value into the unit registered under `name`. The
+    /// value is returned alongside an `ErrorDesc::NoMatchingType` in the case
+    /// that it is neither the unit's type nor a `Vec` of it.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::DynamicStorage;
+    /// let mut storage = DynamicStorage::new();
+    /// storage.allocate_named_for::<String>("name").unwrap();
+    /// storage.insert_boxed_named("name", Box::new(String::from("abc"))).unwrap();
+    /// assert_eq!(&*storage.get::<String>().unwrap(), "abc");
+    /// # }
+    /// ```
+    ///
+    pub fn insert_boxed_named(
+        &self,
+        name: &str,
+        data: Box<dyn Any + Send>,
+    ) -> Result<(), (Box<dyn Any + Send>, ErrorDesc)> {
+        match self
+            .check_finalizing()
+            .and_then(|_| self.unit_get_named(name))
+            .and_then(|unit| self.check_poison(unit.id()).map(|_| unit))
+        {
+            Ok(unit) => match unit.insert_any(data) {
+                Some(err) => Err(err),
+                None => Ok(()),
+            },
+            Err(e) => Err((data, e)),
+        }
+    }
+
+    ///
+    /// Extracts a type erased value from the unit registered under `name`,
+    /// following the same rules as `BlackBox::extract`. The value is `Send` for
+    /// every storage, so it may be handed to another thread before it is
+    /// downcast.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::{DynamicStorage, make_storage};
+    /// let storage = make_storage!(DynamicStorage: usize);
+    /// storage.insert(10usize).unwrap();
+    /// let value = storage.extract_any_named("usize").unwrap();
+    /// assert_eq!(*value.downcast::<usize>().unwrap(), 10);
+    /// # }
+    /// ```
+    ///
+    pub fn extract_any_named(&self, name: &str) -> DynamicResult<Box<dyn Any + Send>> {
+        self.check_finalizing()?;
+        let unit = self.unit_get_named(name)?;
+        self.check_poison(unit.id())?;
+        let mut value = unit.extract()?;
+        self.reshaped(unit.id());
+        self.finalize_any(unit.id(), &mut *value);
+        Ok(value)
+    }
+
+    ///
+    /// Internal function. Returns the map of units mutably, invalidating
+    /// the lookup cache, as the units may be moved or removed
+    ///
+    #[inline]
+    fn data_mut(&mut self) -> &mut TypeMap<Box<U>> {
+        *self.cache.get_mut() = ptr::null_mut();
+        &mut self.data
+    }
+
+    ///
+    /// Removes every unit, including those allocated with
+    /// `allocate_read_mostly_for`, for which `f` returns `false`, along with
+    /// the values they hold. Returns the `TypeId`s of the removed units in the
+    /// order they were allocated in, with those allocated with
+    /// `allocate_read_mostly_for` last.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::{DynamicStorage, ErrorDesc, make_storage};
+    /// use std::any::TypeId;
+    /// let mut storage = make_storage!(DynamicStorage: usize, String, isize);
+    /// storage.insert(1usize).unwrap();
+    /// let allowed = [TypeId::of::<usize>(), TypeId::of::<isize>()];
+    /// let removed = storage.retain_types(|id| allowed.contains(&id));
+    /// assert_eq!(removed, vec![TypeId::of::<String>()]);
+    /// assert_eq!(*storage.get::<usize>().unwrap(), 1);
+    /// assert_eq!(storage.get::<String>().err(), Some(ErrorDesc::NoAllocatedUnit));
+    /// # }
+    /// ```
+    ///
+    pub fn retain_types<F: FnMut(TypeId) -> bool>(&mut self, mut f: F) -> Vec<TypeId> {
+        let removed = self
+            .data
+            .keys()
+            .chain(self.read_mostly.keys())
+            .copied()
+            .filter(|&id| !f(id))
+            .collect::<Vec<_>>();
+        self.remove_types(&removed);
+        removed
+    }
+
+    ///
+    /// Like `retain_types`, but passes `f` the name of each type, as given by
+    /// `std::any::type_name`, instead of its `TypeId`.
+    ///
+    pub fn retain_types_named<F: FnMut(&'static str) -> bool>(&mut self, mut f: F) -> Vec<TypeId> {
+        let removed = self
+            .data
+            .iter()
+            .map(|(&id, unit)| (id, unit.type_name()))
+            .chain(self.read_mostly.iter().map(|(&id, &(name, _))| (id, name)))
+            .filter(|&(_, name)| !f(name))
+            .map(|(id, _)| id)
+            .collect::<Vec<_>>();
+        self.remove_types(&removed);
+        removed
+    }
+
+    ///
+    /// Removes the units for each of `ids`, along with the values they hold,
+    /// the names registered for them, their subscribers and their finalizers,
+    /// which are run on the values first, see `on_remove`. Ids without a unit
+    /// are ignored.
+    ///
+    pub fn remove_types(&mut self, ids: &[TypeId]) {
+        for id in ids {
+            if let Some(drain) = self.finalizers.get(id).map(|finalizer| finalizer.drain) {
+                drain(self);
+                self.finalizers.remove(id);
+            }
+        }
+        let data = self.data_mut();
+        for id in ids {
+            data.remove(id);
+        }
+        for id in ids {
+            self.reshaped(*id);
+            self.read_mostly.remove(id);
+            self.spares.remove(id);
+            #[cfg(feature = "pod")]
+            self.pods.remove(id);
+            #[cfg(feature = "poison")]
+            if let Some(poisoned) = &mut self.poisoned {
+                poisoned.remove(id);
+            }
+            #[cfg(feature = "events")]
+            self.subscribers.remove(id);
+        }
+        self.names.retain(|_, id| !ids.contains(id));
+    }
+
+    ///
+    /// Checks if there is an allocated unit for
+    /// the type parameter in the internal hashmap.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::DynamicStorage;
+    /// let mut storage = DynamicStorage::new();
+    /// assert!(!storage.has_unit::<usize>());
+    /// storage.allocate_for::<usize>();
+    /// assert!(storage.has_unit::<usize>());
+    /// # }
+    /// ```
+    #[inline]
+    pub fn has_unit<T: 'static + Send>(&self) -> bool {
+        self.data.contains_key(&TypeId::of::<T>())
+    }
+
+    ///
+    /// Checks that every unit is stored under the `TypeId` of the type it
+    /// stores, returning those which aren't. Units are only ever stored under
+    /// the wrong `TypeId` through `insert_unit_under`, and the accessors return
+    /// `ErrorDesc::NoMatchingType` for them rather than reading the unit, after
+    /// failing a debug assertion.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::{make_storage, DynamicStorage};
+    /// let storage = make_storage!(DynamicStorage: usize, String);
+    /// assert_eq!(storage.validate(), Ok(()));
+    /// # }
+    /// ```
+    ///
+    pub fn validate(&self) -> Result<(), Vec<IntegrityError>> {
+        let errors = self
+            .data
+            .iter()
+            .filter(|(&key, unit)| unit.id() != key)
+            .map(|(&key, unit)| IntegrityError {
+                key,
+                found: unit.id(),
+                type_name: unit.type_name(),
+            })
+            .collect::<Vec<_>>();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    ///
+    /// Creates a copy of the storage, whose values start out the same as those
+    /// of this storage but don't change along with them afterwards. Units are
+    /// forked without copying their values, which are only cloned by whichever
+    /// storage mutates them first, so forking is cheap whatever the storage
+    /// holds. Names, poisoned units and the policy are carried over, but
+    /// subscribers and outstanding borrows are not.
+    ///
+    /// Only units allocated with `DynamicStorage::allocate_clonable_for` can be
+    /// forked, and this returns `ErrorDesc::UnsupportedOperation` in the case
+    /// that there is any other unit. This also returns an `Err` in the case
+    /// that a unit is borrowed mutably.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::DynamicStorage;
+    /// let mut world = DynamicStorage::new();
+    /// world.allocate_clonable_for::<usize>();
+    /// world.insert(10usize).unwrap();
+    /// let lookahead = world.fork().unwrap();
+    /// *lookahead.get_mut::<usize>().unwrap() -= 3;
+    /// *world.get_mut::<usize>().unwrap() += 1;
+    /// assert_eq!(*lookahead.get::<usize>().unwrap(), 7);
+    /// assert_eq!(*world.get::<usize>().unwrap(), 11);
+    /// # }
+    /// ```
+    ///
+    pub fn fork(&self) -> DynamicResult<Self>
+    where
+        U: 'static,
+    {
+        if !self.read_mostly.is_empty() {
+            return Err(ErrorDesc::UnsupportedOperation);
+        }
+        let mut data = TypeMap::with_capacity(self.data.len());
+        for (&id, unit) in self.data.iter() {
+            let forked = unit
+                .fork()?
+                .downcast::<Box<U>>()
+                .map_err(|_| ErrorDesc::UnsupportedOperation)?;
+            data.insert(id, *forked);
+        }
+        #[cfg(feature = "poison")]
+        let poisoned = self.poisoned.as_ref().map(|flags| {
+            let mut forked = TypeMap::with_capacity(flags.len());
+            for (&id, flag) in flags.iter() {
+                forked.insert(id, AtomicBool::new(flag.load(Ordering::Acquire)));
+            }
+            forked
+        });
+        let mut forked = Self::new();
+        forked.data = data;
+        forked.names = self.names.clone();
+        #[cfg(feature = "poison")]
+        {
+            forked.poisoned = poisoned;
+        }
+        forked.track_holders = self.track_holders;
+        forked.policy = self.policy;
+        for (&id, finalizer) in self.finalizers.iter() {
+            forked.finalizers.insert(id, finalizer.clone());
+        }
+        for (&id, reshapes) in self.reshapes.iter() {
+            forked
+                .reshapes
+                .insert(id, AtomicU64::new(reshapes.load(Ordering::Acquire)));
+        }
+        for (&id, spare) in self.spares.iter() {
+            forked.spares.insert(id, spare.empty());
+        }
+        #[cfg(feature = "pod")]
+        for (&id, unit) in self.pods.iter() {
+            forked.pods.insert(id, *unit);
+        }
+        #[cfg(feature = "instrument")]
+        {
+            forked.borrows = self.borrows.as_ref().map(|_| Arc::default());
+        }
+        Ok(forked)
+    }
+
+    ///
+    /// Stores `unit` under `key`, replacing any unit already there, without
+    /// checking that it stores the type `key` belongs to. This is only meant
+    /// for testing how a storage copes with a corrupted map, see `validate`.
+    ///
+    #[doc(hidden)]
+    pub fn insert_unit_under(&mut self, key: TypeId, unit: Box<U>) {
+        self.data_mut().insert(key, unit);
+    }
+
+    ///
+    /// The number of units allocated in the storage.
+    ///
+    #[inline]
+    pub fn unit_count(&self) -> usize {
+        self.data.len()
+    }
+
+    ///
+    /// Checks if there are no units allocated in the storage.
+    ///
+    #[inline]
+    pub fn is_unit_map_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    ///
+    /// Returns the state of the unit for `T`, which describes how many values
+    /// it holds and how they are accessed. This returns an `Err` instead of
+    /// blocking in the case that the unit is borrowed mutably.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::{DynamicStorage, UnitState, make_storage};
+    /// let storage = make_storage!(DynamicStorage: usize);
+    /// assert_eq!(storage.state::<usize>(), Ok(UnitState::Empty));
+    /// storage.insert(1usize).unwrap();
+    /// assert_eq!(storage.state::<usize>(), Ok(UnitState::Single));
+    /// storage.insert(2usize).unwrap();
+    /// storage.extract::<usize>().unwrap();
+    /// assert_eq!(storage.state::<usize>(), Ok(UnitState::Multiple(1)));
+    /// # }
+    /// ```
+    ///
+    pub fn state<T: 'static + Send>(&self) -> DynamicResult<UnitState> {
+        self.with_storage(|unit: &StorageUnit<T>| unit.state())
+    }
+
+    ///
+    /// Returns the number of values the unit for `T` can hold without
+    /// reallocating: `0` for no values, `1` for a single value, and the
+    /// capacity of the list otherwise. This returns an `Err` instead of
+    /// blocking in the case that the unit is borrowed mutably.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::{DynamicStorage, make_storage};
+    /// let storage = make_storage!(DynamicStorage: usize);
+    /// assert_eq!(storage.capacity::<usize>(), Ok(0));
+    /// let mut values = Vec::with_capacity(16);
+    /// values.push(1usize);
+    /// storage.insert_many(values).unwrap();
+    /// assert!(storage.capacity::<usize>().unwrap() >= 16);
+    /// # }
+    /// ```
+    ///
+    pub fn capacity<T: 'static + Send>(&self) -> DynamicResult<usize> {
+        self.with_storage(|unit: &StorageUnit<T>| spare::capacity(unit, self.spare::<T>()))
+    }
+
+    ///
+    /// Shrinks the capacity of the list of `T`s as much as possible, and
+    /// releases the list kept aside for them, see `allocate_for_with_vec`.
+    ///
+    pub fn shrink_to_fit<T: 'static + Send>(&self) -> DynamicResult<()> {
+        self.with_storage_mut(|unit: &mut StorageUnit<T>| {
+            BufferedUnit::new(unit, self.spare::<T>()).shrink_to_fit()
+        })
+    }
+
+    ///
+    /// Faults in every page of memory behind the list of `T`s while holding a
+    /// shared borrow on the unit, so that a large list which was paged out is
+    /// not faulted in during its first use. See `StorageUnit::prefault`.
+    ///
+    pub fn prefault<T: 'static + Send>(&self) -> DynamicResult<()> {
+        self.with_storage(|unit: &StorageUnit<T>| unit.prefault())
+    }
+
+    ///
+    /// Reserves room for at least `additional` more `T`s and faults in its
+    /// memory, so that inserting them later doesn't reallocate or fault. See
+    /// `StorageUnit::warm_capacity`.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::{DynamicStorage, make_storage};
+    /// let storage = make_storage!(DynamicStorage: u64);
+    /// storage.warm_capacity::<u64>(1 << 16).unwrap();
+    /// assert!(storage.capacity::<u64>().unwrap() >= 1 << 16);
+    /// storage.insert_iter(0..1u64 << 16).unwrap();
+    /// assert!(storage.capacity::<u64>().unwrap() >= 1 << 16);
+    /// # }
+    /// ```
+    ///
+    pub fn warm_capacity<T: 'static + Send>(&self, additional: usize) -> DynamicResult<()> {
+        self.with_storage_mut(|unit: &mut StorageUnit<T>| {
+            BufferedUnit::new(unit, self.spare::<T>()).warm_capacity(additional)
+        })
+    }
+
+    ///
+    /// Describes every unit in the storage, sorted by the name of its type.
+    /// Units which are borrowed exclusively are reported as locked instead of
+    /// being waited on.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::{DynamicStorage, make_storage};
+    /// let storage = make_storage!(DynamicStorage: usize, String, isize);
+    /// storage.insert_many(vec![1usize, 2, 3]).unwrap();
+    /// storage.insert(String::new()).unwrap();
+    /// let _lock = storage.get_mut::<String>().unwrap();
+    /// assert_eq!(
+    ///     storage.summary().to_string(),
+    ///     "alloc::string::String: locked, isize: Empty, usize: Multiple(3)"
+    /// );
+    /// # }
+    /// ```
+    ///
+    pub fn summary(&self) -> StorageSummary {
+        StorageSummary::new(
+            self.data
+                .values()
+                .map(|unit| SummaryEntry {
+                    name: unit.type_name(),
+                    state: unit.state(),
+                    borrowed: unit.is_borrowed(),
+                })
+                .collect(),
+        )
+    }
+
+    ///
+    /// Returns the figures of every unit in the storage, in the order the units
+    /// were allocated in. Units which are borrowed exclusively are reported
+    /// without the figures which need a borrow instead of being waited on.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::{DynamicStorage, UnitState, make_storage};
+    /// let storage = make_storage!(DynamicStorage: usize, String);
+    /// storage.insert_many(vec![1usize, 2, 3]).unwrap();
+    /// let _lock = storage.ind_mut::<usize>(0).unwrap();
+    /// let stats = storage.stats();
+    /// assert_eq!(stats[0].type_name, "usize");
+    /// assert_eq!((stats[0].len, stats[0].locked), (None, true));
+    /// assert_eq!(stats[1].state, Some(UnitState::Empty));
+    /// assert_eq!(stats[1].capacity, Some(0));
+    /// # }
+    /// ```
+    ///
+    pub fn stats(&self) -> Vec<UnitStats> {
+        let mut stats = Vec::with_capacity(self.data.len());
+        self.stats_into(&mut stats);
+        stats
+    }
+
+    ///
+    /// Like `stats`, but replaces the contents of `buf` with the figures,
+    /// so that they can be exported every frame without allocating.
+    ///
+    pub fn stats_into(&self, buf: &mut Vec<UnitStats>) {
+        buf.clear();
+        buf.extend(self.data.iter().map(|(&type_id, unit)| {
+            let state = unit.state();
+            UnitStats {
+                type_name: unit.type_name(),
+                type_id,
+                state,
+                len: state.map(UnitState::count),
+                capacity: self.unit_capacity(&type_id, unit),
+                approx_bytes: self.unit_bytes(&type_id, unit),
+                locked: unit.is_borrowed(),
+            }
+        }));
+    }
+
+    ///
+    /// Inserts a value into the storage and returns it in the case
+    /// that it's impossible to insert or it is already borrowed.
+    ///
+    /// This appends to a list of values in the case that there is
+    /// one or values of the type in the internal storage.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::{DynamicStorage, ErrorDesc};
+    /// let mut storage = DynamicStorage::new();
+    /// assert_eq!(storage.insert(0usize), Err((0usize, ErrorDesc::NoAllocatedUnit)));
+    /// storage.allocate_for::<usize>();
+    /// storage.insert(0usize).unwrap();
+    /// # }
+    /// ```
+    ///
+    /// # Example 2: Appending
+    /// ```
+    /// # fn main() {
+    /// use restor::{DynamicStorage, ErrorDesc};
+    /// let mut storage = DynamicStorage::new();
+    /// storage.allocate_for::<usize>();
+    /// storage.insert(0usize).unwrap();
+    /// storage.insert(1usize).unwrap();
+    /// storage.insert(2usize).unwrap();
+    /// storage.run_for::<usize, (), _>(|x| {
+    ///     assert_eq!(x.unwrap().len(), 3);
+    ///     None
+    /// });
+    /// # }
+    /// ```
+    ///
+    /// ## Note
+    /// This returns a `Result<(), (T, ErrorDesc)>` for ease of use, with calling `.unwrap()`.
+    ///
+    pub fn insert<T: 'static + Send>(&self, data: T) -> Result<(), (T, ErrorDesc)> {
+        traced!(
+            "insert",
+            T,
+            self.insert_with(data, |unit: &mut BufferedUnit<T>, data| {
+                unit.insert(data);
+                notify!(
+                    self,
+                    T,
+                    Inserted {
+                        index: unit.len() - 1
+                    }
+                );
+            }),
+            rejected
+        )
+    }
+
+    ///
+    /// Inserts `data` like `insert`, returning the index it can be found at
+    /// through `ind` right after being inserted. The index is taken while the
+    /// unit is still borrowed, so it is accurate even if other threads are
+    /// inserting at the same time.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::{DynamicStorage, make_storage};
+    /// let storage = make_storage!(DynamicStorage: String);
+    /// assert_eq!(storage.insert_indexed(String::from("a")), Ok(0));
+    /// assert_eq!(storage.insert_indexed(String::from("b")), Ok(1));
+    /// assert_eq!(&*storage.ind::<String>(1).unwrap(), "b");
+    /// # }
+    /// ```
+    ///
+    pub fn insert_indexed<T: 'static + Send>(&self, data: T) -> Result<usize, (T, ErrorDesc)> {
+        traced!(
+            "insert_indexed",
+            T,
+            self.insert_with(data, |unit: &mut BufferedUnit<T>, data| {
+                unit.insert(data);
+                let index = unit.len() - 1;
+                notify!(self, T, Inserted { index: index });
+                index
+            }),
+            rejected
+        )
+    }
+
+    ///
+    /// Sibling to `insert`, this inserts many values at the same time and returns them
+    /// in the case of an error. This will append to a pre-exisiting dataset if there
+    /// is one present, or a single value, if possible.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::{DynamicStorage, ErrorDesc};
+    /// let mut storage = DynamicStorage::new();
+    /// assert_eq!(storage.insert_many(vec![0usize, 1, 2, 3]), Err((vec![0usize, 1, 2, 3], ErrorDesc::NoAllocatedUnit)));
+    /// storage.allocate_for::<usize>();
+    /// storage.insert_many(vec![0usize, 1, 2, 3]).unwrap();
+    /// storage.insert_many(vec![4usize, 5, 6, 7]).unwrap();
+    /// storage.run_for::<usize, (), _>(|x| {
+    ///     assert_eq!(x.unwrap(), &[0usize, 1, 2, 3, 4, 5, 6, 7]);
+    ///     None
+    /// });
+    /// # }
+    /// ```
+    ///
+    /// ## Note
+    /// This returns the `Vec` passed to it in the case of an erroneous attempt
+    /// at inserting into the storage.
+    ///
+    pub fn insert_many<T: 'static + Send>(&self, data: Vec<T>) -> Result<(), (Vec<T>, ErrorDesc)> {
+        traced!(
+            "insert_many",
+            T,
+            self.insert_with(data, |unit: &mut BufferedUnit<T>, data| {
+                let start = unit.len();
+                unit.insert_many(data);
+                for index in start..unit.len() {
+                    notify!(self, T, Inserted { index: index });
+                }
+            }),
+            rejected
+        )
+    }
+
+    ///
+    /// Inserts `value` unless the unit already holds `cap` values, in which case
+    /// `policy` decides what happens, see `OverflowPolicy`. This is all done
+    /// while holding the unit, so that a unit used as a queue never holds more
+    /// than `cap` values no matter how many threads insert into it.
+    ///
+    /// Returns the value which was left out: the oldest one for
+    /// `OverflowPolicy::DropOldest`, and `value` for `OverflowPolicy::DropNewest`.
+    /// `OverflowPolicy::Reject` instead hands `value` back alongside
+    /// `UnitError::Full`. In the case that the unit was filled past `cap` by other
+    /// means, `DropOldest` removes as many values as needed, dropping all but the
+    /// oldest of them.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::{make_storage, DynamicStorage, ErrorDesc, OverflowPolicy, UnitError};
+    /// let storage = make_storage!(DynamicStorage: usize);
+    /// storage.insert_many(vec![1usize, 2]).unwrap();
+    /// assert_eq!(storage.insert_bounded(3usize, 3, OverflowPolicy::Reject), Ok(None));
+    /// assert_eq!(
+    ///     storage.insert_bounded(4usize, 3, OverflowPolicy::Reject),
+    ///     Err((4, ErrorDesc::Unit(UnitError::Full)))
+    /// );
+    /// assert_eq!(storage.insert_bounded(5usize, 3, OverflowPolicy::DropNewest), Ok(Some(5)));
+    /// assert_eq!(storage.insert_bounded(6usize, 3, OverflowPolicy::DropOldest), Ok(Some(1)));
+    /// assert_eq!(storage.extract_many::<usize>().map(Vec::from), Ok(vec![2, 3, 6]));
+    /// # }
+    /// ```
+    ///
+    pub fn insert_bounded<T: 'static + Send>(
+        &self,
+        value: T,
+        cap: usize,
+        policy: OverflowPolicy,
+    ) -> Result<Option<T>, (T, ErrorDesc)> {
+        traced!(
+            "insert_bounded",
+            T,
+            self.insert_with(value, |unit: &mut BufferedUnit<T>, value| {
+                if unit.len() < cap {
+                    unit.insert(value);
+                    notify!(
+                        self,
+                        T,
+                        Inserted {
+                            index: unit.len() - 1
+                        }
+                    );
+                    return Ok((None, Vec::new()));
+                }
+                match policy {
+                    OverflowPolicy::Reject => Err((value, ErrorDesc::Unit(UnitError::Full))),
+                    OverflowPolicy::DropNewest => Ok((Some(value), Vec::new())),
+                    // There is nothing older than `value` to make room for it
+                    OverflowPolicy::DropOldest if cap == 0 => Ok((Some(value), Vec::new())),
+                    OverflowPolicy::DropOldest => {
+                        let excess = unit.len() + 1 - cap;
+                        let displaced = self.extract_front(unit, excess);
+                        unit.insert(value);
+                        notify!(
+                            self,
+                            T,
+                            Inserted {
+                                index: unit.len() - 1
+                            }
+                        );
+                        Ok((None, displaced))
+                    }
+                }
+            })
+            .and_then(|x| x)
+            .map(|(dropped, mut displaced)| {
+                self.finalize(&mut displaced);
+                dropped.or_else(|| displaced.into_iter().next())
+            }),
+            rejected
+        )
+    }
+
+    ///
+    /// Inserts `values` like `insert_bounded`, all while holding the unit once.
+    /// Values which don't fit are handled by `policy` as a whole:
+    ///
+    /// - `OverflowPolicy::Reject` inserts none of them unless all of them fit.
+    /// - `OverflowPolicy::DropOldest` inserts all of them, then removes the
+    ///   oldest values until there are `cap` left. Values of `values` which
+    ///   would be removed right away are not inserted at all.
+    /// - `OverflowPolicy::DropNewest` inserts as many as fit, in order.
+    ///
+    /// Returns the values which were left out, oldest first.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::{make_storage, OverflowPolicy, RwLockStorage};
+    /// let storage = make_storage!(RwLockStorage: usize);
+    /// storage.insert(1usize).unwrap();
+    /// assert_eq!(
+    ///     storage.insert_many_bounded(vec![2usize, 3, 4], 3, OverflowPolicy::DropNewest),
+    ///     Ok(vec![4])
+    /// );
+    /// assert_eq!(
+    ///     storage.insert_many_bounded(vec![5usize, 6], 3, OverflowPolicy::DropOldest),
+    ///     Ok(vec![1, 2])
+    /// );
+    /// assert_eq!(storage.extract_many::<usize>().map(Vec::from), Ok(vec![3, 5, 6]));
+    /// # }
+    /// ```
+    ///
+    pub fn insert_many_bounded<T: 'static + Send>(
+        &self,
+        values: Vec<T>,
+        cap: usize,
+        policy: OverflowPolicy,
+    ) -> Result<Vec<T>, (Vec<T>, ErrorDesc)> {
+        traced!(
+            "insert_many_bounded",
+            T,
+            self.insert_with(values, |unit: &mut BufferedUnit<T>, mut values| {
+                let room = cap.saturating_sub(unit.len());
+                let mut existing = 0;
+                let left_out = if values.len() <= room {
+                    Vec::new()
+                } else {
+                    match policy {
+                        OverflowPolicy::Reject => {
+                            return Err((values, ErrorDesc::Unit(UnitError::Full)));
+                        }
+                        OverflowPolicy::DropNewest => values.split_off(room),
+                        OverflowPolicy::DropOldest => {
+                            let excess = unit.len() + values.len() - cap;
+                            existing = excess.min(unit.len());
+                            let mut left_out = self.extract_front(unit, existing);
+                            left_out.extend(values.drain(..excess - existing));
+                            left_out
+                        }
+                    }
+                };
+                let start = unit.len();
+                unit.insert_many(values);
+                for index in start..unit.len() {
+                    notify!(self, T, Inserted { index: index });
+                }
+                Ok((left_out, existing))
+            })
+            .and_then(|x| x)
+            .map(|(mut left_out, existing)| {
+                self.finalize(&mut left_out[..existing]);
+                left_out
+            }),
+            rejected
+        )
+    }
+
+    ///
+    /// Internal function. Removes the first `count` values of `unit`, which is
+    /// the unit for `T`, in order.
+    ///
+    fn extract_front<T: 'static + Send>(&self, unit: &mut BufferedUnit<T>, count: usize) -> Vec<T> {
+        (0..count)
+            .map(|_| {
+                let value = unit.extract_ind(0).unwrap();
+                notify!(self, T, Extracted { index: 0 });
+                value
+            })
+            .collect()
+    }
+
+    ///
+    /// Inserts every value of `iter`, like `insert_many`, but without collecting
+    /// them into a `Vec` first unless the unit is unavailable. In that case the
+    /// values are collected and handed back alongside the error.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::{DynamicStorage, ErrorDesc};
+    /// let mut storage = DynamicStorage::new();
+    /// assert_eq!(
+    ///     storage.insert_iter((0..3).map(|x: usize| x * 2)),
+    ///     Err((vec![0, 2, 4], ErrorDesc::NoAllocatedUnit))
+    /// );
+    /// storage.allocate_for::<usize>();
+    /// storage.insert_iter([1usize, 2]).unwrap();
+    /// storage.insert_iter(3..5usize).unwrap();
+    /// assert_eq!(storage.ind_multi::<usize>(&[0, 1, 2, 3]), Ok(vec![1, 2, 3, 4]));
+    /// # }
+    /// ```
+    ///
+    pub fn insert_iter<T: 'static + Send, I: IntoIterator<Item = T>>(
+        &self,
+        iter: I,
+    ) -> Result<(), (Vec<T>, ErrorDesc)> {
+        traced!(
+            "insert_iter",
+            T,
+            self.insert_with(iter, |unit: &mut BufferedUnit<T>, iter| {
+                let start = unit.len();
+                unit.extend(iter);
+                for index in start..unit.len() {
+                    notify!(self, T, Inserted { index: index });
+                }
+            })
+            .map_err(|(iter, e)| (iter.into_iter().collect(), e)),
+            rejected
+        )
+    }
+
+    ///
+    /// Internal function. Runs `f` on the `StorageUnit` for `T` and `data` while
+    /// holding a mutable borrow on the unit, handing `data` back in the case that
+    /// the unit couldn't be borrowed.
+    ///
+    #[inline]
+    fn insert_with<T: 'static + Send, V, R, F: FnOnce(&mut BufferedUnit<T>, V) -> R>(
+        &self,
+        data: V,
+        f: F,
+    ) -> Result<R, (V, ErrorDesc)> {
+        match self.borrow_for_insert::<T>() {
+            Ok(mut storage) => {
+                let unit = storage.downcast_mut().unwrap();
+                Ok(f(&mut BufferedUnit::new(unit, self.spare::<T>()), data))
+            }
+            Err(e) => Err((data, e)),
+        }
+    }
+
+    ///
+    /// Internal function. Mutably borrows the unit for `T` to insert into it.
+    /// A poisoned unit is emptied and unpoisoned, so that the inserted values
+    /// replace its contents.
+    ///
+    #[inline]
+    fn borrow_for_insert<T: 'static + Send>(&self) -> DynamicResult<MutBorrowed<'_, U>> {
+        let mut storage = self.lock_for_insert::<T>()?;
+        self.clear_poisoned::<T>(&mut storage);
+        Ok(storage)
+    }
+
+    ///
+    /// Internal function. Mutably borrows the unit for `T` to insert into it,
+    /// without changing anything yet, poisoned or not.
+    ///
+    #[inline]
+    fn lock_for_insert<T: 'static + Send>(&self) -> DynamicResult<MutBorrowed<'_, U>> {
+        self.check_finalizing()?;
+        let unit = self.unit_lookup::<T>()?;
+        unit.expect_changes();
+        unit.storage_mut()
+    }
+
+    ///
+    /// Internal function. Empties and unpoisons the unit for `T`, borrowed as
+    /// `storage`, in the case that it is poisoned.
+    ///
+    #[inline]
+    fn clear_poisoned<T: 'static + Send>(&self, storage: &mut MutBorrowed<'_, U>) {
+        if let Some(flag) = self.poison_flag(TypeId::of::<T>()) {
+            if flag.load(Ordering::Acquire) {
+                *storage.downcast_mut::<StorageUnit<T>>().unwrap() = StorageUnit::new();
+                self.record_change::<T>(ValueChange::Cleared);
+                flag.store(false, Ordering::Release);
+            }
+        }
+    }
+
+    ///
+    /// Inserts `data` like `insert`, and returns a mutable lock on it without
+    /// releasing the unit in between, so that no one else can observe it before
+    /// the lock is dropped. For a unit which already has values, the lock is
+    /// on the newly appended value.
+    ///
+    /// This returns `data` alongside the error in the case that it couldn't be
+    /// inserted.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::{DynamicStorage, make_storage};
+    /// let storage = make_storage!(DynamicStorage: String);
+    /// storage.insert(String::from("first")).unwrap();
+    /// let mut lock = storage.insert_and_get_mut(String::from("second")).unwrap();
+    /// lock.push_str(", configured");
+    /// drop(lock);
+    /// assert_eq!(&*storage.ind::<String>(1).unwrap(), "second, configured");
+    /// # }
+    /// ```
+    ///
+    pub fn insert_and_get_mut<'a, T: 'static + Send>(
+        &'a self,
+        data: T,
+    ) -> Result<MappedMut<'a, U, T>, (T, ErrorDesc)>
+    where
+        MutBorrowed<'a, U>: MapMut<dyn Any + Send, T, Func = fn(&mut (dyn Any + Send)) -> &mut T>,
+    {
+        traced!(
+            "insert_and_get_mut",
+            T,
+            match self.borrow_for_insert::<T>() {
+                Ok(mut storage) => {
+                    let unit = storage.downcast_mut().unwrap();
+                    let mut unit = BufferedUnit::<T>::new(unit, self.spare::<T>());
+                    unit.insert(data);
+                    notify!(
+                        self,
+                        T,
+                        Inserted {
+                            index: unit.len() - 1
+                        }
+                    );
+                    Ok(
+                        self.poisoning::<T, _>(tracked!(
+                            self,
+                            T,
+                            Write,
+                            storage.map(last_mut::<T>)
+                        )),
+                    )
+                }
+                Err(e) => Err((data, e)),
+            },
+            rejected
+        )
+    }
+
+    ///
+    /// Internal function. Returns a reference to the `Unit` for `T`, or an
+    /// `Err` if it is poisoned.
+    ///
+    #[inline]
+    fn unit_get<T: 'static + Send>(&self) -> DynamicResult<&U> {
+        let unit = self.unit_lookup::<T>()?;
+        self.check_poison(TypeId::of::<T>())?;
+        Ok(unit)
+    }
+
+    ///
+    /// Internal function. Like `unit_get`, but for changing the values of the
+    /// unit, which a finalizer may not do, see `on_remove`.
+    ///
+    #[inline]
+    fn unit_get_mut<T: 'static + Send>(&self) -> DynamicResult<&U> {
+        self.check_finalizing()?;
+        self.unit_get::<T>()
+    }
+
+    ///
+    /// Internal function. Returns a reference to the `Unit` for `T`, whether
+    /// or not it is poisoned.
+    ///
+    /// This first checks the most recently looked up unit before falling back
+    /// to the map, as the same type is usually accessed many times in a row.
+    ///
+    #[inline]
+    fn unit_lookup<T: 'static + Send>(&self) -> DynamicResult<&U> {
+        // `data` cannot change while `self` is borrowed immutably, so a `Relaxed`
+        // load is enough to see a pointer which is still valid.
+        let cached = self.cache.load(Ordering::Relaxed);
+        if !cached.is_null() {
+            let unit: &U = unsafe { (*cached).as_ref() };
+            if unit.id() == TypeId::of::<T>() {
+                return Ok(unit);
+            }
+        }
+        let unit = self
+            .data
+            .get(&TypeId::of::<T>())
+            .ok_or_else(|| self.missing_unit(TypeId::of::<T>()))?;
+        // The accessors cast what the unit hands out to `T` without checking, so
+        // a unit stored under the wrong key must never be handed to them
+        if unit.id() != TypeId::of::<T>() {
+            debug_assert!(
+                false,
+                "The unit for {} stores {}",
+                std::any::type_name::<T>(),
+                unit.type_name()
+            );
+            return Err(ErrorDesc::NoMatchingType);
+        }
+        self.cache
+            .store(unit as *const Box<U> as *mut Box<U>, Ordering::Relaxed);
+        Ok(&**unit)
+    }
+
+    ///
+    /// Internal function. Runs `f` on the `StorageUnit` for `T` while holding
+    /// an immutable borrow on it
+    ///
+    #[inline]
+    fn with_storage<T: 'static + Send, R, F: FnOnce(&StorageUnit<T>) -> R>(
+        &self,
+        f: F,
+    ) -> DynamicResult<R> {
+        let storage = self.unit_get::<T>()?.storage()?;
+        Ok(f(storage.downcast_ref().unwrap()))
+    }
+
+    ///
+    /// Internal function. Like `with_storage_mut`, but for `f` which reports
+    /// every change it makes to the values with `notify!`.
+    ///
+    #[inline]
+    fn with_storage_recorded<T: 'static + Send, R, F: FnOnce(&mut BufferedUnit<T>) -> R>(
+        &self,
+        f: F,
+    ) -> DynamicResult<R> {
+        let unit = self.unit_get_mut::<T>()?;
+        unit.expect_changes();
+        let mut storage = unit.storage_mut()?;
+        let _poison = PoisonOnPanic(self.poison_flag(TypeId::of::<T>()));
+        let _reshape = ReshapeOnPanic(self.reshapes.get(&TypeId::of::<T>()));
+        let unit = storage.downcast_mut().unwrap();
+        Ok(f(&mut BufferedUnit::new(unit, self.spare::<T>())))
+    }
+
+    ///
+    /// Internal function. Reports a change made to the values of `T` to their
+    /// unit, see `Unit::record_change`.
+    ///
+    #[inline]
+    fn record_change<T: 'static + Send>(&self, change: ValueChange) {
+        if let ValueChange::Extracted { .. } | ValueChange::Cleared = change {
+            self.reshaped(TypeId::of::<T>());
+        }
+        if let Ok(unit) = self.unit_lookup::<T>() {
+            unit.record_change(change);
+        }
+    }
+
+    ///
+    /// Internal function. Counts that values of the type identified by `id`
+    /// were removed or could have been reordered, invalidating the cursors
+    /// over them. This is done while the unit is still borrowed mutably, so
+    /// that a cursor reading the count while borrowing the unit sees it.
+    ///
+    #[inline]
+    fn reshaped(&self, id: TypeId) {
+        if let Some(reshapes) = self.reshapes.get(&id) {
+            reshapes.fetch_add(1, Ordering::AcqRel);
+        }
+    }
+
+    ///
+    /// Internal function. Runs `f` on the `StorageUnit` for `T` while holding
+    /// a mutable borrow on it, poisoning the unit if `f` panics
+    ///
+    #[inline]
+    fn with_storage_mut<T: 'static + Send, R, F: FnOnce(&mut StorageUnit<T>) -> R>(
+        &self,
+        f: F,
+    ) -> DynamicResult<R> {
+        let mut storage = self.unit_get_mut::<T>()?.storage_mut()?;
+        let _poison = PoisonOnPanic(self.poison_flag(TypeId::of::<T>()));
+        let _reshape = ReshapeOnPanic(self.reshapes.get(&TypeId::of::<T>()));
+        Ok(f(storage.downcast_mut().unwrap()))
+    }
+
+    ///
+    /// Returns a mutable lock on a value of type `T`.
+    /// This will return a `StorageWriteGuard` wrapping:
+    ///
+    /// - A [`RefMut<'a, T>`] in the case of `DynamicStorage`
+    /// - A [`MappedMutexGuard<'a, T>`] in the case of `MutexStorage`
+    /// - A [`MappedRwLockWriteGuard<'a, T>`] in the case of `RwLockStorage`
+    ///
+    /// In the case that there is no unit, or that the data is incompatibly borrowed,
+    /// an `Err` value will be returned, containing a description of the error in the
+    /// enum variant name. This will also return an `Err` variant if more than one `T`
+    /// are stored in the storage, unless the storage was created with
+    /// `GetPolicy::FirstOnMany`, see `with_policy`.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::DynamicStorage;
+    ///
+    /// let mut storage = DynamicStorage::new();
+    /// storage.allocate_for::<String>();
+    /// storage.insert(String::new());
+    ///
+    /// let mut lock = storage.get_mut::<String>().unwrap();
+    /// lock.push_str("Abc");
+    /// drop(lock);
+    ///
+    /// let lock = storage.get::<String>().unwrap();
+    /// assert_eq!(&*lock, "Abc");
+    ///
+    /// # }
+    /// ```
+    ///
+    /// [`MappedMutexGuard<'a, T>`]: https://docs.rs/parking_lot/0.8.0/parking_lot/type.MappedMutexGuard.html
+    /// [`MappedRwLockWriteGuard<'a, T>`]: https://docs.rs/parking_lot/0.8.0/parking_lot/type.MappedRwLockWriteGuard.html
+    /// [`RefMut<'a, T>`]: https://doc.rust-lang.org/std/cell/struct.RefMut.html
+    ///
+    #[inline]
+    pub fn get_mut<'a, T: 'static + Send>(
+        &'a self,
+    ) -> DynamicResult<<MutBorrowed<'a, U> as MapMut<dyn Any + Send, T>>::Output>
+    where
+        MutBorrowed<'a, U>: MapMut<dyn Any + Send, T, Func = fn(&mut (dyn Any + Send)) -> &mut T>,
+    {
+        self.get_mut_with_policy::<T>(self.policy)
+    }
+
+    ///
+    /// Like `get_mut`, but follows `policy` instead of the policy of the storage.
+    ///
+    #[inline]
+    pub fn get_mut_with_policy<'a, T: 'static + Send>(
+        &'a self,
+        policy: GetPolicy,
+    ) -> DynamicResult<MappedMut<'a, U, T>>
+    where
+        MutBorrowed<'a, U>: MapMut<dyn Any + Send, T, Func = fn(&mut (dyn Any + Send)) -> &mut T>,
+    {
+        traced!(
+            "get_mut",
+            T,
+            self.unit_get_mut::<T>()
+                .and_then(|unit| Self::by_policy(policy, || unit.one_mut(), || unit.ind_mut(0)))
+                .map(|x| self.poisoning::<T, _>(tracked!(
+                    self,
+                    T,
+                    Write,
+                    x.map(downcast_mut_unchecked)
+                )))
+        )
+    }
+
+    ///
+    /// Internal function. Returns the single value through `one`, falling back
+    /// to the first of many values through `first` if `policy` allows it.
+    ///
+    #[inline]
+    fn by_policy<G>(
+        policy: GetPolicy,
+        one: impl FnOnce() -> DynamicResult<G>,
+        first: impl FnOnce() -> DynamicResult<G>,
+    ) -> DynamicResult<G> {
+        match one() {
+            Err(ErrorDesc::Unit(UnitError::IsNotOne)) if policy == GetPolicy::FirstOnMany => {
+                // An empty unit is reported in the same way under either policy
+                first().map_err(|e| match e {
+                    ErrorDesc::Unit(_) => ErrorDesc::Unit(UnitError::IsNotOne),
+                    e => e,
+                })
+            }
+            result => result,
+        }
+    }
+
+    ///
+    /// Returns a mutable lock to the element at a given index.
+    /// This will return the only element available if there is
+    /// only one element in the storage, and the index specified
+    /// is 0.
+    ///
+    /// This will return the same lock types as `BlackBox::get_mut()`
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::DynamicStorage;
+    /// let mut storage = DynamicStorage::new();
+    /// storage.allocate_for::<String>();
+    /// storage.insert(String::new());
+    /// {
+    ///     let mut lock = storage.ind_mut::<String>(0).unwrap();
+    ///     lock.push_str("abc");
+    /// }
+    /// storage.insert(String::new());
+    /// storage.ind_mut::<String>(0).unwrap().push_str("def");
+    /// assert_eq!(
+    ///     &storage.run_for::<String, String, _>(|x| {
+    ///         let x = x.unwrap();
+    ///         Some(x[0].clone() + &x[1])
+    ///     }).unwrap(),
+    ///     "abcdef"
+    /// );
+    /// # }
+    /// ```
+    ///
+    #[inline]
+    pub fn ind_mut<'a, T: 'static + Send>(
+        &'a self,
+        ind: usize,
+    ) -> DynamicResult<<MutBorrowed<'a, U> as MapMut<dyn Any + Send, T>>::Output>
+    where
+        MutBorrowed<'a, U>: MapMut<dyn Any + Send, T, Func = fn(&mut (dyn Any + Send)) -> &mut T>,
+    {
+        traced!(
+            "ind_mut",
+            T,
+            self.unit_get_mut::<T>()
+                .and_then(|unit| unit.ind_mut(ind))
+                .map(|x| self.poisoning::<T, _>(tracked!(
+                    self,
+                    T,
+                    Write,
+                    x.map(downcast_mut_unchecked)
+                )))
+        )
+    }
+
+    ///
+    /// Returns a mutable lock to the value `back` places from the end of the
+    /// storage. See `BlackBox::ind_from_end`.
+    ///
+    #[inline]
+    pub fn ind_from_end_mut<'a, T: 'static + Send>(
+        &'a self,
+        back: usize,
+    ) -> DynamicResult<MappedMut<'a, U, T>>
+    where
+        MutBorrowed<'a, U>: MapMut<dyn Any + Send, T, Func = fn(&mut (dyn Any + Send)) -> &mut T>,
+    {
+        traced!(
+            "ind_from_end_mut",
+            T,
+            self.unit_get_mut::<T>()
+                .and_then(|unit| unit.ind_from_end_mut(back))
+                .map(|x| self.poisoning::<T, _>(tracked!(
+                    self,
+                    T,
+                    Write,
+                    x.map(downcast_mut_unchecked)
+                )))
+        )
+    }
+
+    ///
+    /// Returns mutable locks to the values at each of `indices`, in the order
+    /// given, while borrowing the unit once. The locks share the borrow, which
+    /// is released once all of them have been dropped.
+    ///
+    /// Returns `UnitError::DuplicateIndex` in the case that an index is given
+    /// more than once, and `UnitError::OutOfBounds` in the case that one isn't
+    /// less than the number of values.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::{DynamicStorage, ErrorDesc, UnitError, make_storage};
+    /// let storage = make_storage!(DynamicStorage: Vec<&'static str>);
+    /// storage.insert_many(vec![vec!["sword"], vec!["shield"]]).unwrap();
+    /// {
+    ///     let [mut a, mut b] = storage.get_disjoint_ind_mut::<Vec<&str>, 2>([0, 1]).unwrap();
+    ///     std::mem::swap(&mut *a, &mut *b);
+    /// }
+    /// assert_eq!(storage.ind_multi::<Vec<&str>>(&[0, 1]), Ok(vec![vec!["shield"], vec!["sword"]]));
+    /// assert_eq!(
+    ///     storage.get_disjoint_ind_mut::<Vec<&str>, 2>([1, 1]).err(),
+    ///     Some(ErrorDesc::Unit(UnitError::DuplicateIndex))
+    /// );
+    /// # }
+    /// ```
+    ///
+    pub fn get_disjoint_ind_mut<'a, T: 'static + Send, const N: usize>(
+        &'a self,
+        indices: [usize; N],
+    ) -> DynamicResult<[DisjointMut<'a, U, T>; N]>
+    where
+        MutBorrowed<'a, U>: MapMut<
+            dyn Any + Send,
+            StorageUnit<T>,
+            Func = fn(&mut (dyn Any + Send)) -> &mut StorageUnit<T>,
+        >,
+    {
+        let duplicate = indices
+            .iter()
+            .enumerate()
+            .any(|(i, ind)| indices[..i].contains(ind));
+        traced!(
+            "get_disjoint_ind_mut",
+            T,
+            if duplicate {
+                Err(ErrorDesc::Unit(UnitError::DuplicateIndex))
+            } else {
+                self.unit_get_mut::<T>()
+                    .and_then(|unit| unit.storage_mut())
+                    .and_then(|x| {
+                        let mut guard = self.poisoning::<T, _>(tracked!(
+                            self,
+                            T,
+                            Write,
+                            x.map(downcast_mut_unchecked)
+                        ));
+                        let values = guard.as_mut_slice();
+                        if indices.iter().any(|&ind| ind >= values.len()) {
+                            return Err(ErrorDesc::Unit(UnitError::OutOfBounds));
+                        }
+                        let base = values.as_mut_ptr();
+                        let shared = Arc::new(guard);
+                        // The indices are distinct and in bounds, and the values stay
+                        // behind the unit, which `shared` keeps borrowed
+                        Ok(indices
+                            .map(|ind| unsafe { ElemGuardMut::new(shared.clone(), base.add(ind)) }))
+                    })
+            }
+        )
+    }
+
+    ///
+    /// Retrieves an owned `T` from the storage.
+    ///
+    /// Returns an `Err`or in the case that it is impossible to retrieve one.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::DynamicStorage;
+    /// let mut storage = DynamicStorage::new();
+    /// storage.allocate_for::<String>();
+    /// storage.insert(String::new()).unwrap();
+    /// let my_str: String = storage.extract::<String>().unwrap();
+    /// assert_eq!(my_str, String::new());
+    /// # }
+    /// ```
+    ///
+    #[inline]
+    pub fn extract<T: 'static + Send>(&self) -> DynamicResult<T> {
+        traced!(
+            "extract",
+            T,
+            self.with_storage_recorded(|unit: &mut BufferedUnit<T>| {
+                let value = unit.extract_one()?;
+                notify!(self, T, Extracted { index: 0 });
+                Ok(value)
+            })
+            .and_then(|x| x)
+            .map(|value| self.finalized(value))
+        )
+    }
+
+    ///
+    /// Retrieves the owned `T` at `ind`, shifting the values after it down.
+    /// Index `0` of a single value is that value.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::{DynamicStorage, make_storage};
+    /// let storage = make_storage!(DynamicStorage: usize);
+    /// storage.insert_many(vec![1usize, 2, 3]).unwrap();
+    /// assert_eq!(storage.extract_ind::<usize>(1), Ok(2));
+    /// assert_eq!(*storage.ind::<usize>(1).unwrap(), 3);
+    /// # }
+    /// ```
+    ///
+    #[inline]
+    pub fn extract_ind<T: 'static + Send>(&self, ind: usize) -> DynamicResult<T> {
+        traced!(
+            "extract_ind",
+            T,
+            self.with_storage_recorded(|unit: &mut BufferedUnit<T>| {
+                let value = unit.extract_ind(ind)?;
+                notify!(self, T, Extracted { index: ind });
+                Ok(value)
+            })
+            .and_then(|x| x)
+            .map(|value| self.finalized(value))
+        )
+    }
+
+    ///
+    /// Removes and returns the first `T` for which `pred` returns `true`,
+    /// shifting the values after it down, or returns `Ok(None)` in the case
+    /// that there is none. The values are searched and the match removed
+    /// while the unit is borrowed mutably once, so nothing can change the
+    /// values in between, unlike with `position` followed by `extract_ind`.
+    ///
+    /// A single value is treated as being at index `0`, like in `position`.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::{DynamicStorage, make_storage};
+    /// let storage = make_storage!(DynamicStorage: usize);
+    /// storage.insert_many(vec![3usize, 7, 5, 7]).unwrap();
+    /// assert_eq!(storage.remove_where::<usize, _>(|x| *x == 7), Ok(Some(7)));
+    /// assert_eq!(storage.remove_where::<usize, _>(|x| *x == 4), Ok(None));
+    /// assert_eq!(storage.extract_many::<usize>().unwrap().to_vec(), vec![3, 5, 7]);
+    /// # }
+    /// ```
+    ///
+    pub fn remove_where<T: 'static + Send, F: FnMut(&T) -> bool>(
+        &self,
+        pred: F,
+    ) -> DynamicResult<Option<T>> {
+        traced!(
+            "remove_where",
+            T,
+            self.with_storage_recorded(|unit: &mut BufferedUnit<T>| {
+                let index = match unit.as_slice().iter().position(pred) {
+                    Some(index) => index,
+                    None => return Ok(None),
+                };
+                let value = unit.extract_ind(index)?;
+                notify!(self, T, Extracted { index: index });
+                Ok(Some(value))
+            })
+            .and_then(|x| x)
+            .map(|value| value.map(|value| self.finalized(value)))
+        )
+    }
+
+    ///
+    /// Like `remove_where`, but moves the last value into the place of the
+    /// removed one rather than shifting the values after it down, which takes
+    /// the same time no matter how many values there are, but doesn't keep
+    /// the order of the values.
+    ///
+    /// Subscribers see the last value being extracted, and then being
+    /// inserted where the removed one was, see `subscribe`.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::{DynamicStorage, make_storage};
+    /// let storage = make_storage!(DynamicStorage: usize);
+    /// storage.insert_many(vec![3usize, 7, 5, 9]).unwrap();
+    /// assert_eq!(storage.swap_remove_where::<usize, _>(|x| *x == 7), Ok(Some(7)));
+    /// assert_eq!(storage.extract_many::<usize>().unwrap().to_vec(), vec![3, 9, 5]);
+    /// # }
+    /// ```
+    ///
+    pub fn swap_remove_where<T: 'static + Send, F: FnMut(&T) -> bool>(
+        &self,
+        pred: F,
+    ) -> DynamicResult<Option<T>> {
+        traced!(
+            "swap_remove_where",
+            T,
+            self.with_storage_recorded(|unit: &mut BufferedUnit<T>| {
+                let values = unit.as_slice();
+                let last = values.len().saturating_sub(1);
+                let index = match values.iter().position(pred) {
+                    Some(index) => index,
+                    None => return Ok(None),
+                };
+                let value = unit.swap_extract_ind(index)?;
+                notify!(self, T, Extracted { index: last });
+                if index != last {
+                    notify!(self, T, Inserted { index: index });
+                }
+                Ok(Some(value))
+            })
+            .and_then(|x| x)
+            .map(|value| value.map(|value| self.finalized(value)))
+        )
+    }
+
+    ///
+    /// Extracts multiple values and returns them in the form of
+    /// a `Box<[T]>` which can be turned into a `Vec<T>`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() {
+    /// use restor::{DynamicStorage, make_storage};
+    /// let storage = make_storage!(DynamicStorage: usize);
+    /// storage.insert_many(vec![1usize, 2, 3, 4]).unwrap();
+    /// let v: Vec<usize> = storage.extract_many::<usize>().unwrap().into();
+    /// assert_eq!(v, vec![1usize, 2, 3, 4]);
+    /// # }
+    /// ```
+    ///
+    #[inline]
+    pub fn extract_many<T: 'static + Send>(&self) -> DynamicResult<Box<[T]>> {
+        traced!(
+            "extract_many",
+            T,
+            self.with_storage_recorded(|unit: &mut BufferedUnit<T>| {
+                let values = unit.extract_many_boxed()?;
+                notify!(self, T, Cleared {});
+                Ok(values)
+            })
+            .and_then(|x| x)
+            .map(|mut values| {
+                self.finalize(&mut values);
+                values
+            })
+        )
+    }
+
+    ///
+    /// Extracts every value of `T` no matter how many there are: none for a
+    /// unit with no values, which is not an error, one for a single value, and
+    /// all of them for a list. This only returns an `Err` in the case that the
+    /// unit is borrowed or not allocated.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::{DynamicStorage, make_storage};
+    /// let storage = make_storage!(DynamicStorage: usize);
+    /// assert_eq!(storage.extract_any_count::<usize>(), Ok(vec![]));
+    /// storage.insert(1usize).unwrap();
+    /// assert_eq!(storage.extract_any_count::<usize>(), Ok(vec![1]));
+    /// storage.insert_many(vec![2usize, 3]).unwrap();
+    /// assert_eq!(storage.extract_any_count::<usize>(), Ok(vec![2, 3]));
+    /// # }
+    /// ```
+    ///
+    pub fn extract_any_count<T: 'static + Send>(&self) -> DynamicResult<Vec<T>> {
+        traced!(
+            "extract_any_count",
+            T,
+            self.with_storage_recorded(|unit: &mut BufferedUnit<T>| {
+                let values = unit.extract_any_count();
+                if !values.is_empty() {
+                    notify!(self, T, Cleared {});
+                }
+                values
+            })
+            .map(|mut values| {
+                self.finalize(&mut values);
+                values
+            })
+        )
+    }
+
+    ///
+    /// Moves every value of `T` to the end of `buf` under a single mutable
+    /// borrow of the unit, leaving it with no values, and returns how many
+    /// values were moved. Like `extract_any_count`, a unit with no values is
+    /// not an error. The capacity of `buf` is reused, so draining a unit into
+    /// the same buffer over and over doesn't allocate once it is large enough.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::{RwLockStorage, make_storage};
+    /// let storage = make_storage!(RwLockStorage: usize);
+    /// let mut buf = Vec::<usize>::with_capacity(4);
+    /// assert_eq!(storage.extract_many_into(&mut buf), Ok(0));
+    /// storage.insert(1usize).unwrap();
+    /// assert_eq!(storage.extract_many_into(&mut buf), Ok(1));
+    /// storage.insert_many(vec![2usize, 3]).unwrap();
+    /// assert_eq!(storage.extract_many_into(&mut buf), Ok(2));
+    /// assert_eq!(buf, vec![1, 2, 3]);
+    /// assert_eq!(storage.extract_any_count::<usize>(), Ok(vec![]));
+    /// # }
+    /// ```
+    ///
+    pub fn extract_many_into<T: 'static + Send>(&self, buf: &mut Vec<T>) -> DynamicResult<usize> {
+        let start = buf.len();
+        traced!(
+            "extract_many_into",
+            T,
+            self.with_storage_recorded(|unit: &mut BufferedUnit<T>| {
+                let moved = unit.extract_many_into(buf);
+                if moved != 0 {
+                    notify!(self, T, Cleared {});
+                }
+                moved
+            })
+            .inspect(|_| self.finalize(&mut buf[start..]))
+        )
+    }
+
+    ///
+    /// Replaces the buffer the unit for `T` stores its values in with
+    /// `replacement`, keeping the values already in it, and returns the
+    /// previous values in the previous buffer. This allows rotating between
+    /// buffers, such as once per frame, without freeing or allocating any.
+    ///
+    /// The previous values are only returned in a new `Vec` in the case that
+    /// the unit held a single value and had never had a buffer.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::{RwLockStorage, make_storage};
+    /// let storage = make_storage!(RwLockStorage: usize);
+    /// let mut spare = Vec::<usize>::with_capacity(16);
+    /// for frame in 0..3 {
+    ///     storage.insert_many(vec![frame, frame + 1]).unwrap();
+    ///     let mut values = storage.swap_backing_vec(spare).unwrap();
+    ///     assert_eq!(values, vec![frame, frame + 1]);
+    ///     values.clear();
+    ///     spare = values;
+    /// }
+    /// # }
+    /// ```
+    ///
+    pub fn swap_backing_vec<T: 'static + Send>(
+        &self,
+        replacement: Vec<T>,
+    ) -> DynamicResult<Vec<T>> {
+        traced!(
+            "swap_backing_vec",
+            T,
+            self.with_storage_recorded(|unit: &mut BufferedUnit<T>| {
+                let previous = unit.swap_buffer(replacement);
+                if !previous.is_empty() {
+                    notify!(self, T, Cleared {});
+                }
+                for index in 0..unit.len() {
+                    notify!(self, T, Inserted { index: index });
+                }
+                previous
+            })
+            .map(|mut values| {
+                self.finalize(&mut values);
+                values
+            })
+        )
+    }
+
+    ///
+    /// Extracts the single value of `T`, or the first value in the case that
+    /// there is a list of them. This returns `UnitError::IsNone` in the case that
+    /// there are no values, where `extract` returns `UnitError::IsNotOne`.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::{DynamicStorage, ErrorDesc, UnitError, make_storage};
+    /// let storage = make_storage!(DynamicStorage: usize);
+    /// storage.insert_many(vec![1usize, 2]).unwrap();
+    /// assert_eq!(storage.extract_one_loose::<usize>(), Ok(1));
+    /// assert_eq!(storage.extract_one_loose::<usize>(), Ok(2));
+    /// assert_eq!(
+    ///     storage.extract_one_loose::<usize>(),
+    ///     Err(ErrorDesc::Unit(UnitError::IsNone))
+    /// );
+    /// # }
+    /// ```
+    ///
+    pub fn extract_one_loose<T: 'static + Send>(&self) -> DynamicResult<T> {
+        traced!(
+            "extract_one_loose",
+            T,
+            self.with_storage_recorded(|unit: &mut BufferedUnit<T>| {
+                let value = unit.extract_one_loose()?;
+                notify!(self, T, Extracted { index: 0 });
+                Ok(value)
+            })
+            .and_then(|x| x)
+            .map(|value| self.finalized(value))
+        )
+    }
+
+    ///
+    /// Gets an immutable lock on the single value variant of storage.
     ///
     /// This will return an `Err` in the case that it is either borrowed
-    /// incompatibly or there is no allocated unit.
+    /// incompatibly or there is no allocated unit. In the case of many values,
+    /// this follows the policy of the storage, see `GetPolicy`.
+    ///
+    /// # Note
+    /// This method is only available for use in the case where the underlying
+    /// interior mutability type supports it:
+    ///
+    /// - `RwLockStorage`: `RwLock`s
+    /// - `DynamicStorage`: `RefCell`s
+    ///
+    /// And so therefor it is not implemented in the case of `MutexStorage`
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::{DynamicStorage, make_storage};
+    /// let storage = make_storage!(DynamicStorage: usize);
+    /// storage.insert(32usize).unwrap();
+    /// println!("{}", &*storage.get::<usize>().unwrap());
+    /// # }
+    /// ```
+    ///
+    #[inline]
+    pub fn get<'a, T: 'static + Send>(
+        &'a self,
+    ) -> DynamicResult<<Borrowed<'a, U> as Map<dyn Any + Send, T>>::Output>
+    where
+        Borrowed<'a, U>: Map<dyn Any + Send, T, Func = for<'b> fn(&'b (dyn Any + Send)) -> &'b T>,
+    {
+        self.get_with_policy::<T>(self.policy)
+    }
+
+    ///
+    /// Like `get`, but follows `policy` instead of the policy of the storage.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::{DynamicStorage, ErrorDesc, GetPolicy, UnitError, make_storage};
+    /// let storage = make_storage!(DynamicStorage: usize);
+    /// storage.insert_many(vec![1usize, 2]).unwrap();
+    /// assert_eq!(
+    ///     storage.get::<usize>().unwrap_err(),
+    ///     ErrorDesc::Unit(UnitError::IsNotOne)
+    /// );
+    /// let first = storage.get_with_policy::<usize>(GetPolicy::FirstOnMany);
+    /// assert_eq!(*first.unwrap(), 1);
+    /// # }
+    /// ```
+    ///
+    #[inline]
+    pub fn get_with_policy<'a, T: 'static + Send>(
+        &'a self,
+        policy: GetPolicy,
+    ) -> DynamicResult<Mapped<'a, U, T>>
+    where
+        Borrowed<'a, U>: Map<dyn Any + Send, T, Func = for<'b> fn(&'b (dyn Any + Send)) -> &'b T>,
+    {
+        traced!(
+            "get",
+            T,
+            self.unit_get::<T>()
+                .and_then(|unit| Self::by_policy(policy, || unit.one(), || unit.ind(0)))
+                .map(|x| tracked!(self, T, Read, x.map(downcast_ref_unchecked)))
+        )
+    }
+    ///
+    /// Returns the version of the unit for `T`, which is increased every time
+    /// the unit is borrowed mutably: by `get_mut`, `ind_mut`, inserting,
+    /// extracting or any of the methods taking a closure over mutable values.
+    /// The version is increased when the borrow is acquired, so it is already
+    /// up to date while a mutable lock is still held.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::{DynamicStorage, make_storage};
+    /// let storage = make_storage!(DynamicStorage: usize);
+    /// let start = storage.version::<usize>().unwrap();
+    /// storage.insert(0usize).unwrap();
+    /// *storage.get_mut::<usize>().unwrap() += 1;
+    /// assert_eq!(storage.version::<usize>(), Ok(start + 2));
+    /// let _ = storage.get::<usize>().unwrap();
+    /// assert_eq!(storage.version::<usize>(), Ok(start + 2));
+    /// # }
+    /// ```
+    ///
+    pub fn version<T: 'static + Send>(&self) -> DynamicResult<u64> {
+        Ok(self.unit_get::<T>()?.version())
+    }
+
+    ///
+    /// Returns which of readers and writers get the unit for `T` when both
+    /// keep trying to, see `Fairness`. Only the units of a `RwLockStorage`
+    /// allocated with `allocate_for_with_fairness` can favor writers.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::{Fairness, RwLockStorage};
+    /// let mut storage = RwLockStorage::new();
+    /// storage.allocate_for::<usize>();
+    /// storage.allocate_for_with_fairness::<String>(Fairness::WriterPriority);
+    /// assert_eq!(storage.fairness::<usize>(), Ok(Fairness::ReaderPriority));
+    /// assert_eq!(storage.fairness::<String>(), Ok(Fairness::WriterPriority));
+    /// # }
+    /// ```
+    ///
+    pub fn fairness<T: 'static + Send>(&self) -> DynamicResult<Fairness> {
+        Ok(self.unit_get::<T>()?.fairness())
+    }
+
+    ///
+    /// Returns the indices of the values of `T` which were inserted or borrowed
+    /// mutably since this was last called, in ascending order, and forgets them.
+    /// Removing a value moves the indices of those after it down along with the
+    /// values. This is only supported by units allocated with
+    /// `DynamicStorage::allocate_tracked_for`, and fails while the unit is
+    /// borrowed mutably, since the values could still be changed.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::DynamicStorage;
+    /// let mut storage = DynamicStorage::new();
+    /// storage.allocate_tracked_for::<usize>();
+    /// storage.insert_many(vec![0usize, 1, 2, 3]).unwrap();
+    /// assert_eq!(storage.take_dirty::<usize>(), Ok(vec![0, 1, 2, 3]));
+    /// *storage.ind_mut::<usize>(2).unwrap() += 1;
+    /// storage.extract_ind::<usize>(0).unwrap();
+    /// assert_eq!(storage.take_dirty::<usize>(), Ok(vec![1]));
+    /// assert_eq!(storage.take_dirty::<usize>(), Ok(vec![]));
+    /// # }
+    /// ```
+    ///
+    pub fn take_dirty<T: 'static + Send>(&self) -> DynamicResult<Vec<usize>> {
+        self.unit_get::<T>()?.take_dirty()
+    }
+
+    ///
+    /// Returns an immutable lock on the value of `T` along with the version of
+    /// its unit, or `None` in the case that the version is still `last_seen`,
+    /// see `version`.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::{RwLockStorage, make_storage};
+    /// let storage = make_storage!(RwLockStorage: String);
+    /// storage.insert(String::from("abc")).unwrap();
+    /// let (value, seen) = storage.get_if_newer::<String>(0).unwrap().unwrap();
+    /// assert_eq!(&*value, "abc");
+    /// drop(value);
+    /// assert!(storage.get_if_newer::<String>(seen).unwrap().is_none());
+    /// storage.get_mut::<String>().unwrap().push('d');
+    /// assert!(storage.get_if_newer::<String>(seen).unwrap().is_some());
+    /// # }
+    /// ```
+    ///
+    pub fn get_if_newer<'a, T: 'static + Send>(
+        &'a self,
+        last_seen: u64,
+    ) -> DynamicResult<Option<(Mapped<'a, U, T>, u64)>>
+    where
+        Borrowed<'a, U>: Map<dyn Any + Send, T, Func = for<'b> fn(&'b (dyn Any + Send)) -> &'b T>,
+    {
+        let unit = self.unit_get::<T>()?;
+        if unit.version() == last_seen {
+            return Ok(None);
+        }
+        // Read the version while the value is borrowed, so that it can't change in between
+        let value = unit.one()?;
+        let version = unit.version();
+        Ok(Some((
+            tracked!(self, T, Read, value.map(downcast_ref_unchecked)),
+            version,
+        )))
+    }
+
+    #[inline]
+    pub fn ind<'a, T: 'static + Send>(
+        &'a self,
+        ind: usize,
+    ) -> DynamicResult<<Borrowed<'a, U> as Map<dyn Any + Send, T>>::Output>
+    where
+        Borrowed<'a, U>: Map<dyn Any + Send, T, Func = for<'b> fn(&'b (dyn Any + Send)) -> &'b T>,
+    {
+        traced!(
+            "ind",
+            T,
+            self.unit_get::<T>()
+                .and_then(|unit| unit.ind(ind))
+                .map(|x| tracked!(self, T, Read, x.map(downcast_ref_unchecked)))
+        )
+    }
+
+    ///
+    /// Returns an immutable lock to the value `back` places from the end of the
+    /// storage, where `0` is the last value. The position is worked out while the
+    /// unit is borrowed, so unlike using `ind` with a length read beforehand, this
+    /// never refers to a value which has since stopped being the last one.
+    ///
+    /// A single value counts as one element and no values as none, so this returns
+    /// `UnitError::OutOfBounds` whenever `back` isn't less than the number of values.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::{DynamicStorage, ErrorDesc, UnitError, make_storage};
+    /// let storage = make_storage!(DynamicStorage: usize);
+    /// storage.insert(1usize).unwrap();
+    /// assert_eq!(*storage.ind_from_end::<usize>(0).unwrap(), 1);
+    /// storage.insert_many(vec![2usize, 3]).unwrap();
+    /// assert_eq!(*storage.ind_from_end::<usize>(0).unwrap(), 3);
+    /// assert_eq!(*storage.ind_from_end::<usize>(2).unwrap(), 1);
+    /// assert_eq!(
+    ///     storage.ind_from_end::<usize>(3).unwrap_err(),
+    ///     ErrorDesc::Unit(UnitError::OutOfBounds)
+    /// );
+    /// # }
+    /// ```
+    ///
+    #[inline]
+    pub fn ind_from_end<'a, T: 'static + Send>(
+        &'a self,
+        back: usize,
+    ) -> DynamicResult<Mapped<'a, U, T>>
+    where
+        Borrowed<'a, U>: Map<dyn Any + Send, T, Func = for<'b> fn(&'b (dyn Any + Send)) -> &'b T>,
+    {
+        traced!(
+            "ind_from_end",
+            T,
+            self.unit_get::<T>()
+                .and_then(|unit| unit.ind_from_end(back))
+                .map(|x| tracked!(self, T, Read, x.map(downcast_ref_unchecked)))
+        )
+    }
+
+    ///
+    /// Like `get`, but treats a missing value as a normal outcome rather than
+    /// an error:
+    ///
+    /// | Condition                                         | Result                      |
+    /// |---------------------------------------------------|-----------------------------|
+    /// | The unit holds a single value                     | `Ok(Some(guard))`           |
+    /// | There is no unit allocated for `T`                | `Ok(None)`                  |
+    /// | The unit holds no values                          | `Ok(None)`                  |
+    /// | The unit holds a list, even of one value          | `Err(Unit(IsNotOne))`       |
+    /// | The unit is borrowed incompatibly                 | `Err(BorrowedIncompatibly)` |
+    /// | The unit is poisoned                              | `Err(Poisoned)`             |
+    /// | `T` was allocated with `allocate_read_mostly_for` | `Err(UnsupportedOperation)` |
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::{DynamicStorage, ErrorDesc, UnitError};
+    /// let mut storage = DynamicStorage::new();
+    /// assert!(storage.try_get::<usize>().unwrap().is_none());
+    /// storage.allocate_for::<usize>();
+    /// assert!(storage.try_get::<usize>().unwrap().is_none());
+    /// storage.insert(1usize).unwrap();
+    /// assert_eq!(*storage.try_get::<usize>().unwrap().unwrap(), 1);
+    /// storage.insert(2usize).unwrap();
+    /// assert_eq!(
+    ///     storage.try_get::<usize>().err(),
+    ///     Some(ErrorDesc::Unit(UnitError::IsNotOne))
+    /// );
+    /// # }
+    /// ```
+    ///
+    pub fn try_get<'a, T: 'static + Send>(&'a self) -> DynamicResult<Option<Mapped<'a, U, T>>>
+    where
+        Borrowed<'a, U>: Map<dyn Any + Send, T, Func = for<'b> fn(&'b (dyn Any + Send)) -> &'b T>,
+    {
+        self.one_or_none::<T, _, _>(|| self.get::<T>())
+    }
+
+    ///
+    /// Like `get_mut`, but returns `Ok(None)` in the same cases as `try_get`.
     ///
-    /// # Note
-    /// This method is only available for use in the case where the underlying
-    /// interior mutability type supports it:
+    pub fn try_get_mut<'a, T: 'static + Send>(
+        &'a self,
+    ) -> DynamicResult<Option<MappedMut<'a, U, T>>>
+    where
+        MutBorrowed<'a, U>: MapMut<dyn Any + Send, T, Func = fn(&mut (dyn Any + Send)) -> &mut T>,
+    {
+        self.one_or_none::<T, _, _>(|| self.get_mut::<T>())
+    }
+
+    ///
+    /// Like `get_mut`, but in the case that there is no value to return, calls
+    /// `recover` with the error, which can supply a value to use instead:
+    ///
+    /// | Condition                          | Result                                 |
+    /// |------------------------------------|----------------------------------------|
+    /// | The unit holds a single value      | The guard, without calling `recover`   |
+    /// | The unit holds no values           | `recover` is called                    |
+    /// | There is no unit allocated for `T` | `recover` is called                    |
+    /// | Any other error                    | The error, without calling `recover`   |
+    ///
+    /// A value returned by `recover` is inserted into the empty unit and a
+    /// guard to it is returned, while `None` returns the original error. Units
+    /// can only be allocated through `&mut self`, so the original error is
+    /// returned for a missing unit either way, after `recover` has had the
+    /// chance to report it.
+    ///
+    /// The unit is checked to still be empty once it is borrowed to insert the
+    /// value, so that storages recovering on several threads at once only
+    /// insert one value. The others are dropped, and the guard is to the value
+    /// which was inserted first, taken without releasing the unit in between.
+    /// An error past the call to `recover` is wrapped in an `ErrorDesc::Context`
+    /// naming the step which failed.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::{DynamicStorage, ErrorDesc, make_storage};
+    /// let storage = make_storage!(DynamicStorage: String);
+    /// let value = storage.get_or_recover::<String, _>(|_| Some(String::from("default")));
+    /// assert_eq!(&*value.unwrap(), "default");
+    /// let missing = storage.get_or_recover::<usize, _>(|e| {
+    ///     assert_eq!(e, ErrorDesc::NoAllocatedUnit);
+    ///     Some(0)
+    /// });
+    /// assert_eq!(missing.err(), Some(ErrorDesc::NoAllocatedUnit));
+    /// # }
+    /// ```
+    ///
+    pub fn get_or_recover<'a, T: 'static + Send, F: FnOnce(ErrorDesc) -> Option<T>>(
+        &'a self,
+        recover: F,
+    ) -> DynamicResult<MappedMut<'a, U, T>>
+    where
+        MutBorrowed<'a, U>: MapMut<dyn Any + Send, T, Func = fn(&mut (dyn Any + Send)) -> &mut T>,
+    {
+        // As in `one_or_none`, the state tells whether the unit is empty
+        let error = loop {
+            match self.get_mut::<T>() {
+                Err(ErrorDesc::NoAllocatedUnit) => break ErrorDesc::NoAllocatedUnit,
+                Err(ErrorDesc::Unit(UnitError::IsNotOne)) => match self.state::<T>() {
+                    Ok(UnitState::Empty) => break ErrorDesc::Unit(UnitError::IsNotOne),
+                    Ok(UnitState::Multiple(_)) if self.policy == GetPolicy::Strict => {
+                        return Err(ErrorDesc::Unit(UnitError::IsNotOne))
+                    }
+                    Ok(UnitState::Single) | Ok(UnitState::Multiple(_)) => {}
+                    Err(e) => return Err(e.context("checking whether the unit is empty")),
+                },
+                result => return result,
+            }
+        };
+        let value = match recover(error.clone()) {
+            Some(value) if error != ErrorDesc::NoAllocatedUnit => value,
+            _ => return Err(error),
+        };
+        let mut storage = self
+            .borrow_for_insert::<T>()
+            .map_err(|e| e.context("inserting the recovered value"))?;
+        let unit = storage.downcast_mut().unwrap();
+        let mut unit = BufferedUnit::<T>::new(unit, self.spare::<T>());
+        if unit.is_empty() {
+            unit.insert(value);
+            notify!(self, T, Inserted { index: 0 });
+        }
+        // Another thread may have inserted many values in the meantime
+        let value: fn(&mut (dyn Any + Send)) -> &mut T = match &*unit {
+            StorageUnit::One(_) => last_mut::<T>,
+            StorageUnit::Many(many)
+                if !many.is_empty() && self.policy == GetPolicy::FirstOnMany =>
+            {
+                first_mut::<T>
+            }
+            _ => {
+                return Err(
+                    ErrorDesc::Unit(UnitError::IsNotOne).context("borrowing the recovered value")
+                )
+            }
+        };
+        Ok(self.poisoning::<T, _>(tracked!(self, T, Write, storage.map(value))))
+    }
+
+    ///
+    /// Like `ind`, but treats a missing value as a normal outcome rather than
+    /// an error:
+    ///
+    /// | Condition                                         | Result                      |
+    /// |---------------------------------------------------|-----------------------------|
+    /// | There is a value at `ind`                         | `Ok(Some(guard))`           |
+    /// | There is no unit allocated for `T`                | `Ok(None)`                  |
+    /// | There is no value at `ind`                        | `Ok(None)`                  |
+    /// | The unit is borrowed incompatibly                 | `Err(BorrowedIncompatibly)` |
+    /// | The unit is poisoned                              | `Err(Poisoned)`             |
+    /// | `T` was allocated with `allocate_read_mostly_for` | `Err(UnsupportedOperation)` |
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::{DynamicStorage, make_storage};
+    /// let storage = make_storage!(DynamicStorage: usize);
+    /// storage.insert(1usize).unwrap();
+    /// assert_eq!(*storage.try_ind::<usize>(0).unwrap().unwrap(), 1);
+    /// assert!(storage.try_ind::<usize>(1).unwrap().is_none());
+    /// # }
+    /// ```
+    ///
+    pub fn try_ind<'a, T: 'static + Send>(
+        &'a self,
+        ind: usize,
+    ) -> DynamicResult<Option<Mapped<'a, U, T>>>
+    where
+        Borrowed<'a, U>: Map<dyn Any + Send, T, Func = for<'b> fn(&'b (dyn Any + Send)) -> &'b T>,
+    {
+        Self::ind_or_none(self.ind::<T>(ind))
+    }
+
+    ///
+    /// Like `ind_mut`, but returns `Ok(None)` in the same cases as `try_ind`.
+    ///
+    pub fn try_ind_mut<'a, T: 'static + Send>(
+        &'a self,
+        ind: usize,
+    ) -> DynamicResult<Option<MappedMut<'a, U, T>>>
+    where
+        MutBorrowed<'a, U>: MapMut<dyn Any + Send, T, Func = fn(&mut (dyn Any + Send)) -> &mut T>,
+    {
+        Self::ind_or_none(self.ind_mut::<T>(ind))
+    }
+
+    ///
+    /// Internal function. Turns the errors of `get` which mean that there is
+    /// no value into `Ok(None)`. The error for a unit which is not a single
+    /// value doesn't say whether it is empty, so that is checked afterwards,
+    /// and `get` is retried in the case that a value was inserted in between.
+    ///
+    fn one_or_none<T: 'static + Send, G, F: Fn() -> DynamicResult<G>>(
+        &self,
+        get: F,
+    ) -> DynamicResult<Option<G>> {
+        loop {
+            match get() {
+                Ok(guard) => return Ok(Some(guard)),
+                Err(ErrorDesc::NoAllocatedUnit) => return Ok(None),
+                Err(ErrorDesc::Unit(UnitError::IsNotOne)) => match self.state::<T>()? {
+                    UnitState::Empty => return Ok(None),
+                    UnitState::Multiple(_) if self.policy == GetPolicy::Strict => {
+                        return Err(ErrorDesc::Unit(UnitError::IsNotOne))
+                    }
+                    UnitState::Single | UnitState::Multiple(_) => {}
+                },
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    ///
+    /// Internal function. Turns the errors of `ind` which mean that there is
+    /// no value at the index into `Ok(None)`.
+    ///
+    fn ind_or_none<G>(result: DynamicResult<G>) -> DynamicResult<Option<G>> {
+        match result {
+            Ok(guard) => Ok(Some(guard)),
+            Err(ErrorDesc::NoAllocatedUnit)
+            | Err(ErrorDesc::Unit(UnitError::OutOfBounds))
+            | Err(ErrorDesc::Unit(UnitError::IsNone))
+            | Err(ErrorDesc::Unit(UnitError::IsNotMany)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    ///
+    /// Like `get`, but in the case that the unit is borrowed incompatibly,
+    /// returns a `RetryToken` which can be checked cheaply, without borrowing
+    /// the value, to decide when to poll again instead of retrying `get`:
+    ///
+    /// | Condition                       | Result              |
+    /// |---------------------------------|---------------------|
+    /// | The value could be borrowed     | `Ready(guard)`      |
+    /// | The unit is borrowed mutably    | `Pending(token)`    |
+    /// | Any other error                 | `Err(error)`        |
+    ///
+    /// The token is advisory: the unit may be borrowed again between the token
+    /// becoming ready and the next poll, in which case it returns another one.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::{DynamicStorage, PollResult, make_storage};
+    /// let storage = make_storage!(DynamicStorage: usize);
+    /// storage.insert(1usize).unwrap();
+    /// let lock = storage.get_mut::<usize>().unwrap();
+    /// let token = match storage.poll_get::<usize>() {
+    ///     PollResult::Pending(token) => token,
+    ///     _ => unreachable!(),
+    /// };
+    /// assert!(!token.is_ready());
+    /// drop(lock);
+    /// assert!(token.is_ready());
+    /// assert_eq!(*storage.poll_get::<usize>().ready().unwrap(), 1);
+    /// # }
+    /// ```
+    ///
+    pub fn poll_get<'a, T: 'static + Send>(&'a self) -> PollResult<'a, Mapped<'a, U, T>, U>
+    where
+        Borrowed<'a, U>: Map<dyn Any + Send, T, Func = for<'b> fn(&'b (dyn Any + Send)) -> &'b T>,
+    {
+        self.poll::<T, _>(self.get::<T>(), false)
+    }
+
+    ///
+    /// Like `get_mut`, but returns a `RetryToken` in the case that the unit is
+    /// borrowed at all, see `poll_get`.
+    ///
+    pub fn poll_get_mut<'a, T: 'static + Send>(&'a self) -> PollResult<'a, MappedMut<'a, U, T>, U>
+    where
+        MutBorrowed<'a, U>: MapMut<dyn Any + Send, T, Func = fn(&mut (dyn Any + Send)) -> &mut T>,
+    {
+        self.poll::<T, _>(self.get_mut::<T>(), true)
+    }
+
+    ///
+    /// Internal function. Turns the error of a borrow conflict on the unit for
+    /// `T` into a token checking for the conflict to be gone.
+    ///
+    fn poll<T: 'static + Send, G>(
+        &self,
+        result: DynamicResult<G>,
+        exclusive: bool,
+    ) -> PollResult<'_, G, U> {
+        match result {
+            Ok(guard) => PollResult::Ready(guard),
+            Err(ErrorDesc::BorrowedIncompatibly) => match self.unit_lookup::<T>() {
+                Ok(unit) => PollResult::Pending(RetryToken::new(unit, exclusive)),
+                Err(e) => PollResult::Err(e),
+            },
+            Err(e) => PollResult::Err(e),
+        }
+    }
+
+    ///
+    /// Internal function. Returns the unit for `T` straight from the map,
+    /// without going through the cache of `unit_lookup` or checking what the
+    /// unit stores, see `get_unchecked` for when this is sound.
+    ///
+    #[inline]
+    unsafe fn unit_get_unchecked<T: 'static + Send>(&self) -> &U {
+        let unit = self.data.get(&TypeId::of::<T>());
+        debug_assert!(
+            unit.is_some_and(|unit| unit.id() == TypeId::of::<T>()),
+            "There is no unit allocated for {}",
+            std::any::type_name::<T>()
+        );
+        unit.unwrap_unchecked()
+    }
+
+    ///
+    /// The same as `BlackBox::get`, but without checking that there is a unit
+    /// for `T`, or that the stored value is actually a `T`, and without going
+    /// through the cache of the most recently looked up unit.
+    ///
+    /// Borrows are still checked, so this returns an `Err` in the case that
+    /// the value is borrowed incompatibly, or in the case that there isn't
+    /// exactly one value, following the policy of the storage like `get`, see
+    /// `GetPolicy`. Whether the unit is poisoned is not checked, see
+    /// `new_poisoning`.
+    ///
+    /// # Safety
+    /// - A unit must have been allocated for `T`, and not removed since.
+    /// - The unit must store `T`s, which is always the case unless it was
+    ///   stored under the `TypeId` of `T` through `insert_unit_under`, see
+    ///   `validate`.
+    ///
+    /// In debug builds, both are checked with an assertion.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::{DynamicStorage, make_storage};
+    /// let storage = make_storage!(DynamicStorage: usize);
+    /// storage.insert(32usize).unwrap();
+    /// // `usize` was allocated above
+    /// assert_eq!(*unsafe { storage.get_unchecked::<usize>() }.unwrap(), 32);
+    /// # }
+    /// ```
+    ///
+    #[inline]
+    pub unsafe fn get_unchecked<'a, T: 'static + Send>(
+        &'a self,
+    ) -> DynamicResult<<Borrowed<'a, U> as Map<dyn Any + Send, T>>::Output>
+    where
+        Borrowed<'a, U>: Map<dyn Any + Send, T, Func = for<'b> fn(&'b (dyn Any + Send)) -> &'b T>,
+    {
+        let unit = self.unit_get_unchecked::<T>();
+        let guard = Self::by_policy(self.policy, || unit.one(), || unit.ind(0))?
+            .map(|x| downcast_ref_unchecked(x));
+        Ok(tracked!(self, T, Read, guard))
+    }
+
+    ///
+    /// The same as `BlackBox::ind`, but without checking that there is a unit
+    /// for `T`, or that the stored value is actually a `T`, and without going
+    /// through the cache of the most recently looked up unit.
+    ///
+    /// Borrows and bounds are still checked, so this returns an `Err` in the
+    /// case that the value is borrowed incompatibly or `ind` is out of bounds.
+    /// Whether the unit is poisoned is not checked, see `new_poisoning`.
+    ///
+    /// # Safety
+    /// The same as for `get_unchecked`.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::{DynamicStorage, make_storage};
+    /// let storage = make_storage!(DynamicStorage: usize);
+    /// storage.insert_many(vec![1usize, 2, 3]).unwrap();
+    /// // `usize` was allocated above
+    /// let sum: usize = (0..3)
+    ///     .map(|i| *unsafe { storage.ind_unchecked::<usize>(i) }.unwrap())
+    ///     .sum();
+    /// assert_eq!(sum, 6);
+    /// # }
+    /// ```
+    ///
+    #[inline]
+    pub unsafe fn ind_unchecked<'a, T: 'static + Send>(
+        &'a self,
+        ind: usize,
+    ) -> DynamicResult<<Borrowed<'a, U> as Map<dyn Any + Send, T>>::Output>
+    where
+        Borrowed<'a, U>: Map<dyn Any + Send, T, Func = for<'b> fn(&'b (dyn Any + Send)) -> &'b T>,
+    {
+        let guard = self
+            .unit_get_unchecked::<T>()
+            .ind(ind)?
+            .map(|x| downcast_ref_unchecked(x));
+        Ok(tracked!(self, T, Read, guard))
+    }
+    #[inline]
+    pub fn run_for<
+        T: 'static + Send,
+        D: 'static + Any,
+        F: Fn(DynamicResult<&[T]>) -> Option<D> + 'static,
+    >(
+        &self,
+        f: F,
+    ) -> Option<D> {
+        let new_fn = |x: DynamicResult<&[T]>| {
+            let var: Option<D> = f(x);
+            var.map(|x| Box::new(x) as Box<dyn Any>)
+        };
+
+        let ptr = unsafe {
+            std::mem::transmute::<
+                &dyn Fn(DynamicResult<&[T]>) -> Option<Box<dyn Any>>,
+                (*const (), *const ()),
+            >(&new_fn as &dyn Fn(DynamicResult<&[T]>) -> Option<Box<dyn Any>>)
+        };
+
+        let t = TypeId::of::<dyn Fn(DynamicResult<&[T]>) -> Option<Box<dyn Any>> + 'static>();
+
+        let unit = self.unit_get::<T>();
+
+        if let Ok(x) = unit {
+            unsafe {
+                let val = x.run_for((t, ptr));
+                val.map(|x| *x.downcast().unwrap())
+            }
+        } else {
+            None
+        }
+    }
+
+    ///
+    /// Returns a clone of the `Arc<T>` stored in the storage, releasing the
+    /// borrow on it before returning. The handle can therefore be kept around
+    /// for as long as is needed without blocking other accesses.
+    ///
+    /// This follows the same rules as `BlackBox::get::<Arc<T>>`.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::{DynamicStorage, make_storage};
+    /// use std::sync::Arc;
+    /// let storage = make_storage!(DynamicStorage: Arc<String>);
+    /// storage.insert_shared(String::from("config")).unwrap();
+    /// let config = storage.get_shared::<String>().unwrap();
+    /// // No lock is held, so the value can be replaced at once
+    /// *storage.get_mut::<Arc<String>>().unwrap() = Arc::new(String::from("new"));
+    /// assert_eq!(&*config, "config");
+    /// # }
+    /// ```
+    ///
+    pub fn get_shared<T: 'static + Send + Sync>(&self) -> DynamicResult<Arc<T>> {
+        self.with_storage(|unit: &StorageUnit<Arc<T>>| unit.one().map(Arc::clone))?
+    }
+
+    ///
+    /// Wraps `data` in an `Arc` and inserts it, so that it can be retrieved
+    /// with `BlackBox::get_shared`. This requires a unit for `Arc<T>`.
+    ///
+    pub fn insert_shared<T: 'static + Send + Sync>(&self, data: T) -> Result<(), (T, ErrorDesc)> {
+        self.insert(Arc::new(data)).map_err(|(data, e)| {
+            // The `Arc` was never shared, so this is the only reference to it
+            (Arc::try_unwrap(data).ok().unwrap(), e)
+        })
+    }
+
+    ///
+    /// Runs `f` on the `StorageUnit` holding the `T`s, under a single mutable
+    /// borrow of the unit. This allows acting on whether there are no values,
+    /// one value or many of them without another thread changing that in
+    /// between.
+    ///
+    /// The unit is left in whichever state `f` leaves it in, so any invariants
+    /// the rest of the program expects from it are up to `f` to keep.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::{RwLockStorage, StorageUnit, make_storage};
+    /// let storage = make_storage!(RwLockStorage: usize);
+    /// let bump = |unit: &mut StorageUnit<usize>| match unit {
+    ///     StorageUnit::Nope => unit.insert(0),
+    ///     StorageUnit::One(x) => *x += 1,
+    ///     StorageUnit::Many(many) => many.iter_mut().for_each(|x| *x += 1),
+    /// };
+    /// storage.apply(bump).unwrap();
+    /// storage.apply(bump).unwrap();
+    /// assert_eq!(*storage.get::<usize>().unwrap(), 1);
+    /// storage.insert(5usize).unwrap();
+    /// storage.apply(bump).unwrap();
+    /// assert_eq!(storage.ind_multi::<usize>(&[0, 1]), Ok(vec![2, 6]));
+    /// # }
+    /// ```
+    ///
+    pub fn apply<T: 'static + Send, R, F: FnOnce(&mut StorageUnit<T>) -> R>(
+        &self,
+        f: F,
+    ) -> DynamicResult<R> {
+        traced!(
+            "apply",
+            T,
+            self.with_storage_mut(|unit| {
+                self.reshaped(TypeId::of::<T>());
+                f(unit)
+            })
+        )
+    }
+
+    ///
+    /// Runs `f` on the single value of `T` while holding an immutable borrow on
+    /// it, like `get`, but `f` may fail with its own error. Errors from the
+    /// storage are returned as `TryError::Storage`, and those of `f` as
+    /// `TryError::User`.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::{ErrorDesc, RwLockStorage, TryError, UnitError, make_storage};
+    /// let storage = make_storage!(RwLockStorage: String);
+    /// let parse = |x: &String| x.parse::<usize>();
+    /// assert_eq!(
+    ///     storage.try_with(parse),
+    ///     Err(TryError::Storage(ErrorDesc::Unit(UnitError::IsNotOne)))
+    /// );
+    /// storage.insert(String::from("12")).unwrap();
+    /// assert_eq!(storage.try_with(parse), Ok(12));
+    /// *storage.get_mut::<String>().unwrap() = String::from("twelve");
+    /// assert!(storage.try_with(parse).unwrap_err().user().is_some());
+    /// # }
+    /// ```
+    ///
+    pub fn try_with<T: 'static + Send, R, E, F: FnOnce(&T) -> Result<R, E>>(
+        &self,
+        f: F,
+    ) -> Result<R, TryError<E>> {
+        let result = traced!(
+            "try_with",
+            T,
+            self.with_storage(|unit: &StorageUnit<T>| unit.one().map(f))
+                .and_then(|x| x)
+        )?;
+        result.map_err(TryError::User)
+    }
+
+    ///
+    /// Runs `f` on each value of `T` in order while holding an immutable borrow
+    /// on them, stopping at the first error returned by `f`. See `try_with`.
+    ///
+    pub fn try_for_each<T: 'static + Send, E, F: FnMut(&T) -> Result<(), E>>(
+        &self,
+        f: F,
+    ) -> Result<(), TryError<E>> {
+        let result = traced!(
+            "try_for_each",
+            T,
+            self.with_storage(|unit: &StorageUnit<T>| unit.iter().try_for_each(f))
+        )?;
+        result.map_err(TryError::User)
+    }
+
+    ///
+    /// Runs `f` on each value of `T` in order while holding a mutable borrow on
+    /// them, stopping at the first error returned by `f`. The values `f` already
+    /// ran on keep their changes, and the unit is released as usual in either
+    /// case. A panic in `f` poisons the unit, but an error does not.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::{DynamicStorage, TryError, make_storage};
+    /// let storage = make_storage!(DynamicStorage: u8);
+    /// storage.insert_many(vec![100u8, 200, 250]).unwrap();
+    /// let result = storage.try_for_each_mut(|x: &mut u8| {
+    ///     *x = x.checked_add(50).ok_or("overflow")?;
+    ///     Ok(())
+    /// });
+    /// assert_eq!(result, Err(TryError::User("overflow")));
+    /// assert_eq!(*storage.ind::<u8>(0).unwrap(), 150);
+    /// assert_eq!(*storage.ind::<u8>(1).unwrap(), 250);
+    /// assert_eq!(*storage.ind::<u8>(2).unwrap(), 250);
+    /// # }
+    /// ```
+    ///
+    pub fn try_for_each_mut<T: 'static + Send, E, F: FnMut(&mut T) -> Result<(), E>>(
+        &self,
+        f: F,
+    ) -> Result<(), TryError<E>> {
+        let result = traced!(
+            "try_for_each_mut",
+            T,
+            self.with_storage_mut(|unit: &mut StorageUnit<T>| unit.iter_mut().try_for_each(f))
+        )?;
+        result.map_err(TryError::User)
+    }
+
+    ///
+    /// Replaces the single value of `T` with the result of `f`, without holding
+    /// a lock while `f` runs. The value is cloned out, passed to `f`, and the
+    /// result is only written back if the value is still equal to the clone,
+    /// retrying from the start otherwise, or if the unit is locked by someone
+    /// else. After `COMPOSE_ATTEMPTS` tries this gives up and returns
+    /// `ErrorDesc::Contended`.
+    ///
+    /// As `f` may be called several times, it shouldn't have side effects.
+    /// Any other error is wrapped in an `ErrorDesc::Context` telling whether it
+    /// occurred while reading the value or while writing back the new one.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::{RwLockStorage, make_storage};
+    /// let storage = make_storage!(RwLockStorage: usize);
+    /// storage.insert(20usize).unwrap();
+    /// storage.compose(|x: &usize| x * 2 + 2).unwrap();
+    /// assert_eq!(*storage.get::<usize>().unwrap(), 42);
+    /// # }
+    /// ```
+    ///
+    pub fn compose<T: 'static + Send + Clone + PartialEq, F: Fn(&T) -> T>(
+        &self,
+        f: F,
+    ) -> DynamicResult<()> {
+        traced!("compose", T, {
+            let mut result = Err(ErrorDesc::Contended);
+            for _ in 0..COMPOSE_ATTEMPTS {
+                match self.compose_once(&f) {
+                    Ok(false) | Err((_, ErrorDesc::BorrowedIncompatibly)) => {
+                        std::thread::yield_now()
+                    }
+                    done => {
+                        result = done.map(|_| ()).map_err(|(op, e)| e.context(op));
+                        break;
+                    }
+                }
+            }
+            result
+        })
+    }
+
+    ///
+    /// Internal function. Makes a single attempt at `compose`, returning
+    /// whether the new value was written, or the step which failed along with
+    /// its error.
+    ///
+    fn compose_once<T: 'static + Send + Clone + PartialEq, F: Fn(&T) -> T>(
+        &self,
+        f: &F,
+    ) -> Result<bool, (&'static str, ErrorDesc)> {
+        let current = self
+            .with_storage(|unit: &StorageUnit<T>| unit.one().cloned())
+            .and_then(|x| x)
+            .map_err(|e| ("reading the value to compose", e))?;
+        let new = f(&current);
+        self.with_storage_mut(|unit: &mut StorageUnit<T>| {
+            let value = unit.one_mut()?;
+            if *value == current {
+                *value = new;
+                Ok(true)
+            } else {
+                Ok(false)
+            }
+        })
+        .and_then(|x| x)
+        .map_err(|e| ("writing back the composed value", e))
+    }
+
     ///
-    /// - `RwLockStorage`: `RwLock`s
-    /// - `DynamicStorage`: `RefCell`s
+    /// Replaces every `T` in the storage with the result of calling `f` on it.
+    /// This happens under a single mutable borrow of the unit, and does not
+    /// reallocate the values in the case that there are many of them.
     ///
-    /// And so therefor it is not implemented in the case of `MutexStorage`
+    /// In the case that `f` panics, the value being mapped is lost, but the
+    /// rest are left in the storage in their original order and the unit is
+    /// no longer borrowed.
     ///
     /// # Example
     /// ```
     /// # fn main() {
     /// use restor::{DynamicStorage, make_storage};
     /// let storage = make_storage!(DynamicStorage: usize);
-    /// storage.insert(32usize).unwrap();
-    /// println!("{}", &*storage.get::<usize>().unwrap());
+    /// storage.insert_many(vec![1usize, 2, 3]).unwrap();
+    /// storage.map_in_place::<usize, _>(|x| x * 10).unwrap();
+    /// assert_eq!(*storage.ind::<usize>(2).unwrap(), 30);
     /// # }
     /// ```
     ///
-    #[inline]
-    pub fn get<'a, T: 'static + Send>(
-        &'a self,
-    ) -> DynamicResult<<Borrowed<'a, U> as Map<(dyn Any + Send), T>>::Output>
-    where
-        Borrowed<'a, U>: Map<(dyn Any + Send), T, Func = for<'b> fn(&'b (dyn Any + Send)) -> &'b T>,
-    {
-        Ok(self
-            .unit_get::<T>()?
-            .one()?
-            .map(|x| x.downcast_ref().unwrap()))
+    pub fn map_in_place<T: 'static + Send, F: FnMut(T) -> T>(&self, f: F) -> DynamicResult<()> {
+        self.with_storage_mut(|unit: &mut StorageUnit<T>| unit.map_in_place(f))
     }
-    #[inline]
-    pub fn ind<'a, T: 'static + Send>(
-        &'a self,
-        ind: usize,
-    ) -> DynamicResult<<Borrowed<'a, U> as Map<(dyn Any + Send), T>>::Output>
-    where
-        Borrowed<'a, U>: Map<(dyn Any + Send), T, Func = for<'b> fn(&'b (dyn Any + Send)) -> &'b T>,
-    {
-        Ok(self
-            .unit_get::<T>()?
-            .ind(ind)?
-            .map(|x| x.downcast_ref().unwrap()))
+
+    ///
+    /// Finds the index of the first `T` for which `pred` returns `true`.
+    ///
+    /// A single value is treated as being at index `0`, and an empty unit
+    /// results in `Ok(None)`. This returns an `Err` in the case that there
+    /// is no unit for `T` or it is borrowed incompatibly.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::{DynamicStorage, make_storage};
+    /// let storage = make_storage!(DynamicStorage: usize);
+    /// assert_eq!(storage.position::<usize, _>(|x| *x == 8), Ok(None));
+    /// storage.insert_many(vec![2usize, 4, 8, 16]).unwrap();
+    /// assert_eq!(storage.position::<usize, _>(|x| *x == 8), Ok(Some(2)));
+    /// # }
+    /// ```
+    ///
+    pub fn position<T: 'static + Send, F: FnMut(&T) -> bool>(
+        &self,
+        pred: F,
+    ) -> DynamicResult<Option<usize>> {
+        self.with_storage(|unit: &StorageUnit<T>| unit.as_slice().iter().position(pred))
     }
-    #[inline]
-    pub fn run_for<
-        'a,
-        T: 'static + Send,
-        D: 'static + Any,
-        F: Fn(DynamicResult<&[T]>) -> Option<D> + 'static,
-    >(
+
+    ///
+    /// Clones the first `T` for which `pred` returns `true` out of the storage,
+    /// so that no lock needs to be held once it has been found.
+    ///
+    /// This follows the same rules as `BlackBox::position`.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::{DynamicStorage, make_storage};
+    /// let storage = make_storage!(DynamicStorage: String);
+    /// storage.insert_many(vec!["abc".to_string(), "def".to_string()]).unwrap();
+    /// let found = storage.find_clone::<String, _>(|x| x.starts_with('d')).unwrap();
+    /// assert_eq!(found, Some("def".to_string()));
+    /// # }
+    /// ```
+    ///
+    pub fn find_clone<T: 'static + Send + Clone, F: FnMut(&T) -> bool>(
         &self,
-        f: F,
-    ) -> Option<D> {
-        let new_fn = |x: DynamicResult<&[T]>| {
-            let var: Option<D> = f(x);
-            var.map(|x| Box::new(x) as Box<dyn Any>)
-        };
+        mut pred: F,
+    ) -> DynamicResult<Option<T>> {
+        self.with_storage(|unit: &StorageUnit<T>| unit.as_slice().iter().find(|x| pred(x)).cloned())
+    }
 
-        let ptr = unsafe {
-            std::mem::transmute::<_, (*const (), *const ())>(
-                &new_fn as &dyn Fn(DynamicResult<&[T]>) -> Option<Box<dyn Any>>,
-            )
-        };
+    ///
+    /// Clones every `T` out of the storage and returns an iterator over the
+    /// clones, so that the unit is only borrowed while they are being cloned.
+    /// A unit with no values gives an empty iterator.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::{RwLockStorage, make_storage};
+    /// let storage = make_storage!(RwLockStorage: usize);
+    /// storage.insert_many(vec![1usize, 2, 3]).unwrap();
+    /// for x in storage.cloned_iter::<usize>().unwrap() {
+    ///     // The unit isn't borrowed here
+    ///     storage.insert(x * 10).unwrap();
+    /// }
+    /// assert_eq!(storage.ind_multi::<usize>(&[3, 4, 5]), Ok(vec![10, 20, 30]));
+    /// # }
+    /// ```
+    ///
+    pub fn cloned_iter<T: 'static + Send + Clone>(&self) -> DynamicResult<std::vec::IntoIter<T>> {
+        self.with_storage(|unit: &StorageUnit<T>| unit.as_slice().to_vec().into_iter())
+    }
 
-        let t = TypeId::of::<(dyn Fn(DynamicResult<&[T]>) -> Option<Box<dyn Any>> + 'static)>();
+    ///
+    /// Clones the values of `T` out of the storage `chunk` values at a time,
+    /// borrowing the unit once per chunk, which bounds both the memory used
+    /// and how long the unit stays borrowed. See `ClonedChunks` for what is
+    /// seen when the unit is changed in between chunks.
+    ///
+    /// # Panics
+    /// Panics if `chunk` is `0`.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::{DynamicStorage, make_storage};
+    /// let storage = make_storage!(DynamicStorage: usize);
+    /// storage.insert_many((0..5usize).collect()).unwrap();
+    /// let chunks = storage
+    ///     .cloned_iter_chunked::<usize>(2)
+    ///     .unwrap()
+    ///     .collect::<Result<Vec<_>, _>>()
+    ///     .unwrap();
+    /// assert_eq!(chunks, vec![vec![0, 1], vec![2, 3], vec![4]]);
+    /// # }
+    /// ```
+    ///
+    pub fn cloned_iter_chunked<T: 'static + Send + Clone>(
+        &self,
+        chunk: usize,
+    ) -> DynamicResult<ClonedChunks<'_, U, T>> {
+        self.unit_get::<T>()?;
+        Ok(ClonedChunks::new(self, chunk))
+    }
 
-        let unit = self.unit_get::<T>();
+    ///
+    /// Runs `f` on the values at each of `indices`, in the order given, while
+    /// holding a single borrow on the unit. Indices may repeat. All of them are
+    /// checked before `f` is run, returning the error for the first invalid one.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::{ErrorDesc, MutexStorage, UnitError, make_storage};
+    /// let storage = make_storage!(MutexStorage: String);
+    /// storage.insert_many(vec!["a".to_string(), "b".to_string(), "c".to_string()]).unwrap();
+    /// let joined = storage.with_ind_multi::<String, _, _>(&[2, 0, 2], |x| {
+    ///     x.iter().map(|s| s.as_str()).collect::<String>()
+    /// });
+    /// assert_eq!(joined, Ok("cac".to_string()));
+    /// assert_eq!(
+    ///     storage.with_ind_multi::<String, _, _>(&[0, 3], |x| x.len()),
+    ///     Err(ErrorDesc::Unit(UnitError::OutOfBounds))
+    /// );
+    /// # }
+    /// ```
+    ///
+    pub fn with_ind_multi<T: 'static + Send, R, F: FnOnce(&[&T]) -> R>(
+        &self,
+        indices: &[usize],
+        f: F,
+    ) -> DynamicResult<R> {
+        self.with_storage(|unit: &StorageUnit<T>| {
+            let values = indices
+                .iter()
+                .map(|&ind| unit.ind(ind))
+                .collect::<DynamicResult<Vec<_>>>()?;
+            Ok(f(&values))
+        })
+        .and_then(|x| x)
+    }
 
-        if let Ok(x) = unit {
-            unsafe {
-                let val = x.run_for((t, ptr));
-                val.map(|x| *x.downcast().unwrap())
-            }
-        } else {
-            None
-        }
+    ///
+    /// Clones the values at each of `indices`, in the order given, while
+    /// holding a single borrow on the unit, see `with_ind_multi`.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::{RwLockStorage, make_storage};
+    /// let storage = make_storage!(RwLockStorage: usize);
+    /// storage.insert_many(vec![10usize, 20, 30]).unwrap();
+    /// assert_eq!(storage.ind_multi::<usize>(&[1, 1, 0]), Ok(vec![20, 20, 10]));
+    /// # }
+    /// ```
+    ///
+    pub fn ind_multi<T: 'static + Send + Clone>(&self, indices: &[usize]) -> DynamicResult<Vec<T>> {
+        self.with_ind_multi(indices, |values: &[&T]| {
+            values.iter().map(|&x| x.clone()).collect()
+        })
     }
 }
 
 impl
     BlackBox<
-        (dyn for<'a> Unit<
-            'a,
-            Borrowed = MappedRwLockReadGuard<'a, (dyn Any + Send)>,
-            MutBorrowed = MappedRwLockWriteGuard<'a, (dyn Any + Send)>,
-            Owned = Box<(dyn Any + Send)>,
-        > + Send),
+        dyn for<'a> Unit<
+                'a,
+                Borrowed = MappedRwLockReadGuard<'a, dyn Any + Send>,
+                MutBorrowed = MappedRwLockWriteGuard<'a, dyn Any + Send>,
+                Owned = Box<dyn Any + Send>,
+            > + Send,
     >
 {
+    ///
+    /// Allocates a unit for `T`, unless there already is one. The unit is
+    /// registered under the name of `T`, as given by `std::any::type_name`,
+    /// unless another type was already given that name with
+    /// `allocate_named_for`, in which case the name is skipped and the unit can
+    /// only be reached by its type. With the `tracing` feature, a skipped name
+    /// is reported as a warning.
+    ///
     #[inline]
     pub fn allocate_for<T: 'static + Send>(&mut self) {
-        self.data
-            .entry(TypeId::of::<T>())
-            .or_insert_with(|| Box::new(RwLockUnit::new(StorageUnit::<T>::new())));
+        self.allocate_unit::<T, _>(|| Box::new(RwLockUnit::new(StorageUnit::<T>::new())));
+    }
+
+    ///
+    /// Allocates a unit for `T` like `allocate_for`, which settles borrows
+    /// between readers and writers according to `fairness`, see `Fairness`.
+    /// This does nothing in the case that there already is a unit for `T`.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::{ErrorDesc, Fairness, RwLockStorage};
+    /// let mut storage = RwLockStorage::new();
+    /// storage.allocate_for_with_fairness::<usize>(Fairness::WriterPriority);
+    /// storage.insert(0usize).unwrap();
+    ///
+    /// let reading = storage.get::<usize>().unwrap();
+    /// assert_eq!(storage.get_mut::<usize>().err(), Some(ErrorDesc::BorrowedIncompatibly));
+    /// // The writer which failed holds off new readers
+    /// assert_eq!(storage.get::<usize>().err(), Some(ErrorDesc::BorrowedIncompatibly));
+    /// drop(reading);
+    /// *storage.get_mut::<usize>().unwrap() += 1;
+    /// assert_eq!(*storage.get::<usize>().unwrap(), 1);
+    /// # }
+    /// ```
+    ///
+    pub fn allocate_for_with_fairness<T: 'static + Send + Sync>(&mut self, fairness: Fairness) {
+        self.allocate_unit::<T, _>(|| {
+            Box::new(RwLockUnit::with_fairness(StorageUnit::<T>::new(), fairness))
+        });
+    }
+
+    ///
+    /// Allocates a unit for `T` which stores its values in `vec`, keeping the
+    /// values already in it, and reuses its capacity rather than allocating.
+    /// Like `allocate_for`, this does nothing in the case that there already is
+    /// a unit for `T`, and `vec` is dropped; see `swap_backing_vec` to give a
+    /// buffer to an existing unit.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::RwLockStorage;
+    /// let mut storage = RwLockStorage::new();
+    /// storage.allocate_for_with_vec::<usize>(Vec::with_capacity(1024));
+    /// storage.insert_many(vec![1usize, 2, 3]).unwrap();
+    /// assert_eq!(storage.capacity::<usize>(), Ok(1024));
+    /// # }
+    /// ```
+    ///
+    pub fn allocate_for_with_vec<T: 'static + Send>(&mut self, vec: Vec<T>) {
+        self.allocate_unit_with_vec(vec, |unit| Box::new(RwLockUnit::new(unit)));
+    }
+
+    ///
+    /// Allocates a unit for `T`, like `allocate_for`, and additionally
+    /// registers it under `name`. This returns an `ErrorDesc::NameCollision`
+    /// without allocating anything in the case that `name` already refers to
+    /// the unit of another type.
+    ///
+    pub fn allocate_named_for<T: 'static + Send>(&mut self, name: &str) -> DynamicResult<()> {
+        self.register_name::<T>(name)?;
+        self.allocate_for::<T>();
+        Ok(())
+    }
+
+    ///
+    /// Allocates a value of `T` which is read without taking a lock, through
+    /// `load`, and is only ever replaced as a whole, through `store`. Borrowing
+    /// it through the other methods, such as `get_mut`, returns
+    /// `ErrorDesc::UnsupportedOperation`. This does nothing in the case that
+    /// there is already a unit for `T`.
+    ///
+    /// # Note
+    /// A replaced value is only released by a later `store` which finds no
+    /// `load` in progress, or once the storage is dropped, as a reader may
+    /// still be cloning it. Under a steady stream of loads, replaced values
+    /// can pile up in between, so this is meant for values which are rarely
+    /// replaced.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::{ErrorDesc, RwLockStorage};
+    /// let mut storage = RwLockStorage::new();
+    /// storage.allocate_read_mostly_for::<String>();
+    /// storage.store(String::from("config")).unwrap();
+    /// assert_eq!(&*storage.load::<String>().unwrap(), "config");
+    /// assert_eq!(
+    ///     storage.get_mut::<String>().err(),
+    ///     Some(ErrorDesc::UnsupportedOperation)
+    /// );
+    /// # }
+    /// ```
+    ///
+    pub fn allocate_read_mostly_for<T: 'static + Send + Sync>(&mut self) {
+        if !self.has_unit::<T>() {
+            self.read_mostly.get_or_insert_with(TypeId::of::<T>(), || {
+                (std::any::type_name::<T>(), Box::new(ReadMostly::<T>::new()))
+            });
+        }
+    }
+
+    ///
+    /// Returns the value of `T` allocated with `allocate_read_mostly_for`,
+    /// without taking a lock. This returns an `Err` in the case that nothing
+    /// has been stored yet.
+    ///
+    #[inline]
+    pub fn load<T: 'static + Send + Sync>(&self) -> DynamicResult<Arc<T>> {
+        self.read_mostly_get::<T>()?
+            .load()
+            .ok_or(ErrorDesc::Unit(UnitError::IsNone))
+    }
+
+    ///
+    /// Replaces the value of `T` allocated with `allocate_read_mostly_for`,
+    /// returning the previous value if there was one. Readers see either the
+    /// previous value or `value`, never anything in between.
+    ///
+    pub fn store<T: 'static + Send + Sync>(
+        &self,
+        value: T,
+    ) -> Result<Option<Arc<T>>, (T, ErrorDesc)> {
+        match self.read_mostly_get::<T>() {
+            Ok(unit) => Ok(unit.store(value)),
+            Err(e) => Err((value, e)),
+        }
     }
 }
 
 impl
     BlackBox<
-        (dyn for<'a> Unit<
-            'a,
-            Borrowed = MappedMutexGuard<'a, (dyn Any + Send)>,
-            MutBorrowed = MappedMutexGuard<'a, (dyn Any + Send)>,
-            Owned = Box<(dyn Any + Send)>,
-        > + Send),
+        dyn for<'a> Unit<
+                'a,
+                Borrowed = MappedMutexGuard<'a, dyn Any + Send>,
+                MutBorrowed = MappedMutexGuard<'a, dyn Any + Send>,
+                Owned = Box<dyn Any + Send>,
+            > + Send,
     >
 {
+    ///
+    /// Creates a storage which remembers the thread holding the lock on each
+    /// unit, so that a thread failing to lock a unit it already holds gets an
+    /// `ErrorDesc::RecursiveBorrow` rather than `ErrorDesc::BorrowedIncompatibly`.
+    /// This is meant for tracking down such bugs, as it takes an extra lock on
+    /// every access.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::{ErrorDesc, MutexStorage};
+    /// let mut storage = MutexStorage::new_debug();
+    /// storage.allocate_for::<usize>();
+    /// storage.insert(0usize).unwrap();
+    /// let lock = storage.get_mut::<usize>().unwrap();
+    /// assert_eq!(storage.get_mut::<usize>().err(), Some(ErrorDesc::RecursiveBorrow));
+    /// # }
+    /// ```
+    ///
+    pub fn new_debug() -> Self {
+        let mut storage = Self::new();
+        storage.track_holders = true;
+        storage
+    }
+
+    ///
+    /// Allocates a unit for `T`, unless there already is one. The unit is
+    /// registered under the name of `T` unless another type already uses it,
+    /// like for `RwLockStorage::allocate_for`.
+    ///
     #[inline]
     pub fn allocate_for<T: 'static + Send>(&mut self) {
-        self.data
-            .entry(TypeId::of::<T>())
-            .or_insert_with(|| Box::new(MutexUnit::new(StorageUnit::<T>::new())));
+        let tracked = self.track_holders;
+        self.allocate_unit::<T, _>(|| {
+            Box::new(if tracked {
+                MutexUnit::new_tracked(StorageUnit::<T>::new())
+            } else {
+                MutexUnit::new(StorageUnit::<T>::new())
+            })
+        });
+    }
+
+    ///
+    /// Allocates a unit for `T` which stores its values in `vec`, keeping the
+    /// values already in it, and reuses its capacity rather than allocating.
+    /// Like `allocate_for`, this does nothing in the case that there already is
+    /// a unit for `T`, and `vec` is dropped; see `swap_backing_vec` to give a
+    /// buffer to an existing unit.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::MutexStorage;
+    /// let mut storage = MutexStorage::new();
+    /// storage.allocate_for_with_vec::<usize>(Vec::with_capacity(1024));
+    /// storage.insert_many(vec![1usize, 2, 3]).unwrap();
+    /// assert_eq!(storage.capacity::<usize>(), Ok(1024));
+    /// # }
+    /// ```
+    ///
+    pub fn allocate_for_with_vec<T: 'static + Send>(&mut self, vec: Vec<T>) {
+        let tracked = self.track_holders;
+        self.allocate_unit_with_vec(vec, |unit| {
+            Box::new(if tracked {
+                MutexUnit::new_tracked(unit)
+            } else {
+                MutexUnit::new(unit)
+            })
+        });
+    }
+
+    ///
+    /// Allocates a unit for `T`, like `allocate_for`, and additionally
+    /// registers it under `name`. This returns an `ErrorDesc::NameCollision`
+    /// without allocating anything in the case that `name` already refers to
+    /// the unit of another type.
+    ///
+    pub fn allocate_named_for<T: 'static + Send>(&mut self, name: &str) -> DynamicResult<()> {
+        self.register_name::<T>(name)?;
+        self.allocate_for::<T>();
+        Ok(())
+    }
+
+    ///
+    /// Allocates a value of `T` which is read without taking a lock, through
+    /// `load`, and is only ever replaced as a whole, through `store`. Borrowing
+    /// it through the other methods, such as `get_mut`, returns
+    /// `ErrorDesc::UnsupportedOperation`. This does nothing in the case that
+    /// there is already a unit for `T`.
+    ///
+    /// # Note
+    /// A replaced value is only released by a later `store` which finds no
+    /// `load` in progress, or once the storage is dropped, as a reader may
+    /// still be cloning it. Under a steady stream of loads, replaced values
+    /// can pile up in between, so this is meant for values which are rarely
+    /// replaced.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::{ErrorDesc, MutexStorage};
+    /// let mut storage = MutexStorage::new();
+    /// storage.allocate_read_mostly_for::<String>();
+    /// storage.store(String::from("config")).unwrap();
+    /// assert_eq!(&*storage.load::<String>().unwrap(), "config");
+    /// assert_eq!(
+    ///     storage.get_mut::<String>().err(),
+    ///     Some(ErrorDesc::UnsupportedOperation)
+    /// );
+    /// # }
+    /// ```
+    ///
+    pub fn allocate_read_mostly_for<T: 'static + Send + Sync>(&mut self) {
+        if !self.has_unit::<T>() {
+            self.read_mostly.get_or_insert_with(TypeId::of::<T>(), || {
+                (std::any::type_name::<T>(), Box::new(ReadMostly::<T>::new()))
+            });
+        }
+    }
+
+    ///
+    /// Returns the value of `T` allocated with `allocate_read_mostly_for`,
+    /// without taking a lock. This returns an `Err` in the case that nothing
+    /// has been stored yet.
+    ///
+    #[inline]
+    pub fn load<T: 'static + Send + Sync>(&self) -> DynamicResult<Arc<T>> {
+        self.read_mostly_get::<T>()?
+            .load()
+            .ok_or(ErrorDesc::Unit(UnitError::IsNone))
+    }
+
+    ///
+    /// Replaces the value of `T` allocated with `allocate_read_mostly_for`,
+    /// returning the previous value if there was one. Readers see either the
+    /// previous value or `value`, never anything in between.
+    ///
+    pub fn store<T: 'static + Send + Sync>(
+        &self,
+        value: T,
+    ) -> Result<Option<Arc<T>>, (T, ErrorDesc)> {
+        match self.read_mostly_get::<T>() {
+            Ok(unit) => Ok(unit.store(value)),
+            Err(e) => Err((value, e)),
+        }
     }
 }
 
 impl
     BlackBox<
-        (dyn for<'a> Unit<
+        dyn for<'a> Unit<
             'a,
-            Borrowed = Ref<'a, (dyn Any + Send)>,
-            MutBorrowed = RefMut<'a, (dyn Any + Send)>,
-            Owned = Box<(dyn Any + Send)>,
-        >),
+            Borrowed = Ref<'a, dyn Any + Send>,
+            MutBorrowed = RefMut<'a, dyn Any + Send>,
+            Owned = Box<dyn Any + Send>,
+        >,
     >
 {
+    ///
+    /// Allocates a unit for `T`, unless there already is one. The unit is
+    /// registered under the name of `T` unless another type already uses it,
+    /// like for `RwLockStorage::allocate_for`.
+    ///
     #[inline]
     pub fn allocate_for<T: 'static + Send>(&mut self) {
-        self.data
-            .entry(TypeId::of::<T>())
-            .or_insert_with(|| Box::new(RefCellUnit::new(StorageUnit::<T>::new())));
+        self.allocate_unit::<T, _>(|| Box::new(RefCellUnit::new(StorageUnit::<T>::new())));
+    }
+
+    ///
+    /// Allocates a unit for `T` which stores its values in `vec`, keeping the
+    /// values already in it, and reuses its capacity rather than allocating.
+    /// Like `allocate_for`, this does nothing in the case that there already is
+    /// a unit for `T`, and `vec` is dropped; see `swap_backing_vec` to give a
+    /// buffer to an existing unit.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::DynamicStorage;
+    /// let mut storage = DynamicStorage::new();
+    /// storage.allocate_for_with_vec::<usize>(Vec::with_capacity(1024));
+    /// storage.insert_many(vec![1usize, 2, 3]).unwrap();
+    /// assert_eq!(storage.capacity::<usize>(), Ok(1024));
+    /// # }
+    /// ```
+    ///
+    pub fn allocate_for_with_vec<T: 'static + Send>(&mut self, vec: Vec<T>) {
+        self.allocate_unit_with_vec(vec, |unit| Box::new(RefCellUnit::new(unit)));
+    }
+
+    ///
+    /// Allocates a unit for `T`, like `allocate_for`, whose values are shared
+    /// with the forks of the storage rather than copied, see `fork`. The values
+    /// are cloned by whichever storage mutates them first after a fork.
+    ///
+    pub fn allocate_clonable_for<T: 'static + Send + Clone>(&mut self) {
+        self.allocate_unit::<T, _>(|| Box::new(CowUnit::new(StorageUnit::<T>::new())));
+    }
+
+    ///
+    /// Allocates a unit for `T`, like `allocate_for`, which keeps track of the
+    /// values which are inserted or borrowed mutably, so that only those can be
+    /// handled with `take_dirty`. See `DirtyUnit` for when values are marked.
+    ///
+    pub fn allocate_tracked_for<T: 'static + Send>(&mut self) {
+        self.allocate_unit::<T, _>(|| Box::new(DirtyUnit::new(StorageUnit::<T>::new())));
+    }
+
+    ///
+    /// Allocates a unit for `T`, like `allocate_for`, and additionally
+    /// registers it under `name`. This returns an `ErrorDesc::NameCollision`
+    /// without allocating anything in the case that `name` already refers to
+    /// the unit of another type.
+    ///
+    pub fn allocate_named_for<T: 'static + Send>(&mut self, name: &str) -> DynamicResult<()> {
+        self.register_name::<T>(name)?;
+        self.allocate_for::<T>();
+        Ok(())
     }
 }
 
 unsafe impl Send
     for BlackBox<
-        (dyn for<'a> Unit<
-            'a,
-            Borrowed = MappedMutexGuard<'a, (dyn Any + Send)>,
-            MutBorrowed = MappedMutexGuard<'a, (dyn Any + Send)>,
-            Owned = Box<(dyn Any + Send)>,
-        > + Send),
+        dyn for<'a> Unit<
+                'a,
+                Borrowed = MappedMutexGuard<'a, dyn Any + Send>,
+                MutBorrowed = MappedMutexGuard<'a, dyn Any + Send>,
+                Owned = Box<dyn Any + Send>,
+            > + Send,
     >
 {
 }
 
 unsafe impl Sync
     for BlackBox<
-        (dyn for<'a> Unit<
-            'a,
-            Borrowed = MappedMutexGuard<'a, (dyn Any + Send)>,
-            MutBorrowed = MappedMutexGuard<'a, (dyn Any + Send)>,
-            Owned = Box<(dyn Any + Send)>,
-        > + Send),
+        dyn for<'a> Unit<
+                'a,
+                Borrowed = MappedMutexGuard<'a, dyn Any + Send>,
+                MutBorrowed = MappedMutexGuard<'a, dyn Any + Send>,
+                Owned = Box<dyn Any + Send>,
+            > + Send,
     >
 {
 }
 
 unsafe impl Send
     for BlackBox<
-        (dyn for<'a> Unit<
-            'a,
-            Borrowed = MappedRwLockReadGuard<'a, (dyn Any + Send)>,
-            MutBorrowed = MappedRwLockWriteGuard<'a, (dyn Any + Send)>,
-            Owned = Box<(dyn Any + Send)>,
-        > + Send),
+        dyn for<'a> Unit<
+                'a,
+                Borrowed = MappedRwLockReadGuard<'a, dyn Any + Send>,
+                MutBorrowed = MappedRwLockWriteGuard<'a, dyn Any + Send>,
+                Owned = Box<dyn Any + Send>,
+            > + Send,
     >
 {
 }
 
 unsafe impl Sync
     for BlackBox<
-        (dyn for<'a> Unit<
-            'a,
-            Borrowed = MappedRwLockReadGuard<'a, (dyn Any + Send)>,
-            MutBorrowed = MappedRwLockWriteGuard<'a, (dyn Any + Send)>,
-            Owned = Box<(dyn Any + Send)>,
-        > + Send),
+        dyn for<'a> Unit<
+                'a,
+                Borrowed = MappedRwLockReadGuard<'a, dyn Any + Send>,
+                MutBorrowed = MappedRwLockWriteGuard<'a, dyn Any + Send>,
+                Owned = Box<dyn Any + Send>,
+            > + Send,
     >
 {
 }
@@ -0,0 +1,145 @@
+//! Mutating the values of a unit from many threads at once, see
+//! `RwLockStorage::scope_chunks_mut`.
+#[cfg(feature = "tracing")]
+use super::trace;
+use super::{BlackBox, DynamicResult, StorageUnit, Unit};
+use crate::{MutexStorage, RwLockStorage};
+use parking_lot::Mutex;
+use std::any::Any;
+use std::num::NonZeroUsize;
+use std::thread;
+
+impl<U: ?Sized + for<'a> Unit<'a, Owned = Box<dyn Any + Send>>> BlackBox<U> {
+    ///
+    /// Internal function. Runs `f` on every chunk of the values of `T` from
+    /// up to `threads` scoped threads, which take the next chunk as soon as
+    /// they are done with one, while holding a mutable borrow on the unit.
+    ///
+    fn par_chunks_mut<T: 'static + Send, F: Fn(usize, &mut [T]) + Sync>(
+        &self,
+        threads: usize,
+        chunk_size: usize,
+        f: F,
+    ) -> DynamicResult<()> {
+        assert!(chunk_size != 0, "chunk_size must not be zero");
+        traced!(
+            "scope_chunks_mut",
+            T,
+            self.with_storage_mut(|unit: &mut StorageUnit<T>| {
+                let values = unit.as_mut_slice();
+                let workers = threads.min(values.len().div_ceil(chunk_size));
+                if workers <= 1 {
+                    for (i, chunk) in values.chunks_mut(chunk_size).enumerate() {
+                        f(i, chunk);
+                    }
+                    return;
+                }
+                let chunks = Mutex::new(values.chunks_mut(chunk_size).enumerate());
+                let panics = thread::scope(|scope| {
+                    let workers = (0..workers)
+                        .map(|_| {
+                            scope.spawn(|| loop {
+                                // Taken in its own statement, so that the lock is
+                                // released before `f` runs on the chunk
+                                let next = chunks.lock().next();
+                                match next {
+                                    Some((i, chunk)) => f(i, chunk),
+                                    None => break,
+                                }
+                            })
+                        })
+                        .collect::<Vec<_>>();
+                    // Every worker is joined, so that the scope doesn't panic on its own
+                    workers
+                        .into_iter()
+                        .filter_map(|worker| worker.join().err())
+                        .collect::<Vec<_>>()
+                });
+                // Resumed while the unit is borrowed, so that it is poisoned
+                // before anyone else can borrow it
+                if let Some(payload) = panics.into_iter().next() {
+                    std::panic::resume_unwind(payload);
+                }
+            })
+        )
+    }
+}
+
+macro_rules! impl_scope_chunks_mut {
+    ($storage:ty) => {
+        impl $storage {
+            ///
+            /// Splits the values of `T` into chunks of `chunk_size` values, the
+            /// last of which may be shorter, and runs `f` on each chunk along
+            /// with its index from as many threads as
+            /// `std::thread::available_parallelism` gives. The unit is borrowed
+            /// mutably once, for as long as it takes to go through every chunk,
+            /// and each chunk is only ever given to a single thread.
+            ///
+            /// The threads take the next chunk as soon as they are done with
+            /// one, so a chunk which takes longer doesn't hold up the others.
+            /// In the case that there is a single chunk, or that a single
+            /// thread is available, `f` runs on the calling thread instead.
+            ///
+            /// A panic in `f` is resumed on the calling thread once every
+            /// thread is done, while the unit is still borrowed, so that the
+            /// unit is poisoned like by any other mutation which panics, and
+            /// then released.
+            ///
+            /// This is only implemented for the storages which can be shared
+            /// between threads, `RwLockStorage` and `MutexStorage`.
+            ///
+            /// # Panics
+            /// In the case that `chunk_size` is zero, or that `f` panics.
+            ///
+            /// # Example
+            /// ```
+            /// # fn main() {
+            /// use restor::{make_storage, RwLockStorage};
+            /// let storage = make_storage!(RwLockStorage: u64);
+            /// storage.insert_many((0..10_000u64).collect()).unwrap();
+            /// storage
+            ///     .scope_chunks_mut(1024, |i, chunk: &mut [u64]| {
+            ///         // Chunk `i` starts at the value at `i * 1024`
+            ///         assert_eq!(chunk[0], i as u64 * 1024);
+            ///         chunk.iter_mut().for_each(|x| *x *= 2);
+            ///     })
+            ///     .unwrap();
+            /// assert_eq!(*storage.ind::<u64>(5000).unwrap(), 10_000);
+            /// # }
+            /// ```
+            ///
+            /// ```compile_fail
+            /// # use restor::{make_storage, DynamicStorage};
+            /// # fn main() {
+            /// let storage = make_storage!(DynamicStorage: u64);
+            /// storage.scope_chunks_mut(16, |_, chunk: &mut [u64]| chunk[0] = 0);
+            /// # }
+            /// ```
+            ///
+            pub fn scope_chunks_mut<T: 'static + Send, F: Fn(usize, &mut [T]) + Sync>(
+                &self,
+                chunk_size: usize,
+                f: F,
+            ) -> DynamicResult<()> {
+                let threads = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+                self.par_chunks_mut(threads, chunk_size, f)
+            }
+
+            ///
+            /// Like `scope_chunks_mut`, but with up to `threads` threads.
+            ///
+            pub fn scope_chunks_mut_on<T: 'static + Send, F: Fn(usize, &mut [T]) + Sync>(
+                &self,
+                threads: NonZeroUsize,
+                chunk_size: usize,
+                f: F,
+            ) -> DynamicResult<()> {
+                self.par_chunks_mut(threads.get(), chunk_size, f)
+            }
+        }
+    };
+}
+
+impl_scope_chunks_mut!(RwLockStorage);
+impl_scope_chunks_mut!(MutexStorage);
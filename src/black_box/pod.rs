@@ -0,0 +1,357 @@
+//! A compact binary snapshot of the units holding plain data, see
+//! `BlackBox::dump_pod` and `BlackBox::restore_pod`.
+#[cfg(feature = "events")]
+use super::StorageEvent;
+use super::{
+    AllocateSet, BlackBox, BufferedUnit, DynamicResult, ErrorDesc, StorageUnit, Unit, ValueChange,
+};
+use std::any::{Any, TypeId};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::io::{self, Read, Write};
+use std::mem::size_of;
+
+/// The bytes every dump starts with.
+const MAGIC: [u8; 4] = *b"RPOD";
+/// The version of the format, which is written after `MAGIC`.
+const VERSION: u32 = 1;
+
+///
+/// A type whose values are plain bytes, which can be dumped by copying them
+/// and restored from any bytes of the right length, see `BlackBox::dump_pod`.
+///
+/// This is the contract of `bytemuck::Pod`, and is implemented for the
+/// integer and floating point types and arrays of them.
+///
+/// # Safety
+/// Implementing this asserts that the type is inhabited, has no padding, has
+/// no pointers or references, and that any bit pattern is a valid value, as
+/// is the case of a `#[repr(C)]` struct of fields which are all `Pod` without
+/// any padding in between them.
+///
+pub unsafe trait Pod: Copy + Send + Sync + 'static {}
+
+macro_rules! impl_pod {
+    ($($t:ty),*) => {
+        $(unsafe impl Pod for $t {})*
+    };
+}
+
+impl_pod!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64);
+
+unsafe impl<T: Pod, const N: usize> Pod for [T; N] {}
+
+///
+/// A unit allocated with `BlackBox::allocate_pod_for`.
+///
+pub(crate) struct PodUnit<U: ?Sized> {
+    /// The tag the unit is dumped under.
+    tag: &'static str,
+    /// The size of a single value.
+    size: usize,
+    /// Writes the number of values followed by their bytes, under an immutable borrow.
+    dump: fn(&BlackBox<U>, &mut dyn Write) -> DynamicResult<io::Result<()>>,
+    /// Replaces the values by the given number of values read from the bytes.
+    restore: fn(&BlackBox<U>, usize, &[u8]) -> DynamicResult<()>,
+}
+
+impl<U: ?Sized> Clone for PodUnit<U> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<U: ?Sized> Copy for PodUnit<U> {}
+
+///
+/// The reasons `BlackBox::restore_pod` can fail for.
+///
+#[derive(Debug)]
+pub enum PodError {
+    /// The dump couldn't be read, or was cut short.
+    Io(io::Error),
+    /// The dump doesn't start with the bytes every dump starts with.
+    NotADump,
+    /// The dump was written in another version of the format, or on a
+    /// machine of the other endianness.
+    UnsupportedVersion(u32),
+    /// The values dumped under `tag` have another size than the values of
+    /// the type which is allocated under `tag`.
+    SizeMismatch {
+        tag: String,
+        expected: usize,
+        found: usize,
+    },
+    /// The unit tagged `tag` couldn't be filled, see `BlackBox::restore_pod`.
+    Storage { tag: String, error: ErrorDesc },
+}
+
+impl Display for PodError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            PodError::Io(e) => write!(f, "the dump couldn't be read: {}", e),
+            PodError::NotADump => write!(f, "the data isn't a dump of plain data units"),
+            PodError::UnsupportedVersion(version) => write!(
+                f,
+                "the dump is of version {}, where version {} is supported",
+                version, VERSION
+            ),
+            PodError::SizeMismatch {
+                tag,
+                expected,
+                found,
+            } => write!(
+                f,
+                "the values tagged `{}` are {} bytes each, but {} bytes were dumped for each",
+                tag, expected, found
+            ),
+            PodError::Storage { tag, error } => {
+                write!(f, "the unit tagged `{}` couldn't be filled: {}", tag, error)
+            }
+        }
+    }
+}
+
+impl Error for PodError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PodError::Io(e) => Some(e),
+            PodError::Storage { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for PodError {
+    fn from(e: io::Error) -> Self {
+        PodError::Io(e)
+    }
+}
+
+fn dump<T: Pod, U: ?Sized + for<'a> Unit<'a, Owned = Box<dyn Any + Send>>>(
+    storage: &BlackBox<U>,
+    w: &mut dyn Write,
+) -> DynamicResult<io::Result<()>> {
+    storage.with_storage(|unit: &StorageUnit<T>| {
+        let values = unit.as_slice();
+        w.write_all(&(values.len() as u64).to_ne_bytes())?;
+        // Safe as `T: Pod` has no padding, so every byte of the values is initialized
+        let bytes = unsafe {
+            std::slice::from_raw_parts(values.as_ptr() as *const u8, std::mem::size_of_val(values))
+        };
+        w.write_all(bytes)
+    })
+}
+
+fn restore<T: Pod, U: ?Sized + for<'a> Unit<'a, Owned = Box<dyn Any + Send>>>(
+    storage: &BlackBox<U>,
+    count: usize,
+    bytes: &[u8],
+) -> DynamicResult<()> {
+    debug_assert_eq!(Some(bytes.len()), count.checked_mul(size_of::<T>()));
+    let mut values = Vec::<T>::with_capacity(count);
+    // Safe as `T: Pod` is valid for any bytes, and `bytes` holds `count` values
+    unsafe {
+        std::ptr::copy_nonoverlapping(bytes.as_ptr(), values.as_mut_ptr() as *mut u8, bytes.len());
+        values.set_len(count);
+    }
+    let mut previous = storage
+        .insert_with(values, |unit: &mut BufferedUnit<T>, mut values| {
+            let previous = unit.extract_any_count();
+            if !previous.is_empty() {
+                notify!(storage, T, Cleared {});
+            }
+            if values.len() == 1 {
+                unit.insert(values.pop().unwrap());
+            } else {
+                unit.insert_many(values);
+            }
+            for index in 0..unit.len() {
+                notify!(storage, T, Inserted { index: index });
+            }
+            previous
+        })
+        .map_err(|(_, e)| e.context("replacing the values of the unit"))?;
+    storage.finalize(&mut previous);
+    Ok(())
+}
+
+fn read_u64(r: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    r.read_exact(&mut bytes)?;
+    Ok(u64::from_ne_bytes(bytes))
+}
+
+///
+/// Reads exactly `len` bytes, without trusting `len` enough to allocate them
+/// all up front.
+///
+fn read_bytes(r: &mut impl Read, len: u64) -> io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    if r.by_ref().take(len).read_to_end(&mut bytes)? as u64 != len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(bytes)
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+impl<U: ?Sized + for<'a> Unit<'a, Owned = Box<dyn Any + Send>>> BlackBox<U> {
+    ///
+    /// Allocates a unit for `T` like `allocate_for`, whose values are written
+    /// by `dump_pod` under `tag` and read back by `restore_pod`. The tag is
+    /// what identifies the values in a dump, so it should stay the same from
+    /// one build to the next, unlike the `TypeId` of `T`.
+    ///
+    /// This returns `ErrorDesc::NameCollision` in the case that `tag` is already
+    /// used for another type. Allocating `T` again replaces its tag.
+    ///
+    pub fn allocate_pod_for<T: Pod>(&mut self, tag: &'static str) -> DynamicResult<()>
+    where
+        (T,): AllocateSet<Self>,
+    {
+        let id = TypeId::of::<T>();
+        if self
+            .pods
+            .iter()
+            .any(|(&other, unit)| other != id && unit.tag == tag)
+        {
+            return Err(ErrorDesc::NameCollision);
+        }
+        self.allocate_all::<(T,)>();
+        self.pods.insert(
+            id,
+            PodUnit {
+                tag,
+                size: size_of::<T>(),
+                dump: dump::<T, U>,
+                restore: restore::<T, U>,
+            },
+        );
+        Ok(())
+    }
+
+    ///
+    /// Writes the values of every unit allocated with `allocate_pod_for` to
+    /// `w`, as their raw bytes. Each unit is borrowed immutably while its
+    /// values are written, one unit after the other.
+    ///
+    /// A dump is made of:
+    /// - The bytes `RPOD`, followed by the version of the format, which is 1,
+    ///   as a `u32`.
+    /// - The number of units, as a `u64`.
+    /// - For each unit, in the order they were allocated in: the length of
+    ///   its tag as a `u64` followed by the tag in UTF-8, the size of a value
+    ///   and the number of values as `u64`s, and then the bytes of the values.
+    ///
+    /// Numbers and values are written in the native byte order, so a dump is
+    /// meant to be restored on the same kind of machine. Restoring one written
+    /// on a machine of the other endianness fails on the version.
+    ///
+    /// This fails in the case that `w` fails, or that a unit couldn't be
+    /// borrowed, after having written part of the dump.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::DynamicStorage;
+    ///
+    /// let mut storage = DynamicStorage::new();
+    /// storage.allocate_pod_for::<[f32; 2]>("position").unwrap();
+    /// storage.allocate_pod_for::<u64>("tick").unwrap();
+    /// storage.insert_many(vec![[0.0f32, 1.0], [2.0, 3.0]]).unwrap();
+    /// storage.insert(7u64).unwrap();
+    /// let mut dump = Vec::new();
+    /// storage.dump_pod(&mut dump).unwrap();
+    ///
+    /// let mut restored = DynamicStorage::new();
+    /// restored.allocate_pod_for::<[f32; 2]>("position").unwrap();
+    /// restored.allocate_pod_for::<u64>("tick").unwrap();
+    /// assert_eq!(restored.restore_pod(&mut &dump[..]).unwrap(), Vec::<String>::new());
+    /// assert_eq!(*restored.ind::<[f32; 2]>(1).unwrap(), [2.0, 3.0]);
+    /// assert_eq!(*restored.get::<u64>().unwrap(), 7);
+    /// # }
+    /// ```
+    ///
+    pub fn dump_pod(&self, w: &mut impl Write) -> io::Result<()> {
+        w.write_all(&MAGIC)?;
+        w.write_all(&VERSION.to_ne_bytes())?;
+        w.write_all(&(self.pods.len() as u64).to_ne_bytes())?;
+        for unit in self.pods.values() {
+            w.write_all(&(unit.tag.len() as u64).to_ne_bytes())?;
+            w.write_all(unit.tag.as_bytes())?;
+            w.write_all(&(unit.size as u64).to_ne_bytes())?;
+            (unit.dump)(self, w).map_err(|e| {
+                io::Error::other(format!(
+                    "the unit tagged `{}` couldn't be read: {}",
+                    unit.tag, e
+                ))
+            })??;
+        }
+        Ok(())
+    }
+
+    ///
+    /// Replaces the values of the units allocated with `allocate_pod_for` by
+    /// those read from a dump written by `dump_pod`, see there for the format.
+    /// Units whose tag isn't in the dump are left as they are, and the tags
+    /// in the dump which no unit has are skipped and returned.
+    ///
+    /// The whole dump is read and checked before any unit is changed, so that
+    /// nothing changes in the case that it can't be read, or that the values
+    /// of a unit have changed size since they were dumped. Each unit is then
+    /// filled like by `insert`, emptying a poisoned unit, and finalizing the
+    /// values it held. In the case that a unit can't be borrowed, the units
+    /// before it keep their new values.
+    ///
+    pub fn restore_pod(&self, r: &mut impl Read) -> Result<Vec<String>, PodError> {
+        let mut magic = [0; 4];
+        r.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(PodError::NotADump);
+        }
+        let mut version = [0; 4];
+        r.read_exact(&mut version)?;
+        let version = u32::from_ne_bytes(version);
+        if version != VERSION {
+            return Err(PodError::UnsupportedVersion(version));
+        }
+
+        let mut records = Vec::new();
+        let mut skipped = Vec::new();
+        for _ in 0..read_u64(r)? {
+            let len = read_u64(r)?;
+            let tag = String::from_utf8(read_bytes(r, len)?)
+                .map_err(|_| invalid("a tag isn't valid UTF-8"))?;
+            let size = read_u64(r)?;
+            let count = read_u64(r)?;
+            let len = size
+                .checked_mul(count)
+                .ok_or_else(|| invalid("the values of a unit are too large"))?;
+            match self.pods.values().find(|unit| unit.tag == tag) {
+                Some(unit) if unit.size as u64 != size => {
+                    return Err(PodError::SizeMismatch {
+                        tag,
+                        expected: unit.size,
+                        found: size as usize,
+                    });
+                }
+                Some(unit) => records.push((unit, tag, count as usize, read_bytes(r, len)?)),
+                None => {
+                    if io::copy(&mut r.by_ref().take(len), &mut io::sink())? != len {
+                        return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+                    }
+                    skipped.push(tag);
+                }
+            }
+        }
+
+        for (unit, tag, count, bytes) in records {
+            (unit.restore)(self, count, &bytes)
+                .map_err(|error| PodError::Storage { tag, error })?;
+        }
+        Ok(skipped)
+    }
+}
@@ -0,0 +1,91 @@
+use std::any::Any;
+use std::fmt::{Debug, Formatter};
+
+use super::{ErrorDesc, Unit};
+
+///
+/// The outcome of `BlackBox::poll_get` and `BlackBox::poll_get_mut`.
+///
+#[must_use]
+pub enum PollResult<'a, G, U: ?Sized> {
+    /// The value was borrowed.
+    Ready(G),
+    /// The unit is borrowed incompatibly; the token tells when to try again.
+    Pending(RetryToken<'a, U>),
+    /// Any other error, which trying again won't fix.
+    Err(ErrorDesc),
+}
+
+impl<'a, G, U: ?Sized> PollResult<'a, G, U> {
+    ///
+    /// Returns the guard in the case that the value was borrowed.
+    ///
+    pub fn ready(self) -> Option<G> {
+        match self {
+            PollResult::Ready(guard) => Some(guard),
+            _ => None,
+        }
+    }
+
+    pub fn is_ready(&self) -> bool {
+        matches!(self, PollResult::Ready(_))
+    }
+
+    pub fn is_pending(&self) -> bool {
+        matches!(self, PollResult::Pending(_))
+    }
+}
+
+impl<'a, G, U: ?Sized> Debug for PollResult<'a, G, U> {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            PollResult::Ready(_) => write!(f, "Ready(..)"),
+            PollResult::Pending(token) => f.debug_tuple("Pending").field(token).finish(),
+            PollResult::Err(e) => f.debug_tuple("Err").field(e).finish(),
+        }
+    }
+}
+
+///
+/// Tells when a unit which was borrowed incompatibly might be borrowed, as
+/// returned by `BlackBox::poll_get` and `BlackBox::poll_get_mut`.
+///
+/// The token is only advisory: the unit may be borrowed again between
+/// `is_ready` returning `true` and the next poll, which then returns another
+/// token. Checking it never blocks, but briefly takes and releases the lock of
+/// units based on `Mutex`es and `RwLock`s.
+///
+pub struct RetryToken<'a, U: ?Sized> {
+    unit: &'a U,
+    exclusive: bool,
+}
+
+impl<'a, U: ?Sized> RetryToken<'a, U> {
+    #[inline]
+    pub(crate) fn new(unit: &'a U, exclusive: bool) -> Self {
+        Self { unit, exclusive }
+    }
+}
+
+impl<'a, U: ?Sized + for<'b> Unit<'b, Owned = Box<dyn Any + Send>>> RetryToken<'a, U> {
+    ///
+    /// Whether the borrow which was polled for would no longer conflict with
+    /// those held on the unit.
+    ///
+    #[inline]
+    pub fn is_ready(&self) -> bool {
+        if self.exclusive {
+            !self.unit.is_borrowed()
+        } else {
+            self.unit.state().is_some()
+        }
+    }
+}
+
+impl<'a, U: ?Sized> Debug for RetryToken<'a, U> {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        f.debug_struct("RetryToken")
+            .field("exclusive", &self.exclusive)
+            .finish()
+    }
+}
@@ -0,0 +1,124 @@
+//! Carrying the capacities of the units of a storage over to the next run,
+//! see `CapacityProfile`.
+use super::{BlackBox, Unit};
+use std::any::Any;
+
+///
+/// The number of values and the capacity of each unit of a storage, as taken
+/// by `BlackBox::capacity_profile`, which `BlackBox::apply_capacity_profile`
+/// reserves in another storage, such as the one of the next run of a program,
+/// so that its lists of values don't have to grow back to their usual sizes.
+///
+/// Units are told apart by the name of the type they store, as given by
+/// `std::any::type_name`, which stays the same from one run to the next of a
+/// program built with the same compiler, unlike `TypeId`s. With the `serde`
+/// feature, a profile can be serialized and deserialized to be kept in between.
+///
+/// # Example
+/// ```
+/// # fn main() {
+/// use restor::{make_storage, RwLockStorage};
+///
+/// let storage = make_storage!(RwLockStorage: u64, String);
+/// storage.insert_many((0..1000u64).collect()).unwrap();
+/// let profile = storage.capacity_profile();
+///
+/// let mut next = make_storage!(RwLockStorage: u64, String);
+/// assert_eq!(next.apply_capacity_profile(&profile), Vec::<String>::new());
+/// assert!(next.capacity::<u64>().unwrap() >= 1000);
+/// assert_eq!(next.capacity::<String>(), Ok(0));
+/// # }
+/// ```
+///
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CapacityProfile {
+    /// An entry for each unit, in the order the units were allocated in.
+    pub units: Vec<CapacityEntry>,
+}
+
+///
+/// The figures of a single unit in a `CapacityProfile`.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CapacityEntry {
+    /// The name of the type stored in the unit, as given by `std::any::type_name`.
+    pub name: String,
+    /// The number of values in the unit, see `UnitState::count`.
+    pub len: usize,
+    /// The number of values the unit could hold without reallocating, see
+    /// `BlackBox::capacity`.
+    pub capacity: usize,
+}
+
+impl<U: ?Sized + for<'a> Unit<'a, Owned = Box<dyn Any + Send>>> BlackBox<U> {
+    ///
+    /// Takes the number of values and the capacity of every unit, see
+    /// `CapacityProfile`. The units which are borrowed exclusively are left
+    /// out instead of being waited on.
+    ///
+    pub fn capacity_profile(&self) -> CapacityProfile {
+        CapacityProfile {
+            units: self
+                .data
+                .iter()
+                .filter_map(|(id, unit)| {
+                    Some(CapacityEntry {
+                        name: unit.type_name().to_string(),
+                        len: unit.state()?.count(),
+                        capacity: self.unit_capacity(id, unit)?,
+                    })
+                })
+                .collect(),
+        }
+    }
+
+    ///
+    /// Reserves the capacity recorded in `profile` for each unit of this
+    /// storage which is registered under the name of an entry, see
+    /// `has_unit_named`, without inserting any value. Units which already
+    /// have as much room are left as they are.
+    ///
+    /// Returns the names of the entries which were skipped, as there is no
+    /// unit registered under them, in the order they are in `profile`.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::{make_storage, CapacityEntry, CapacityProfile, DynamicStorage};
+    ///
+    /// let profile = CapacityProfile {
+    ///     units: vec![
+    ///         CapacityEntry { name: "usize".to_string(), len: 10, capacity: 16 },
+    ///         CapacityEntry { name: "gone".to_string(), len: 1, capacity: 1 },
+    ///     ],
+    /// };
+    /// let mut storage = make_storage!(DynamicStorage: usize);
+    /// assert_eq!(storage.apply_capacity_profile(&profile), vec!["gone".to_string()]);
+    /// assert!(storage.capacity::<usize>().unwrap() >= 16);
+    /// assert_eq!(storage.state::<usize>().unwrap().count(), 0);
+    /// # }
+    /// ```
+    ///
+    #[must_use]
+    pub fn apply_capacity_profile(&mut self, profile: &CapacityProfile) -> Vec<String> {
+        let mut skipped = Vec::new();
+        for entry in &profile.units {
+            let found = self
+                .names
+                .get(&*entry.name)
+                .and_then(|id| Some((self.data.get(id)?, self.spares.get(id)?)));
+            match found {
+                Some((unit, spare)) => {
+                    // Nothing else can borrow the unit while `self` is borrowed mutably
+                    if let Ok(mut storage) = unit.storage_mut() {
+                        spare.reserve(&mut *storage, entry.capacity);
+                    }
+                }
+                None => skipped.push(entry.name.clone()),
+            }
+        }
+        skipped
+    }
+}
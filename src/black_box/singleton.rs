@@ -0,0 +1,170 @@
+//! A storage holding at most one value of each type, see `SingletonStorage`.
+#[cfg(feature = "events")]
+use super::StorageEvent;
+use super::{
+    AllocateSet, BlackBox, Borrowed, BufferedUnit, DynamicResult, ErrorDesc, GetPolicy, Map,
+    MapMut, Mapped, MappedMut, MutBorrowed, Unit, UnitError, UnitState, ValueChange,
+};
+use std::any::Any;
+#[cfg(feature = "events")]
+use std::any::TypeId;
+
+///
+/// A storage which holds at most one value of each type, like a map from
+/// types to values. This wraps any of `DynamicStorage`, `MutexStorage` and
+/// `RwLockStorage`, and only exposes the operations which keep a single value
+/// in every unit: `insert` replaces the value instead of adding another, and
+/// allocates the unit when needed.
+///
+/// The storage is borrowed mutably to insert and remove values, so those
+/// can't fail, whereas `get` and `get_mut` borrow the unit like they do on
+/// the wrapped storage, and fail in the same cases.
+///
+/// # Example
+/// ```
+/// # fn main() {
+/// use restor::{DynamicStorage, SingletonStorage};
+///
+/// let mut storage = SingletonStorage::<DynamicStorage>::default();
+/// assert!(!storage.contains::<u32>());
+/// assert_eq!(storage.insert(1u32), None);
+/// assert_eq!(storage.insert(2u32), Some(1));
+/// *storage.get_mut::<u32>().unwrap() += 1;
+/// assert_eq!(*storage.get::<u32>().unwrap(), 3);
+/// assert_eq!(storage.remove::<u32>(), Some(3));
+/// assert!(!storage.contains::<u32>());
+/// # }
+/// ```
+///
+pub struct SingletonStorage<S> {
+    inner: S,
+}
+
+impl<U: ?Sized + for<'a> Unit<'a, Owned = Box<dyn Any + Send>>> Default
+    for SingletonStorage<BlackBox<U>>
+{
+    fn default() -> Self {
+        Self {
+            inner: BlackBox::new(),
+        }
+    }
+}
+
+impl<U: ?Sized + for<'a> Unit<'a, Owned = Box<dyn Any + Send>>> SingletonStorage<BlackBox<U>> {
+    ///
+    /// Wraps `storage`, keeping the values it already holds. This hands
+    /// `storage` back with `UnitError::IsNotOne`, wrapped in an
+    /// `ErrorDesc::Context`, in the case that any of its units holds more than
+    /// one value.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::{make_storage, ErrorDesc, RwLockStorage, SingletonStorage, UnitError};
+    ///
+    /// let storage = make_storage!(RwLockStorage: usize, String);
+    /// storage.insert(String::from("kept")).unwrap();
+    /// storage.insert_many(vec![1usize, 2]).unwrap();
+    /// let (storage, error) = SingletonStorage::from_storage(storage).err().unwrap();
+    /// assert_eq!(error.root(), &ErrorDesc::Unit(UnitError::IsNotOne));
+    ///
+    /// storage.extract_ind::<usize>(0).unwrap();
+    /// let storage = SingletonStorage::from_storage(storage).ok().unwrap();
+    /// assert_eq!(&*storage.get::<String>().unwrap(), "kept");
+    /// assert_eq!(*storage.get::<usize>().unwrap(), 2);
+    /// let storage: RwLockStorage = storage.into_inner();
+    /// # }
+    /// ```
+    ///
+    #[allow(clippy::result_large_err)]
+    pub fn from_storage(storage: BlackBox<U>) -> Result<Self, (BlackBox<U>, ErrorDesc)> {
+        let many = storage
+            .data
+            .values()
+            .any(|unit| matches!(unit.state(), Some(UnitState::Multiple(n)) if n > 1));
+        if many {
+            let error = ErrorDesc::Unit(UnitError::IsNotOne);
+            Err((
+                storage,
+                error.context("checking that no unit holds many values"),
+            ))
+        } else {
+            Ok(Self { inner: storage })
+        }
+    }
+
+    ///
+    /// Returns the wrapped storage, holding at most one value of each type.
+    ///
+    pub fn into_inner(self) -> BlackBox<U> {
+        self.inner
+    }
+
+    ///
+    /// Stores `value` as the value of `T`, allocating a unit for `T` if there
+    /// is none, and returns the value it replaced.
+    ///
+    pub fn insert<T: 'static + Send>(&mut self, value: T) -> Option<T>
+    where
+        (T,): AllocateSet<BlackBox<U>>,
+    {
+        self.inner.allocate_all::<(T,)>();
+        let inner = &self.inner;
+        let replaced = inner.insert_with(value, |unit: &mut BufferedUnit<T>, value| {
+            let replaced = unit.extract_one_loose().ok();
+            if replaced.is_some() {
+                notify!(inner, T, Extracted { index: 0 });
+            }
+            unit.insert(value);
+            notify!(inner, T, Inserted { index: 0 });
+            replaced
+        });
+        match replaced {
+            Ok(replaced) => replaced.map(|value| inner.finalized(value)),
+            // Nothing else can borrow the unit while `self` is borrowed mutably
+            Err(_) => unreachable!(),
+        }
+    }
+
+    ///
+    /// Borrows the value of `T` immutably, like `BlackBox::get`.
+    ///
+    pub fn get<'a, T: 'static + Send>(&'a self) -> DynamicResult<Mapped<'a, U, T>>
+    where
+        Borrowed<'a, U>: Map<dyn Any + Send, T, Func = for<'b> fn(&'b (dyn Any + Send)) -> &'b T>,
+    {
+        self.inner.get_with_policy::<T>(GetPolicy::FirstOnMany)
+    }
+
+    ///
+    /// Borrows the value of `T` mutably, like `BlackBox::get_mut`.
+    ///
+    pub fn get_mut<'a, T: 'static + Send>(&'a self) -> DynamicResult<MappedMut<'a, U, T>>
+    where
+        MutBorrowed<'a, U>: MapMut<dyn Any + Send, T, Func = fn(&mut (dyn Any + Send)) -> &mut T>,
+    {
+        self.inner.get_mut_with_policy::<T>(GetPolicy::FirstOnMany)
+    }
+
+    ///
+    /// Removes the value of `T` and returns it, leaving its unit empty. This
+    /// returns `None` in the case that there is no value, or that its unit was
+    /// poisoned.
+    ///
+    pub fn remove<T: 'static + Send>(&mut self) -> Option<T> {
+        self.inner.extract_one_loose::<T>().ok()
+    }
+
+    ///
+    /// Whether there is a value of `T`.
+    ///
+    pub fn contains<T: 'static + Send>(&self) -> bool {
+        matches!(self.inner.state::<T>(), Ok(state) if state.count() > 0)
+    }
+}
+
+impl<U: ?Sized> From<SingletonStorage<BlackBox<U>>> for BlackBox<U> {
+    fn from(storage: SingletonStorage<BlackBox<U>>) -> Self {
+        storage.inner
+    }
+}
@@ -0,0 +1,388 @@
+//! The lists which units keep aside while they have no values or a single one,
+//! so that a unit which is filled and emptied over and over stops allocating,
+//! see `BlackBox::allocate_for_with_vec` and `BlackBox::swap_backing_vec`.
+use super::unit::warm;
+use super::{BlackBox, DynamicResult, StorageUnit, Unit, UnitState};
+use crate::concurrent_black_box::sync::Mutex;
+use std::any::{Any, TypeId};
+use std::mem::{replace, take};
+use std::ops::{Deref, DerefMut};
+
+///
+/// The list kept aside for the unit of `T` while the unit isn't `Many`. It
+/// never holds any values itself: it is taken out to become the list of the
+/// unit once the unit has more than a single value, and the list of the unit
+/// is put back once its last value is removed.
+///
+pub(crate) struct Spare<T>(Mutex<Vec<T>>);
+
+impl<T> Spare<T> {
+    pub(crate) fn new() -> Self {
+        Spare(Mutex::new(Vec::new()))
+    }
+
+    /// The number of values the list has room for.
+    fn capacity(&self) -> usize {
+        self.0.lock().capacity()
+    }
+
+    /// Takes the list out in the case that it has room for `room` values.
+    fn take(&self, room: usize) -> Option<Vec<T>> {
+        let mut list = self.0.lock();
+        if list.capacity() == 0 || list.capacity() < room {
+            None
+        } else {
+            Some(take(&mut *list))
+        }
+    }
+
+    /// Keeps `list`, which is empty, instead of the list in the case that it
+    /// has more room.
+    fn keep(&self, list: Vec<T>) {
+        debug_assert!(list.is_empty());
+        let mut spare = self.0.lock();
+        if list.capacity() > spare.capacity() {
+            *spare = list;
+        }
+    }
+}
+
+///
+/// The `Spare<T>` of each type as stored in a `BlackBox`, which doesn't know
+/// `T`.
+///
+pub(crate) trait AnySpare: Send + Sync {
+    fn as_any(&self) -> &dyn Any;
+
+    /// The number of values the list has room for.
+    fn capacity(&self) -> usize;
+
+    /// The number of bytes taken up by the list.
+    fn approx_bytes(&self) -> usize;
+
+    /// Reserves room for `capacity` values in total in `unit`, which is the
+    /// `StorageUnit` of the type, see `BlackBox::apply_capacity_profile`.
+    fn reserve(&self, unit: &mut (dyn Any + Send), capacity: usize);
+
+    /// Creates a `Spare` for the same type with an empty list, see
+    /// `BlackBox::fork`.
+    fn empty(&self) -> Box<dyn AnySpare>;
+}
+
+impl<T: 'static + Send> AnySpare for Spare<T> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn capacity(&self) -> usize {
+        Spare::capacity(self)
+    }
+
+    fn approx_bytes(&self) -> usize {
+        self.capacity() * std::mem::size_of::<T>()
+    }
+
+    fn reserve(&self, unit: &mut (dyn Any + Send), capacity: usize) {
+        let mut unit = BufferedUnit::new(unit.downcast_mut().unwrap(), Some(self));
+        if capacity > unit.capacity() {
+            let additional = capacity - unit.len();
+            unit.reserve(additional);
+        }
+    }
+
+    fn empty(&self) -> Box<dyn AnySpare> {
+        Box::new(Spare::<T>::new())
+    }
+}
+
+///
+/// The number of values `unit` can hold without reallocating, counting the
+/// room of `spare`, which is the list kept aside for it.
+///
+pub(super) fn capacity<T>(unit: &StorageUnit<T>, spare: Option<&Spare<T>>) -> usize {
+    match (unit, spare) {
+        (StorageUnit::Many(_), _) | (_, None) => unit.capacity(),
+        (_, Some(spare)) => unit.capacity().max(spare.capacity()),
+    }
+}
+
+///
+/// The `StorageUnit<T>` of a storage along with the list kept aside for it. The
+/// unit takes the list up again instead of allocating when it goes from a
+/// single value to many, and its list is put back once its last value is
+/// removed. This shadows the methods of `StorageUnit` which do either, and
+/// derefs to the unit for every other one, which keeps to the semantics of a
+/// `StorageUnit` on its own.
+///
+pub(crate) struct BufferedUnit<'a, T: 'static> {
+    unit: &'a mut StorageUnit<T>,
+    spare: Option<&'a Spare<T>>,
+}
+
+impl<'a, T> BufferedUnit<'a, T> {
+    pub(crate) fn new(unit: &'a mut StorageUnit<T>, spare: Option<&'a Spare<T>>) -> Self {
+        Self { unit, spare }
+    }
+
+    ///
+    /// Takes the spare list with the single value of the unit moved into it, in
+    /// the case that it has room for `additional` more values, leaving the unit
+    /// `Nope`. This must not be called on a `Many` unit.
+    ///
+    fn take_spare(&mut self, additional: usize) -> Option<Vec<T>> {
+        debug_assert!(self.unit.many().is_err());
+        let mut list = self.spare?.take(self.unit.len() + additional)?;
+        if let StorageUnit::One(x) = replace(self.unit, StorageUnit::Nope) {
+            list.push(x);
+        }
+        Some(list)
+    }
+
+    /// Keeps `list`, which is empty, aside for the unit.
+    fn keep(&self, list: Vec<T>) {
+        if let Some(spare) = self.spare {
+            spare.keep(list);
+        }
+    }
+
+    /// Puts the list of a `Many` unit which was just emptied aside, leaving the
+    /// unit `Nope`.
+    fn release(&mut self) {
+        if let StorageUnit::Many(many) = self.unit {
+            if many.is_empty() {
+                let list = take(many);
+                *self.unit = StorageUnit::Nope;
+                self.keep(list);
+            }
+        }
+    }
+
+    /// See `StorageUnit::capacity`, which this counts the spare list in.
+    pub(crate) fn capacity(&self) -> usize {
+        capacity(self.unit, self.spare)
+    }
+
+    pub(crate) fn insert(&mut self, new: T) {
+        if let StorageUnit::One(_) = self.unit {
+            if let Some(mut list) = self.take_spare(1) {
+                list.push(new);
+                *self.unit = StorageUnit::Many(list);
+                return;
+            }
+        }
+        self.unit.insert(new);
+    }
+
+    pub(crate) fn insert_many(&mut self, mut new: Vec<T>) {
+        if !new.is_empty() && self.unit.many().is_err() {
+            if let Some(mut list) = self.take_spare(new.len()) {
+                list.append(&mut new);
+                *self.unit = StorageUnit::Many(list);
+                self.keep(new);
+                return;
+            }
+        }
+        self.unit.insert_many(new);
+    }
+
+    pub(crate) fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        if self.unit.many().is_ok() {
+            return self.unit.extend(iter);
+        }
+        let iter = iter.into_iter();
+        match self.take_spare(iter.size_hint().0) {
+            Some(mut list) => {
+                let before = list.len();
+                list.extend(iter);
+                // Extending by any values gives `Many`, as with `insert_many`
+                if list.len() > before {
+                    *self.unit = StorageUnit::Many(list);
+                } else {
+                    if let Some(x) = list.pop() {
+                        *self.unit = StorageUnit::One(x);
+                    }
+                    self.keep(list);
+                }
+            }
+            None => self.unit.extend(iter),
+        }
+    }
+
+    pub(crate) fn extract_one(&mut self) -> DynamicResult<T> {
+        match self.unit {
+            StorageUnit::Many(many) if !many.is_empty() => self.extract_ind(0),
+            _ => self.unit.extract_one(),
+        }
+    }
+
+    pub(crate) fn extract_ind(&mut self, ind: usize) -> DynamicResult<T> {
+        match self.unit {
+            StorageUnit::Many(many) if ind < many.len() => {
+                let value = many.remove(ind);
+                self.release();
+                Ok(value)
+            }
+            _ => self.unit.extract_ind(ind),
+        }
+    }
+
+    pub(crate) fn swap_extract_ind(&mut self, ind: usize) -> DynamicResult<T> {
+        match self.unit {
+            StorageUnit::Many(many) if ind < many.len() => {
+                let value = many.swap_remove(ind);
+                self.release();
+                Ok(value)
+            }
+            _ => self.unit.swap_extract_ind(ind),
+        }
+    }
+
+    pub(crate) fn extract_many_into(&mut self, buf: &mut Vec<T>) -> usize {
+        match self.unit {
+            StorageUnit::Many(many) => {
+                let moved = many.len();
+                buf.append(many);
+                self.release();
+                moved
+            }
+            _ => self.unit.extract_many_into(buf),
+        }
+    }
+
+    pub(crate) fn extract_one_loose(&mut self) -> DynamicResult<T> {
+        match self.unit {
+            StorageUnit::Many(many) if !many.is_empty() => self.extract_ind(0),
+            _ => self.unit.extract_one_loose(),
+        }
+    }
+
+    ///
+    /// Replaces the list of the unit with `replacement`, keeping the values
+    /// already in it, and returns the previous values in the previous list.
+    /// A `replacement` with no values or a single value is kept aside, and
+    /// one with more becomes the list of the unit.
+    ///
+    pub(crate) fn swap_buffer(&mut self, mut replacement: Vec<T>) -> Vec<T> {
+        let previous = match replace(self.unit, StorageUnit::Nope) {
+            StorageUnit::Many(many) => many,
+            other => {
+                *self.unit = other;
+                self.take_spare(0)
+                    .unwrap_or_else(|| self.unit.extract_any_count())
+            }
+        };
+        if replacement.len() < 2 {
+            if let Some(x) = replacement.pop() {
+                *self.unit = StorageUnit::One(x);
+            }
+            self.keep(replacement);
+        } else {
+            *self.unit = StorageUnit::Many(replacement);
+        }
+        previous
+    }
+
+    ///
+    /// Reserves room for at least `additional` more values, in the list of a
+    /// `Many` unit, or in the spare list for any other unit.
+    ///
+    pub(crate) fn reserve(&mut self, additional: usize) {
+        match (&mut *self.unit, self.spare) {
+            (StorageUnit::Many(many), _) => many.reserve(additional),
+            (unit, Some(spare)) => spare.0.lock().reserve(unit.len() + additional),
+            (_, None) => {}
+        }
+    }
+
+    ///
+    /// Like `reserve`, but also faults the room in, see
+    /// `StorageUnit::warm_capacity`.
+    ///
+    pub(crate) fn warm_capacity(&mut self, additional: usize) {
+        match (&mut *self.unit, self.spare) {
+            (StorageUnit::Many(many), _) => warm(many, additional),
+            (unit, Some(spare)) => warm(&mut spare.0.lock(), unit.len() + additional),
+            (_, None) => {}
+        }
+    }
+
+    /// Shrinks the list of the unit, and releases the spare list.
+    pub(crate) fn shrink_to_fit(&mut self) {
+        self.unit.shrink_to_fit();
+        if let Some(spare) = self.spare {
+            *spare.0.lock() = Vec::new();
+        }
+    }
+}
+
+impl<T> Deref for BufferedUnit<'_, T> {
+    type Target = StorageUnit<T>;
+
+    fn deref(&self) -> &StorageUnit<T> {
+        self.unit
+    }
+}
+
+impl<T> DerefMut for BufferedUnit<'_, T> {
+    fn deref_mut(&mut self) -> &mut StorageUnit<T> {
+        self.unit
+    }
+}
+
+impl<U: ?Sized + for<'a> Unit<'a, Owned = Box<dyn Any + Send>>> BlackBox<U> {
+    ///
+    /// Internal function. The list kept aside for the unit of `T`, or `None`
+    /// in the case that there is no unit for `T`.
+    ///
+    pub(super) fn spare<T: 'static + Send>(&self) -> Option<&Spare<T>> {
+        self.spares
+            .get(&TypeId::of::<T>())
+            .and_then(|spare| spare.as_any().downcast_ref())
+    }
+
+    ///
+    /// Internal function. Allocates a unit for `T` using `make`, like
+    /// `allocate_unit`, which starts out with the values of `vec`, and keeps
+    /// the list of `vec` for its values. `make` is handed the `StorageUnit` to
+    /// wrap.
+    ///
+    pub(super) fn allocate_unit_with_vec<T, F>(&mut self, mut vec: Vec<T>, make: F)
+    where
+        T: 'static + Send,
+        F: FnOnce(StorageUnit<T>) -> Box<U>,
+    {
+        if self.has_unit::<T>() {
+            return;
+        }
+        let unit = if vec.len() < 2 {
+            vec.pop().map_or(StorageUnit::Nope, StorageUnit::One)
+        } else {
+            StorageUnit::Many(take(&mut vec))
+        };
+        self.allocate_unit::<T, _>(|| make(unit));
+        if let Some(spare) = self.spare::<T>() {
+            spare.keep(vec);
+        }
+    }
+
+    ///
+    /// Internal function. The capacity of `unit`, which is the unit for the
+    /// type identified by `id`, counting the list kept aside for it.
+    ///
+    pub(super) fn unit_capacity(&self, id: &TypeId, unit: &U) -> Option<usize> {
+        let capacity = unit.capacity()?;
+        match (unit.state(), self.spares.get(id)) {
+            (Some(UnitState::Multiple(_)), _) | (_, None) => Some(capacity),
+            (_, Some(spare)) => Some(capacity.max(spare.capacity())),
+        }
+    }
+
+    ///
+    /// Internal function. The number of bytes taken up by `unit`, which is the
+    /// unit for the type identified by `id`, and the list kept aside for it.
+    ///
+    pub(super) fn unit_bytes(&self, id: &TypeId, unit: &U) -> Option<usize> {
+        let spare = self.spares.get(id).map_or(0, |spare| spare.approx_bytes());
+        Some(unit.approx_bytes()? + spare)
+    }
+}
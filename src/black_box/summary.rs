@@ -0,0 +1,117 @@
+use std::any::TypeId;
+use std::fmt::{Display, Formatter};
+
+use super::UnitState;
+
+///
+/// The description of a single unit in a `StorageSummary`.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct SummaryEntry {
+    /// The name of the type stored in the unit.
+    pub name: &'static str,
+    /// The state of the unit, or `None` if it was borrowed exclusively.
+    pub state: Option<UnitState>,
+    /// Whether there was any borrow held on the unit.
+    pub borrowed: bool,
+}
+
+impl SummaryEntry {
+    ///
+    /// The number of values in the unit, if it could be read.
+    ///
+    pub fn count(&self) -> Option<usize> {
+        self.state.map(UnitState::count)
+    }
+}
+
+impl Display for SummaryEntry {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self.state {
+            None => write!(f, "{}: locked", self.name),
+            Some(state) if self.borrowed => write!(f, "{}: {} (borrowed)", self.name, state),
+            Some(state) => write!(f, "{}: {}", self.name, state),
+        }
+    }
+}
+
+///
+/// A snapshot of the units in a storage, as returned by `BlackBox::summary`.
+/// The entries are sorted by name, so that the output is deterministic.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize), serde(transparent))]
+pub struct StorageSummary {
+    entries: Vec<SummaryEntry>,
+}
+
+impl StorageSummary {
+    pub(crate) fn new(mut entries: Vec<SummaryEntry>) -> Self {
+        entries.sort_by(|a, b| a.name.cmp(b.name));
+        Self { entries }
+    }
+
+    pub fn entries(&self) -> &[SummaryEntry] {
+        &self.entries
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, SummaryEntry> {
+        self.entries.iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a StorageSummary {
+    type Item = &'a SummaryEntry;
+    type IntoIter = std::slice::Iter<'a, SummaryEntry>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl Display for StorageSummary {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        if self.entries.is_empty() {
+            return write!(f, "(no units)");
+        }
+        for (i, entry) in self.entries.iter().enumerate() {
+            if i != 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", entry)?;
+        }
+        Ok(())
+    }
+}
+
+///
+/// The figures describing a single unit, as returned by `BlackBox::stats`,
+/// meant to be exported to a metrics system rather than read.
+///
+/// The figures which can only be read by borrowing the unit are `None` in the
+/// case that it was borrowed exclusively, or that its kind of unit doesn't
+/// report them.
+///
+/// With the `serde` feature, this implements `Serialize`, leaving out
+/// `type_id`, which differs between builds.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct UnitStats {
+    /// The name of the type stored in the unit.
+    pub type_name: &'static str,
+    /// The `TypeId` of the type stored in the unit.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub type_id: TypeId,
+    /// The state of the unit.
+    pub state: Option<UnitState>,
+    /// The number of values in the unit.
+    pub len: Option<usize>,
+    /// The number of values the unit can hold without reallocating.
+    pub capacity: Option<usize>,
+    /// The memory used by the unit, see `StorageUnit::approx_bytes`.
+    pub approx_bytes: Option<usize>,
+    /// Whether there was any borrow held on the unit.
+    pub locked: bool,
+}
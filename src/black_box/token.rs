@@ -0,0 +1,248 @@
+//! Looking up units by their place in the storage rather than by hashing
+//! their `TypeId`, see `TypeToken`.
+#[cfg(feature = "tracing")]
+use super::trace;
+#[cfg(feature = "instrument")]
+use super::BorrowKind;
+use super::{
+    downcast_mut_unchecked, downcast_ref_unchecked, AllocateSet, BlackBox, Borrowed, DynamicResult,
+    ErrorDesc, Map, MapMut, Mapped, MappedMut, MutBorrowed, Unit,
+};
+use std::any::{Any, TypeId};
+use std::convert::TryFrom;
+use std::fmt::{Debug, Formatter};
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+///
+/// Internal function. Returns an id which no storage created before was given.
+///
+pub(super) fn next_storage_id() -> u64 {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    NEXT.fetch_add(1, Ordering::Relaxed)
+}
+
+///
+/// A handle on the unit for `T` in one storage, as returned by
+/// `BlackBox::register_token`, which holds where the unit is among the units
+/// of the storage. Accessing the unit through a token indexes the units
+/// directly, instead of hashing the `TypeId` of `T` to look the unit up, which
+/// pays off for a fixed set of types accessed over and over again.
+///
+/// A token only belongs to the storage which registered it, and using it on
+/// any other storage panics in debug builds. In release builds, it is looked
+/// up by type like when no token is used. Either way, a token never hands out
+/// a unit for another type.
+///
+/// Removing a unit moves the units allocated after it, in which case their
+/// tokens fall back to looking the unit up by type, and `register_token` gives
+/// an up to date token again.
+///
+/// # Example
+/// ```
+/// # fn main() {
+/// use restor::RwLockStorage;
+///
+/// let mut storage = RwLockStorage::new();
+/// let position = storage.register_token::<(f32, f32)>();
+/// let speed = storage.register_token::<f32>();
+/// storage.insert((0.0f32, 0.0f32)).unwrap();
+/// storage.insert(2.0f32).unwrap();
+/// for _ in 0..3 {
+///     let speed = *storage.get_by_token(speed).unwrap();
+///     storage.get_mut_by_token(position).unwrap().0 += speed;
+/// }
+/// assert_eq!(*storage.get_by_token(position).unwrap(), (6.0, 0.0));
+/// # }
+/// ```
+///
+pub struct TypeToken<T> {
+    /// Where the unit for `T` was among the units of the storage.
+    index: u16,
+    /// The id of the storage which registered the token.
+    storage: u64,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Clone for TypeToken<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for TypeToken<T> {}
+
+impl<T> Debug for TypeToken<T> {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        f.debug_struct("TypeToken")
+            .field("type", &std::any::type_name::<T>())
+            .field("index", &self.index)
+            .finish()
+    }
+}
+
+impl<U: ?Sized + for<'a> Unit<'a, Owned = Box<dyn Any + Send>>> BlackBox<U> {
+    ///
+    /// Allocates a unit for `T` if there is none, and returns a token for it,
+    /// through which it is accessed without hashing, see `TypeToken`.
+    ///
+    /// # Panics
+    /// In the case that the unit for `T` comes after the first 65536 units
+    /// of the storage, which a token can't refer to.
+    ///
+    pub fn register_token<T: 'static + Send>(&mut self) -> TypeToken<T>
+    where
+        (T,): AllocateSet<Self>,
+    {
+        self.allocate_all::<(T,)>();
+        let index = self.data.index_of(&TypeId::of::<T>()).unwrap();
+        TypeToken {
+            index: u16::try_from(index).unwrap_or_else(|_| {
+                panic!(
+                    "The unit for {} is unit number {}, which is too far in for a token",
+                    std::any::type_name::<T>(),
+                    index
+                )
+            }),
+            storage: self.id,
+            _marker: PhantomData,
+        }
+    }
+
+    ///
+    /// Internal function. Returns a reference to the unit `token` refers to,
+    /// or an `Err` if it is poisoned, like `unit_get`.
+    ///
+    #[inline]
+    fn unit_by_token<T: 'static + Send>(&self, token: TypeToken<T>) -> DynamicResult<&U> {
+        debug_assert!(
+            token.storage == self.id,
+            "A token for {} was used on a storage other than the one which registered it",
+            std::any::type_name::<T>()
+        );
+        let unit = match self.data.get_index(usize::from(token.index)) {
+            Some((id, unit)) if *id == TypeId::of::<T>() && token.storage == self.id => {
+                // Checked like in `unit_lookup`, as the accessors cast what the
+                // unit hands out to `T` without checking
+                if unit.id() != TypeId::of::<T>() {
+                    debug_assert!(
+                        false,
+                        "The unit for {} stores {}",
+                        std::any::type_name::<T>(),
+                        unit.type_name()
+                    );
+                    return Err(ErrorDesc::NoMatchingType);
+                }
+                &**unit
+            }
+            // The unit was moved by removing one before it, or was removed itself
+            _ => self.unit_lookup::<T>()?,
+        };
+        self.check_poison(TypeId::of::<T>())?;
+        Ok(unit)
+    }
+
+    ///
+    /// Internal function. Like `unit_by_token`, but for changing the values of
+    /// the unit, see `unit_get_mut`.
+    ///
+    #[inline]
+    fn unit_by_token_mut<T: 'static + Send>(&self, token: TypeToken<T>) -> DynamicResult<&U> {
+        self.check_finalizing()?;
+        self.unit_by_token(token)
+    }
+
+    ///
+    /// Like `get`, but for the unit `token` refers to, see `TypeToken`.
+    ///
+    #[inline]
+    pub fn get_by_token<'a, T: 'static + Send>(
+        &'a self,
+        token: TypeToken<T>,
+    ) -> DynamicResult<Mapped<'a, U, T>>
+    where
+        Borrowed<'a, U>: Map<dyn Any + Send, T, Func = for<'b> fn(&'b (dyn Any + Send)) -> &'b T>,
+    {
+        traced!(
+            "get_by_token",
+            T,
+            self.unit_by_token(token)
+                .and_then(|unit| Self::by_policy(self.policy, || unit.one(), || unit.ind(0)))
+                .map(|x| tracked!(self, T, Read, x.map(downcast_ref_unchecked)))
+        )
+    }
+
+    ///
+    /// Like `get_mut`, but for the unit `token` refers to, see `TypeToken`.
+    ///
+    #[inline]
+    pub fn get_mut_by_token<'a, T: 'static + Send>(
+        &'a self,
+        token: TypeToken<T>,
+    ) -> DynamicResult<MappedMut<'a, U, T>>
+    where
+        MutBorrowed<'a, U>: MapMut<dyn Any + Send, T, Func = fn(&mut (dyn Any + Send)) -> &mut T>,
+    {
+        traced!(
+            "get_mut_by_token",
+            T,
+            self.unit_by_token_mut(token)
+                .and_then(|unit| {
+                    Self::by_policy(self.policy, || unit.one_mut(), || unit.ind_mut(0))
+                })
+                .map(|x| self.poisoning::<T, _>(tracked!(
+                    self,
+                    T,
+                    Write,
+                    x.map(downcast_mut_unchecked)
+                )))
+        )
+    }
+
+    ///
+    /// Like `ind`, but for the unit `token` refers to, see `TypeToken`.
+    ///
+    #[inline]
+    pub fn ind_by_token<'a, T: 'static + Send>(
+        &'a self,
+        token: TypeToken<T>,
+        ind: usize,
+    ) -> DynamicResult<Mapped<'a, U, T>>
+    where
+        Borrowed<'a, U>: Map<dyn Any + Send, T, Func = for<'b> fn(&'b (dyn Any + Send)) -> &'b T>,
+    {
+        traced!(
+            "ind_by_token",
+            T,
+            self.unit_by_token(token)
+                .and_then(|unit| unit.ind(ind))
+                .map(|x| tracked!(self, T, Read, x.map(downcast_ref_unchecked)))
+        )
+    }
+
+    ///
+    /// Like `ind_mut`, but for the unit `token` refers to, see `TypeToken`.
+    ///
+    #[inline]
+    pub fn ind_mut_by_token<'a, T: 'static + Send>(
+        &'a self,
+        token: TypeToken<T>,
+        ind: usize,
+    ) -> DynamicResult<MappedMut<'a, U, T>>
+    where
+        MutBorrowed<'a, U>: MapMut<dyn Any + Send, T, Func = fn(&mut (dyn Any + Send)) -> &mut T>,
+    {
+        traced!(
+            "ind_mut_by_token",
+            T,
+            self.unit_by_token_mut(token)
+                .and_then(|unit| unit.ind_mut(ind))
+                .map(|x| self.poisoning::<T, _>(tracked!(
+                    self,
+                    T,
+                    Write,
+                    x.map(downcast_mut_unchecked)
+                )))
+        )
+    }
+}
@@ -0,0 +1,102 @@
+//! Instrumentation of storage operations, only compiled with the `tracing` feature.
+use super::ErrorDesc;
+use std::any::type_name;
+use std::convert::TryFrom;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// How long a guard may be held before it is reported, in nanoseconds, where
+/// `0` disables the reports
+static HOLD_WARNING: AtomicU64 = AtomicU64::new(0);
+
+///
+/// Emits an event describing the outcome of `op` on the unit for `T`. Borrow
+/// conflicts are reported as warnings, other errors at the debug level, and
+/// successes at the trace level.
+///
+pub(crate) fn record<T>(op: &'static str, error: Option<&ErrorDesc>) {
+    match error {
+        None => tracing::trace!(op, type_name = type_name::<T>(), "ok"),
+        Some(e @ ErrorDesc::BorrowedIncompatibly)
+        | Some(e @ ErrorDesc::RecursiveBorrow)
+        | Some(e @ ErrorDesc::Contended) => {
+            tracing::warn!(op, type_name = type_name::<T>(), error = %e, "borrow conflict")
+        }
+        Some(e) => tracing::debug!(op, type_name = type_name::<T>(), error = %e, "failed"),
+    }
+}
+
+///
+/// Emits a warning for the unit for `T` not being registered under the name of
+/// `T`, which another type already uses.
+///
+pub(crate) fn name_skipped<T>() {
+    tracing::warn!(
+        type_name = type_name::<T>(),
+        "name already taken by another unit"
+    );
+}
+
+///
+/// Reports the guards of every storage which are held for longer than
+/// `threshold`, with a warning emitted as each of them is dropped, naming the
+/// type it locked and for how long. `None` stops the reports, which is the
+/// default.
+///
+/// Only the guards taken while a threshold is set are timed.
+///
+/// # Example
+/// ```
+/// # fn main() {
+/// use restor::{make_storage, set_hold_warning, RwLockStorage};
+/// use std::time::Duration;
+///
+/// set_hold_warning(Some(Duration::from_millis(50)));
+/// let storage = make_storage!(RwLockStorage: usize);
+/// storage.insert(0usize).unwrap();
+/// // Emits a warning once dropped, if it is held for more than 50ms
+/// let guard = storage.get_mut::<usize>().unwrap();
+/// # drop(guard);
+/// # set_hold_warning(None);
+/// # }
+/// ```
+///
+pub fn set_hold_warning(threshold: Option<Duration>) {
+    let nanos = match threshold {
+        None => 0,
+        // Kept apart from `0`, which disables the reports
+        Some(t) => u64::try_from(t.as_nanos()).unwrap_or(u64::MAX).max(1),
+    };
+    HOLD_WARNING.store(nanos, Ordering::Relaxed);
+}
+
+///
+/// Internal function. Returns when a guard is taken, if guards are timed.
+///
+#[inline]
+pub(crate) fn hold_started() -> Option<Instant> {
+    if HOLD_WARNING.load(Ordering::Relaxed) == 0 {
+        None
+    } else {
+        Some(Instant::now())
+    }
+}
+
+///
+/// Internal function. Emits a warning if the guard on a `T` taken at `since`
+/// has been held for longer than the threshold.
+///
+pub(crate) fn hold_ended<T: ?Sized>(kind: &'static str, since: Option<Instant>) {
+    let threshold = HOLD_WARNING.load(Ordering::Relaxed);
+    if let Some(since) = since {
+        let held = since.elapsed();
+        if threshold != 0 && held.as_nanos() > u128::from(threshold) {
+            tracing::warn!(
+                kind,
+                type_name = type_name::<T>(),
+                held = ?held,
+                "lock held for too long"
+            );
+        }
+    }
+}
@@ -0,0 +1,239 @@
+//! Inserting into many units at once, see `BlackBox::transaction`.
+use super::spare::BufferedUnit;
+#[cfg(feature = "events")]
+use super::StorageEvent;
+use super::{BlackBox, DynamicResult, ErrorDesc, MutBorrowed, Unit, ValueChange};
+use std::any::{Any, TypeId};
+use std::fmt::{Debug, Formatter};
+
+///
+/// The values of a single type staged in a `Transaction`.
+///
+trait Staged<U: ?Sized + for<'a> Unit<'a, Owned = Box<dyn Any + Send>>> {
+    fn id(&self) -> TypeId;
+    fn type_name(&self) -> &'static str;
+    fn len(&self) -> usize;
+    /// Borrows the unit to insert into, without changing it yet.
+    fn lock<'a>(&self, storage: &'a BlackBox<U>) -> DynamicResult<MutBorrowed<'a, U>>;
+    /// Inserts the values into the unit, borrowed through `lock`.
+    fn commit(self: Box<Self>, storage: &BlackBox<U>, unit: &mut MutBorrowed<'_, U>);
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+    fn into_any(self: Box<Self>) -> Box<dyn Any>;
+}
+
+impl<T: 'static + Send, U: ?Sized + for<'a> Unit<'a, Owned = Box<dyn Any + Send>>> Staged<U>
+    for Vec<T>
+{
+    fn id(&self) -> TypeId {
+        TypeId::of::<T>()
+    }
+    fn type_name(&self) -> &'static str {
+        std::any::type_name::<T>()
+    }
+    fn len(&self) -> usize {
+        Vec::len(self)
+    }
+    fn lock<'a>(&self, storage: &'a BlackBox<U>) -> DynamicResult<MutBorrowed<'a, U>> {
+        storage.lock_for_insert::<T>()
+    }
+    fn commit(self: Box<Self>, storage: &BlackBox<U>, unit: &mut MutBorrowed<'_, U>) {
+        storage.clear_poisoned::<T>(unit);
+        let unit = unit.downcast_mut().unwrap();
+        let mut unit = BufferedUnit::<T>::new(unit, storage.spare::<T>());
+        let start = unit.len();
+        let mut values = *self;
+        if values.len() == 1 {
+            unit.insert(values.pop().unwrap());
+        } else {
+            unit.insert_many(values);
+        }
+        for index in start..unit.len() {
+            notify!(storage, T, Inserted { index: index });
+        }
+    }
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
+}
+
+///
+/// Values staged to be inserted into many units at once, as started by
+/// `BlackBox::transaction`. Nothing is inserted until `commit` is called.
+///
+/// Committing first borrows every unit which values were staged for, in the
+/// order of their `TypeId`s, and only inserts once all of them could be
+/// borrowed. In the case that any of them couldn't, because it isn't allocated,
+/// is borrowed elsewhere or would be changed from a finalizer, nothing is
+/// inserted, and the transaction is handed back with all of its values along
+/// with the error of each type which failed. A poisoned unit doesn't fail the
+/// transaction, but is emptied and unpoisoned like by `insert`.
+///
+/// A type with a single staged value has it inserted like by `insert`, and
+/// one with many like by `insert_many`.
+///
+/// Other threads see either none or all of the values of a committed
+/// transaction, as long as they borrow the units one at a time.
+///
+pub struct Transaction<'a, U: ?Sized + for<'b> Unit<'b, Owned = Box<dyn Any + Send>>> {
+    storage: &'a BlackBox<U>,
+    /// The values of each type, in the order the types were first staged in.
+    staged: Vec<Box<dyn Staged<U> + 'a>>,
+}
+
+impl<'a, U: ?Sized + for<'b> Unit<'b, Owned = Box<dyn Any + Send>>> Transaction<'a, U> {
+    ///
+    /// Stages `value` to be inserted into the unit for `T`, after the values
+    /// of `T` which were staged before it.
+    ///
+    pub fn insert<T: 'static + Send>(&mut self, value: T) -> &mut Self {
+        self.staged_for::<T>().push(value);
+        self
+    }
+
+    ///
+    /// Stages `values` to be inserted into the unit for `T`, after the values
+    /// of `T` which were staged before them.
+    ///
+    pub fn insert_many<T: 'static + Send>(&mut self, values: Vec<T>) -> &mut Self {
+        self.staged_for::<T>().extend(values);
+        self
+    }
+
+    ///
+    /// Takes the values staged for `T` out of the transaction, in the order
+    /// they were staged in. This is how the values of a transaction which
+    /// failed to commit are retrieved.
+    ///
+    pub fn take<T: 'static + Send>(&mut self) -> Vec<T> {
+        match self.staged.iter().position(|s| s.id() == TypeId::of::<T>()) {
+            Some(i) => *self.staged.remove(i).into_any().downcast().unwrap(),
+            None => Vec::new(),
+        }
+    }
+
+    ///
+    /// The number of values staged for all types.
+    ///
+    pub fn len(&self) -> usize {
+        self.staged.iter().map(|s| s.len()).sum()
+    }
+
+    ///
+    /// Whether no value is staged.
+    ///
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    ///
+    /// Inserts every staged value, or none of them. This returns the
+    /// transaction as it was, along with the name of each type whose unit
+    /// couldn't be borrowed and why, in the case that nothing was inserted.
+    /// Each error is wrapped in an `ErrorDesc::Context`, see `ErrorDesc::root`.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::{make_storage, ErrorDesc, RwLockStorage};
+    ///
+    /// #[derive(Debug, PartialEq)]
+    /// struct Position(i32, i32);
+    /// #[derive(Debug, PartialEq)]
+    /// struct Name(&'static str);
+    ///
+    /// let storage = make_storage!(RwLockStorage: Position);
+    /// let mut txn = storage.transaction();
+    /// txn.insert(Position(1, 2)).insert(Name("player"));
+    /// let (mut txn, errors) = txn.commit().unwrap_err();
+    /// assert_eq!(errors.len(), 1);
+    /// assert_eq!(errors[0].0, std::any::type_name::<Name>());
+    /// assert_eq!(errors[0].1.root(), &ErrorDesc::NoAllocatedUnit);
+    /// // Nothing was inserted, and the values are still there
+    /// assert!(storage.get::<Position>().is_err());
+    /// assert_eq!(txn.take::<Name>(), vec![Name("player")]);
+    ///
+    /// txn.commit().unwrap();
+    /// assert_eq!(*storage.get::<Position>().unwrap(), Position(1, 2));
+    /// # }
+    /// ```
+    ///
+    #[allow(clippy::type_complexity)]
+    pub fn commit(mut self) -> Result<(), (Self, Vec<(&'static str, ErrorDesc)>)> {
+        self.staged.sort_by_key(|s| s.id());
+        let storage = self.storage;
+        let mut units = Vec::with_capacity(self.staged.len());
+        let mut errors = Vec::new();
+        for staged in &self.staged {
+            match staged.lock(storage) {
+                Ok(unit) => units.push(unit),
+                Err(e) => errors.push((
+                    staged.type_name(),
+                    e.context("borrowing the unit to commit to"),
+                )),
+            }
+        }
+        if !errors.is_empty() {
+            return Err((self, errors));
+        }
+        for (staged, mut unit) in self.staged.drain(..).zip(units) {
+            staged.commit(storage, &mut unit);
+        }
+        Ok(())
+    }
+}
+
+impl<'a, U: ?Sized + for<'b> Unit<'b, Owned = Box<dyn Any + Send>>> Debug for Transaction<'a, U> {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        f.debug_map()
+            .entries(self.staged.iter().map(|s| (s.type_name(), s.len())))
+            .finish()
+    }
+}
+
+impl<U: ?Sized + for<'a> Unit<'a, Owned = Box<dyn Any + Send>>> BlackBox<U> {
+    ///
+    /// Starts staging values to be inserted into many units at once, so that
+    /// either all of them are inserted or none of them are, see `Transaction`.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::{make_storage, DynamicStorage};
+    ///
+    /// let storage = make_storage!(DynamicStorage: usize, String);
+    /// let mut txn = storage.transaction();
+    /// txn.insert(1usize).insert_many(vec![2usize, 3]);
+    /// txn.insert(String::from("entity"));
+    /// txn.commit().unwrap();
+    /// assert_eq!(&*storage.extract_many::<usize>().unwrap(), &[1, 2, 3]);
+    /// assert_eq!(&*storage.get::<String>().unwrap(), "entity");
+    /// # }
+    /// ```
+    ///
+    pub fn transaction(&self) -> Transaction<'_, U> {
+        Transaction {
+            storage: self,
+            staged: Vec::new(),
+        }
+    }
+}
+
+impl<'a, U: ?Sized + for<'b> Unit<'b, Owned = Box<dyn Any + Send>>> Transaction<'a, U> {
+    ///
+    /// Internal function. Returns the values staged for `T`, staging `T`
+    /// first if it isn't yet.
+    ///
+    fn staged_for<T: 'static + Send>(&mut self) -> &mut Vec<T> {
+        let i = match self.staged.iter().position(|s| s.id() == TypeId::of::<T>()) {
+            Some(i) => i,
+            None => {
+                self.staged.push(Box::new(Vec::<T>::new()));
+                self.staged.len() - 1
+            }
+        };
+        self.staged[i].as_any_mut().downcast_mut().unwrap()
+    }
+}
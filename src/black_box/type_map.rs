@@ -0,0 +1,151 @@
+use std::any::TypeId;
+use std::collections::HashMap;
+use std::hash::{BuildHasherDefault, Hasher};
+
+///
+/// Hashes a `TypeId` by keeping the bits it writes as they are. A `TypeId` is
+/// already a hash of its type, so hashing it again only slows down lookups.
+///
+#[derive(Default)]
+pub(crate) struct IdHasher(u64);
+
+impl Hasher for IdHasher {
+    #[inline]
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    #[inline]
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = self.0.rotate_left(8) ^ u64::from(byte);
+        }
+    }
+
+    #[inline]
+    fn write_u64(&mut self, n: u64) {
+        self.0 ^= n;
+    }
+}
+
+///
+/// A map keyed by `TypeId` which keeps its entries in the order they were
+/// first inserted in. `TypeId`s differ between builds and a `HashMap` iterates
+/// in a different order every run, so this is what makes going through every
+/// unit of a storage happen in the same order each time.
+///
+pub(crate) struct TypeMap<V> {
+    entries: Vec<(TypeId, V)>,
+    indices: HashMap<TypeId, usize, BuildHasherDefault<IdHasher>>,
+}
+
+impl<V> TypeMap<V> {
+    pub(crate) fn new() -> Self {
+        Self::with_capacity(0)
+    }
+
+    pub(crate) fn with_capacity(capacity: usize) -> Self {
+        Self {
+            entries: Vec::with_capacity(capacity),
+            indices: HashMap::with_capacity_and_hasher(capacity, Default::default()),
+        }
+    }
+
+    #[inline]
+    pub(crate) fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    #[inline]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    #[inline]
+    pub(crate) fn contains_key(&self, id: &TypeId) -> bool {
+        self.indices.contains_key(id)
+    }
+
+    #[inline]
+    pub(crate) fn get(&self, id: &TypeId) -> Option<&V> {
+        self.indices.get(id).map(|&index| &self.entries[index].1)
+    }
+
+    ///
+    /// Returns where the entry for `id` is in the order, which changes when
+    /// an entry before it is removed.
+    ///
+    #[inline]
+    pub(crate) fn index_of(&self, id: &TypeId) -> Option<usize> {
+        self.indices.get(id).copied()
+    }
+
+    ///
+    /// Returns the entry at `index` in the order, without hashing.
+    ///
+    #[inline]
+    pub(crate) fn get_index(&self, index: usize) -> Option<(&TypeId, &V)> {
+        self.entries.get(index).map(|(id, value)| (id, value))
+    }
+
+    ///
+    /// Returns the value for `id`, inserting the one returned by `make` at the
+    /// end in the case that there is none.
+    ///
+    pub(crate) fn get_or_insert_with<F: FnOnce() -> V>(&mut self, id: TypeId, make: F) -> &mut V {
+        let index = match self.indices.get(&id) {
+            Some(&index) => index,
+            None => {
+                self.entries.push((id, make()));
+                self.indices.insert(id, self.entries.len() - 1);
+                self.entries.len() - 1
+            }
+        };
+        &mut self.entries[index].1
+    }
+
+    ///
+    /// Inserts `value` for `id`, returning the previous value. A replaced
+    /// value keeps its place in the order.
+    ///
+    pub(crate) fn insert(&mut self, id: TypeId, value: V) -> Option<V> {
+        match self.indices.get(&id) {
+            Some(&index) => Some(std::mem::replace(&mut self.entries[index].1, value)),
+            None => {
+                self.entries.push((id, value));
+                self.indices.insert(id, self.entries.len() - 1);
+                None
+            }
+        }
+    }
+
+    ///
+    /// Removes the value for `id`, keeping the others in the same order.
+    ///
+    pub(crate) fn remove(&mut self, id: &TypeId) -> Option<V> {
+        let index = self.indices.remove(id)?;
+        let (_, value) = self.entries.remove(index);
+        for (id, _) in &self.entries[index..] {
+            *self.indices.get_mut(id).unwrap() -= 1;
+        }
+        Some(value)
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&TypeId, &V)> {
+        self.entries.iter().map(|(id, value)| (id, value))
+    }
+
+    pub(crate) fn keys(&self) -> impl Iterator<Item = &TypeId> {
+        self.entries.iter().map(|(id, _)| id)
+    }
+
+    pub(crate) fn values(&self) -> impl Iterator<Item = &V> {
+        self.entries.iter().map(|(_, value)| value)
+    }
+}
+
+impl<V> Default for TypeMap<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,14 +1,20 @@
 use std::any::{Any, TypeId};
-use std::fmt::{Debug, Formatter};
-use std::mem::swap;
+use std::error::Error;
+use std::fmt::{Debug, Display, Formatter};
+use std::mem::{replace, swap, MaybeUninit};
 use std::ops::{BitAnd, Deref, DerefMut};
+use std::ptr;
 
 pub type DynamicResult<Ok> = Result<Ok, ErrorDesc>;
 
 /// The basic error descriptions for why a dynamically typed resource operation didn't work. It does
 /// not contain however, the description for unit-related errors which handled with a `UnitError` by
 /// using the `Unit` variant of `ErrorDesc`.
-#[derive(Debug, PartialEq)]
+///
+/// Only `Two` and `Context` hold allocations, and neither is ever built by the accessors such
+/// as `get`, `ind` and `get_mut`, so their errors cost nothing to construct. `ErrorDesc::kind`
+/// gives a `Copy` summary of an error which can be kept around or compared freely.
+#[derive(Debug, Clone, PartialEq)]
 pub enum ErrorDesc {
     /// Returned if there is an incompatible borrow on the contents of the unit. It follows the same
     /// rules for runtime checking as a `RefCell<T>`. Usually bundled with a `Ref<T>`/`RefMut<T>` in
@@ -45,13 +51,256 @@ pub enum ErrorDesc {
     /// # }
     /// ```
     NoAllocatedUnit,
-    /// This is an internal error that should be ignored by the user. This should never be created.
+    /// Returned when a value is passed to a unit in a type erased form, such as through
+    /// `BlackBox::insert_boxed_named`, and is not of the unit's type.
     NoMatchingType,
+    /// Returned when registering a name for a unit with `allocate_named_for` in the case that the
+    /// name already refers to the unit of another type.
+    /// ## Example:
+    /// ```
+    /// # use restor::*;
+    /// # fn main() {
+    /// let mut storage = DynamicStorage::new();
+    /// storage.allocate_named_for::<usize>("config").unwrap();
+    /// assert_eq!(
+    ///     storage.allocate_named_for::<isize>("config"),
+    ///     Err(ErrorDesc::NameCollision)
+    /// );
+    /// # }
+    /// ```
+    NameCollision,
+    /// The case where the unit couldn't be locked because the current thread already holds
+    /// it. This is only distinguished from `BorrowedIncompatibly` for a storage created with
+    /// `MutexStorage::new_debug`.
+    RecursiveBorrow,
+    /// The case where the operation isn't supported by the kind of unit allocated for the
+    /// type, such as borrowing a value allocated with `allocate_read_mostly_for`.
+    UnsupportedOperation,
+    /// The case where a mutation of the unit panicked in a storage created with
+    /// `BlackBox::new_poisoning`, so its contents may be only partially updated.
+    /// This is returned until the poison is cleared with `BlackBox::clear_poison`,
+    /// or the contents are replaced through `BlackBox::insert`.
+    Poisoned,
+    /// The case where `BlackBox::compose` gave up because the value kept being changed or
+    /// locked by others between reading it and writing back the new one.
+    Contended,
+    /// The case where the storage behind a `StorageWeakHandle` was already dropped.
+    StorageDropped,
+    /// The case where values were removed from the unit read by a `Cursor`, or may have been
+    /// reordered, since the cursor last read it, so its position no longer refers to the value
+    /// after the last one it read. The cursor keeps returning this until it is reset with
+    /// `Cursor::restart`.
+    Invalidated,
     /// Contains an error specific to unit operations. Please refer to the `UnitError` documentation
     /// for more information.
     Unit(UnitError),
     /// The case where there were two errors
     Two(Box<(ErrorDesc, ErrorDesc)>),
+    /// Wraps an error that occurred during one step of a composite operation, describing which
+    /// step failed in `op`. This is built using `ErrorDesc::context`, and the original error can
+    /// be retrieved with `ErrorDesc::root` or by walking `Error::source`.
+    /// ## Example:
+    /// ```
+    /// # use restor::*;
+    /// # fn main() {
+    /// let err = ErrorDesc::NoAllocatedUnit.context("insert during get_or_insert");
+    /// assert_eq!(err.root(), &ErrorDesc::NoAllocatedUnit);
+    /// assert_eq!(
+    ///     err.to_string(),
+    ///     "insert during get_or_insert: there is no unit allocated for the type"
+    /// );
+    /// # }
+    /// ```
+    Context {
+        op: &'static str,
+        source: Box<ErrorDesc>,
+    },
+}
+
+impl ErrorDesc {
+    ///
+    /// Wraps this error with a description of the operation during which it occurred.
+    ///
+    pub fn context(self, op: &'static str) -> Self {
+        ErrorDesc::Context {
+            op,
+            source: Box::new(self),
+        }
+    }
+
+    ///
+    /// Returns what kind of error this is, looking through any `Context` wrappers.
+    ///
+    /// # Example
+    /// ```
+    /// # use restor::*;
+    /// # fn main() {
+    /// let storage = make_storage!(DynamicStorage: usize);
+    /// storage.insert(0usize).unwrap();
+    /// let _lock = storage.get_mut::<usize>().unwrap();
+    /// let err = storage.get::<usize>().unwrap_err();
+    /// assert_eq!(err.kind(), ErrorKind::BorrowedIncompatibly);
+    /// assert_eq!(err.context("polling").kind(), ErrorKind::BorrowedIncompatibly);
+    /// # }
+    /// ```
+    ///
+    pub fn kind(&self) -> ErrorKind {
+        match self.root() {
+            ErrorDesc::BorrowedIncompatibly => ErrorKind::BorrowedIncompatibly,
+            ErrorDesc::NoAllocatedUnit => ErrorKind::NoAllocatedUnit,
+            ErrorDesc::NoMatchingType => ErrorKind::NoMatchingType,
+            ErrorDesc::NameCollision => ErrorKind::NameCollision,
+            ErrorDesc::RecursiveBorrow => ErrorKind::RecursiveBorrow,
+            ErrorDesc::UnsupportedOperation => ErrorKind::UnsupportedOperation,
+            ErrorDesc::Poisoned => ErrorKind::Poisoned,
+            ErrorDesc::Contended => ErrorKind::Contended,
+            ErrorDesc::StorageDropped => ErrorKind::StorageDropped,
+            ErrorDesc::Invalidated => ErrorKind::Invalidated,
+            ErrorDesc::Unit(e) => ErrorKind::Unit(*e),
+            ErrorDesc::Two(_) => ErrorKind::Two,
+            ErrorDesc::Context { .. } => unreachable!(),
+        }
+    }
+
+    ///
+    /// Walks through any `Context` wrappers, returning the error which originally occurred.
+    ///
+    pub fn root(&self) -> &ErrorDesc {
+        let mut current = self;
+        while let ErrorDesc::Context { source, .. } = current {
+            current = source;
+        }
+        current
+    }
+}
+
+impl Display for ErrorDesc {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            ErrorDesc::BorrowedIncompatibly => write!(f, "the unit is borrowed incompatibly"),
+            ErrorDesc::NoAllocatedUnit => write!(f, "there is no unit allocated for the type"),
+            ErrorDesc::NoMatchingType => write!(f, "the value does not match the unit's type"),
+            ErrorDesc::NameCollision => write!(f, "the name refers to the unit of another type"),
+            ErrorDesc::RecursiveBorrow => {
+                write!(f, "the unit is already borrowed by the current thread")
+            }
+            ErrorDesc::UnsupportedOperation => {
+                write!(f, "the operation is not supported by the unit")
+            }
+            ErrorDesc::Poisoned => write!(f, "the unit was poisoned by a panic"),
+            ErrorDesc::Contended => write!(f, "the value kept changing while being composed"),
+            ErrorDesc::StorageDropped => write!(f, "the storage was dropped"),
+            ErrorDesc::Invalidated => write!(f, "the unit was changed under the cursor"),
+            ErrorDesc::Unit(e) => write!(f, "{}", e),
+            ErrorDesc::Two(errors) => write!(f, "{} and {}", errors.0, errors.1),
+            ErrorDesc::Context { op, source } => write!(f, "{}: {}", op, source),
+        }
+    }
+}
+
+impl Error for ErrorDesc {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ErrorDesc::Context { source, .. } => Some(&**source),
+            _ => None,
+        }
+    }
+}
+
+///
+/// The error of a fallible closure run on the values of a storage, such as with
+/// `BlackBox::try_with`, which is either an error from the storage itself, or the
+/// error returned by the closure.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub enum TryError<E> {
+    /// The closure could not be run, or could not be run on every value.
+    Storage(ErrorDesc),
+    /// The closure returned this error, stopping early.
+    User(E),
+}
+
+impl<E> TryError<E> {
+    ///
+    /// Returns the error of the closure, or `None` if the error came from the storage.
+    ///
+    pub fn user(self) -> Option<E> {
+        match self {
+            TryError::User(e) => Some(e),
+            TryError::Storage(_) => None,
+        }
+    }
+}
+
+impl<E> From<ErrorDesc> for TryError<E> {
+    fn from(e: ErrorDesc) -> Self {
+        TryError::Storage(e)
+    }
+}
+
+impl<E: Display> Display for TryError<E> {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            TryError::Storage(e) => write!(f, "{}", e),
+            TryError::User(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl<E: Error + 'static> Error for TryError<E> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TryError::Storage(e) => Some(e),
+            TryError::User(e) => Some(e),
+        }
+    }
+}
+
+///
+/// A unit which is stored under the `TypeId` of another type than the one it
+/// stores, as reported by `BlackBox::validate`.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IntegrityError {
+    /// The `TypeId` the unit is stored under.
+    pub key: TypeId,
+    /// The `TypeId` of the type the unit stores, as given by `Unit::id`.
+    pub found: TypeId,
+    /// The name of the type the unit stores, as given by `Unit::type_name`.
+    pub type_name: &'static str,
+}
+
+impl Display for IntegrityError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "A unit storing `{}` is stored under {:?}",
+            self.type_name, self.key
+        )
+    }
+}
+
+impl Error for IntegrityError {}
+
+///
+/// The kind of an `ErrorDesc`, without the other errors it may hold. There is one
+/// kind for each variant of `ErrorDesc` except `Context`, which takes the kind of
+/// the error it wraps.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    BorrowedIncompatibly,
+    NoAllocatedUnit,
+    NoMatchingType,
+    NameCollision,
+    RecursiveBorrow,
+    UnsupportedOperation,
+    Poisoned,
+    Contended,
+    StorageDropped,
+    Invalidated,
+    Unit(UnitError),
+    Two,
 }
 
 impl BitAnd for ErrorDesc {
@@ -68,14 +317,142 @@ impl BitAnd for ErrorDesc {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum UnitError {
     IsNotOne,
     IsNotMany,
     IsNone,
     OutOfBounds,
+    /// The case where the same index was given more than once for values which
+    /// are borrowed mutably at the same time, see `BlackBox::get_disjoint_ind_mut`.
+    DuplicateIndex,
+    /// The case where a bounded insertion was rejected because the unit already
+    /// held as many values as it may, see `BlackBox::insert_bounded`.
+    Full,
+}
+
+impl Display for UnitError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            UnitError::IsNotOne => write!(f, "the unit does not contain exactly one value"),
+            UnitError::IsNotMany => write!(f, "the unit does not contain many values"),
+            UnitError::IsNone => write!(f, "the unit is empty"),
+            UnitError::OutOfBounds => write!(f, "the index is out of bounds"),
+            UnitError::DuplicateIndex => write!(f, "the index was given more than once"),
+            UnitError::Full => write!(f, "the unit is full"),
+        }
+    }
+}
+
+impl Error for UnitError {}
+
+///
+/// A change made to the values of a unit while it was borrowed through
+/// `storage_mut`, as reported to the unit by `Unit::record_change`.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueChange {
+    /// A value was inserted, and now lives at `index`.
+    Inserted { index: usize },
+    /// The value at `index` was removed, moving those after it down.
+    Extracted { index: usize },
+    /// Every value was removed.
+    Cleared,
+}
+
+///
+/// The shape of the values held by a unit, as reported by `StorageUnit::state`
+/// and `BlackBox::summary`.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum UnitState {
+    /// There are no values.
+    Empty,
+    /// There is a single value.
+    Single,
+    /// There is a list of values, of the given length. This may be `0` or `1`,
+    /// see `StorageUnit`.
+    Multiple(usize),
+}
+
+///
+/// Which of readers and writers get the unit for `T` of a `RwLockStorage` when
+/// both keep trying to, as chosen with `RwLockStorage::allocate_for_with_fairness`
+/// and reported by `BlackBox::fairness`.
+///
+/// Every borrow of a `RwLockStorage` is a try-lock, which fails rather than
+/// waits, so with readers overlapping one another a writer may never find the
+/// unit free. `WriterPriority` lets a writer which failed hold off new readers
+/// for a moment, during which the readers already holding the unit finish.
+///
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Fairness {
+    /// Readers borrow the unit whenever no writer holds it, even while writers
+    /// are failing to, which is how every unit behaves unless told otherwise.
+    #[default]
+    ReaderPriority,
+    /// After a writer fails to borrow the unit because of readers, new readers
+    /// fail with `ErrorDesc::BorrowedIncompatibly` until a writer borrows it,
+    /// or for at most 10 milliseconds after the last failed attempt, so that a
+    /// writer which gives up doesn't hold readers off for longer than that.
+    WriterPriority,
+}
+
+impl UnitState {
+    ///
+    /// The number of values in this state.
+    ///
+    pub fn count(self) -> usize {
+        match self {
+            UnitState::Empty => 0,
+            UnitState::Single => 1,
+            UnitState::Multiple(count) => count,
+        }
+    }
+}
+
+impl Display for UnitState {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            UnitState::Empty => write!(f, "Empty"),
+            UnitState::Single => write!(f, "Single"),
+            UnitState::Multiple(count) => write!(f, "Multiple({})", count),
+        }
+    }
 }
 
+///
+/// The container for the values of a single type. This is what each `Unit` wraps
+/// in its lock, but it can also be used on its own.
+///
+/// - `Nope`: There are no values stored.
+/// - `One`: There is exactly one value stored.
+/// - `Many`: There is a list of values stored.
+///
+/// When accessing the values positionally, `One` acts as a list with a single value
+/// at index `0`.
+///
+/// A unit only becomes `Many` through a second insertion or through `insert_many`
+/// with at least one value, and it stays `Many` until its last value is extracted,
+/// at which point it goes back to `Nope`. It never goes from `Many` to `One`, so a
+/// `Many` holding a single value is still read through `ind` rather than `one`.
+///
+/// # Example
+/// ```
+/// # fn main() {
+/// use restor::StorageUnit;
+/// let mut unit = StorageUnit::new();
+/// unit.insert(1usize);
+/// assert_eq!(unit.get(0), Some(&1));
+/// unit.extend(vec![2, 3]);
+/// assert_eq!(unit.len(), 3);
+/// assert_eq!(unit.iter().sum::<usize>(), 6);
+/// # }
+/// ```
+///
+#[derive(Debug)]
 pub enum StorageUnit<T: Sized + 'static> {
     Nope,
     One(T),
@@ -87,20 +464,207 @@ impl<T: Sized> StorageUnit<T> {
         StorageUnit::Nope
     }
 
+    ///
+    /// The number of values stored in the unit.
+    ///
+    pub fn len(&self) -> usize {
+        match self {
+            StorageUnit::Nope => 0,
+            StorageUnit::One(_) => 1,
+            StorageUnit::Many(many) => many.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    ///
+    /// The variant of the unit, along with the number of values for `Many`.
+    ///
+    pub fn state(&self) -> UnitState {
+        match self {
+            StorageUnit::Nope => UnitState::Empty,
+            StorageUnit::One(_) => UnitState::Single,
+            StorageUnit::Many(many) => UnitState::Multiple(many.len()),
+        }
+    }
+
+    ///
+    /// The number of values the unit can hold without reallocating.
+    /// This is `0` for `Nope`, `1` for `One`, and the capacity of the
+    /// `Vec` for `Many`.
+    ///
+    pub fn capacity(&self) -> usize {
+        match self {
+            StorageUnit::Nope => 0,
+            StorageUnit::One(_) => 1,
+            StorageUnit::Many(many) => many.capacity(),
+        }
+    }
+
+    ///
+    /// The number of bytes taken up by the unit and the buffer of its list.
+    /// Memory owned by the values themselves, such as the contents of a
+    /// `String`, is not counted.
+    ///
+    pub fn approx_bytes(&self) -> usize {
+        let buffer = match self {
+            StorageUnit::Many(many) => many.capacity() * std::mem::size_of::<T>(),
+            _ => 0,
+        };
+        std::mem::size_of::<Self>() + buffer
+    }
+
+    ///
+    /// Shrinks the capacity of a `Many` unit as much as possible.
+    ///
+    pub fn shrink_to_fit(&mut self) {
+        if let StorageUnit::Many(many) = self {
+            many.shrink_to_fit();
+        }
+    }
+
+    ///
+    /// Reads a byte from every page of memory behind the list of a `Many` unit,
+    /// so that any of them which aren't resident are faulted in now rather
+    /// than on their first use. The values are left untouched, and this does
+    /// nothing for units which aren't `Many`.
+    ///
+    pub fn prefault(&self) {
+        if let StorageUnit::Many(many) = self {
+            let bytes = std::mem::size_of_val(&many[..]);
+            let start = many.as_ptr() as *const MaybeUninit<u8>;
+            for offset in page_offsets(bytes) {
+                // Reading the bytes as `MaybeUninit` is fine even for padding
+                unsafe { ptr::read_volatile(start.add(offset)) };
+            }
+        }
+    }
+
+    ///
+    /// Reserves room for at least `additional` more values in the list of a
+    /// `Many` unit, without touching the values. Like `warm_capacity`, but
+    /// without faulting the room in. This does nothing for units which aren't
+    /// `Many`, as they have no list to keep the room in.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::{StorageUnit, UnitState};
+    /// let mut unit = StorageUnit::<usize>::from(vec![1, 2]);
+    /// unit.reserve(64);
+    /// assert_eq!(unit.state(), UnitState::Multiple(2));
+    /// assert!(unit.capacity() >= 66);
+    /// # }
+    /// ```
+    ///
+    pub fn reserve(&mut self, additional: usize) {
+        if let StorageUnit::Many(many) = self {
+            many.reserve(additional);
+        }
+    }
+
+    ///
+    /// Reserves room for at least `additional` more values in the list of a
+    /// `Many` unit, and writes to every page of the room which isn't used yet,
+    /// so that storing the values doesn't fault them in one at a time. The
+    /// values are left untouched, and this does nothing for units which aren't
+    /// `Many`, see `BlackBox::warm_capacity` for warming those.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::{StorageUnit, UnitState};
+    /// let mut unit = StorageUnit::<usize>::from(vec![0]);
+    /// unit.warm_capacity(1024);
+    /// assert_eq!(unit.state(), UnitState::Multiple(1));
+    /// assert!(unit.capacity() >= 1025);
+    /// unit.extend(1..1025usize);
+    /// assert!(unit.capacity() >= 1025);
+    /// # }
+    /// ```
+    ///
+    pub fn warm_capacity(&mut self, additional: usize) {
+        if let StorageUnit::Many(many) = self {
+            warm(many, additional);
+        }
+    }
+
+    ///
+    /// Returns a reference to the value at `ind`, or `None` if it is out of bounds.
+    ///
+    pub fn get(&self, ind: usize) -> Option<&T> {
+        self.as_slice().get(ind)
+    }
+
+    pub fn get_mut(&mut self, ind: usize) -> Option<&mut T> {
+        self.as_mut_slice().get_mut(ind)
+    }
+
+    ///
+    /// Like `StorageUnit::get`, but describes why the value could not be
+    /// returned: `IsNone` if there are no values and `ind` is `0`, `IsNotMany`
+    /// if there aren't many values, and `OutOfBounds` otherwise.
+    ///
+    pub fn ind(&self, ind: usize) -> DynamicResult<&T> {
+        match self {
+            StorageUnit::Many(many) => many.get(ind).ok_or(ErrorDesc::Unit(UnitError::OutOfBounds)),
+            _ => self.get(ind).ok_or_else(|| self.ind_error(ind)),
+        }
+    }
+
+    pub fn ind_mut(&mut self, ind: usize) -> DynamicResult<&mut T> {
+        let error = self.ind_error(ind);
+        self.get_mut(ind).ok_or(error)
+    }
+
+    ///
+    /// Returns the value `back` places from the end, where `0` is the last value. A
+    /// single value counts as one element, and no values as none, so this returns
+    /// `OutOfBounds` whenever `back` isn't less than `len()`.
+    ///
+    pub fn ind_from_end(&self, back: usize) -> DynamicResult<&T> {
+        self.as_slice()
+            .iter()
+            .rev()
+            .nth(back)
+            .ok_or(ErrorDesc::Unit(UnitError::OutOfBounds))
+    }
+
+    pub fn ind_from_end_mut(&mut self, back: usize) -> DynamicResult<&mut T> {
+        self.as_mut_slice()
+            .iter_mut()
+            .rev()
+            .nth(back)
+            .ok_or(ErrorDesc::Unit(UnitError::OutOfBounds))
+    }
+
+    fn ind_error(&self, ind: usize) -> ErrorDesc {
+        match self {
+            StorageUnit::Many(_) => ErrorDesc::Unit(UnitError::OutOfBounds),
+            StorageUnit::Nope if ind == 0 => ErrorDesc::Unit(UnitError::IsNone),
+            _ => ErrorDesc::Unit(UnitError::IsNotMany),
+        }
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, T> {
+        self.as_slice().iter()
+    }
+
+    pub fn iter_mut(&mut self) -> std::slice::IterMut<'_, T> {
+        self.as_mut_slice().iter_mut()
+    }
+
     pub fn insert(&mut self, new: T) {
         match self {
             StorageUnit::Nope => {
                 *self = StorageUnit::One(new);
             }
             StorageUnit::One(_) => {
-                let mut rep = StorageUnit::Many(vec![new]);
-                swap(self, &mut rep);
-                if let StorageUnit::One(prev) = rep {
-                    if let StorageUnit::Many(v) = self {
-                        v.insert(0, prev);
-                    } else {
-                        unreachable!()
-                    }
+                // Both values go into a single allocation, rather than growing a `Vec` of one
+                if let StorageUnit::One(prev) = replace(self, StorageUnit::Nope) {
+                    *self = StorageUnit::Many(vec![prev, new]);
                 } else {
                     unreachable!()
                 }
@@ -112,6 +676,9 @@ impl<T: Sized> StorageUnit<T> {
     }
 
     pub fn insert_many(&mut self, mut new: Vec<T>) {
+        if new.is_empty() {
+            return;
+        }
         match self {
             StorageUnit::Nope => {
                 *self = StorageUnit::Many(new);
@@ -159,6 +726,26 @@ impl<T: Sized> StorageUnit<T> {
         }
     }
 
+    ///
+    /// Views the contents of the unit as a slice, treating `One` as a
+    /// single element slice and `Nope` as an empty one.
+    ///
+    pub fn as_slice(&self) -> &[T] {
+        match self {
+            StorageUnit::Nope => &[],
+            StorageUnit::One(x) => std::slice::from_ref(x),
+            StorageUnit::Many(x) => x,
+        }
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        match self {
+            StorageUnit::Nope => &mut [],
+            StorageUnit::One(x) => std::slice::from_mut(x),
+            StorageUnit::Many(x) => x,
+        }
+    }
+
     pub fn many_mut(&mut self) -> DynamicResult<&mut Vec<T>> {
         if let StorageUnit::Many(x) = self {
             Ok(x)
@@ -170,7 +757,8 @@ impl<T: Sized> StorageUnit<T> {
     pub fn extract_one(&mut self) -> DynamicResult<T> {
         match self {
             StorageUnit::Nope => Err(ErrorDesc::Unit(UnitError::IsNotOne)),
-            StorageUnit::Many(x) => Ok(x.remove(0)),
+            StorageUnit::Many(many) if many.is_empty() => Err(ErrorDesc::Unit(UnitError::IsNone)),
+            StorageUnit::Many(_) => self.extract_ind(0),
             StorageUnit::One(_) => {
                 let mut repl = StorageUnit::Nope;
                 swap(&mut repl, self);
@@ -183,6 +771,41 @@ impl<T: Sized> StorageUnit<T> {
         }
     }
 
+    ///
+    /// Removes the value at `ind`, following the same rules as `StorageUnit::ind`.
+    ///
+    pub fn extract_ind(&mut self, ind: usize) -> DynamicResult<T> {
+        match self {
+            StorageUnit::Many(many) if ind < many.len() => {
+                let value = many.remove(ind);
+                // A unit is only `Many` while it has values
+                if many.is_empty() {
+                    *self = StorageUnit::Nope;
+                }
+                Ok(value)
+            }
+            StorageUnit::One(_) if ind == 0 => self.extract_one(),
+            _ => Err(self.ind_error(ind)),
+        }
+    }
+
+    ///
+    /// Removes the value at `ind` like `extract_ind`, but moves the last value
+    /// into its place instead of shifting the values after it down.
+    ///
+    pub fn swap_extract_ind(&mut self, ind: usize) -> DynamicResult<T> {
+        match self {
+            StorageUnit::Many(many) if ind < many.len() => {
+                let value = many.swap_remove(ind);
+                if many.is_empty() {
+                    *self = StorageUnit::Nope;
+                }
+                Ok(value)
+            }
+            _ => self.extract_ind(ind),
+        }
+    }
+
     pub fn extract_many(&mut self) -> DynamicResult<Vec<T>> {
         match self {
             StorageUnit::Nope => Err(ErrorDesc::Unit(UnitError::IsNotMany)),
@@ -222,6 +845,114 @@ impl<T: Sized> StorageUnit<T> {
             }
         }
     }
+
+    ///
+    /// Removes every value from the unit regardless of how many there are,
+    /// returning an empty `Vec` for `Nope`.
+    ///
+    pub fn extract_any_count(&mut self) -> Vec<T> {
+        replace(self, StorageUnit::Nope).into_iter().collect()
+    }
+
+    ///
+    /// Moves every value to the end of `buf`, leaving no values behind, and
+    /// returns how many were moved. Like `extract_any_count`, but without
+    /// allocating a new `Vec` for them.
+    ///
+    pub fn extract_many_into(&mut self, buf: &mut Vec<T>) -> usize {
+        match replace(self, StorageUnit::Nope) {
+            StorageUnit::Nope => 0,
+            StorageUnit::One(x) => {
+                buf.push(x);
+                1
+            }
+            StorageUnit::Many(mut many) => {
+                let moved = many.len();
+                buf.append(&mut many);
+                moved
+            }
+        }
+    }
+
+    ///
+    /// Removes the single value, or the first value of a list, returning
+    /// `UnitError::IsNone` only in the case that there are no values.
+    ///
+    pub fn extract_one_loose(&mut self) -> DynamicResult<T> {
+        match self {
+            StorageUnit::Nope => Err(ErrorDesc::Unit(UnitError::IsNone)),
+            StorageUnit::Many(many) if many.is_empty() => Err(ErrorDesc::Unit(UnitError::IsNone)),
+            _ => self.extract_ind(0),
+        }
+    }
+
+    ///
+    /// Replaces every value in the unit with the result of calling `f` on it, without
+    /// reallocating the underlying `Vec`.
+    ///
+    /// If `f` panics, the value it was handed is lost, but every other value is kept, in
+    /// order, and the unit is left in a valid state.
+    ///
+    pub fn map_in_place<F: FnMut(T) -> T>(&mut self, mut f: F) {
+        match self {
+            StorageUnit::Nope => {}
+            StorageUnit::One(_) => {
+                let mut repl = StorageUnit::Nope;
+                swap(&mut repl, self);
+                if let StorageUnit::One(data) = repl {
+                    *self = StorageUnit::One(f(data));
+                } else {
+                    unreachable!()
+                }
+            }
+            StorageUnit::Many(many) => {
+                let len = many.len();
+                // The `Vec` is emptied while its elements are being moved in and out of it, so
+                // that a panic in `f` cannot cause a double drop. `MapGuard` restores the length.
+                unsafe {
+                    many.set_len(0);
+                }
+                let mut guard = MapGuard {
+                    vec: many,
+                    len,
+                    mapped: 0,
+                };
+                while guard.mapped < guard.len {
+                    unsafe {
+                        let slot = guard.vec.as_mut_ptr().add(guard.mapped);
+                        ptr::write(slot, f(ptr::read(slot)));
+                    }
+                    guard.mapped += 1;
+                }
+            }
+        }
+    }
+}
+
+/// Restores the length of a `Vec` that is being mapped by `StorageUnit::map_in_place`, closing
+/// the hole left by the element being mapped if the mapping function panicked.
+struct MapGuard<'a, T> {
+    vec: &'a mut Vec<T>,
+    len: usize,
+    mapped: usize,
+}
+
+impl<'a, T> Drop for MapGuard<'a, T> {
+    fn drop(&mut self) {
+        unsafe {
+            if self.mapped < self.len {
+                let base = self.vec.as_mut_ptr();
+                ptr::copy(
+                    base.add(self.mapped + 1),
+                    base.add(self.mapped),
+                    self.len - self.mapped - 1,
+                );
+                self.vec.set_len(self.len - 1);
+            } else {
+                self.vec.set_len(self.len);
+            }
+        }
+    }
 }
 
 impl<T> Default for StorageUnit<T> {
@@ -230,6 +961,78 @@ impl<T> Default for StorageUnit<T> {
     }
 }
 
+impl<T> From<T> for StorageUnit<T> {
+    fn from(value: T) -> Self {
+        StorageUnit::One(value)
+    }
+}
+
+impl<T> From<Vec<T>> for StorageUnit<T> {
+    fn from(values: Vec<T>) -> Self {
+        StorageUnit::Many(values)
+    }
+}
+
+/// The offsets of a byte in each page of memory of a region `bytes` long,
+/// assuming pages of 4KiB, which is the smallest size in common use.
+fn page_offsets(bytes: usize) -> impl Iterator<Item = usize> {
+    const PAGE_SIZE: usize = 4096;
+    (0..bytes).step_by(PAGE_SIZE).chain(bytes.checked_sub(1))
+}
+
+/// Reserves room for at least `additional` more values in `list`, and writes
+/// to every page of the room which isn't used yet, see `StorageUnit::warm_capacity`.
+pub(super) fn warm<T>(list: &mut Vec<T>, additional: usize) {
+    list.reserve(additional);
+    let spare = list.spare_capacity_mut();
+    let bytes = std::mem::size_of_val(spare);
+    let start = spare.as_mut_ptr() as *mut MaybeUninit<u8>;
+    for offset in page_offsets(bytes) {
+        unsafe { ptr::write_volatile(start.add(offset), MaybeUninit::new(0)) };
+    }
+}
+
+impl<T> Extend<T> for StorageUnit<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        if let StorageUnit::Many(many) = self {
+            many.extend(iter);
+        } else {
+            self.insert_many(iter.into_iter().collect());
+        }
+    }
+}
+
+impl<T> IntoIterator for StorageUnit<T> {
+    type Item = T;
+    type IntoIter = std::vec::IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        match self {
+            StorageUnit::Nope => Vec::new().into_iter(),
+            StorageUnit::One(x) => vec![x].into_iter(),
+            StorageUnit::Many(many) => many.into_iter(),
+        }
+    }
+}
+
+impl<'a, T> IntoIterator for &'a StorageUnit<T> {
+    type Item = &'a T;
+    type IntoIter = std::slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a mut StorageUnit<T> {
+    type Item = &'a mut T;
+    type IntoIter = std::slice::IterMut<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
 impl<T: Clone> Clone for StorageUnit<T> {
     fn clone(&self) -> Self {
         match self {
@@ -240,35 +1043,159 @@ impl<T: Clone> Clone for StorageUnit<T> {
     }
 }
 
-pub trait Unit<'a> {
-    type Borrowed: Deref<Target = (dyn Any + Send)> + 'a;
-    type MutBorrowed: Deref<Target = (dyn Any + Send)> + DerefMut + 'a;
-    type Owned: Deref<Target = (dyn Any + Send)> + DerefMut;
+///
+/// The interior mutability around the `StorageUnit<T>` of a single type `T`,
+/// which a `BlackBox` looks up by `TypeId` and stores values of `T` in. This is
+/// implemented by `RefCellUnit`, `MutexUnit` and `RwLockUnit`, and may be
+/// implemented outside of this crate to store values behind other kinds of
+/// locks, see the `backend` module.
+///
+/// Every method which borrows the unit should fail rather than block in the case
+/// that it can't be borrowed, returning `ErrorDesc::BorrowedIncompatibly`, or
+/// `ErrorDesc::RecursiveBorrow` in the case that the current thread is the one
+/// holding it. Otherwise, the methods succeed and fail in the same cases as the
+/// methods of `StorageUnit` they are named after, with the same errors, which
+/// `backend::conformance::run` checks.
+///
+/// # Safety
+/// The typed accessors of `BlackBox` cast the values handed out by a unit to
+/// `T` without checking their type, and hand out `&mut T` based on the borrows
+/// of the unit, so implementations must uphold the following:
+///  - `id` always returns `TypeId::of::<T>()`, for the `T` the unit stores, and
+///    not the `TypeId` of the unit itself.
+///  - The guards returned by `one`, `ind` and `ind_from_end`, and their mutable
+///    variants, deref to a `T`, and those of `storage` and `storage_mut` to the
+///    `StorageUnit<T>`.
+///  - A guard returned by one of the mutable variants, or by `storage_mut`, is
+///    never alive at the same time as any other guard of the same unit.
+///  - Nothing moves or drops the values while a guard refers to them. In
+///    particular, `insert_any` may reallocate the values, and so must fail
+///    while any guard is alive.
+///
+pub unsafe trait Unit<'a> {
+    /// The guard of a shared borrow, which derefs to the borrowed value.
+    type Borrowed: Deref<Target = dyn Any + Send> + 'a;
+    /// The guard of an exclusive borrow, which derefs to the borrowed value.
+    type MutBorrowed: Deref<Target = dyn Any + Send> + DerefMut + 'a;
+    /// A value moved out of the unit. This is always `Box<dyn Any + Send>` for
+    /// units which are stored in a `BlackBox`.
+    type Owned: Deref<Target = dyn Any + Send> + DerefMut;
 
+    /// Borrows the single value, see `StorageUnit::one`.
     fn one(&'a self) -> DynamicResult<Self::Borrowed>;
+    /// Borrows the single value mutably, see `StorageUnit::one_mut`.
     fn one_mut(&'a self) -> DynamicResult<Self::MutBorrowed>;
 
+    /// Borrows the value at `ind`, see `StorageUnit::ind`.
     fn ind(&'a self, ind: usize) -> DynamicResult<Self::Borrowed>;
+    /// Borrows the value at `ind` mutably, see `StorageUnit::ind_mut`.
     fn ind_mut(&'a self, ind: usize) -> DynamicResult<Self::MutBorrowed>;
+    /// Borrows the value `back` places from the end, see `StorageUnit::ind_from_end`.
+    fn ind_from_end(&'a self, back: usize) -> DynamicResult<Self::Borrowed>;
+    /// Borrows the value `back` places from the end mutably, see
+    /// `StorageUnit::ind_from_end_mut`.
+    fn ind_from_end_mut(&'a self, back: usize) -> DynamicResult<Self::MutBorrowed>;
 
+    /// Moves out the single value as a `Box<T>`, see `StorageUnit::extract_one`.
     fn extract(&self) -> DynamicResult<Self::Owned>;
+    /// Moves out the value at `ind` as a `Box<T>`, see `StorageUnit::extract_ind`.
     fn extract_ind(&self, ind: usize) -> DynamicResult<Self::Owned>;
+    /// Moves out every value as a `Box<Box<[T]>>`, see `StorageUnit::extract_many_boxed`.
     fn extract_many(&self) -> DynamicResult<Self::Owned>;
 
+    /// Inserts a `Box<T>` with `StorageUnit::insert`, or a `Box<Vec<T>>` with
+    /// `StorageUnit::insert_many`. Any other value is returned alongside an
+    /// `ErrorDesc::NoMatchingType`.
+    #[must_use]
     fn insert_any(&self, new: Self::Owned) -> Option<(Self::Owned, ErrorDesc)>;
+    /// Borrows the values, and runs the closure behind `func` on `StorageUnit::many`,
+    /// returning `None` in the case that the unit can't be borrowed. This is best
+    /// implemented with `backend::run_for_values`.
+    ///
+    /// # Safety
+    /// `func` must be a `TypeId` and fat pointer pair produced by `BlackBox::run_for`, where the
+    /// pointer refers to a live `dyn Fn(DynamicResult<&[T]>) -> Option<Box<dyn Any>>`.
     unsafe fn run_for(&self, func: (TypeId, (*const (), *const ()))) -> Option<Box<dyn Any>>;
 
+    /// Borrows the whole `StorageUnit<T>`.
     fn storage(&'a self) -> DynamicResult<Self::Borrowed>;
+    /// Borrows the whole `StorageUnit<T>` mutably.
     fn storage_mut(&'a self) -> DynamicResult<Self::MutBorrowed>;
 
+    /// The `TypeId` of the type stored in this unit.
     fn id(&self) -> TypeId;
+    /// The name of the type stored in this unit, as given by `std::any::type_name`.
+    fn type_name(&self) -> &'static str;
+    /// The state of the unit, or `None` if it is borrowed exclusively. This never blocks.
+    fn state(&self) -> Option<UnitState>;
+    /// Whether there is any borrow held on the unit. This never blocks.
+    fn is_borrowed(&self) -> bool;
+    /// The capacity of the unit, see `StorageUnit::capacity`, or `None` if it is borrowed
+    /// exclusively. This never blocks. By default, this is unknown and returns `None`.
+    fn capacity(&self) -> Option<usize> {
+        None
+    }
+    /// The memory used by the unit, see `StorageUnit::approx_bytes`, or `None` if it is
+    /// borrowed exclusively. This never blocks. By default, this is unknown and returns `None`.
+    fn approx_bytes(&self) -> Option<usize> {
+        None
+    }
+    /// The number of times the unit has been borrowed mutably, which is counted when the
+    /// borrow is acquired rather than when it is released.
+    fn version(&self) -> u64;
+    /// Which of readers and writers get the unit when both keep trying to, see `Fairness`.
+    /// By default, this is `Fairness::ReaderPriority`.
+    fn fairness(&self) -> Fairness {
+        Fairness::ReaderPriority
+    }
+    /// Creates a unit holding the same values, which don't change along with those of this
+    /// unit. The new unit is returned as a `Box` of the trait object this unit is stored as,
+    /// see `BlackBox::fork`. By default, units can't be forked, and this returns
+    /// `ErrorDesc::UnsupportedOperation`.
+    fn fork(&self) -> DynamicResult<Box<dyn Any>> {
+        Err(ErrorDesc::UnsupportedOperation)
+    }
+    /// Returns the indices of the values which were borrowed mutably since this was last
+    /// called, in ascending order, and forgets them, see `BlackBox::take_dirty`. By default,
+    /// units don't keep track of this, and this returns `ErrorDesc::UnsupportedOperation`.
+    fn take_dirty(&self) -> DynamicResult<Vec<usize>> {
+        Err(ErrorDesc::UnsupportedOperation)
+    }
+    /// Called right before `storage_mut` by the methods of `BlackBox` which go on
+    /// to report every change they make to the values through `record_change`,
+    /// rather than handing the whole storage out. By default, this does nothing.
+    fn expect_changes(&self) {}
+    /// Reports a change made to the values through the storage borrowed after
+    /// `expect_changes` was called. By default, this does nothing.
+    fn record_change(&self, _change: ValueChange) {}
+}
+
+///
+/// Runs the closure behind `func`, as passed to `Unit::run_for`, on `values`,
+/// in the case that it takes a `DynamicResult<&[T]>`, returning `None` otherwise.
+///
+/// # Safety
+/// `func` must be the pair passed to `Unit::run_for` by `BlackBox::run_for`.
+///
+pub unsafe fn run_for_values<T: 'static>(
+    (t, ptr): (TypeId, (*const (), *const ())),
+    values: DynamicResult<&[T]>,
+) -> Option<Box<dyn Any>> {
+    if t != TypeId::of::<dyn Fn(DynamicResult<&[T]>) -> Option<Box<dyn Any>> + 'static>() {
+        return None;
+    }
+    let func = std::mem::transmute::<
+        (*const (), *const ()),
+        &dyn Fn(DynamicResult<&[T]>) -> Option<Box<dyn Any>>,
+    >(ptr);
+    func(values)
 }
 
 impl<
         'a,
-        R: Deref<Target = (dyn Any + Send)> + 'a,
-        RM: Deref<Target = (dyn Any + Send)> + DerefMut + 'a,
-        O: Deref<Target = (dyn Any + Send)> + DerefMut,
+        R: Deref<Target = dyn Any + Send> + 'a,
+        RM: Deref<Target = dyn Any + Send> + DerefMut + 'a,
+        O: Deref<Target = dyn Any + Send> + DerefMut,
     > PartialEq for dyn Unit<'a, Borrowed = R, MutBorrowed = RM, Owned = O>
 {
     fn eq(&self, other: &Self) -> bool {
@@ -278,9 +1205,9 @@ impl<
 
 impl<
         'a,
-        R: Deref<Target = (dyn Any + Send)> + 'a,
-        RM: Deref<Target = (dyn Any + Send)> + DerefMut + 'a,
-        O: Deref<Target = (dyn Any + Send)> + DerefMut,
+        R: Deref<Target = dyn Any + Send> + 'a,
+        RM: Deref<Target = dyn Any + Send> + DerefMut + 'a,
+        O: Deref<Target = dyn Any + Send> + DerefMut,
     > Debug for dyn Unit<'a, Borrowed = R, MutBorrowed = RM, Owned = O>
 {
     fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
@@ -0,0 +1,309 @@
+//! Read-only access to a storage, see `StorageView` and `SharedView`.
+use super::{
+    BlackBox, Borrowed, DynamicResult, Map, Mapped, StorageUnit, TryError, Unit, UnitState,
+};
+use std::any::Any;
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+
+///
+/// A borrow of a storage which can only read it, as created by
+/// `BlackBox::as_view`. A view has none of the methods which insert, extract
+/// or borrow values mutably, so a function taking a view can't change the
+/// storage, which is checked when compiling rather than when running. Reads
+/// through a view behave exactly like the same reads on the storage.
+///
+/// A view is just a reference to the storage, so it is as cheap to copy and
+/// to pass around as one.
+///
+/// # Example
+/// ```
+/// # fn main() {
+/// use restor::{make_storage, DynamicStorage, StorageView};
+///
+/// fn total(view: StorageView<'_, restor::RefCellUnitTrait>) -> usize {
+///     let mut total = 0;
+///     view.try_for_each(|x: &usize| {
+///         total += x;
+///         Ok::<_, ()>(())
+///     })
+///     .unwrap();
+///     total
+/// }
+///
+/// let storage = make_storage!(DynamicStorage: usize);
+/// storage.insert_many(vec![1usize, 2, 3]).unwrap();
+/// assert_eq!(total(storage.as_view()), 6);
+/// # }
+/// ```
+///
+/// Nothing can be changed through a view:
+/// ```compile_fail
+/// # use restor::{make_storage, DynamicStorage};
+/// # fn main() {
+/// let storage = make_storage!(DynamicStorage: usize);
+/// storage.as_view().insert(0usize).unwrap();
+/// # }
+/// ```
+///
+/// ```compile_fail
+/// # use restor::{make_storage, RwLockStorage};
+/// # fn main() {
+/// let storage = make_storage!(RwLockStorage: usize);
+/// storage.insert(0usize).unwrap();
+/// *storage.as_view().get_mut::<usize>().unwrap() += 1;
+/// # }
+/// ```
+///
+/// ```compile_fail
+/// # use restor::{make_storage, RwLockStorage};
+/// # fn main() {
+/// let storage = make_storage!(RwLockStorage: usize);
+/// storage.insert(0usize).unwrap();
+/// let _ = storage.as_view().extract::<usize>();
+/// # }
+/// ```
+///
+pub struct StorageView<'a, U: ?Sized> {
+    storage: &'a BlackBox<U>,
+}
+
+impl<'a, U: ?Sized> Clone for StorageView<'a, U> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, U: ?Sized> Copy for StorageView<'a, U> {}
+
+impl<'a, U: ?Sized> Debug for StorageView<'a, U> {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        f.debug_struct("StorageView").finish_non_exhaustive()
+    }
+}
+
+impl<'a, U: ?Sized + for<'b> Unit<'b, Owned = Box<dyn Any + Send>>> StorageView<'a, U> {
+    ///
+    /// Borrows the value of `T` immutably, like `BlackBox::get`.
+    ///
+    #[inline]
+    pub fn get<T: 'static + Send>(&self) -> DynamicResult<Mapped<'a, U, T>>
+    where
+        Borrowed<'a, U>: Map<dyn Any + Send, T, Func = for<'b> fn(&'b (dyn Any + Send)) -> &'b T>,
+    {
+        self.storage.get::<T>()
+    }
+
+    ///
+    /// Borrows the value of `T` at `ind` immutably, like `BlackBox::ind`.
+    ///
+    #[inline]
+    pub fn ind<T: 'static + Send>(&self, ind: usize) -> DynamicResult<Mapped<'a, U, T>>
+    where
+        Borrowed<'a, U>: Map<dyn Any + Send, T, Func = for<'b> fn(&'b (dyn Any + Send)) -> &'b T>,
+    {
+        self.storage.ind::<T>(ind)
+    }
+
+    ///
+    /// Borrows the value of `T` `back` places from the end immutably, like
+    /// `BlackBox::ind_from_end`.
+    ///
+    #[inline]
+    pub fn ind_from_end<T: 'static + Send>(&self, back: usize) -> DynamicResult<Mapped<'a, U, T>>
+    where
+        Borrowed<'a, U>: Map<dyn Any + Send, T, Func = for<'b> fn(&'b (dyn Any + Send)) -> &'b T>,
+    {
+        self.storage.ind_from_end::<T>(back)
+    }
+
+    ///
+    /// Whether there is a unit for `T`, like `BlackBox::has_unit`.
+    ///
+    #[inline]
+    pub fn contains<T: 'static + Send>(&self) -> bool {
+        self.storage.has_unit::<T>()
+    }
+
+    ///
+    /// The number of values of `T`, as given by `BlackBox::state`.
+    ///
+    pub fn len<T: 'static + Send>(&self) -> DynamicResult<usize> {
+        self.storage.state::<T>().map(UnitState::count)
+    }
+
+    ///
+    /// Whether there is no value of `T`, as given by `BlackBox::state`.
+    ///
+    pub fn is_empty<T: 'static + Send>(&self) -> DynamicResult<bool> {
+        self.len::<T>().map(|len| len == 0)
+    }
+
+    ///
+    /// The state of the unit for `T`, like `BlackBox::state`.
+    ///
+    pub fn state<T: 'static + Send>(&self) -> DynamicResult<UnitState> {
+        self.storage.state::<T>()
+    }
+
+    ///
+    /// The version of the unit for `T`, like `BlackBox::version`.
+    ///
+    pub fn version<T: 'static + Send>(&self) -> DynamicResult<u64> {
+        self.storage.version::<T>()
+    }
+
+    ///
+    /// Runs `f` on the values of `T` while holding an immutable borrow on
+    /// them, like `StorageWeakHandle::with`.
+    ///
+    pub fn with<T: 'static + Send, R, F: FnOnce(&StorageUnit<T>) -> R>(
+        &self,
+        f: F,
+    ) -> DynamicResult<R> {
+        self.storage.with_storage(f)
+    }
+
+    ///
+    /// Runs `f` on the value of `T`, like `BlackBox::try_with`.
+    ///
+    pub fn try_with<T: 'static + Send, R, E, F: FnOnce(&T) -> Result<R, E>>(
+        &self,
+        f: F,
+    ) -> Result<R, TryError<E>> {
+        self.storage.try_with(f)
+    }
+
+    ///
+    /// Runs `f` on each value of `T`, like `BlackBox::try_for_each`.
+    ///
+    pub fn try_for_each<T: 'static + Send, E, F: FnMut(&T) -> Result<(), E>>(
+        &self,
+        f: F,
+    ) -> Result<(), TryError<E>> {
+        self.storage.try_for_each(f)
+    }
+
+    ///
+    /// Finds the index of the first value of `T` matching `f`, like
+    /// `BlackBox::position`.
+    ///
+    pub fn position<T: 'static + Send, F: FnMut(&T) -> bool>(
+        &self,
+        f: F,
+    ) -> DynamicResult<Option<usize>> {
+        self.storage.position(f)
+    }
+
+    ///
+    /// Clones the first value of `T` matching `f`, like `BlackBox::find_clone`.
+    ///
+    pub fn find_clone<T: 'static + Send + Clone, F: FnMut(&T) -> bool>(
+        &self,
+        f: F,
+    ) -> DynamicResult<Option<T>> {
+        self.storage.find_clone(f)
+    }
+
+    ///
+    /// Clones every value of `T`, like `BlackBox::cloned_iter`.
+    ///
+    pub fn cloned_iter<T: 'static + Send + Clone>(&self) -> DynamicResult<std::vec::IntoIter<T>> {
+        self.storage.cloned_iter()
+    }
+
+    ///
+    /// Runs `f` on the values of `T` at `indices`, like
+    /// `BlackBox::with_ind_multi`.
+    ///
+    pub fn with_ind_multi<T: 'static + Send, R, F: FnOnce(&[&T]) -> R>(
+        &self,
+        indices: &[usize],
+        f: F,
+    ) -> DynamicResult<R> {
+        self.storage.with_ind_multi(indices, f)
+    }
+
+    ///
+    /// Clones the values of `T` at `indices`, like `BlackBox::ind_multi`.
+    ///
+    pub fn ind_multi<T: 'static + Send + Clone>(&self, indices: &[usize]) -> DynamicResult<Vec<T>> {
+        self.storage.ind_multi(indices)
+    }
+}
+
+impl<U: ?Sized + for<'a> Unit<'a, Owned = Box<dyn Any + Send>>> BlackBox<U> {
+    ///
+    /// Borrows the storage such that it can only be read, see `StorageView`.
+    ///
+    #[inline]
+    pub fn as_view(&self) -> StorageView<'_, U> {
+        StorageView { storage: self }
+    }
+}
+
+///
+/// A shared, owning reference to a storage which can only read it, the
+/// counterpart of `StorageView` for a storage shared between threads. Every
+/// read goes through the `StorageView` returned by `view`.
+///
+/// # Example
+/// ```
+/// # fn main() {
+/// use restor::{make_storage, RwLockStorage, SharedView};
+/// use std::sync::Arc;
+///
+/// let storage = Arc::new(make_storage!(RwLockStorage: usize));
+/// storage.insert(3usize).unwrap();
+/// let view = SharedView::new(storage.clone());
+/// let reader = std::thread::spawn(move || *view.view().get::<usize>().unwrap());
+/// assert_eq!(reader.join().unwrap(), 3);
+/// # }
+/// ```
+///
+pub struct SharedView<U: ?Sized> {
+    storage: Arc<BlackBox<U>>,
+}
+
+impl<U: ?Sized> SharedView<U>
+where
+    BlackBox<U>: Send + Sync,
+{
+    /// Shares `storage` such that it can only be read through the new view
+    pub fn new(storage: Arc<BlackBox<U>>) -> Self {
+        Self { storage }
+    }
+}
+
+impl<U: ?Sized + for<'a> Unit<'a, Owned = Box<dyn Any + Send>>> SharedView<U> {
+    ///
+    /// Borrows the storage such that it can only be read, see `StorageView`.
+    ///
+    #[inline]
+    pub fn view(&self) -> StorageView<'_, U> {
+        self.storage.as_view()
+    }
+}
+
+impl<U: ?Sized> Clone for SharedView<U> {
+    fn clone(&self) -> Self {
+        Self {
+            storage: self.storage.clone(),
+        }
+    }
+}
+
+impl<U: ?Sized> From<Arc<BlackBox<U>>> for SharedView<U>
+where
+    BlackBox<U>: Send + Sync,
+{
+    fn from(storage: Arc<BlackBox<U>>) -> Self {
+        Self::new(storage)
+    }
+}
+
+impl<U: ?Sized> Debug for SharedView<U> {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        f.debug_struct("SharedView").finish_non_exhaustive()
+    }
+}
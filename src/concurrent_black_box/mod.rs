@@ -1,358 +1,412 @@
 use std::any::{Any, TypeId};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
 
 use super::black_box::{
-    DynamicResult,
+    run_for_values, DynamicResult,
     ErrorDesc::{self, *},
-    StorageUnit, Unit, UnitError,
+    Fairness, StorageUnit, Unit, UnitState,
 };
-use parking_lot::{
-    MappedMutexGuard, MappedRwLockReadGuard, MappedRwLockWriteGuard, Mutex, MutexGuard, RwLock,
-    RwLockReadGuard, RwLockWriteGuard,
+
+pub(crate) mod sync;
+use self::sync::{
+    current, AtomicU64, MappedMutexGuard, MappedRwLockReadGuard, MappedRwLockWriteGuard, Mutex,
+    MutexGuard, Ordering, RwLock, RwLockReadGuard, RwLockWriteGuard, ThreadId,
 };
 
+mod read_mostly;
+pub(crate) use self::read_mostly::ReadMostly;
+
 pub struct MutexUnit<T> {
     inner: Mutex<T>,
+    version: AtomicU64,
+    /// The thread which most recently locked `inner`, when holders are tracked,
+    /// see `MutexStorage::new_debug`.
+    holder: Option<Mutex<Option<ThreadId>>>,
 }
 
 impl<T> MutexUnit<T> {
     pub fn new(data: T) -> Self {
         Self {
             inner: Mutex::new(data),
+            version: AtomicU64::new(0),
+            holder: None,
         }
     }
-}
 
-impl<'a, T: 'static + Send> Unit<'a> for MutexUnit<StorageUnit<T>> {
-    type Borrowed = MappedMutexGuard<'a, (dyn Any + Send)>;
-    type MutBorrowed = MappedMutexGuard<'a, (dyn Any + Send)>;
-    type Owned = Box<(dyn Any + Send)>;
-    fn one(&'a self) -> DynamicResult<MappedMutexGuard<'a, (dyn Any + Send)>> {
-        if let Some(mut nx) = self.inner.try_lock() {
-            match nx.one_mut() {
-                Ok(_) => Ok(MutexGuard::map(nx, |x| {
-                    let r: &mut (dyn Any + Send) = &mut *x.one_mut().unwrap();
-                    r
-                })),
-                Err(e) => Err(e),
-            }
-        } else {
-            Err(ErrorDesc::BorrowedIncompatibly)
+    ///
+    /// Creates a unit which keeps track of the thread holding its lock, so that
+    /// failing to lock it from that same thread is reported as
+    /// `ErrorDesc::RecursiveBorrow` instead of `ErrorDesc::BorrowedIncompatibly`.
+    ///
+    pub fn new_tracked(data: T) -> Self {
+        Self {
+            holder: Some(Mutex::new(None)),
+            ..Self::new(data)
         }
     }
-    fn one_mut(&'a self) -> DynamicResult<MappedMutexGuard<'a, (dyn Any + Send)>> {
-        if let Some(mut nx) = self.inner.try_lock() {
-            match nx.one_mut() {
-                Ok(_) => Ok(MutexGuard::map(nx, |x| {
-                    let r: &mut (dyn Any + Send) = &mut *x.one_mut().unwrap();
-                    r
-                })),
-                Err(e) => Err(e),
-            }
-        } else {
-            Err(ErrorDesc::BorrowedIncompatibly)
-        }
+
+    /// Marks the unit as modified, which is done whenever it is locked mutably.
+    #[inline]
+    fn bump(&self) {
+        self.version.fetch_add(1, Ordering::AcqRel);
     }
 
-    fn ind(&'a self, ind: usize) -> DynamicResult<MappedMutexGuard<'a, (dyn Any + Send)>> {
-        if let Some(mut nx) = self.inner.try_lock() {
-            match nx.many_mut() {
-                Ok(slice) => match slice.get_mut(ind) {
-                    Some(_) => Ok(MutexGuard::map(nx, |x| {
-                        let r: &mut (dyn Any + Send) = &mut x.many_mut().unwrap()[ind];
-                        r
-                    })),
-                    None => Err(ErrorDesc::Unit(UnitError::OutOfBounds)),
-                },
-                Err(e) => match nx.one_mut() {
-                    Ok(_) => Ok(MutexGuard::map(nx, |x| {
-                        &mut *x.one_mut().unwrap() as &mut _
-                    })),
-                    Err(ne) => Err(e & ne),
-                },
+    ///
+    /// Tries to lock the unit, recording or checking the holder of the lock if
+    /// holders are tracked.
+    ///
+    /// The holder is the last thread to have locked the unit, which is the one
+    /// holding it whenever it can't be locked. The exception is the moment
+    /// between another thread locking it and recording itself, in which a
+    /// conflict may be reported as recursive by the previous holder.
+    ///
+    #[inline]
+    fn lock(&self) -> DynamicResult<MutexGuard<'_, T>> {
+        match (self.inner.try_lock(), &self.holder) {
+            (Some(guard), None) => Ok(guard),
+            (None, None) => Err(BorrowedIncompatibly),
+            (Some(guard), Some(holder)) => {
+                *holder.lock() = Some(current().id());
+                Ok(guard)
+            }
+            (None, Some(holder)) => {
+                if *holder.lock() == Some(current().id()) {
+                    Err(RecursiveBorrow)
+                } else {
+                    Err(BorrowedIncompatibly)
+                }
             }
-        } else {
-            Err(ErrorDesc::BorrowedIncompatibly)
         }
     }
-    fn ind_mut(&'a self, ind: usize) -> DynamicResult<MappedMutexGuard<'a, (dyn Any + Send)>> {
-        if let Some(mut nx) = self.inner.try_lock() {
-            match nx.many_mut() {
-                Ok(slice) => match slice.get_mut(ind) {
-                    Some(_) => Ok(MutexGuard::map(nx, |x| {
-                        let r: &mut (dyn Any + Send) = &mut x.many_mut().unwrap()[ind];
-                        r
-                    })),
-                    None => Err(ErrorDesc::Unit(UnitError::OutOfBounds)),
-                },
-                Err(e) => match nx.one_mut() {
-                    Ok(_) => Ok(MutexGuard::map(nx, |x| {
-                        &mut *x.one_mut().unwrap() as &mut _
-                    })),
-                    Err(ne) => Err(e & ne),
-                },
+}
+
+impl<T: Default> Default for MutexUnit<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+unsafe impl<'a, T: 'static + Send> Unit<'a> for MutexUnit<StorageUnit<T>> {
+    type Borrowed = MappedMutexGuard<'a, dyn Any + Send>;
+    type MutBorrowed = MappedMutexGuard<'a, dyn Any + Send>;
+    type Owned = Box<dyn Any + Send>;
+    fn one(&'a self) -> DynamicResult<MappedMutexGuard<'a, dyn Any + Send>> {
+        MutexGuard::try_map(self.lock()?, |x| {
+            x.one_mut().ok().map(|x| x as &mut (dyn Any + Send))
+        })
+        .map_err(|nx| nx.one().err().unwrap())
+    }
+    fn one_mut(&'a self) -> DynamicResult<MappedMutexGuard<'a, dyn Any + Send>> {
+        match MutexGuard::try_map(self.lock()?, |x| {
+            x.one_mut().ok().map(|x| x as &mut (dyn Any + Send))
+        }) {
+            Ok(x) => {
+                self.bump();
+                Ok(x)
             }
-        } else {
-            Err(ErrorDesc::BorrowedIncompatibly)
+            Err(nx) => Err(nx.one().err().unwrap()),
         }
     }
 
-    fn extract(&self) -> DynamicResult<Box<(dyn Any + Send)>> {
-        if let Some(mut x) = self.inner.try_lock() {
-            match x.extract_one() {
-                Ok(x) => Ok(Box::new(x)),
-                Err(e) => Err(e),
+    fn ind(&'a self, ind: usize) -> DynamicResult<MappedMutexGuard<'a, dyn Any + Send>> {
+        MutexGuard::try_map(self.lock()?, |x| {
+            x.ind_mut(ind).ok().map(|x| x as &mut (dyn Any + Send))
+        })
+        .map_err(|nx| nx.ind(ind).err().unwrap())
+    }
+    fn ind_mut(&'a self, ind: usize) -> DynamicResult<MappedMutexGuard<'a, dyn Any + Send>> {
+        match MutexGuard::try_map(self.lock()?, |x| {
+            x.ind_mut(ind).ok().map(|x| x as &mut (dyn Any + Send))
+        }) {
+            Ok(x) => {
+                self.bump();
+                Ok(x)
             }
-        } else {
-            Err(ErrorDesc::BorrowedIncompatibly)
+            Err(nx) => Err(nx.ind(ind).err().unwrap()),
         }
     }
-    fn extract_ind(&self, ind: usize) -> DynamicResult<Box<(dyn Any + Send)>> {
-        if let Some(mut borrowed) = self.inner.try_lock() {
-            match borrowed.many_mut() {
-                Ok(_) => borrowed.many_mut().and_then(|x| {
-                    if ind < x.len() {
-                        let x: Box<(dyn Any + Send)> = Box::new(x.remove(ind));
-                        Ok(x)
-                    } else {
-                        Err(ErrorDesc::Unit(UnitError::OutOfBounds))
-                    }
-                }),
-                Err(e) => {
-                    if ind == 0 {
-                        borrowed
-                            .extract_one()
-                            .map(|x| Box::new(x) as _)
-                            .map_err(|ne| ne & e)
-                    } else {
-                        Err(e)
-                    }
-                }
+    fn ind_from_end(&'a self, back: usize) -> DynamicResult<MappedMutexGuard<'a, dyn Any + Send>> {
+        MutexGuard::try_map(self.lock()?, |x| {
+            x.ind_from_end_mut(back)
+                .ok()
+                .map(|x| x as &mut (dyn Any + Send))
+        })
+        .map_err(|nx| nx.ind_from_end(back).err().unwrap())
+    }
+    fn ind_from_end_mut(
+        &'a self,
+        back: usize,
+    ) -> DynamicResult<MappedMutexGuard<'a, dyn Any + Send>> {
+        match MutexGuard::try_map(self.lock()?, |x| {
+            x.ind_from_end_mut(back)
+                .ok()
+                .map(|x| x as &mut (dyn Any + Send))
+        }) {
+            Ok(x) => {
+                self.bump();
+                Ok(x)
             }
-        } else {
-            Err(ErrorDesc::BorrowedIncompatibly)
+            Err(nx) => Err(nx.ind_from_end(back).err().unwrap()),
         }
     }
-    fn extract_many(&self) -> DynamicResult<Box<(dyn Any + Send)>> {
-        Ok(Box::new(
-            self.inner
-                .try_lock()
-                .ok_or(ErrorDesc::BorrowedIncompatibly)?
-                .extract_many_boxed(),
-        ))
+
+    fn extract(&self) -> DynamicResult<Box<dyn Any + Send>> {
+        let value = self.lock()?.extract_one()?;
+        self.bump();
+        Ok(Box::new(value))
+    }
+    fn extract_ind(&self, ind: usize) -> DynamicResult<Box<dyn Any + Send>> {
+        let value = self.lock()?.extract_ind(ind)?;
+        self.bump();
+        Ok(Box::new(value))
+    }
+    fn extract_many(&self) -> DynamicResult<Box<dyn Any + Send>> {
+        let values = self.lock()?.extract_many_boxed()?;
+        self.bump();
+        Ok(Box::new(values))
     }
 
-    fn insert_any(&self, new: Box<(dyn Any + Send)>) -> Option<(Box<(dyn Any + Send)>, ErrorDesc)> {
-        let newtype = new.type_id();
-        if let Some(mut x) = self.inner.try_lock() {
-            if new.is::<T>() {
-                x.insert(*new.downcast::<T>().unwrap_or_else(|_| {
-                    panic!(
-                        "Tried to insert an object with type {:?} into a storage of type {:?}",
-                        newtype,
-                        TypeId::of::<T>()
-                    )
-                }));
-                None
-            } else if new.is::<Box<Vec<T>>>() {
-                x.insert_many(*new.downcast::<Vec<T>>().unwrap());
-                None
-            } else {
-                Some((new, ErrorDesc::NoMatchingType))
+    fn insert_any(&self, new: Box<dyn Any + Send>) -> Option<(Box<dyn Any + Send>, ErrorDesc)> {
+        let newtype = (*new).type_id();
+        match self.lock() {
+            Ok(mut x) => {
+                if new.is::<T>() {
+                    x.insert(*new.downcast::<T>().unwrap_or_else(|_| {
+                        panic!(
+                            "Tried to insert an object with type {:?} into a storage of type {:?}",
+                            newtype,
+                            TypeId::of::<T>()
+                        )
+                    }));
+                    self.bump();
+                    None
+                } else if new.is::<Vec<T>>() {
+                    x.insert_many(*new.downcast::<Vec<T>>().unwrap());
+                    self.bump();
+                    None
+                } else {
+                    Some((new, ErrorDesc::NoMatchingType))
+                }
             }
-        } else {
-            Some((new, ErrorDesc::BorrowedIncompatibly))
+            Err(e) => Some((new, e)),
         }
     }
-    fn storage(&'a self) -> DynamicResult<MappedMutexGuard<'a, (dyn Any + Send)>> {
-        self.inner
-            .try_lock()
-            .map(|x| MutexGuard::map::<(dyn Any + Send), _>(x, |z| &mut *z))
-            .ok_or(BorrowedIncompatibly)
-    }
-    fn storage_mut(&'a self) -> DynamicResult<MappedMutexGuard<'a, (dyn Any + Send)>> {
-        self.storage()
-    }
-    unsafe fn run_for(&self, (t, ptr): (TypeId, (*const (), *const ()))) -> Option<Box<dyn Any>> {
-        if t == TypeId::of::<dyn Fn(DynamicResult<&[T]>) -> Option<Box<dyn Any>> + 'static>() {
-            if let Some(x) = self.inner.try_lock() {
-                let func = std::mem::transmute::<
-                    _,
-                    &dyn Fn(DynamicResult<&[T]>) -> Option<Box<dyn Any>>,
-                >(ptr);
-                func(x.many())
-            } else {
-                None
-            }
-        } else {
-            None
-        }
+    fn storage(&'a self) -> DynamicResult<MappedMutexGuard<'a, dyn Any + Send>> {
+        self.lock()
+            .map(|x| MutexGuard::map::<dyn Any + Send, _>(x, |z| &mut *z))
+    }
+    fn storage_mut(&'a self) -> DynamicResult<MappedMutexGuard<'a, dyn Any + Send>> {
+        let storage = self.storage()?;
+        self.bump();
+        Ok(storage)
+    }
+    unsafe fn run_for(&self, func: (TypeId, (*const (), *const ()))) -> Option<Box<dyn Any>> {
+        let values = self.lock().ok()?;
+        run_for_values(func, values.many())
     }
 
     fn id(&self) -> TypeId {
         TypeId::of::<T>()
     }
+    fn type_name(&self) -> &'static str {
+        std::any::type_name::<T>()
+    }
+    fn state(&self) -> Option<UnitState> {
+        self.inner.try_lock().map(|x| x.state())
+    }
+    fn is_borrowed(&self) -> bool {
+        self.inner.try_lock().is_none()
+    }
+    fn capacity(&self) -> Option<usize> {
+        self.inner.try_lock().map(|x| x.capacity())
+    }
+    fn approx_bytes(&self) -> Option<usize> {
+        self.inner.try_lock().map(|x| x.approx_bytes())
+    }
+    fn version(&self) -> u64 {
+        self.version.load(Ordering::Acquire)
+    }
 }
 
 unsafe impl<T: Send> Send for MutexUnit<StorageUnit<T>> {}
 
+/// How long new readers of a `Fairness::WriterPriority` unit back off after a writer failed
+/// to borrow it, unless a writer borrows it first.
+const WRITER_PATIENCE: Duration = Duration::from_millis(10);
+
+/// The number of nanoseconds since the first time this was called in the process.
+fn nanos_since_start() -> u64 {
+    static START: OnceLock<Instant> = OnceLock::new();
+    START.get_or_init(Instant::now).elapsed().as_nanos() as u64
+}
+
 pub struct RwLockUnit<T> {
     inner: RwLock<T>,
+    version: AtomicU64,
+    fairness: Fairness,
+    /// Until when new readers back off for a writer, as given by `nanos_since_start`, or zero
+    /// when no writer is waiting. This is only used with `Fairness::WriterPriority`.
+    writer_waiting: AtomicU64,
 }
 
 impl<T> RwLockUnit<T> {
     pub fn new(data: T) -> Self {
+        Self::with_fairness(data, Fairness::ReaderPriority)
+    }
+
+    pub fn with_fairness(data: T, fairness: Fairness) -> Self {
         Self {
             inner: RwLock::new(data),
+            version: AtomicU64::new(0),
+            fairness,
+            writer_waiting: AtomicU64::new(0),
         }
     }
-}
 
-impl<'a, T: 'static + Send> Unit<'a> for RwLockUnit<StorageUnit<T>> {
-    type Borrowed = MappedRwLockReadGuard<'a, (dyn Any + Send)>;
-    type MutBorrowed = MappedRwLockWriteGuard<'a, (dyn Any + Send)>;
-    type Owned = Box<(dyn Any + Send)>;
-    fn one(&'a self) -> DynamicResult<MappedRwLockReadGuard<'a, (dyn Any + Send)>> {
-        if let Some(nx) = self.inner.try_read() {
-            match nx.one() {
-                Ok(_) => Ok(RwLockReadGuard::map(nx, |x| {
-                    let r: &(dyn Any + Send) = &*x.one().unwrap();
-                    r
-                })),
-                Err(e) => Err(e),
-            }
-        } else {
-            Err(ErrorDesc::BorrowedIncompatibly)
+    /// Locks the unit immutably, unless a writer is waiting for it, see `Fairness`.
+    #[inline]
+    fn read(&self) -> DynamicResult<RwLockReadGuard<'_, T>> {
+        if self.fairness == Fairness::WriterPriority
+            && self.writer_waiting.load(Ordering::Acquire) > nanos_since_start()
+        {
+            return Err(BorrowedIncompatibly);
         }
+        self.inner.try_read().ok_or(BorrowedIncompatibly)
     }
-    fn one_mut(&'a self) -> DynamicResult<MappedRwLockWriteGuard<'a, (dyn Any + Send)>> {
-        if let Some(mut nx) = self.inner.try_write() {
-            match nx.one_mut() {
-                Ok(_) => Ok(RwLockWriteGuard::map(nx, |x| {
-                    let r: &mut (dyn Any + Send) = &mut *x.one_mut().unwrap();
-                    r
-                })),
-                Err(e) => Err(e),
+
+    /// Locks the unit mutably, keeping track of the writers waiting for it, see `Fairness`.
+    #[inline]
+    fn write(&self) -> DynamicResult<RwLockWriteGuard<'_, T>> {
+        let locked = self.inner.try_write();
+        if self.fairness == Fairness::WriterPriority {
+            let waiting = match locked {
+                Some(_) => 0,
+                None => nanos_since_start() + WRITER_PATIENCE.as_nanos() as u64,
+            };
+            self.writer_waiting.store(waiting, Ordering::Release);
+        }
+        locked.ok_or(BorrowedIncompatibly)
+    }
+
+    /// Marks the unit as modified, which is done whenever it is locked mutably.
+    #[inline]
+    fn bump(&self) {
+        self.version.fetch_add(1, Ordering::AcqRel);
+    }
+}
+
+impl<T: Default> Default for RwLockUnit<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+unsafe impl<'a, T: 'static + Send> Unit<'a> for RwLockUnit<StorageUnit<T>> {
+    type Borrowed = MappedRwLockReadGuard<'a, dyn Any + Send>;
+    type MutBorrowed = MappedRwLockWriteGuard<'a, dyn Any + Send>;
+    type Owned = Box<dyn Any + Send>;
+    fn one(&'a self) -> DynamicResult<MappedRwLockReadGuard<'a, dyn Any + Send>> {
+        let nx = self.read()?;
+        RwLockReadGuard::try_map(nx, |x| x.one().ok().map(|x| x as &(dyn Any + Send)))
+            .map_err(|nx| nx.one().err().unwrap())
+    }
+    fn one_mut(&'a self) -> DynamicResult<MappedRwLockWriteGuard<'a, dyn Any + Send>> {
+        let nx = self.write()?;
+        match RwLockWriteGuard::try_map(nx, |x| {
+            x.one_mut().ok().map(|x| x as &mut (dyn Any + Send))
+        }) {
+            Ok(x) => {
+                self.bump();
+                Ok(x)
             }
-        } else {
-            Err(ErrorDesc::BorrowedIncompatibly)
+            Err(nx) => Err(nx.one().err().unwrap()),
         }
     }
 
-    fn ind(&'a self, ind: usize) -> DynamicResult<MappedRwLockReadGuard<'a, (dyn Any + Send)>> {
-        if let Some(nx) = self.inner.try_read() {
-            match nx.many() {
-                Ok(slice) => match slice.get(ind) {
-                    Some(_) => Ok(RwLockReadGuard::map(nx, |x| {
-                        let r: &(dyn Any + Send) = &x.many().unwrap()[ind];
-                        r
-                    })),
-                    None => Err(ErrorDesc::Unit(UnitError::OutOfBounds)),
-                },
-                Err(e) => {
-                    if ind == 0 {
-                        match nx.one() {
-                            Ok(_) => Ok(RwLockReadGuard::map(nx, |x| x.one().unwrap() as &_)),
-                            Err(ne) => Err(e & ne),
-                        }
-                    } else {
-                        Err(e)
-                    }
-                }
+    fn ind(&'a self, ind: usize) -> DynamicResult<MappedRwLockReadGuard<'a, dyn Any + Send>> {
+        let nx = self.read()?;
+        RwLockReadGuard::try_map(nx, |x| x.ind(ind).ok().map(|x| x as &(dyn Any + Send)))
+            .map_err(|nx| nx.ind(ind).err().unwrap())
+    }
+    fn ind_mut(&'a self, ind: usize) -> DynamicResult<MappedRwLockWriteGuard<'a, dyn Any + Send>> {
+        let nx = self.write()?;
+        match RwLockWriteGuard::try_map(nx, |x| {
+            x.ind_mut(ind).ok().map(|x| x as &mut (dyn Any + Send))
+        }) {
+            Ok(x) => {
+                self.bump();
+                Ok(x)
             }
-        } else {
-            Err(ErrorDesc::BorrowedIncompatibly)
+            Err(nx) => Err(nx.ind(ind).err().unwrap()),
         }
     }
-    fn ind_mut(
+    fn ind_from_end(
         &'a self,
-        ind: usize,
-    ) -> DynamicResult<MappedRwLockWriteGuard<'a, (dyn Any + Send)>> {
-        if let Some(mut nx) = self.inner.try_write() {
-            match nx.many() {
-                Ok(slice) => match slice.get(ind) {
-                    Some(_) => Ok(RwLockWriteGuard::map(nx, |x| {
-                        let r: &mut (dyn Any + Send) = &mut x.many_mut().unwrap()[ind];
-                        r
-                    })),
-                    None => Err(ErrorDesc::Unit(UnitError::OutOfBounds)),
-                },
-                Err(e) => {
-                    if ind == 0 {
-                        match nx.one_mut() {
-                            Ok(_) => Ok(RwLockWriteGuard::map(nx, |nx| {
-                                nx.one_mut().unwrap() as &mut _
-                            })),
-                            Err(ne) => Err(e & ne),
-                        }
-                    } else {
-                        Err(e)
-                    }
-                }
+        back: usize,
+    ) -> DynamicResult<MappedRwLockReadGuard<'a, dyn Any + Send>> {
+        let nx = self.read()?;
+        RwLockReadGuard::try_map(nx, |x| {
+            x.ind_from_end(back).ok().map(|x| x as &(dyn Any + Send))
+        })
+        .map_err(|nx| nx.ind_from_end(back).err().unwrap())
+    }
+    fn ind_from_end_mut(
+        &'a self,
+        back: usize,
+    ) -> DynamicResult<MappedRwLockWriteGuard<'a, dyn Any + Send>> {
+        let nx = self.write()?;
+        match RwLockWriteGuard::try_map(nx, |x| {
+            x.ind_from_end_mut(back)
+                .ok()
+                .map(|x| x as &mut (dyn Any + Send))
+        }) {
+            Ok(x) => {
+                self.bump();
+                Ok(x)
             }
-        } else {
-            Err(ErrorDesc::BorrowedIncompatibly)
+            Err(nx) => Err(nx.ind_from_end(back).err().unwrap()),
         }
     }
 
-    fn extract(&self) -> DynamicResult<Box<(dyn Any + Send)>> {
-        if let Some(mut x) = self.inner.try_write() {
+    fn extract(&self) -> DynamicResult<Box<dyn Any + Send>> {
+        if let Ok(mut x) = self.write() {
             match x.extract_one() {
-                Ok(x) => Ok(Box::new(x)),
+                Ok(x) => {
+                    self.bump();
+                    Ok(Box::new(x))
+                }
                 Err(e) => Err(e),
             }
         } else {
             Err(ErrorDesc::BorrowedIncompatibly)
         }
     }
-    fn extract_ind(&self, ind: usize) -> DynamicResult<Box<(dyn Any + Send)>> {
-        if let Some(mut borrowed) = self.inner.try_write() {
-            match borrowed.many_mut() {
-                Ok(_) => borrowed.many_mut().and_then(|x| {
-                    if ind < x.len() {
-                        let x: Box<(dyn Any + Send)> = Box::new(x.remove(ind));
-                        Ok(x)
-                    } else {
-                        Err(ErrorDesc::Unit(UnitError::OutOfBounds))
-                    }
-                }),
-                Err(e) => {
-                    if ind == 0 {
-                        match borrowed.extract_one() {
-                            Ok(x) => Ok(Box::new(x)),
-                            Err(ne) => Err(ne & e),
-                        }
-                    } else {
-                        Err(e)
-                    }
-                }
-            }
-        } else {
-            Err(ErrorDesc::BorrowedIncompatibly)
-        }
+    fn extract_ind(&self, ind: usize) -> DynamicResult<Box<dyn Any + Send>> {
+        let mut borrowed = self.write()?;
+        let value = borrowed.extract_ind(ind)?;
+        self.bump();
+        Ok(Box::new(value))
     }
-    fn extract_many(&self) -> DynamicResult<Box<(dyn Any + Send)>> {
-        Ok(Box::new(
-            self.inner
-                .try_write()
-                .ok_or(ErrorDesc::BorrowedIncompatibly)?
-                .extract_many_boxed(),
-        ))
-    }
-    fn storage(&'a self) -> DynamicResult<MappedRwLockReadGuard<'a, (dyn Any + Send)>> {
-        self.inner
-            .try_read()
-            .map(|x| RwLockReadGuard::map::<(dyn Any + Send), _>(x, |z| &*z))
-            .ok_or(BorrowedIncompatibly)
-    }
-    fn storage_mut(&'a self) -> DynamicResult<MappedRwLockWriteGuard<'a, (dyn Any + Send)>> {
-        self.inner
-            .try_write()
-            .map(|x| RwLockWriteGuard::map::<(dyn Any + Send), _>(x, |z| &mut *z))
-            .ok_or(BorrowedIncompatibly)
-    }
-    fn insert_any(&self, new: Box<(dyn Any + Send)>) -> Option<(Box<(dyn Any + Send)>, ErrorDesc)> {
-        let newtype = new.type_id();
-        if let Some(mut x) = self.inner.try_write() {
+    fn extract_many(&self) -> DynamicResult<Box<dyn Any + Send>> {
+        let values = self.write()?.extract_many_boxed()?;
+        self.bump();
+        Ok(Box::new(values))
+    }
+    fn storage(&'a self) -> DynamicResult<MappedRwLockReadGuard<'a, dyn Any + Send>> {
+        self.read()
+            .map(|x| RwLockReadGuard::map::<dyn Any + Send, _>(x, |z| z))
+    }
+    fn storage_mut(&'a self) -> DynamicResult<MappedRwLockWriteGuard<'a, dyn Any + Send>> {
+        let storage = self.write()?;
+        self.bump();
+        Ok(RwLockWriteGuard::map::<dyn Any + Send, _>(storage, |z| {
+            &mut *z
+        }))
+    }
+    fn insert_any(&self, new: Box<dyn Any + Send>) -> Option<(Box<dyn Any + Send>, ErrorDesc)> {
+        let newtype = (*new).type_id();
+        if let Ok(mut x) = self.write() {
             if new.is::<T>() {
                 x.insert(*new.downcast::<T>().unwrap_or_else(|_| {
                     panic!(
@@ -361,9 +415,11 @@ impl<'a, T: 'static + Send> Unit<'a> for RwLockUnit<StorageUnit<T>> {
                         TypeId::of::<T>()
                     )
                 }));
+                self.bump();
                 None
-            } else if new.is::<Box<Vec<T>>>() {
+            } else if new.is::<Vec<T>>() {
                 x.insert_many(*new.downcast::<Vec<T>>().unwrap());
+                self.bump();
                 None
             } else {
                 Some((new, ErrorDesc::NoMatchingType))
@@ -372,25 +428,35 @@ impl<'a, T: 'static + Send> Unit<'a> for RwLockUnit<StorageUnit<T>> {
             Some((new, ErrorDesc::BorrowedIncompatibly))
         }
     }
-    unsafe fn run_for(&self, (t, ptr): (TypeId, (*const (), *const ()))) -> Option<Box<dyn Any>> {
-        if t == TypeId::of::<(dyn Fn(DynamicResult<&[T]>) -> Option<Box<dyn Any>> + 'static)>() {
-            if let Some(x) = self.inner.try_read() {
-                let func = std::mem::transmute::<
-                    _,
-                    &dyn Fn(DynamicResult<&[T]>) -> Option<Box<dyn Any>>,
-                >(ptr);
-                func(x.many())
-            } else {
-                None
-            }
-        } else {
-            None
-        }
+    unsafe fn run_for(&self, func: (TypeId, (*const (), *const ()))) -> Option<Box<dyn Any>> {
+        let values = self.read().ok()?;
+        run_for_values(func, values.many())
     }
 
     fn id(&self) -> TypeId {
         TypeId::of::<T>()
     }
+    fn type_name(&self) -> &'static str {
+        std::any::type_name::<T>()
+    }
+    fn state(&self) -> Option<UnitState> {
+        self.inner.try_read().map(|x| x.state())
+    }
+    fn is_borrowed(&self) -> bool {
+        self.inner.try_write().is_none()
+    }
+    fn capacity(&self) -> Option<usize> {
+        self.inner.try_read().map(|x| x.capacity())
+    }
+    fn approx_bytes(&self) -> Option<usize> {
+        self.inner.try_read().map(|x| x.approx_bytes())
+    }
+    fn version(&self) -> u64 {
+        self.version.load(Ordering::Acquire)
+    }
+    fn fairness(&self) -> Fairness {
+        self.fairness
+    }
 }
 
 unsafe impl<T: Send> Send for RwLockUnit<StorageUnit<T>> {}
@@ -0,0 +1,97 @@
+use std::marker::PhantomData;
+use std::ptr;
+use std::sync::Arc;
+
+use super::sync::{AtomicPtr, AtomicUsize, Mutex, Ordering};
+
+///
+/// A value which is read without taking a lock, and is only ever replaced as
+/// a whole. This is what backs `RwLockStorage::allocate_read_mostly_for` and
+/// its `MutexStorage` counterpart.
+///
+/// `current` holds a reference to the current `Arc`, in the form of a pointer
+/// from `Arc::into_raw`. Readers announce themselves in `readers` before
+/// loading `current` and cloning the `Arc`, and leave once they have cloned it,
+/// so that the reference of a replaced value can't be released under them.
+/// Replaced values wait in `retired` until a `store` sees no reader at all,
+/// which releases every one of them at once.
+///
+pub(crate) struct ReadMostly<T> {
+    current: AtomicPtr<T>,
+    readers: AtomicUsize,
+    retired: Mutex<Vec<*const T>>,
+    _marker: PhantomData<Arc<T>>,
+}
+
+// The raw pointers are references to `Arc<T>`s, which are only released once
+// no reader may be cloning them
+unsafe impl<T: Send + Sync> Send for ReadMostly<T> {}
+unsafe impl<T: Send + Sync> Sync for ReadMostly<T> {}
+
+impl<T> ReadMostly<T> {
+    pub(crate) fn new() -> Self {
+        Self {
+            current: AtomicPtr::new(ptr::null_mut()),
+            readers: AtomicUsize::new(0),
+            retired: Mutex::new(Vec::new()),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns the current value, or `None` if nothing has been stored yet.
+    #[inline]
+    pub(crate) fn load(&self) -> Option<Arc<T>> {
+        self.readers.fetch_add(1, Ordering::SeqCst);
+        let current = self.current.load(Ordering::SeqCst);
+        // A `store` which replaced `current` since it was loaded sees this
+        // reader, and so keeps its reference until the reader has left
+        let value = if current.is_null() {
+            None
+        } else {
+            unsafe {
+                Arc::increment_strong_count(current);
+                Some(Arc::from_raw(current))
+            }
+        };
+        self.readers.fetch_sub(1, Ordering::Release);
+        value
+    }
+
+    /// Replaces the current value, returning the previous one.
+    pub(crate) fn store(&self, value: T) -> Option<Arc<T>> {
+        let new = Arc::into_raw(Arc::new(value)) as *mut T;
+        let old = self.current.swap(new, Ordering::SeqCst);
+        let previous = if old.is_null() {
+            None
+        } else {
+            unsafe {
+                Arc::increment_strong_count(old);
+                Some(Arc::from_raw(old))
+            }
+        };
+        let mut retired = self.retired.lock();
+        if !old.is_null() {
+            retired.push(old);
+        }
+        // Every retired value was replaced before this point, so a reader
+        // which could still be cloning one of them would be counted here
+        if self.readers.load(Ordering::SeqCst) == 0 {
+            for old in retired.drain(..) {
+                drop(unsafe { Arc::from_raw(old) });
+            }
+        }
+        previous
+    }
+}
+
+impl<T> Drop for ReadMostly<T> {
+    fn drop(&mut self) {
+        let current = *self.current.get_mut();
+        if !current.is_null() {
+            drop(unsafe { Arc::from_raw(current) });
+        }
+        for old in self.retired.get_mut().drain(..) {
+            drop(unsafe { Arc::from_raw(old) });
+        }
+    }
+}
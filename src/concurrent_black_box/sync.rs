@@ -0,0 +1,13 @@
+//! The synchronization primitives behind the concurrent units, and behind the
+//! spare lists of every storage. They are only imported through here, so that
+//! they can be swapped out in one place, such as for the types of a model
+//! checker.
+//!
+//! The mapped guards are also part of the public `Unit` types of the concurrent
+//! storages, and so are named directly by those.
+pub(crate) use parking_lot::{
+    MappedMutexGuard, MappedRwLockReadGuard, MappedRwLockWriteGuard, Mutex, MutexGuard, RwLock,
+    RwLockReadGuard, RwLockWriteGuard,
+};
+pub(crate) use std::sync::atomic::{AtomicPtr, AtomicU64, AtomicUsize, Ordering};
+pub(crate) use std::thread::{current, ThreadId};
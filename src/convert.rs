@@ -0,0 +1,190 @@
+//!
+//! Conversions between plain structs and the storages holding their fields,
+//! as implemented by `restor_struct!`.
+//!
+use crate::{DynamicResult, DynamicStorage, ErrorDesc, UnitError};
+use std::marker::PhantomData;
+
+///
+/// A type which can be turned into a `DynamicStorage` holding its parts, as
+/// implemented by `restor_struct!`.
+///
+pub trait IntoStorage {
+    ///
+    /// Moves the parts of `self` into a new storage, each into the unit for
+    /// its type.
+    ///
+    fn into_storage(self) -> DynamicStorage;
+}
+
+///
+/// A type which can be put back together from the parts held by a
+/// `DynamicStorage`, as implemented by `restor_struct!`.
+///
+pub trait FromStorage: Sized {
+    ///
+    /// Extracts the parts of `Self` out of `storage`. This returns the error
+    /// along with the name of the part which couldn't be extracted, in which
+    /// case the parts extracted before it are dropped along with `storage`.
+    ///
+    fn from_storage(storage: DynamicStorage) -> Result<Self, (ErrorDesc, &'static str)>;
+}
+
+///
+/// Internal trait. How a single field is moved into and out of a storage, as
+/// selected by `restor_struct!` for each field.
+///
+#[doc(hidden)]
+pub trait Field {
+    type Value;
+    fn store(value: Self::Value, storage: &mut DynamicStorage);
+    fn load(storage: &DynamicStorage) -> DynamicResult<Self::Value>;
+}
+
+///
+/// Internal type. A field of type `T`, which is held by the unit for `T`.
+///
+#[doc(hidden)]
+pub struct Required<T>(PhantomData<T>);
+
+impl<T: 'static + Send> Field for Required<T> {
+    type Value = T;
+    fn store(value: T, storage: &mut DynamicStorage) {
+        storage.allocate_for::<T>();
+        storage
+            .insert(value)
+            .ok()
+            .expect("a unit of a storage which isn't shared can't be borrowed");
+    }
+    fn load(storage: &DynamicStorage) -> DynamicResult<T> {
+        storage.extract_one_loose::<T>()
+    }
+}
+
+///
+/// Internal type. A field of type `Option<T>`, which is `None` when there is
+/// no unit for `T`, or when the unit is empty.
+///
+#[doc(hidden)]
+pub struct Optional<T>(PhantomData<T>);
+
+impl<T: 'static + Send> Field for Optional<T> {
+    type Value = Option<T>;
+    fn store(value: Option<T>, storage: &mut DynamicStorage) {
+        if let Some(value) = value {
+            Required::<T>::store(value, storage);
+        }
+    }
+    fn load(storage: &DynamicStorage) -> DynamicResult<Option<T>> {
+        match storage.extract_one_loose::<T>() {
+            Ok(value) => Ok(Some(value)),
+            Err(ErrorDesc::NoAllocatedUnit) | Err(ErrorDesc::Unit(UnitError::IsNone)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+///
+/// Implements `IntoStorage` and `FromStorage` for a struct, given its fields
+/// along with their types. Each field is held by the unit for its type, so a
+/// field of type `Option<T>` is held by the unit for `T`, which isn't
+/// allocated for `None`. This only recognizes a field as optional when its
+/// type is spelled `Option<T>`.
+///
+/// Fields are inserted and extracted in the order they are listed in, so
+/// many fields of the same type share a unit, and come back in their places.
+///
+/// A struct with lifetime or type parameters lists them after its name. The
+/// traits are then only implemented when every field can be held by a storage,
+/// which means that a struct borrowing anything which isn't `'static` can't
+/// be converted:
+/// ```compile_fail
+/// # use restor::{convert::IntoStorage, restor_struct};
+/// # fn main() {
+/// struct Named<'a> {
+///     name: &'a str,
+/// }
+/// restor_struct!(Named<'a> { name: &'a str });
+///
+/// let name = String::from("local");
+/// let storage = Named { name: &name }.into_storage();
+/// # }
+/// ```
+///
+/// # Example
+/// ```
+/// # fn main() {
+/// use restor::convert::{FromStorage, IntoStorage};
+/// use restor::{restor_struct, ErrorDesc};
+///
+/// #[derive(Debug, PartialEq)]
+/// struct Time(f32);
+/// #[derive(Debug, PartialEq)]
+/// struct Config(&'static str);
+///
+/// #[derive(Debug, PartialEq)]
+/// struct Resources {
+///     time: Time,
+///     config: Option<Config>,
+/// }
+/// restor_struct!(Resources {
+///     time: Time,
+///     config: Option<Config>,
+/// });
+///
+/// let storage = Resources { time: Time(0.5), config: None }.into_storage();
+/// assert!(!storage.has_unit::<Config>());
+/// storage.get_mut::<Time>().unwrap().0 += 1.0;
+/// let resources = Resources::from_storage(storage).unwrap();
+/// assert_eq!(resources, Resources { time: Time(1.5), config: None });
+///
+/// let storage = restor::DynamicStorage::new();
+/// assert_eq!(
+///     Resources::from_storage(storage),
+///     Err((ErrorDesc::NoAllocatedUnit, "time"))
+/// );
+/// # }
+/// ```
+///
+#[macro_export]
+macro_rules! restor_struct {
+    ($name:ident $(<$($param:tt),*>)? { $($fields:tt)* }) => {
+        $crate::restor_struct!(@field $name [$($($param),*)?] [] $($fields)*);
+    };
+    (@field $name:ident $params:tt [$($done:tt)*] $field:ident : Option<$t:ty> $(, $($rest:tt)*)?) => {
+        $crate::restor_struct!(
+            @field $name $params [$($done)* ($field, Option<$t>, $crate::convert::Optional<$t>)] $($($rest)*)?
+        );
+    };
+    (@field $name:ident $params:tt [$($done:tt)*] $field:ident : $t:ty $(, $($rest:tt)*)?) => {
+        $crate::restor_struct!(
+            @field $name $params [$($done)* ($field, $t, $crate::convert::Required<$t>)] $($($rest)*)?
+        );
+    };
+    (@field $name:ident [$($param:tt),*] [$(($field:ident, $t:ty, $kind:ty))*]) => {
+        impl<$($param),*> $crate::convert::IntoStorage for $name<$($param),*>
+        where
+            $($kind: $crate::convert::Field<Value = $t>),*
+        {
+            fn into_storage(self) -> $crate::DynamicStorage {
+                let mut storage = $crate::DynamicStorage::new();
+                $(<$kind as $crate::convert::Field>::store(self.$field, &mut storage);)*
+                storage
+            }
+        }
+
+        impl<$($param),*> $crate::convert::FromStorage for $name<$($param),*>
+        where
+            $($kind: $crate::convert::Field<Value = $t>),*
+        {
+            fn from_storage(
+                storage: $crate::DynamicStorage,
+            ) -> ::std::result::Result<Self, ($crate::ErrorDesc, &'static str)> {
+                ::std::result::Result::Ok($name {
+                    $($field: <$kind as $crate::convert::Field>::load(&storage)
+                        .map_err(|e| (e, stringify!($field)))?,)*
+                })
+            }
+        }
+    };
+}
@@ -24,32 +24,85 @@
 //! # }
 //! ```
 //!
+//! ## Features
+//! - `tracing`: Emits [`tracing`][tr] events for `get`, `get_mut`, `ind`, `ind_mut`, `insert`,
+//!   `insert_many`, `extract` and `extract_many`, naming the type and the outcome. Borrow
+//!   conflicts are reported as warnings, as are guards held for longer than the threshold given to
+//!   `set_hold_warning`.
+//! - `events`: Adds `BlackBox::subscribe`, which reports the insertions into and extractions from
+//!   a unit over a channel.
+//! - `instrument`: Adds `BlackBox::new_instrumented`, a storage which keeps track of the guards it
+//!   hands out, so that those held for too long can be listed with `outstanding_borrows`.
+//! - `serde`: Implements `Serialize` for `StorageSummary`, `UnitStats` and the types they hold,
+//!   so that the introspection of a storage can be exported directly.
+//! - `testing`: Adds the `testing` module, with `RecordingStorage`, a storage wrapper which records
+//!   the accesses made through it and can fail the next access to a type on demand.
+//! - `poison`: Adds `BlackBox::new_poisoning`, a storage which poisons a unit when a mutation of
+//!   it panics, or when a mutable guard on it is dropped while panicking. Without it, the mutable
+//!   guards are no larger than those of the backend.
+//! - `pod`: Adds `BlackBox::allocate_pod_for`, `dump_pod` and `restore_pod`, which write the values
+//!   of plain data types, as marked by `Pod`, to a compact binary dump and read them back.
+//!
+//! [tr]: https://docs.rs/tracing
+//!
+// Keeps the examples from declaring storages `mut` when they don't need to be
+#![doc(test(attr(deny(unused_mut))))]
+
+pub mod backend;
 mod black_box;
 mod concurrent_black_box;
+pub mod convert;
+#[cfg(feature = "testing")]
+pub mod testing;
+
+///
+/// The commonly used items of this crate, meant to be glob imported.
+///
+pub mod prelude {
+    pub use crate::{
+        make_storage, BlackBox, DynamicResult, DynamicStorage, ErrorDesc, GuardExt, Map, MapMut,
+        MutexStorage, RwLockStorage, Unit,
+    };
+}
 
+///
+/// A storage which can be shared between threads, with a `Mutex` around the
+/// values of each type, so that values are only ever borrowed mutably. See
+/// `BlackBox` for which methods take `&self` and which take `&mut self`.
+///
 pub type MutexStorage = BlackBox<
-    (dyn for<'a> Unit<
-        'a,
-        Borrowed = MappedMutexGuard<'a, (dyn Any + Send)>,
-        MutBorrowed = MappedMutexGuard<'a, (dyn Any + Send)>,
-        Owned = Box<(dyn Any + Send)>,
-    > + Send),
+    dyn for<'a> Unit<
+            'a,
+            Borrowed = MappedMutexGuard<'a, dyn Any + Send>,
+            MutBorrowed = MappedMutexGuard<'a, dyn Any + Send>,
+            Owned = Box<dyn Any + Send>,
+        > + Send,
 >;
+///
+/// A storage which can be shared between threads, with a `RwLock` around the
+/// values of each type, so that they can be read from many threads at once.
+/// See `BlackBox` for which methods take `&self` and which take `&mut self`.
+///
 pub type RwLockStorage = BlackBox<
-    (dyn for<'a> Unit<
-        'a,
-        Borrowed = MappedRwLockReadGuard<'a, (dyn Any + Send)>,
-        MutBorrowed = MappedRwLockWriteGuard<'a, (dyn Any + Send)>,
-        Owned = Box<(dyn Any + Send)>,
-    > + Send),
+    dyn for<'a> Unit<
+            'a,
+            Borrowed = MappedRwLockReadGuard<'a, dyn Any + Send>,
+            MutBorrowed = MappedRwLockWriteGuard<'a, dyn Any + Send>,
+            Owned = Box<dyn Any + Send>,
+        > + Send,
 >;
+///
+/// A storage for a single thread, with a `RefCell` around the values of each
+/// type. See `BlackBox` for which methods take `&self` and which take
+/// `&mut self`.
+///
 pub type DynamicStorage = BlackBox<
-    (dyn for<'a> Unit<
+    dyn for<'a> Unit<
         'a,
-        Borrowed = Ref<'a, (dyn Any + Send)>,
-        MutBorrowed = RefMut<'a, (dyn Any + Send)>,
-        Owned = Box<(dyn Any + Send)>,
-    >),
+        Borrowed = Ref<'a, dyn Any + Send>,
+        MutBorrowed = RefMut<'a, dyn Any + Send>,
+        Owned = Box<dyn Any + Send>,
+    >,
 >;
 
 ///
@@ -90,9 +143,24 @@ macro_rules! make_storage {
     }
 }
 
+#[cfg(feature = "tracing")]
+pub use black_box::set_hold_warning;
+#[cfg(feature = "events")]
+pub use black_box::StorageEvent;
 pub use black_box::{
-    BlackBox, ErrorDesc, MutexUnitTrait, RefCellUnitTrait, RwLockUnitTrait, Unit, UnitError,
+    AllocateSet, Backend, BlackBox, CapacityEntry, CapacityProfile, ClonedChunks, CowUnit, Cursor,
+    DirtyUnit, DynamicResult, ElemGuardMut, ErrorDesc, ErrorKind, Fairness, GetPolicy, GuardExt,
+    IntegrityError, Map, MapMut, MissingTypes, MutexBackend, MutexUnitTrait, OverflowPolicy,
+    Poisoning, PollResult, RefCellBackend, RefCellUnitTrait, RetryToken, RwLockBackend,
+    RwLockUnitTrait, Sets, SharedView, SingletonStorage, StorageHandle, StorageReadGuard,
+    StorageSummary, StorageUnit, StorageView, StorageWeakHandle, StorageWriteGuard, SummaryEntry,
+    Transaction, TryError, TypeToken, Unit, UnitError, UnitState, UnitStats, ValueChange,
+    COMPOSE_ATTEMPTS,
 };
+#[cfg(feature = "instrument")]
+pub use black_box::{BorrowKind, BorrowReport, Tracked};
+#[cfg(feature = "pod")]
+pub use black_box::{Pod, PodError};
 pub use concurrent_black_box::{MutexUnit, RwLockUnit};
 use parking_lot::{MappedMutexGuard, MappedRwLockReadGuard, MappedRwLockWriteGuard};
 use std::any::Any;
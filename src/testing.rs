@@ -0,0 +1,361 @@
+//! Helpers for testing code which uses a storage, only compiled with the
+//! `testing` feature.
+//!
+//! `RecordingStorage` wraps any of `DynamicStorage`, `RwLockStorage` and
+//! `MutexStorage`, and records every access made through it, so that a test can
+//! check which types the code under test read and wrote. It can also be told to
+//! fail the next access to a type with `fail_next`, to see how that code copes
+//! with a borrow conflict without staging a competing guard.
+//!
+//! ## Example
+//! ```
+//! # use restor::{make_storage, DynamicStorage, ErrorDesc};
+//! # use restor::testing::RecordingStorage;
+//! # fn main() {
+//! #[derive(Debug)]
+//! struct Config(u32);
+//! #[derive(Debug)]
+//! struct Physics(u32);
+//! struct Audio;
+//!
+//! fn step(storage: &RecordingStorage<DynamicStorage>) -> Result<(), ErrorDesc> {
+//!     let config = storage.get::<Config>()?;
+//!     storage.get_mut::<Physics>()?.0 += config.0;
+//!     Ok(())
+//! }
+//!
+//! let storage = make_storage!(DynamicStorage: Config, Physics, Audio);
+//! storage.insert(Config(2)).unwrap();
+//! storage.insert(Physics(0)).unwrap();
+//! let storage = RecordingStorage::new(storage);
+//!
+//! step(&storage).unwrap();
+//! let recorder = storage.recorder();
+//! assert!(recorder.read::<Config>() && !recorder.wrote::<Config>());
+//! assert!(recorder.wrote::<Physics>());
+//! assert_eq!(recorder.touched().len(), 2);
+//!
+//! storage.fail_next::<Physics>(ErrorDesc::BorrowedIncompatibly);
+//! assert_eq!(step(&storage), Err(ErrorDesc::BorrowedIncompatibly));
+//! assert_eq!(storage.storage().get::<Physics>().unwrap().0, 2);
+//! # }
+//! ```
+//!
+use crate::black_box::{BlackBox, DynamicResult, ErrorDesc, Map, MapMut, Unit};
+use parking_lot::Mutex;
+use std::any::{type_name, Any, TypeId};
+
+type Borrowed<'a, U> = <U as Unit<'a>>::Borrowed;
+type MutBorrowed<'a, U> = <U as Unit<'a>>::MutBorrowed;
+type Mapped<'a, U, T> = <Borrowed<'a, U> as Map<dyn Any + Send, T>>::Output;
+type MappedMut<'a, U, T> = <MutBorrowed<'a, U> as MapMut<dyn Any + Send, T>>::Output;
+
+///
+/// Whether an access only read values, as `get` and `ind` do, or could change
+/// them, as every other recorded method does.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AccessKind {
+    Read,
+    Write,
+}
+
+///
+/// A single call made through a `RecordingStorage`.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct Access {
+    /// The name of the type which was accessed.
+    pub type_name: &'static str,
+    /// The id of the type which was accessed.
+    pub type_id: TypeId,
+    /// The name of the method which was called, such as `"get_mut"`.
+    pub op: &'static str,
+    /// Whether the method only reads values.
+    pub kind: AccessKind,
+    /// The error returned by the call, including one injected by `fail_next`.
+    pub outcome: Result<(), ErrorDesc>,
+}
+
+///
+/// The accesses made through a `RecordingStorage`, in the order they were made.
+///
+#[derive(Default)]
+pub struct Recorder {
+    accesses: Mutex<Vec<Access>>,
+}
+
+impl Recorder {
+    ///
+    /// Returns every access recorded so far, oldest first.
+    ///
+    pub fn accesses(&self) -> Vec<Access> {
+        self.accesses.lock().clone()
+    }
+
+    ///
+    /// Returns the accesses to `T` recorded so far, oldest first.
+    ///
+    pub fn accesses_of<T: 'static>(&self) -> Vec<Access> {
+        self.accesses
+            .lock()
+            .iter()
+            .filter(|access| access.type_id == TypeId::of::<T>())
+            .cloned()
+            .collect()
+    }
+
+    ///
+    /// Whether `T` was read, successfully or not.
+    ///
+    pub fn read<T: 'static>(&self) -> bool {
+        self.any::<T>(AccessKind::Read)
+    }
+
+    ///
+    /// Whether `T` was borrowed mutably, inserted or extracted, successfully
+    /// or not.
+    ///
+    pub fn wrote<T: 'static>(&self) -> bool {
+        self.any::<T>(AccessKind::Write)
+    }
+
+    ///
+    /// Returns the names of the types which were accessed, in the order they
+    /// were first accessed in.
+    ///
+    pub fn touched(&self) -> Vec<&'static str> {
+        let mut names = Vec::new();
+        for access in self.accesses.lock().iter() {
+            if !names.contains(&access.type_name) {
+                names.push(access.type_name);
+            }
+        }
+        names
+    }
+
+    ///
+    /// Forgets every access recorded so far.
+    ///
+    pub fn clear(&self) {
+        self.accesses.lock().clear();
+    }
+
+    fn any<T: 'static>(&self, kind: AccessKind) -> bool {
+        self.accesses
+            .lock()
+            .iter()
+            .any(|access| access.type_id == TypeId::of::<T>() && access.kind == kind)
+    }
+
+    fn push<T: 'static>(&self, op: &'static str, kind: AccessKind, outcome: Result<(), ErrorDesc>) {
+        self.accesses.lock().push(Access {
+            type_name: type_name::<T>(),
+            type_id: TypeId::of::<T>(),
+            op,
+            kind,
+            outcome,
+        });
+    }
+}
+
+///
+/// A storage which records every access made through it in its `Recorder`,
+/// and which can fail accesses on demand, see the module documentation.
+///
+/// Only the methods of the storage which are wrapped here are recorded. The
+/// storage itself can still be reached with `storage` and `storage_mut` to set
+/// it up or look at it without recording anything.
+///
+pub struct RecordingStorage<S> {
+    storage: S,
+    recorder: Recorder,
+    failures: Mutex<Vec<(TypeId, ErrorDesc)>>,
+}
+
+impl<U: ?Sized + for<'a> Unit<'a, Owned = Box<dyn Any + Send>>> RecordingStorage<BlackBox<U>> {
+    pub fn new(storage: BlackBox<U>) -> Self {
+        Self {
+            storage,
+            recorder: Recorder::default(),
+            failures: Mutex::new(Vec::new()),
+        }
+    }
+
+    ///
+    /// Returns the accesses made through this so far.
+    ///
+    pub fn recorder(&self) -> &Recorder {
+        &self.recorder
+    }
+
+    ///
+    /// Returns the wrapped storage, through which nothing is recorded.
+    ///
+    pub fn storage(&self) -> &BlackBox<U> {
+        &self.storage
+    }
+
+    ///
+    /// Returns the wrapped storage mutably, for example to allocate units.
+    /// Nothing is recorded through it.
+    ///
+    pub fn storage_mut(&mut self) -> &mut BlackBox<U> {
+        &mut self.storage
+    }
+
+    pub fn into_inner(self) -> BlackBox<U> {
+        self.storage
+    }
+
+    ///
+    /// Makes the next access to `T` through this return `error` without
+    /// touching the storage. Failures for the same type are used up in the
+    /// order they were added in.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() {
+    /// use restor::{make_storage, ErrorDesc, RwLockStorage};
+    /// use restor::testing::RecordingStorage;
+    /// let storage = RecordingStorage::new(make_storage!(RwLockStorage: usize));
+    /// storage.fail_next::<usize>(ErrorDesc::Contended);
+    /// assert_eq!(storage.insert(1usize), Err((1, ErrorDesc::Contended)));
+    /// assert_eq!(storage.insert(2usize), Ok(()));
+    /// # }
+    /// ```
+    ///
+    pub fn fail_next<T: 'static>(&self, error: ErrorDesc) {
+        self.failures.lock().push((TypeId::of::<T>(), error));
+    }
+
+    ///
+    /// Internal function. Takes the first failure injected for `T`, if any.
+    ///
+    fn injected<T: 'static>(&self) -> Option<ErrorDesc> {
+        let mut failures = self.failures.lock();
+        let index = failures
+            .iter()
+            .position(|(id, _)| *id == TypeId::of::<T>())?;
+        Some(failures.remove(index).1)
+    }
+
+    ///
+    /// Internal function. Runs `f` unless a failure was injected for `T`, and
+    /// records the outcome.
+    ///
+    fn checked<T: 'static, R, F: FnOnce() -> DynamicResult<R>>(
+        &self,
+        op: &'static str,
+        kind: AccessKind,
+        f: F,
+    ) -> DynamicResult<R> {
+        let result = match self.injected::<T>() {
+            Some(e) => Err(e),
+            None => f(),
+        };
+        let outcome = result.as_ref().map(|_| ()).map_err(Clone::clone);
+        self.recorder.push::<T>(op, kind, outcome);
+        result
+    }
+
+    ///
+    /// Records a call to `BlackBox::get`.
+    ///
+    pub fn get<'a, T: 'static + Send>(&'a self) -> DynamicResult<Mapped<'a, U, T>>
+    where
+        Borrowed<'a, U>: Map<dyn Any + Send, T, Func = for<'b> fn(&'b (dyn Any + Send)) -> &'b T>,
+    {
+        self.checked::<T, _, _>("get", AccessKind::Read, || self.storage.get::<T>())
+    }
+
+    ///
+    /// Records a call to `BlackBox::get_mut`.
+    ///
+    pub fn get_mut<'a, T: 'static + Send>(&'a self) -> DynamicResult<MappedMut<'a, U, T>>
+    where
+        MutBorrowed<'a, U>: MapMut<dyn Any + Send, T, Func = fn(&mut (dyn Any + Send)) -> &mut T>,
+    {
+        self.checked::<T, _, _>("get_mut", AccessKind::Write, || self.storage.get_mut::<T>())
+    }
+
+    ///
+    /// Records a call to `BlackBox::ind`.
+    ///
+    pub fn ind<'a, T: 'static + Send>(&'a self, ind: usize) -> DynamicResult<Mapped<'a, U, T>>
+    where
+        Borrowed<'a, U>: Map<dyn Any + Send, T, Func = for<'b> fn(&'b (dyn Any + Send)) -> &'b T>,
+    {
+        self.checked::<T, _, _>("ind", AccessKind::Read, || self.storage.ind::<T>(ind))
+    }
+
+    ///
+    /// Records a call to `BlackBox::ind_mut`.
+    ///
+    pub fn ind_mut<'a, T: 'static + Send>(
+        &'a self,
+        ind: usize,
+    ) -> DynamicResult<MappedMut<'a, U, T>>
+    where
+        MutBorrowed<'a, U>: MapMut<dyn Any + Send, T, Func = fn(&mut (dyn Any + Send)) -> &mut T>,
+    {
+        self.checked::<T, _, _>("ind_mut", AccessKind::Write, || {
+            self.storage.ind_mut::<T>(ind)
+        })
+    }
+
+    ///
+    /// Records a call to `BlackBox::insert`. An injected failure hands `data`
+    /// back alongside the error.
+    ///
+    pub fn insert<T: 'static + Send>(&self, data: T) -> Result<(), (T, ErrorDesc)> {
+        let result = match self.injected::<T>() {
+            Some(e) => Err((data, e)),
+            None => self.storage.insert(data),
+        };
+        let outcome = result.as_ref().map(|_| ()).map_err(|(_, e)| e.clone());
+        self.recorder
+            .push::<T>("insert", AccessKind::Write, outcome);
+        result
+    }
+
+    ///
+    /// Records a call to `BlackBox::insert_many`. An injected failure hands
+    /// `data` back alongside the error.
+    ///
+    pub fn insert_many<T: 'static + Send>(&self, data: Vec<T>) -> Result<(), (Vec<T>, ErrorDesc)> {
+        let result = match self.injected::<T>() {
+            Some(e) => Err((data, e)),
+            None => self.storage.insert_many(data),
+        };
+        let outcome = result.as_ref().map(|_| ()).map_err(|(_, e)| e.clone());
+        self.recorder
+            .push::<T>("insert_many", AccessKind::Write, outcome);
+        result
+    }
+
+    ///
+    /// Records a call to `BlackBox::extract`.
+    ///
+    pub fn extract<T: 'static + Send>(&self) -> DynamicResult<T> {
+        self.checked::<T, _, _>("extract", AccessKind::Write, || self.storage.extract::<T>())
+    }
+
+    ///
+    /// Records a call to `BlackBox::extract_ind`.
+    ///
+    pub fn extract_ind<T: 'static + Send>(&self, ind: usize) -> DynamicResult<T> {
+        self.checked::<T, _, _>("extract_ind", AccessKind::Write, || {
+            self.storage.extract_ind::<T>(ind)
+        })
+    }
+
+    ///
+    /// Records a call to `BlackBox::extract_many`.
+    ///
+    pub fn extract_many<T: 'static + Send>(&self) -> DynamicResult<Box<[T]>> {
+        self.checked::<T, _, _>("extract_many", AccessKind::Write, || {
+            self.storage.extract_many::<T>()
+        })
+    }
+}
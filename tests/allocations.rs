@@ -0,0 +1,159 @@
+//! Checks that the error paths of the accessors don't allocate, so that polling
+//! a unit which is expected to be locked is cheap, and that a unit given a
+//! buffer with `allocate_for_with_vec` keeps reusing it.
+use restor::{make_storage, DynamicStorage, ErrorKind, MutexStorage, RwLockStorage, UnitError};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+/// Counts the allocations made by each thread, as the tests run in parallel
+struct Counting;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.with(|x| x.set(x.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+fn allocations<R>(f: impl FnOnce() -> R) -> usize {
+    let before = ALLOCATIONS.with(Cell::get);
+    drop(f());
+    ALLOCATIONS.with(Cell::get) - before
+}
+
+macro_rules! no_allocations {
+    ($name:ident, $storage:ty, $read:ident) => {
+        #[test]
+        fn $name() {
+            let storage = make_storage!($storage: usize);
+            storage.insert_many(vec![1usize, 2]).unwrap();
+            let empty = make_storage!($storage: isize);
+            let lock = storage.ind_mut::<usize>(0).unwrap();
+            // Make sure any lazily initialized state is in place first
+            let _ = storage.$read::<usize>(0);
+
+            let borrowed = allocations(|| {
+                for _ in 0..100 {
+                    let err = storage.get_mut::<usize>().unwrap_err();
+                    assert_eq!(err.kind(), ErrorKind::BorrowedIncompatibly);
+                    assert!(storage.$read::<usize>(1).is_err());
+                    assert!(storage.ind_mut::<usize>(1).is_err());
+                }
+            });
+            assert_eq!(borrowed, 0);
+            drop(lock);
+
+            let missing = allocations(|| {
+                for _ in 0..100 {
+                    let err = storage.$read::<u8>(0).unwrap_err();
+                    assert_eq!(err.kind(), ErrorKind::NoAllocatedUnit);
+                    let err = storage.get_mut::<usize>().unwrap_err();
+                    assert_eq!(err.kind(), ErrorKind::Unit(UnitError::IsNotOne));
+                    assert!(storage.ind_mut::<usize>(5).is_err());
+                    assert!(empty.ind_mut::<isize>(0).is_err());
+                }
+            });
+            assert_eq!(missing, 0);
+        }
+    };
+}
+
+no_allocations!(dynamic, DynamicStorage, ind);
+no_allocations!(mutex, MutexStorage, ind_mut);
+no_allocations!(rwlock, RwLockStorage, ind);
+
+#[test]
+fn context_allocates() {
+    let err = restor::ErrorDesc::BorrowedIncompatibly;
+    assert_eq!(allocations(|| err.clone()), 0);
+    assert_eq!(allocations(|| err.clone().context("polling")), 1);
+}
+
+/// The allocations made by each of `frames` runs of `frame`, once it has
+/// warmed up, like in `benches/frames.rs`
+fn frame_allocations(frames: usize, mut frame: impl FnMut()) -> Vec<usize> {
+    for _ in 0..4 {
+        frame();
+    }
+    (0..frames).map(|_| allocations(&mut frame)).collect()
+}
+
+macro_rules! buffered_frames {
+    ($name:ident, $storage:ty) => {
+        mod $name {
+            use super::*;
+
+            pub(super) fn storage() -> $storage {
+                let mut storage = <$storage>::new();
+                storage.allocate_for_with_vec::<usize>(Vec::with_capacity(4));
+                storage
+            }
+
+            #[test]
+            fn insert_and_get_mut() {
+                let storage = storage();
+                let mut buf = Vec::with_capacity(4);
+                let frames = frame_allocations(16, || {
+                    storage.insert(0usize).unwrap();
+                    *storage.insert_and_get_mut(1usize).unwrap() += 1;
+                    storage.extract_many_into::<usize>(&mut buf).unwrap();
+                    buf.clear();
+                });
+                assert_eq!(frames, vec![0; 16]);
+            }
+
+            #[test]
+            fn transaction() {
+                let storage = storage();
+                let mut buf = Vec::with_capacity(4);
+                let mut commits = Vec::new();
+                frame_allocations(16, || {
+                    storage.insert(0usize).unwrap();
+                    let mut txn = storage.transaction();
+                    txn.insert(1usize);
+                    commits.push(allocations(|| txn.commit().unwrap()));
+                    storage.extract_many_into::<usize>(&mut buf).unwrap();
+                    buf.clear();
+                });
+                // Only the list of the units borrowed by the commit is allocated
+                assert_eq!(commits[4..], vec![1; 16][..]);
+            }
+        }
+    };
+}
+
+buffered_frames!(dynamic_frames, DynamicStorage);
+buffered_frames!(mutex_frames, MutexStorage);
+buffered_frames!(rwlock_frames, RwLockStorage);
+
+#[test]
+fn get_or_recover_frames() {
+    let dynamic = dynamic_frames::storage();
+    let rwlock = rwlock_frames::storage();
+    let mutex = mutex_frames::storage();
+    let mut buf = Vec::with_capacity(4);
+    let frames = frame_allocations(16, || {
+        drop(dynamic.get_or_recover::<usize, _>(|_| Some(0)).unwrap());
+        dynamic.insert(1usize).unwrap();
+        dynamic.extract_many_into::<usize>(&mut buf).unwrap();
+        drop(rwlock.get_or_recover::<usize, _>(|_| Some(0)).unwrap());
+        rwlock.insert(1usize).unwrap();
+        rwlock.extract_many_into::<usize>(&mut buf).unwrap();
+        drop(mutex.get_or_recover::<usize, _>(|_| Some(0)).unwrap());
+        mutex.insert(1usize).unwrap();
+        mutex.extract_many_into::<usize>(&mut buf).unwrap();
+        buf.clear();
+    });
+    assert_eq!(frames, vec![0; 16]);
+}
@@ -0,0 +1,28 @@
+use restor::backend::{
+    conformance, CowUnit, DirtyUnit, MutexUnit, RefCellUnit, RwLockUnit, StorageUnit,
+};
+
+#[test]
+fn refcell_unit_conforms() {
+    conformance::run::<RefCellUnit<StorageUnit<u32>>>();
+}
+
+#[test]
+fn mutex_unit_conforms() {
+    conformance::run::<MutexUnit<StorageUnit<u32>>>();
+}
+
+#[test]
+fn rwlock_unit_conforms() {
+    conformance::run::<RwLockUnit<StorageUnit<u32>>>();
+}
+
+#[test]
+fn cow_unit_conforms() {
+    conformance::run::<CowUnit<StorageUnit<u32>>>();
+}
+
+#[test]
+fn dirty_unit_conforms() {
+    conformance::run::<DirtyUnit<StorageUnit<u32>>>();
+}
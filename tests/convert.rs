@@ -0,0 +1,110 @@
+use restor::convert::{FromStorage, IntoStorage};
+use restor::{restor_struct, DynamicStorage, ErrorDesc, UnitError};
+
+#[derive(Debug, PartialEq)]
+struct Time(f64);
+#[derive(Debug, PartialEq)]
+struct Input(Vec<char>);
+#[derive(Debug, PartialEq)]
+struct Config {
+    name: String,
+}
+
+#[derive(Debug, PartialEq)]
+struct Resources {
+    time: Time,
+    input: Input,
+    config: Option<Config>,
+    frames: Option<Vec<u8>>,
+}
+restor_struct!(Resources {
+    time: Time,
+    input: Input,
+    config: Option<Config>,
+    frames: Option<Vec<u8>>
+});
+
+fn resources() -> Resources {
+    Resources {
+        time: Time(1.0),
+        input: Input(vec!['w', 'a']),
+        config: Some(Config {
+            name: String::from("test"),
+        }),
+        frames: None,
+    }
+}
+
+#[test]
+fn round_trip() {
+    let storage = resources().into_storage();
+    assert_eq!(storage.get::<Time>().unwrap().0, 1.0);
+    assert_eq!(&storage.get::<Config>().unwrap().name, "test");
+    // `None` leaves the unit unallocated
+    assert!(!storage.has_unit::<Vec<u8>>());
+    assert_eq!(Resources::from_storage(storage), Ok(resources()));
+}
+
+#[test]
+fn optional_fields_follow_their_units() {
+    let storage = resources().into_storage();
+    storage.extract::<Config>().unwrap();
+    let mut storage = storage;
+    storage.allocate_for::<Vec<u8>>();
+    storage.insert(vec![1u8, 2]).unwrap();
+
+    let converted = Resources::from_storage(storage).unwrap();
+    // An empty unit is `None` as well
+    assert_eq!(converted.config, None);
+    assert_eq!(converted.frames, Some(vec![1, 2]));
+}
+
+#[test]
+fn reports_the_failing_field() {
+    let storage = resources().into_storage();
+    storage.extract::<Input>().unwrap();
+    assert_eq!(
+        Resources::from_storage(storage),
+        Err((ErrorDesc::Unit(UnitError::IsNone), "input"))
+    );
+
+    let mut storage = DynamicStorage::new();
+    storage.allocate_for::<Time>();
+    storage.insert(Time(0.0)).unwrap();
+    assert_eq!(
+        Resources::from_storage(storage),
+        Err((ErrorDesc::NoAllocatedUnit, "input"))
+    );
+}
+
+#[derive(Debug, PartialEq)]
+struct Pair<T> {
+    first: T,
+    second: T,
+    label: &'static str,
+}
+restor_struct!(Pair<T> {
+    first: T,
+    second: T,
+    label: &'static str,
+});
+
+#[test]
+fn fields_of_the_same_type_keep_their_places() {
+    let pair = Pair {
+        first: 1usize,
+        second: 2usize,
+        label: "pair",
+    };
+    let storage = pair.into_storage();
+    assert_eq!(&*storage.extract_many::<usize>().unwrap(), &[1, 2]);
+    storage.insert_many(vec![3usize, 4]).unwrap();
+    assert_eq!(
+        Pair::from_storage(storage),
+        Ok(Pair {
+            first: 3usize,
+            second: 4,
+            label: "pair"
+        })
+    );
+}
@@ -0,0 +1,81 @@
+#![cfg(feature = "events")]
+use restor::{RwLockStorage, StorageEvent};
+use std::any::TypeId;
+use std::sync::Arc;
+use std::thread::spawn;
+
+#[test]
+fn observe_sequence() {
+    let mut storage = RwLockStorage::new();
+    storage.allocate_for::<usize>();
+    storage.allocate_for::<isize>();
+    let receiver = storage.subscribe::<usize>().unwrap();
+    let storage = Arc::new(storage);
+
+    let listener = spawn(move || receiver.iter().take(6).collect::<Vec<_>>());
+
+    storage.insert(0usize).unwrap();
+    storage.insert(0isize).unwrap();
+    storage.insert_many(vec![1usize, 2]).unwrap();
+    storage.extract::<usize>().unwrap();
+    storage.extract_many::<usize>().unwrap();
+    storage.extract::<usize>().unwrap_err();
+    storage.insert(3usize).unwrap();
+
+    let id = TypeId::of::<usize>();
+    assert_eq!(
+        listener.join().unwrap(),
+        vec![
+            StorageEvent::Inserted { id, index: 0 },
+            StorageEvent::Inserted { id, index: 1 },
+            StorageEvent::Inserted { id, index: 2 },
+            StorageEvent::Extracted { id, index: 0 },
+            StorageEvent::Cleared { id },
+            StorageEvent::Inserted { id, index: 0 },
+        ]
+    );
+}
+
+#[test]
+fn disconnected_subscriber() {
+    let mut storage = RwLockStorage::new();
+    storage.allocate_for::<usize>();
+    assert!(storage.subscribe::<isize>().is_err());
+    let first = storage.subscribe::<usize>().unwrap();
+    let second = storage.subscribe::<usize>().unwrap();
+    drop(first);
+    storage.insert(0usize).unwrap();
+    assert_eq!(
+        second.try_recv(),
+        Ok(StorageEvent::Inserted {
+            id: TypeId::of::<usize>(),
+            index: 0
+        })
+    );
+}
+
+#[test]
+fn remove_where_events() {
+    let mut storage = RwLockStorage::new();
+    storage.allocate_for::<usize>();
+    storage.insert_many(vec![1usize, 2, 3, 4]).unwrap();
+    let receiver = storage.subscribe::<usize>().unwrap();
+
+    storage.remove_where::<usize, _>(|x| *x == 2).unwrap();
+    storage.remove_where::<usize, _>(|x| *x == 9).unwrap();
+    // The last value takes the place of the removed one
+    storage.swap_remove_where::<usize, _>(|x| *x == 1).unwrap();
+    storage.swap_remove_where::<usize, _>(|x| *x == 3).unwrap();
+
+    let id = TypeId::of::<usize>();
+    assert_eq!(
+        receiver.try_iter().collect::<Vec<_>>(),
+        vec![
+            StorageEvent::Extracted { id, index: 1 },
+            StorageEvent::Extracted { id, index: 2 },
+            StorageEvent::Inserted { id, index: 0 },
+            StorageEvent::Extracted { id, index: 1 },
+        ]
+    );
+    assert_eq!(*storage.ind::<usize>(0).unwrap(), 4);
+}
@@ -0,0 +1,66 @@
+#![cfg(feature = "instrument")]
+use restor::{BorrowKind, DynamicStorage, MutexStorage, RwLockStorage};
+use std::time::Duration;
+
+#[derive(Debug)]
+struct Position(#[allow(dead_code)] f32);
+#[derive(Debug)]
+struct Velocity(#[allow(dead_code)] f32);
+
+#[test]
+fn leaked_guard_is_reported() {
+    let mut storage = RwLockStorage::new_instrumented();
+    storage.allocate_for::<Position>();
+    storage.allocate_for::<Velocity>();
+    storage.insert(Position(0.0)).unwrap();
+    storage.insert(Velocity(1.0)).unwrap();
+
+    // A frame which borrows both types, but only releases one of them
+    let leaked = {
+        let _velocity = storage.get::<Velocity>().unwrap();
+        storage.get_mut::<Position>().unwrap()
+    };
+    std::thread::sleep(Duration::from_millis(5));
+
+    let reports = storage.outstanding_borrows();
+    assert_eq!(reports.len(), 1);
+    assert!(reports[0].type_name.ends_with("Position"));
+    assert_eq!(reports[0].kind, BorrowKind::Write);
+    assert!(reports[0].age >= Duration::from_millis(5));
+    #[cfg(debug_assertions)]
+    assert!(reports[0].backtrace.is_some());
+
+    drop(leaked);
+    assert!(storage.outstanding_borrows().is_empty());
+}
+
+#[test]
+fn every_backend_is_tracked() {
+    let mut dynamic = DynamicStorage::new_instrumented();
+    dynamic.allocate_for::<usize>();
+    dynamic.insert_many(vec![1usize, 2]).unwrap();
+    let first = dynamic.ind::<usize>(0).unwrap();
+    let second = dynamic.ind::<usize>(1).unwrap();
+    let kinds = dynamic
+        .outstanding_borrows()
+        .iter()
+        .map(|x| (x.type_name, x.kind))
+        .collect::<Vec<_>>();
+    assert_eq!(kinds, vec![("usize", BorrowKind::Read); 2]);
+    drop((first, second));
+    assert!(dynamic.outstanding_borrows().is_empty());
+
+    let mut mutex = MutexStorage::new_instrumented();
+    mutex.allocate_for::<usize>();
+    let inserted = mutex.insert_and_get_mut(3usize).unwrap();
+    assert_eq!(mutex.outstanding_borrows()[0].kind, BorrowKind::Write);
+    drop(inserted);
+    assert!(mutex.outstanding_borrows().is_empty());
+
+    // Storages which aren't instrumented don't report anything
+    let mut storage = RwLockStorage::new();
+    storage.allocate_for::<usize>();
+    storage.insert(0usize).unwrap();
+    let _guard = storage.get::<usize>().unwrap();
+    assert!(storage.outstanding_borrows().is_empty());
+}
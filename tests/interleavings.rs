@@ -0,0 +1,118 @@
+//! Runs two operations on a concurrent storage at the same time many times
+//! over, checking after each run that nothing was lost or duplicated no
+//! matter how they interleaved. Operations which find the unit locked fail
+//! rather than wait, so every outcome is checked, not only the successes.
+use restor::{ErrorDesc, MutexStorage, RwLockStorage, UnitError};
+use std::sync::{Arc, Barrier};
+use std::thread::spawn;
+
+const RUNS: usize = 300;
+
+/// Runs `a` and `b` on separate threads, starting them as close together as possible
+fn race<S, A, B, RA, RB>(storage: &Arc<S>, a: A, b: B) -> (RA, RB)
+where
+    S: Send + Sync + 'static,
+    A: FnOnce(&S) -> RA + Send + 'static,
+    B: FnOnce(&S) -> RB + Send + 'static,
+    RA: Send + 'static,
+    RB: Send + 'static,
+{
+    let barrier = Arc::new(Barrier::new(2));
+    let (sa, ba) = (storage.clone(), barrier.clone());
+    let ta = spawn(move || {
+        ba.wait();
+        a(&sa)
+    });
+    let (sb, bb) = (storage.clone(), barrier);
+    let tb = spawn(move || {
+        bb.wait();
+        b(&sb)
+    });
+    (ta.join().unwrap(), tb.join().unwrap())
+}
+
+fn borrowed<T>(result: &Result<T, ErrorDesc>) -> bool {
+    result.as_ref().err() == Some(&ErrorDesc::BorrowedIncompatibly)
+}
+
+macro_rules! interleavings {
+    ($name:ident, $storage:ty) => {
+        mod $name {
+            use super::*;
+
+            #[test]
+            fn insert_and_get() {
+                for _ in 0..RUNS {
+                    let x = Arc::new(restor::make_storage!($storage: usize));
+                    let (inserted, got) = race(
+                        &x,
+                        |x| x.insert(1usize).map_err(|e| e.1),
+                        |x| x.get_mut::<usize>().map(|x| *x),
+                    );
+                    match &got {
+                        Ok(value) => assert_eq!(*value, 1),
+                        Err(e) => assert!(
+                            *e == ErrorDesc::Unit(UnitError::IsNotOne)
+                                || *e == ErrorDesc::BorrowedIncompatibly,
+                            "{}",
+                            e
+                        ),
+                    }
+                    if inserted.is_ok() {
+                        assert_eq!(*x.get_mut::<usize>().unwrap(), 1);
+                    } else {
+                        assert!(borrowed(&inserted));
+                        assert!(got.is_err());
+                    }
+                }
+            }
+
+            #[test]
+            fn insert_and_extract() {
+                for _ in 0..RUNS {
+                    let x = Arc::new(restor::make_storage!($storage: usize));
+                    x.insert(1usize).unwrap();
+                    let (inserted, extracted) = race(
+                        &x,
+                        |x| x.insert(2usize).map_err(|e| e.1),
+                        |x| x.extract::<usize>(),
+                    );
+                    assert!(inserted.is_ok() || borrowed(&inserted));
+                    assert!(extracted.is_ok() || borrowed(&extracted));
+                    let mut remaining = x.extract_any_count::<usize>().unwrap();
+                    // Values are only ever extracted from the front
+                    if let Ok(value) = extracted {
+                        assert_eq!(value, 1);
+                        remaining.insert(0, value);
+                    }
+                    let expected = if inserted.is_ok() { vec![1, 2] } else { vec![1] };
+                    assert_eq!(remaining, expected);
+                }
+            }
+
+            #[test]
+            fn double_get_mut() {
+                for _ in 0..RUNS {
+                    let x = Arc::new(restor::make_storage!($storage: usize));
+                    x.insert(0usize).unwrap();
+                    let increment = |x: &$storage| {
+                        x.get_mut::<usize>().map(|mut value| {
+                            let seen = *value;
+                            std::thread::yield_now();
+                            *value = seen + 1;
+                        })
+                    };
+                    let (a, b) = race(&x, increment, increment);
+                    assert!(a.is_ok() || borrowed(&a));
+                    assert!(b.is_ok() || borrowed(&b));
+                    // An increment is never lost to another one
+                    let succeeded = a.is_ok() as usize + b.is_ok() as usize;
+                    assert_eq!(*x.get_mut::<usize>().unwrap(), succeeded);
+                }
+            }
+        }
+    };
+}
+
+interleavings!(mutex, MutexStorage);
+interleavings!(rwlock, RwLockStorage);
@@ -1,4 +1,4 @@
-use restor::{ErrorDesc, MutexStorage};
+use restor::{ErrorDesc, MutexStorage, UnitError};
 use std::sync::Arc;
 use std::thread::spawn;
 use std::time::Duration;
@@ -108,9 +108,9 @@ fn concurrent_ind_mut() {
             *z = 10;
         }
     });
-    t.join();
+    t.join().unwrap();
     let xc = x.clone();
-    let t = spawn(move || {
+    let _t = spawn(move || {
         let y = xc.ind_mut::<usize>(1);
         assert!(y.is_ok());
         if let Ok(z) = y {
@@ -135,6 +135,633 @@ fn concurrent_ind_mut() {
             panic!("{:?}", *z.unwrap())
         }
     });
-    t1.join();
-    t2.join();
+    t1.join().unwrap();
+    t2.join().unwrap();
+}
+
+#[test]
+fn map_in_place() {
+    let mut x = MutexStorage::new();
+    x.allocate_for::<usize>();
+    x.allocate_for::<String>();
+    x.insert_many(vec![1usize, 2, 3]).unwrap();
+    x.insert(String::from("abc")).unwrap();
+    x.map_in_place::<usize, _>(|v| v * 2).unwrap();
+    x.map_in_place::<String, _>(|s| s + "def").unwrap();
+    assert_eq!(*x.ind_mut::<usize>(0).unwrap(), 2);
+    assert_eq!(*x.ind_mut::<usize>(1).unwrap(), 4);
+    assert_eq!(*x.ind_mut::<usize>(2).unwrap(), 6);
+    assert_eq!(&*x.get_mut::<String>().unwrap(), "abcdef");
+}
+
+#[test]
+fn map_in_place_panic() {
+    let mut x = MutexStorage::new();
+    x.allocate_for::<String>();
+    x.insert_many(vec![
+        String::from("a"),
+        String::from("b"),
+        String::from("c"),
+    ])
+    .unwrap();
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        x.map_in_place::<String, _>(|s| {
+            if s == "b" {
+                panic!("Mapping failed");
+            }
+            s + "!"
+        })
+    }));
+    assert!(result.is_err());
+    assert_eq!(&*x.ind_mut::<String>(0).unwrap(), "a!");
+    assert_eq!(&*x.ind_mut::<String>(1).unwrap(), "c");
+    assert_eq!(
+        x.ind_mut::<String>(2).map(|_| ()),
+        Err(ErrorDesc::Unit(UnitError::OutOfBounds))
+    );
+}
+
+#[test]
+fn position() {
+    let mut x = MutexStorage::new();
+    x.allocate_for::<usize>();
+    assert_eq!(x.position::<usize, _>(|v| *v == 3), Ok(None));
+    x.insert(3usize).unwrap();
+    assert_eq!(x.position::<usize, _>(|v| *v == 3), Ok(Some(0)));
+    x.insert_many(vec![4usize, 5, 6]).unwrap();
+    assert_eq!(x.position::<usize, _>(|v| *v == 6), Ok(Some(3)));
+    assert_eq!(x.position::<usize, _>(|v| *v == 7), Ok(None));
+    assert_eq!(
+        x.position::<isize, _>(|v| *v == 0),
+        Err(ErrorDesc::NoAllocatedUnit)
+    );
+    let _lock = x.ind_mut::<usize>(0).unwrap();
+    assert_eq!(
+        x.position::<usize, _>(|v| *v == 3),
+        Err(ErrorDesc::BorrowedIncompatibly)
+    );
+}
+
+#[test]
+fn find_clone() {
+    let mut x = MutexStorage::new();
+    x.allocate_for::<String>();
+    assert_eq!(x.find_clone::<String, _>(|_| true), Ok(None));
+    x.insert(String::from("abc")).unwrap();
+    assert_eq!(
+        x.find_clone::<String, _>(|s| s.len() == 3),
+        Ok(Some(String::from("abc")))
+    );
+    x.insert_many(vec![String::from("de"), String::from("fg")])
+        .unwrap();
+    assert_eq!(
+        x.find_clone::<String, _>(|s| s.len() == 2),
+        Ok(Some(String::from("de")))
+    );
+    // No guard escapes, so the unit can be mutably borrowed right away
+    x.ind_mut::<String>(1).unwrap().push('!');
+}
+
+#[test]
+fn shared() {
+    use std::sync::Arc;
+    let mut x = MutexStorage::new();
+    assert_eq!(
+        x.insert_shared(String::from("abc")),
+        Err((String::from("abc"), ErrorDesc::NoAllocatedUnit))
+    );
+    x.allocate_for::<Arc<String>>();
+    x.insert_shared(String::from("abc")).unwrap();
+    let first = x.get_shared::<String>().unwrap();
+    // No guard outlives the call
+    assert!(x.get_mut::<Arc<String>>().is_ok());
+    let second = x.get_shared::<String>().unwrap();
+    assert!(Arc::ptr_eq(&first, &second));
+    assert_eq!(&*first, "abc");
+    x.insert_shared(String::from("def")).unwrap();
+    assert_eq!(
+        x.get_shared::<String>(),
+        Err(ErrorDesc::Unit(UnitError::IsNotOne))
+    );
+}
+
+#[test]
+fn guard_ext() {
+    use restor::GuardExt;
+    let x = restor::make_storage!(MutexStorage: String);
+    x.insert(String::from("abc")).unwrap();
+    let owned = x.get_mut::<String>().unwrap().cloned();
+    // The guard was consumed, so the unit is free again
+    x.get_mut::<String>().unwrap().push_str("def");
+    assert_eq!(owned, "abc");
+    assert_eq!(x.get_mut::<String>().unwrap().map_owned(|s| s.len()), 6);
+}
+
+#[test]
+fn ind_multi() {
+    let mut x = MutexStorage::new();
+    x.allocate_for::<usize>();
+    x.allocate_for::<String>();
+    assert_eq!(x.ind_multi::<u8>(&[0]), Err(ErrorDesc::NoAllocatedUnit));
+    x.insert(String::from("one")).unwrap();
+    assert_eq!(
+        x.ind_multi::<String>(&[0, 0]),
+        Ok(vec![String::from("one"), String::from("one")])
+    );
+    x.insert_many(vec![0usize, 10, 20, 30]).unwrap();
+    assert_eq!(x.ind_multi::<usize>(&[3, 0, 3, 1]), Ok(vec![30, 0, 30, 10]));
+    assert_eq!(x.ind_multi::<usize>(&[]), Ok(vec![]));
+    assert_eq!(
+        x.ind_multi::<usize>(&[1, 4, 2]),
+        Err(ErrorDesc::Unit(UnitError::OutOfBounds))
+    );
+    let mut called = false;
+    assert_eq!(
+        x.with_ind_multi::<usize, _, _>(&[2, 5], |_| called = true),
+        Err(ErrorDesc::Unit(UnitError::OutOfBounds))
+    );
+    assert!(!called);
+    assert_eq!(
+        x.with_ind_multi::<usize, _, _>(&[2, 1], |x| *x[0] - *x[1]),
+        Ok(10)
+    );
+    // The borrow is released afterwards
+    x.extract_many::<usize>().unwrap();
+}
+
+#[test]
+fn insert_iter() {
+    let mut x = MutexStorage::new();
+    assert_eq!(
+        x.insert_iter((0..3usize).map(|x| x * 2)),
+        Err((vec![0, 2, 4], ErrorDesc::NoAllocatedUnit))
+    );
+    x.allocate_for::<usize>();
+    x.allocate_for::<String>();
+    x.insert_iter(std::iter::empty::<usize>()).unwrap();
+    assert_eq!(x.capacity::<usize>(), Ok(0));
+    x.insert_iter([1usize]).unwrap();
+    x.insert_iter(2..4usize).unwrap();
+    x.insert_iter(vec![4usize, 5].into_iter().map(|x| x * 10))
+        .unwrap();
+    assert_eq!(
+        x.ind_multi::<usize>(&[0, 1, 2, 3, 4]),
+        Ok(vec![1, 2, 3, 40, 50])
+    );
+    x.insert_iter(["a", "b"].iter().map(|s| s.to_string()))
+        .unwrap();
+    let _lock = x.ind_mut::<String>(0).unwrap();
+    assert_eq!(
+        x.insert_iter(["c"].iter().map(|s| s.to_string())),
+        Err((vec![String::from("c")], ErrorDesc::BorrowedIncompatibly))
+    );
+}
+
+#[test]
+fn recursive_borrow() {
+    let mut x = MutexStorage::new_debug();
+    x.allocate_for::<usize>();
+    x.insert_many(vec![0usize, 1]).unwrap();
+    let x = Arc::new(x);
+
+    let lock = x.ind_mut::<usize>(0).unwrap();
+    assert_eq!(
+        x.ind_mut::<usize>(1).err(),
+        Some(ErrorDesc::RecursiveBorrow)
+    );
+    assert_eq!(x.extract::<usize>().err(), Some(ErrorDesc::RecursiveBorrow));
+    assert_eq!(
+        x.insert(2usize).err(),
+        Some((2, ErrorDesc::RecursiveBorrow))
+    );
+    let xc = x.clone();
+    let t = spawn(move || xc.ind_mut::<usize>(1).map(|_| ()));
+    assert_eq!(t.join().unwrap(), Err(ErrorDesc::BorrowedIncompatibly));
+    drop(lock);
+
+    let xc = x.clone();
+    let locked = Arc::new(std::sync::Barrier::new(2));
+    let barrier = locked.clone();
+    let t = spawn(move || {
+        let _lock = xc.ind_mut::<usize>(0).unwrap();
+        barrier.wait();
+        barrier.wait();
+    });
+    locked.wait();
+    assert_eq!(
+        x.ind_mut::<usize>(0).err(),
+        Some(ErrorDesc::BorrowedIncompatibly)
+    );
+    locked.wait();
+    t.join().unwrap();
+    assert!(x.ind_mut::<usize>(0).is_ok());
+
+    // Without tracking, both are reported the same way
+    let y = restor::make_storage!(MutexStorage: usize);
+    y.insert(0usize).unwrap();
+    let _lock = y.get_mut::<usize>().unwrap();
+    assert_eq!(
+        y.get_mut::<usize>().err(),
+        Some(ErrorDesc::BorrowedIncompatibly)
+    );
+}
+
+#[test]
+fn extract_regardless_of_count() {
+    let mut x = MutexStorage::new();
+    x.allocate_for::<usize>();
+    assert_eq!(x.extract_any_count::<usize>(), Ok(vec![]));
+    assert_eq!(
+        x.extract_one_loose::<usize>(),
+        Err(ErrorDesc::Unit(UnitError::IsNone))
+    );
+
+    x.insert(1usize).unwrap();
+    assert_eq!(x.extract_any_count::<usize>(), Ok(vec![1]));
+    x.insert(1usize).unwrap();
+    assert_eq!(x.extract_one_loose::<usize>(), Ok(1));
+    assert_eq!(x.extract_any_count::<usize>(), Ok(vec![]));
+
+    x.insert_many(vec![1usize, 2, 3]).unwrap();
+    assert_eq!(x.extract_one_loose::<usize>(), Ok(1));
+    assert_eq!(x.extract_any_count::<usize>(), Ok(vec![2, 3]));
+    assert_eq!(x.extract_any_count::<usize>(), Ok(vec![]));
+
+    assert_eq!(
+        x.extract_any_count::<isize>(),
+        Err(ErrorDesc::NoAllocatedUnit)
+    );
+}
+
+#[test]
+fn guards_are_shared_across_threads() {
+    let x = restor::make_storage!(MutexStorage: String);
+    x.insert(String::from("abc")).unwrap();
+    let mut guard = x.get_mut::<String>().unwrap();
+    guard.push('d');
+    let guard = &guard;
+    std::thread::scope(|s| {
+        s.spawn(move || assert_eq!(&**guard, "abcd"));
+        s.spawn(move || assert_eq!(guard.len(), 4));
+    });
+}
+
+#[test]
+fn insert_indexed() {
+    let mut x = MutexStorage::new();
+    assert_eq!(
+        x.insert_indexed(0usize),
+        Err((0usize, ErrorDesc::NoAllocatedUnit))
+    );
+    x.allocate_for::<usize>();
+    for value in 10..15usize {
+        let index = x.insert_indexed(value).unwrap();
+        assert_eq!(*x.ind_mut::<usize>(index).unwrap(), value);
+    }
+    x.extract_ind::<usize>(1).unwrap();
+    let index = x.insert_indexed(20usize).unwrap();
+    assert_eq!(index, 4);
+    assert_eq!(*x.ind_mut::<usize>(index).unwrap(), 20);
+}
+
+#[test]
+fn try_get_outcomes() {
+    let mut x = MutexStorage::new();
+    // No unit allocated
+    assert!(x.try_get_mut::<usize>().unwrap().is_none());
+    assert!(x.try_ind_mut::<usize>(0).unwrap().is_none());
+
+    // No values
+    x.allocate_for::<usize>();
+    assert!(x.try_get_mut::<usize>().unwrap().is_none());
+    assert!(x.try_get_mut::<usize>().unwrap().is_none());
+    assert!(x.try_ind_mut::<usize>(0).unwrap().is_none());
+    assert!(x.try_ind_mut::<usize>(1).unwrap().is_none());
+
+    // A single value
+    x.insert(1usize).unwrap();
+    assert_eq!(*x.try_get_mut::<usize>().unwrap().unwrap(), 1);
+    *x.try_get_mut::<usize>().unwrap().unwrap() += 1;
+    assert_eq!(*x.try_ind_mut::<usize>(0).unwrap().unwrap(), 2);
+    assert!(x.try_ind_mut::<usize>(1).unwrap().is_none());
+
+    // A list, even of one value
+    x.insert(3usize).unwrap();
+    x.extract::<usize>().unwrap();
+    let not_one = Some(ErrorDesc::Unit(UnitError::IsNotOne));
+    assert_eq!(x.try_get_mut::<usize>().err(), not_one);
+    assert_eq!(x.try_get_mut::<usize>().err(), not_one);
+    assert_eq!(*x.try_ind_mut::<usize>(0).unwrap().unwrap(), 3);
+    assert!(x.try_ind_mut::<usize>(1).unwrap().is_none());
+    x.extract::<usize>().unwrap();
+    x.insert(4usize).unwrap();
+
+    // Borrowed incompatibly
+    {
+        let _lock = x.get_mut::<usize>().unwrap();
+        let borrowed = Some(ErrorDesc::BorrowedIncompatibly);
+        assert_eq!(x.try_get_mut::<usize>().err(), borrowed);
+        assert_eq!(x.try_get_mut::<usize>().err(), borrowed);
+        assert_eq!(x.try_ind_mut::<usize>(0).err(), borrowed);
+        assert_eq!(x.try_ind_mut::<usize>(1).err(), borrowed);
+    }
+
+    // Read-mostly
+    x.allocate_read_mostly_for::<isize>();
+    let unsupported = Some(ErrorDesc::UnsupportedOperation);
+    assert_eq!(x.try_get_mut::<isize>().err(), unsupported);
+    assert_eq!(x.try_get_mut::<isize>().err(), unsupported);
+    assert_eq!(x.try_ind_mut::<isize>(0).err(), unsupported);
+}
+
+#[test]
+fn ind_from_end() {
+    let x = restor::make_storage!(MutexStorage: usize);
+    let out_of_bounds = Err(ErrorDesc::Unit(UnitError::OutOfBounds));
+    assert_eq!(x.ind_from_end_mut::<usize>(0).map(|x| *x), out_of_bounds);
+    x.insert(1usize).unwrap();
+    assert_eq!(x.ind_from_end_mut::<usize>(0).map(|x| *x), Ok(1));
+    assert_eq!(x.ind_from_end_mut::<usize>(1).map(|x| *x), out_of_bounds);
+    x.insert_many(vec![2usize, 3]).unwrap();
+    assert_eq!(x.ind_from_end_mut::<usize>(0).map(|x| *x), Ok(3));
+    assert_eq!(x.ind_from_end_mut::<usize>(2).map(|x| *x), Ok(1));
+    assert_eq!(x.ind_from_end_mut::<usize>(3).map(|x| *x), out_of_bounds);
+    assert_eq!(
+        x.ind_from_end_mut::<usize>(usize::MAX).map(|x| *x),
+        out_of_bounds
+    );
+    *x.ind_from_end_mut::<usize>(1).unwrap() = 20;
+    assert_eq!(x.ind_mut::<usize>(1).map(|x| *x), Ok(20));
+}
+
+#[test]
+fn try_for_each_mut_releases_lock() {
+    use restor::TryError;
+
+    let x = Arc::new(restor::make_storage!(MutexStorage: usize));
+    x.insert_many(vec![1usize, 2, 3]).unwrap();
+    let result = x.try_for_each_mut(|value: &mut usize| {
+        if *value == 2 {
+            return Err(*value);
+        }
+        *value += 1;
+        Ok(())
+    });
+    assert_eq!(result, Err(TryError::User(2)));
+    // The lock was released when `f` returned its error, even for another thread
+    let other = x.clone();
+    spawn(move || *other.ind_mut::<usize>(2).unwrap() += 1)
+        .join()
+        .unwrap();
+    assert_eq!(*x.ind_mut::<usize>(0).unwrap(), 2);
+    assert_eq!(*x.ind_mut::<usize>(2).unwrap(), 4);
+}
+
+#[test]
+fn get_policy() {
+    use restor::GetPolicy;
+
+    let not_one = Err(ErrorDesc::Unit(UnitError::IsNotOne));
+    let mut x = MutexStorage::new();
+    x.allocate_for::<usize>();
+    assert_eq!(x.policy(), GetPolicy::Strict);
+    x.insert_many(vec![1usize, 2]).unwrap();
+    assert_eq!(x.get_mut::<usize>().map(|x| *x), not_one);
+    assert_eq!(
+        x.get_mut_with_policy::<usize>(GetPolicy::FirstOnMany)
+            .map(|x| *x),
+        Ok(1)
+    );
+    x.set_policy(GetPolicy::FirstOnMany);
+    *x.get_mut::<usize>().unwrap() += 10;
+    assert_eq!(x.ind_mut::<usize>(0).map(|x| *x), Ok(11));
+    assert_eq!(x.try_get_mut::<usize>().unwrap().map(|x| *x), Some(11));
+    assert_eq!(
+        x.get_mut_with_policy::<usize>(GetPolicy::Strict)
+            .map(|x| *x),
+        not_one
+    );
+    x.extract_many::<usize>().unwrap();
+    assert_eq!(x.get_mut::<usize>().map(|x| *x), not_one);
+    assert_eq!(x.try_get_mut::<usize>().map(|x| x.is_none()), Ok(true));
+}
+
+#[test]
+fn extract_many_into() {
+    let x = restor::make_storage!(MutexStorage: usize);
+    let mut buf = vec![0usize];
+    assert_eq!(x.extract_many_into(&mut buf), Ok(0));
+    x.insert(1usize).unwrap();
+    assert_eq!(x.extract_many_into(&mut buf), Ok(1));
+    x.insert_many(vec![2usize, 3, 4]).unwrap();
+    assert_eq!(x.extract_many_into(&mut buf), Ok(3));
+    assert_eq!(buf, vec![0, 1, 2, 3, 4]);
+    // The unit is left with no values
+    assert_eq!(x.extract_many_into(&mut buf), Ok(0));
+    assert_eq!(buf.len(), 5);
+    assert_eq!(x.extract_any_count::<usize>(), Ok(vec![]));
+    assert_eq!(
+        x.extract_many_into(&mut Vec::<String>::new()),
+        Err(ErrorDesc::NoAllocatedUnit)
+    );
+}
+
+#[test]
+fn swap_backing_vec() {
+    let mut x = MutexStorage::new();
+    x.allocate_for_with_vec::<usize>(Vec::with_capacity(32));
+    assert_eq!(x.capacity::<usize>(), Ok(32));
+    let mut spare = Vec::<usize>::with_capacity(32);
+    for frame in 0..4usize {
+        x.insert(frame).unwrap();
+        x.insert(frame + 1).unwrap();
+        let mut values = x.swap_backing_vec(spare).unwrap();
+        assert_eq!(values, vec![frame, frame + 1]);
+        assert_eq!(values.capacity(), 32);
+        values.clear();
+        spare = values;
+    }
+    // A single value in the new buffer is read as one
+    x.swap_backing_vec(vec![7usize]).unwrap();
+    assert_eq!(x.extract::<usize>(), Ok(7));
+    assert_eq!(x.capacity::<usize>(), Ok(1));
+    assert_eq!(
+        x.swap_backing_vec(Vec::<String>::new()),
+        Err(ErrorDesc::NoAllocatedUnit)
+    );
+}
+
+#[test]
+fn get_disjoint_ind_mut() {
+    let x = restor::make_storage!(MutexStorage: String);
+    x.insert_many(vec!["a".to_string(), "b".to_string(), "c".to_string()])
+        .unwrap();
+    {
+        let [mut first, mut last] = x.get_disjoint_ind_mut::<String, 2>([0, 2]).unwrap();
+        std::mem::swap(&mut *first, &mut *last);
+        drop(first);
+        // The unit stays borrowed until every guard is dropped
+        assert_eq!(
+            x.ind_mut::<String>(1).err(),
+            Some(ErrorDesc::BorrowedIncompatibly)
+        );
+        last.push('!');
+    }
+    assert_eq!(
+        x.extract_many::<String>().map(Vec::from),
+        Ok(vec!["c".to_string(), "b".to_string(), "a!".to_string()])
+    );
+
+    x.insert(String::from("only")).unwrap();
+    let [mut only] = x.get_disjoint_ind_mut::<String, 1>([0]).unwrap();
+    only.push('!');
+    drop(only);
+    assert_eq!(x.extract::<String>(), Ok(String::from("only!")));
+}
+
+#[test]
+fn get_disjoint_ind_mut_errors() {
+    let x = restor::make_storage!(MutexStorage: usize);
+    x.insert_many(vec![1usize, 2, 3]).unwrap();
+    assert_eq!(
+        x.get_disjoint_ind_mut::<usize, 3>([0, 2, 0]).err(),
+        Some(ErrorDesc::Unit(UnitError::DuplicateIndex))
+    );
+    assert_eq!(
+        x.get_disjoint_ind_mut::<usize, 2>([1, 3]).err(),
+        Some(ErrorDesc::Unit(UnitError::OutOfBounds))
+    );
+    assert_eq!(
+        x.get_disjoint_ind_mut::<String, 1>([0]).err(),
+        Some(ErrorDesc::NoAllocatedUnit)
+    );
+    {
+        let _guard = x.ind_mut::<usize>(0).unwrap();
+        assert_eq!(
+            x.get_disjoint_ind_mut::<usize, 2>([1, 2]).err(),
+            Some(ErrorDesc::BorrowedIncompatibly)
+        );
+    }
+    // A failed call leaves the unit unborrowed
+    assert_eq!(x.ind_multi::<usize>(&[0, 1, 2]), Ok(vec![1, 2, 3]));
+}
+
+#[test]
+fn poll_get_mut() {
+    use restor::PollResult;
+    use std::sync::mpsc::channel;
+
+    let x = restor::make_storage!(MutexStorage: usize);
+    x.insert(1usize).unwrap();
+    let (locked, wait_locked) = channel();
+    let (release, wait_release) = channel::<()>();
+    std::thread::scope(|s| {
+        let x = &x;
+        s.spawn(move || {
+            let mut lock = x.get_mut::<usize>().unwrap();
+            locked.send(()).unwrap();
+            wait_release.recv().unwrap();
+            *lock += 1;
+        });
+        wait_locked.recv().unwrap();
+        let token = match x.poll_get_mut::<usize>() {
+            PollResult::Pending(token) => token,
+            other => panic!("Expected a token, got {:?}", other),
+        };
+        assert!(!token.is_ready());
+        release.send(()).unwrap();
+        while !token.is_ready() {
+            std::thread::yield_now();
+        }
+    });
+    assert_eq!(x.poll_get_mut::<usize>().ready().map(|x| *x), Some(2));
+}
+
+#[test]
+fn warm_capacity() {
+    let x = restor::make_storage!(MutexStorage: u64);
+    x.warm_capacity::<u64>(1 << 20).unwrap();
+    let capacity = x.capacity::<u64>().unwrap();
+    assert!(capacity >= 1 << 20);
+    x.insert_iter(0..1u64 << 20).unwrap();
+    assert_eq!(x.capacity::<u64>(), Ok(capacity));
+    x.prefault::<u64>().unwrap();
+    assert_eq!(
+        x.run_for::<u64, bool, _>(|x| Some(x.unwrap().iter().copied().eq(0..1 << 20))),
+        Some(true)
+    );
+    assert_eq!(x.prefault::<String>(), Err(ErrorDesc::NoAllocatedUnit));
+}
+
+#[test]
+fn transaction_releases_units_on_abort() {
+    let storage = restor::make_storage!(MutexStorage: usize, String);
+    storage.insert(0usize).unwrap();
+    let mut txn = storage.transaction();
+    txn.insert(1usize).insert(String::from("entity"));
+    let (txn, errors) = {
+        let _held = storage.get_mut::<usize>().unwrap();
+        txn.commit().unwrap_err()
+    };
+    assert_eq!(
+        errors,
+        vec![(
+            std::any::type_name::<usize>(),
+            ErrorDesc::BorrowedIncompatibly.context("borrowing the unit to commit to")
+        )]
+    );
+    // The unit for `String` wasn't left locked, nor changed
+    assert_eq!(storage.state::<String>().map(|s| s.count()), Ok(0));
+    storage.insert(String::from("other")).unwrap();
+    assert_eq!(txn.len(), 2);
+    txn.commit().unwrap();
+    assert_eq!(&*storage.extract_many::<usize>().unwrap(), &[0, 1]);
+    assert_eq!(
+        &*storage.extract_many::<String>().unwrap(),
+        &[String::from("other"), String::from("entity")]
+    );
+}
+
+#[test]
+fn scope_chunks_mut_from_many_threads() {
+    use std::num::NonZeroUsize;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    let storage = Arc::new(restor::make_storage!(MutexStorage: u32));
+    storage.insert_many(vec![1u32; 4096]).unwrap();
+    let callers = (0..4)
+        .map(|_| {
+            let storage = storage.clone();
+            spawn(move || loop {
+                // Whoever holds the unit goes through all of it at once
+                let result = storage.scope_chunks_mut_on(
+                    NonZeroUsize::new(3).unwrap(),
+                    256,
+                    |_, chunk: &mut [u32]| chunk.iter_mut().for_each(|x| *x += 1),
+                );
+                match result {
+                    Ok(()) => break,
+                    Err(ErrorDesc::BorrowedIncompatibly) => std::thread::yield_now(),
+                    Err(e) => panic!("{:?}", e),
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    callers
+        .into_iter()
+        .for_each(|caller| caller.join().unwrap());
+    let values = storage.extract_many::<u32>().unwrap();
+    assert!(values.iter().all(|&x| x == 5));
+
+    // Without poisoning, the unit is simply released after a panic
+    storage.insert_many(vec![0u32; 100]).unwrap();
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        storage.scope_chunks_mut(10, |i, _: &mut [u32]| {
+            if i == 3 {
+                panic!("worker failed");
+            }
+        })
+    }));
+    assert!(result.is_err());
+    assert_eq!(storage.extract_many::<u32>().map(|x| x.len()), Ok(100));
 }
@@ -0,0 +1,55 @@
+//! Checks that every storage reports borrow conflicts the same way, with the
+//! exception that a `MutexStorage` has no shared borrows, so it reads through
+//! `ind_mut` and reading conflicts with reading.
+use restor::{DynamicStorage, ErrorDesc, MutexStorage, RwLockStorage, UnitError};
+
+fn borrowed() -> Option<ErrorDesc> {
+    Some(ErrorDesc::BorrowedIncompatibly)
+}
+
+macro_rules! matrix {
+    ($name:ident, $storage:ty, $read:ident, $shared:expr $(, $get:ident)?) => {
+        #[test]
+        fn $name() {
+            let mut x = <$storage>::new();
+            x.allocate_for::<usize>();
+            x.insert_many(vec![0usize, 1, 2]).unwrap();
+
+            for &write in &[false, true] {
+                let _guard = if write {
+                    Err(x.ind_mut::<usize>(0).unwrap())
+                } else {
+                    Ok(x.$read::<usize>(0).unwrap())
+                };
+                // Whether reading is possible while the guard is held
+                let readable = $shared && !write;
+                let read = if readable { None } else { borrowed() };
+                assert_eq!(x.insert(3usize).err().map(|x| x.1), borrowed());
+                assert_eq!(x.insert_many(vec![3usize]).err().map(|x| x.1), borrowed());
+                assert_eq!(x.extract::<usize>().err(), borrowed());
+                assert_eq!(x.extract_many::<usize>().err(), borrowed());
+                assert_eq!(x.extract_ind::<usize>(1).err(), borrowed());
+                assert_eq!(x.ind_mut::<usize>(1).err(), borrowed());
+                assert_eq!(x.$read::<usize>(1).err(), read);
+                $(assert_eq!(
+                    x.$get::<usize>().err(),
+                    if readable {
+                        Some(ErrorDesc::Unit(UnitError::IsNotOne))
+                    } else {
+                        borrowed()
+                    }
+                );)?
+                assert_eq!(x.position::<usize, _>(|&x| x == 1).err(), read);
+                assert_eq!(
+                    x.run_for::<usize, usize, _>(|x| x.ok().map(|x| x.len())),
+                    if readable { Some(3) } else { None }
+                );
+            }
+            assert_eq!(x.extract_many::<usize>().map(|x| x.len()), Ok(3));
+        }
+    };
+}
+
+matrix!(dynamic, DynamicStorage, ind, true, get);
+matrix!(rwlock, RwLockStorage, ind, true, get);
+matrix!(mutex, MutexStorage, ind_mut, false);
@@ -0,0 +1,222 @@
+#![cfg(feature = "pod")]
+//! Round trips through the binary dump of plain data units.
+use restor::{DynamicStorage, ErrorDesc, Pod, PodError, RwLockStorage, StorageUnit, UnitState};
+use std::io;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(C)]
+struct Particle {
+    position: [f32; 3],
+    mass: f32,
+}
+
+unsafe impl Pod for Particle {}
+
+fn simulation() -> DynamicStorage {
+    let mut storage = DynamicStorage::new();
+    storage.allocate_pod_for::<Particle>("particles").unwrap();
+    storage.allocate_pod_for::<u64>("tick").unwrap();
+    storage.allocate_pod_for::<[i32; 2]>("grid").unwrap();
+    storage
+}
+
+fn dump(storage: &DynamicStorage) -> Vec<u8> {
+    let mut dump = Vec::new();
+    storage.dump_pod(&mut dump).unwrap();
+    dump
+}
+
+#[test]
+fn round_trip() {
+    let storage = simulation();
+    let particles = (0..100)
+        .map(|i| Particle {
+            position: [i as f32, -1.5, 2.0],
+            mass: 0.25 * i as f32,
+        })
+        .collect::<Vec<_>>();
+    storage.insert_many(particles.clone()).unwrap();
+    storage.insert(1234u64).unwrap();
+    let dump = dump(&storage);
+    // Each value is dumped as its raw bytes
+    assert!(dump.len() > 100 * std::mem::size_of::<Particle>() + 8);
+
+    // The format doesn't depend on the kind of storage
+    let mut restored = RwLockStorage::new();
+    restored.allocate_pod_for::<Particle>("particles").unwrap();
+    restored.allocate_pod_for::<u64>("tick").unwrap();
+    restored.allocate_pod_for::<[i32; 2]>("grid").unwrap();
+    restored.insert([1i32, 2]).unwrap();
+    assert_eq!(
+        restored.restore_pod(&mut &dump[..]).unwrap(),
+        Vec::<String>::new()
+    );
+    assert_eq!(
+        restored
+            .cloned_iter::<Particle>()
+            .unwrap()
+            .collect::<Vec<_>>(),
+        particles
+    );
+    // A single value is restored as one, and an empty unit as empty
+    assert_eq!(restored.state::<u64>(), Ok(UnitState::Single));
+    assert_eq!(*restored.get::<u64>().unwrap(), 1234);
+    assert_eq!(restored.state::<[i32; 2]>(), Ok(UnitState::Empty));
+}
+
+#[test]
+fn changed_size_fails_without_changes() {
+    let storage = simulation();
+    storage.insert(5u64).unwrap();
+    storage.insert([3i32, 4]).unwrap();
+    let dump = dump(&storage);
+
+    // The grid went from pairs to triples since the dump was written
+    let mut restored = DynamicStorage::new();
+    restored.allocate_pod_for::<u64>("tick").unwrap();
+    restored.allocate_pod_for::<[i32; 3]>("grid").unwrap();
+    restored.insert(1u64).unwrap();
+    let error = restored.restore_pod(&mut &dump[..]).unwrap_err();
+    match &error {
+        PodError::SizeMismatch {
+            tag,
+            expected,
+            found,
+        } => assert_eq!((&tag[..], *expected, *found), ("grid", 12, 8)),
+        e => panic!("unexpected error: {:?}", e),
+    }
+    assert_eq!(
+        error.to_string(),
+        "the values tagged `grid` are 12 bytes each, but 8 bytes were dumped for each"
+    );
+    // The tick comes before the grid in the dump, but wasn't restored either
+    assert_eq!(*restored.get::<u64>().unwrap(), 1);
+}
+
+#[test]
+fn unknown_tags_are_skipped() {
+    let storage = simulation();
+    storage.insert(9u64).unwrap();
+    storage
+        .insert(Particle {
+            position: [0.0; 3],
+            mass: 1.0,
+        })
+        .unwrap();
+    let dump = dump(&storage);
+
+    let mut restored = DynamicStorage::new();
+    restored.allocate_pod_for::<u64>("tick").unwrap();
+    restored.allocate_pod_for::<f64>("temperature").unwrap();
+    restored.insert(21.5f64).unwrap();
+    assert_eq!(
+        restored.restore_pod(&mut &dump[..]).unwrap(),
+        vec![String::from("particles"), String::from("grid")]
+    );
+    assert_eq!(*restored.get::<u64>().unwrap(), 9);
+    // Units missing from the dump keep their values
+    assert_eq!(*restored.get::<f64>().unwrap(), 21.5);
+}
+
+#[test]
+fn restore_replaces_values() {
+    let storage = simulation();
+    storage.insert_many(vec![[1i32, 1], [2, 2]]).unwrap();
+    let dump = dump(&storage);
+
+    let mut restored = simulation();
+    restored.insert_many(vec![[9i32, 9]; 5]).unwrap();
+    let finalized = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let count = finalized.clone();
+    restored.on_remove::<[i32; 2], _>(move |_, _| {
+        count.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+    });
+    restored.restore_pod(&mut &dump[..]).unwrap();
+    assert_eq!(finalized.load(std::sync::atomic::Ordering::SeqCst), 5);
+    assert_eq!(
+        restored
+            .as_view()
+            .with(|unit: &StorageUnit<[i32; 2]>| unit.as_slice().to_vec()),
+        Ok(vec![[1, 1], [2, 2]])
+    );
+}
+
+#[test]
+fn borrowed_unit_fails_with_its_tag() {
+    let storage = simulation();
+    storage.insert(1u64).unwrap();
+    let dump = dump(&storage);
+
+    let _lock = storage.get::<u64>().unwrap();
+    match storage.restore_pod(&mut &dump[..]) {
+        Err(PodError::Storage { tag, error }) => {
+            assert_eq!(tag, "tick");
+            assert_eq!(error.root(), &ErrorDesc::BorrowedIncompatibly);
+            assert_eq!(
+                error.to_string(),
+                "replacing the values of the unit: the unit is borrowed incompatibly"
+            );
+        }
+        e => panic!("unexpected result: {:?}", e),
+    }
+    drop(_lock);
+
+    let _lock = storage.get_mut::<u64>().unwrap();
+    let error = storage.dump_pod(&mut Vec::new()).unwrap_err();
+    assert!(error.to_string().contains("`tick`"));
+}
+
+#[test]
+fn malformed_dumps_are_rejected() {
+    let storage = simulation();
+    storage.insert(1u64).unwrap();
+    let dump = dump(&storage);
+
+    let restored = simulation();
+    restored.insert(2u64).unwrap();
+    let mut not_a_dump = dump.clone();
+    not_a_dump[0] = b'X';
+    assert!(matches!(
+        restored.restore_pod(&mut &not_a_dump[..]),
+        Err(PodError::NotADump)
+    ));
+
+    let mut other_version = dump.clone();
+    other_version[4..8].copy_from_slice(&2u32.to_ne_bytes());
+    assert!(matches!(
+        restored.restore_pod(&mut &other_version[..]),
+        Err(PodError::UnsupportedVersion(2))
+    ));
+
+    for len in [3, 10, dump.len() - 1] {
+        match restored.restore_pod(&mut &dump[..len]) {
+            Err(PodError::Io(e)) => assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof),
+            e => panic!("unexpected result for {} bytes: {:?}", len, e),
+        }
+    }
+    assert_eq!(*restored.get::<u64>().unwrap(), 2);
+}
+
+#[test]
+fn tags_are_unique() {
+    let mut storage = DynamicStorage::new();
+    storage.allocate_pod_for::<u32>("counter").unwrap();
+    assert_eq!(
+        storage.allocate_pod_for::<u64>("counter"),
+        Err(ErrorDesc::NameCollision)
+    );
+    assert!(!storage.has_unit::<u64>());
+    // Allocating the same type again changes its tag
+    storage.allocate_pod_for::<u32>("count").unwrap();
+    storage.allocate_pod_for::<u64>("counter").unwrap();
+    storage.insert(3u32).unwrap();
+    let dump = dump(&storage);
+
+    let mut restored = DynamicStorage::new();
+    restored.allocate_pod_for::<u32>("count").unwrap();
+    assert_eq!(
+        restored.restore_pod(&mut &dump[..]).unwrap(),
+        vec![String::from("counter")]
+    );
+    assert_eq!(*restored.get::<u32>().unwrap(), 3);
+}
@@ -0,0 +1,181 @@
+#![cfg(feature = "poison")]
+use restor::{DynamicStorage, ErrorDesc, MutexStorage, RwLockStorage};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+#[test]
+fn poisoning() {
+    use std::sync::Arc;
+    use std::thread::spawn;
+
+    let mut x = RwLockStorage::new_poisoning();
+    x.allocate_for::<usize>();
+    x.allocate_for::<isize>();
+    x.insert_many(vec![1usize, 2, 3]).unwrap();
+    x.insert(0isize).unwrap();
+    let x = Arc::new(x);
+
+    let xc = x.clone();
+    let t = spawn(move || {
+        xc.map_in_place::<usize, _>(|x| {
+            if x == 2 {
+                panic!("Interrupted");
+            }
+            x * 10
+        })
+    });
+    assert!(t.join().is_err());
+    assert_eq!(x.ind::<usize>(0).err(), Some(ErrorDesc::Poisoned));
+    assert_eq!(x.extract_many::<usize>().err(), Some(ErrorDesc::Poisoned));
+    // Other units are untouched
+    assert_eq!(*x.get::<isize>().unwrap(), 0);
+
+    x.clear_poison::<usize>().unwrap();
+    // The panicking element was dropped, and the rest were left as they were
+    assert_eq!(*x.ind::<usize>(0).unwrap(), 10);
+    assert_eq!(*x.ind::<usize>(1).unwrap(), 3);
+
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        x.map_in_place::<usize, _>(|_| panic!("Interrupted"))
+    }));
+    assert!(result.is_err());
+    assert_eq!(x.get::<usize>().err(), Some(ErrorDesc::Poisoned));
+    // Inserting replaces the contents of a poisoned unit
+    x.insert(4usize).unwrap();
+    assert_eq!(*x.get::<usize>().unwrap(), 4);
+    assert_eq!(x.clear_poison::<String>(), Err(ErrorDesc::NoAllocatedUnit));
+}
+
+#[test]
+fn poisoned_units_keep_their_names() {
+    let mut x = RwLockStorage::new_poisoning();
+    x.allocate_named_for::<usize>("count").unwrap();
+    x.insert(1usize).unwrap();
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        x.map_in_place::<usize, _>(|_| panic!("Interrupted"))
+    }));
+    assert!(result.is_err());
+    // The unit still exists, but can't be used by name until it is cleared
+    assert!(x.has_unit_named("count"));
+    assert_eq!(
+        x.extract_any_named("count").map(|_| ()),
+        Err(ErrorDesc::Poisoned)
+    );
+    assert_eq!(
+        x.insert_boxed_named("count", Box::new(2usize))
+            .map_err(|(_, e)| e),
+        Err(ErrorDesc::Poisoned)
+    );
+    x.clear_poison::<usize>().unwrap();
+    x.insert_boxed_named("count", Box::new(2usize)).unwrap();
+    assert!(x.has_unit_named("count"));
+}
+
+#[test]
+fn guards_poison_on_panic() {
+    let mut x = RwLockStorage::new_poisoning();
+    x.allocate_for::<usize>();
+    x.allocate_for::<String>();
+    x.insert(String::from("abc")).unwrap();
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        let mut guard = x.get_mut::<String>().unwrap();
+        guard.push('d');
+        panic!("Interrupted");
+    }));
+    assert!(result.is_err());
+    assert_eq!(x.get::<String>().err(), Some(ErrorDesc::Poisoned));
+    x.clear_poison::<String>().unwrap();
+    assert_eq!(&*x.get::<String>().unwrap(), "abcd");
+
+    x.insert_many(vec![1usize, 2, 3]).unwrap();
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        *x.ind_mut::<usize>(1).unwrap() = 20;
+        let _guard = x.ind_mut::<usize>(2).unwrap();
+        panic!("Interrupted");
+    }));
+    assert!(result.is_err());
+    assert_eq!(x.ind::<usize>(0).err(), Some(ErrorDesc::Poisoned));
+    x.clear_poison::<usize>().unwrap();
+    assert_eq!(*x.ind::<usize>(1).unwrap(), 20);
+
+    // A guard dropped normally leaves the unit alone
+    drop(x.get_mut::<String>().unwrap());
+    assert!(x.get::<String>().is_ok());
+}
+
+#[test]
+fn try_get_poisoned() {
+    let mut x = DynamicStorage::new_poisoning();
+    x.allocate_for::<usize>();
+    x.insert(1usize).unwrap();
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        x.apply(|_: &mut restor::StorageUnit<usize>| panic!("Interrupted"))
+    }));
+    assert!(result.is_err());
+    let poisoned = Some(ErrorDesc::Poisoned);
+    assert_eq!(x.try_get::<usize>().err(), poisoned);
+    assert_eq!(x.try_get_mut::<usize>().err(), poisoned);
+    assert_eq!(x.try_ind::<usize>(0).err(), poisoned);
+
+    let mut x = MutexStorage::new_poisoning();
+    x.allocate_for::<usize>();
+    x.insert(1usize).unwrap();
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        x.apply(|_: &mut restor::StorageUnit<usize>| panic!("Interrupted"))
+    }));
+    assert!(result.is_err());
+    assert_eq!(x.try_get_mut::<usize>().err(), poisoned);
+    assert_eq!(x.try_ind_mut::<usize>(0).err(), poisoned);
+}
+
+#[test]
+fn transaction_empties_poisoned_units() {
+    let mut storage = DynamicStorage::new_poisoning();
+    storage.allocate_for::<usize>();
+    storage.allocate_for::<String>();
+    storage.insert(String::from("old")).unwrap();
+    let _ = catch_unwind(AssertUnwindSafe(|| {
+        storage
+            .apply::<String, _, _>(|_| panic!("poisoning the unit"))
+            .unwrap()
+    }));
+
+    let mut txn = storage.transaction();
+    txn.insert(1usize).insert(String::from("new"));
+    txn.commit().unwrap();
+    // The poisoned unit was emptied, like by insert
+    assert_eq!(storage.extract::<String>(), Ok(String::from("new")));
+    assert_eq!(storage.state::<String>().map(|s| s.count()), Ok(0));
+}
+
+#[test]
+fn scope_chunks_mut_poisons_on_worker_panic() {
+    use std::num::NonZeroUsize;
+
+    let mut storage = RwLockStorage::new_poisoning();
+    storage.allocate_for::<usize>();
+    storage.insert_many((0..1000usize).collect()).unwrap();
+    let payload = catch_unwind(AssertUnwindSafe(|| {
+        storage.scope_chunks_mut_on(NonZeroUsize::new(4).unwrap(), 10, |i, _: &mut [usize]| {
+            if i == 42 {
+                std::panic::panic_any(i);
+            }
+        })
+    }))
+    .unwrap_err();
+    assert_eq!(payload.downcast_ref::<usize>(), Some(&42));
+    // The unit was released and poisoned
+    assert_eq!(storage.ind::<usize>(0).err(), Some(ErrorDesc::Poisoned));
+    storage.clear_poison::<usize>().unwrap();
+    assert_eq!(*storage.ind::<usize>(999).unwrap(), 999);
+}
+
+#[test]
+fn poisoned_units_are_reported_through_tokens() {
+    let mut storage = RwLockStorage::new_poisoning();
+    let token = storage.register_token::<usize>();
+    storage.insert(0usize).unwrap();
+    let _ = catch_unwind(AssertUnwindSafe(|| {
+        storage.try_for_each_mut(|_: &mut usize| -> Result<(), ()> { panic!("poisoning the unit") })
+    }));
+    assert_eq!(storage.get_by_token(token).err(), Some(ErrorDesc::Poisoned));
+}
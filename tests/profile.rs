@@ -0,0 +1,115 @@
+use restor::{make_storage, CapacityEntry, DynamicStorage, MutexStorage, RwLockStorage, UnitState};
+
+macro_rules! profile_tests {
+    ($name:ident, $storage:ty) => {
+        mod $name {
+            use super::*;
+
+            #[test]
+            fn profile_presizes_a_fresh_storage() {
+                let storage = make_storage!($storage: u32, String, u8);
+                storage.insert_many((0..1000u32).collect()).unwrap();
+                storage.extract_many::<u32>().unwrap();
+                storage.insert_many((0..300u32).collect()).unwrap();
+                storage.insert(String::from("one")).unwrap();
+                let profile = storage.capacity_profile();
+                assert_eq!(
+                    profile.units,
+                    vec![
+                        CapacityEntry {
+                            name: "u32".to_string(),
+                            len: 300,
+                            capacity: storage.capacity::<u32>().unwrap(),
+                        },
+                        CapacityEntry {
+                            name: "alloc::string::String".to_string(),
+                            len: 1,
+                            capacity: 1,
+                        },
+                        CapacityEntry {
+                            name: "u8".to_string(),
+                            len: 0,
+                            capacity: 0,
+                        },
+                    ]
+                );
+
+                let mut fresh = make_storage!($storage: u8, u32, String);
+                assert!(fresh.apply_capacity_profile(&profile).is_empty());
+                assert!(fresh.capacity::<u32>().unwrap() >= 300);
+                // Nothing was inserted
+                assert_eq!(fresh.state::<u32>(), Ok(UnitState::Empty));
+                assert_eq!(fresh.state::<String>(), Ok(UnitState::Empty));
+                assert_eq!(fresh.capacity::<u8>(), Ok(0));
+                // Values inserted afterwards go into the reserved room
+                let reserved = fresh.capacity::<u32>().unwrap();
+                fresh.insert_many((0..300u32).collect()).unwrap();
+                assert_eq!(fresh.capacity::<u32>(), Ok(reserved));
+            }
+
+            #[test]
+            fn unknown_names_are_reported() {
+                let storage = make_storage!($storage: u32, u16, u64);
+                storage.insert_many(vec![1u16; 40]).unwrap();
+                storage.insert_many(vec![1u64; 50]).unwrap();
+                let profile = storage.capacity_profile();
+
+                let mut fresh = make_storage!($storage: u64);
+                assert_eq!(
+                    fresh.apply_capacity_profile(&profile),
+                    vec!["u32".to_string(), "u16".to_string()]
+                );
+                assert!(fresh.capacity::<u64>().unwrap() >= 50);
+                assert!(!fresh.has_unit::<u16>());
+            }
+
+            #[test]
+            fn larger_units_are_kept() {
+                let storage = make_storage!($storage: u32);
+                storage.insert_many(vec![0u32; 8]).unwrap();
+                let profile = storage.capacity_profile();
+
+                let mut fresh = make_storage!($storage: u32);
+                fresh.warm_capacity::<u32>(100).unwrap();
+                let capacity = fresh.capacity::<u32>().unwrap();
+                assert!(fresh.apply_capacity_profile(&profile).is_empty());
+                assert_eq!(fresh.capacity::<u32>(), Ok(capacity));
+            }
+        }
+    };
+}
+
+profile_tests!(dynamic, DynamicStorage);
+profile_tests!(mutex, MutexStorage);
+profile_tests!(rwlock, RwLockStorage);
+
+#[test]
+fn locked_units_are_left_out() {
+    let storage = make_storage!(DynamicStorage: u32, u8);
+    storage.insert_many(vec![1u32, 2]).unwrap();
+    let lock = storage.ind_mut::<u32>(0).unwrap();
+    let names = |storage: &DynamicStorage| {
+        storage
+            .capacity_profile()
+            .units
+            .into_iter()
+            .map(|entry| entry.name)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(names(&storage), vec!["u8".to_string()]);
+    drop(lock);
+    assert_eq!(names(&storage), vec!["u32".to_string(), "u8".to_string()]);
+}
+
+#[test]
+fn profiles_match_custom_names() {
+    let storage = make_storage!(RwLockStorage: u32);
+    storage.insert_many(vec![0u32; 64]).unwrap();
+    let mut profile = storage.capacity_profile();
+    profile.units[0].name = "ids".to_string();
+
+    let mut fresh = RwLockStorage::new();
+    fresh.allocate_named_for::<u32>("ids").unwrap();
+    assert!(fresh.apply_capacity_profile(&profile).is_empty());
+    assert!(fresh.capacity::<u32>().unwrap() >= 64);
+}
@@ -0,0 +1,142 @@
+use restor::{make_storage, DynamicStorage, ErrorDesc, MutexStorage, RwLockStorage, UnitState};
+
+macro_rules! remove_where_tests {
+    ($name:ident, $storage:ty) => {
+        mod $name {
+            use super::*;
+
+            fn setup() -> $storage {
+                let storage = make_storage!($storage: usize, String);
+                storage.insert_many(vec![1usize, 2, 3, 4, 5]).unwrap();
+                storage
+            }
+
+            fn values(storage: &$storage) -> Vec<usize> {
+                storage.extract_any_count::<usize>().unwrap()
+            }
+
+            #[test]
+            fn match_at_front() {
+                let storage = setup();
+                assert_eq!(storage.remove_where::<usize, _>(|x| x % 2 == 1), Ok(Some(1)));
+                assert_eq!(values(&storage), vec![2, 3, 4, 5]);
+
+                let storage = setup();
+                assert_eq!(storage.swap_remove_where::<usize, _>(|x| *x == 1), Ok(Some(1)));
+                assert_eq!(values(&storage), vec![5, 2, 3, 4]);
+            }
+
+            #[test]
+            fn match_at_back() {
+                let storage = setup();
+                assert_eq!(storage.remove_where::<usize, _>(|x| *x == 5), Ok(Some(5)));
+                assert_eq!(values(&storage), vec![1, 2, 3, 4]);
+
+                let storage = setup();
+                assert_eq!(storage.swap_remove_where::<usize, _>(|x| *x == 5), Ok(Some(5)));
+                assert_eq!(values(&storage), vec![1, 2, 3, 4]);
+            }
+
+            #[test]
+            fn first_match_only() {
+                let storage = setup();
+                let mut seen = Vec::new();
+                let removed = storage.remove_where::<usize, _>(|x| {
+                    seen.push(*x);
+                    *x > 2
+                });
+                assert_eq!(removed, Ok(Some(3)));
+                // The search stops at the first match
+                assert_eq!(seen, vec![1, 2, 3]);
+                assert_eq!(values(&storage), vec![1, 2, 4, 5]);
+            }
+
+            #[test]
+            fn no_match() {
+                let storage = setup();
+                assert_eq!(storage.remove_where::<usize, _>(|x| *x > 5), Ok(None));
+                assert_eq!(storage.swap_remove_where::<usize, _>(|x| *x > 5), Ok(None));
+                assert_eq!(values(&storage), vec![1, 2, 3, 4, 5]);
+
+                // An empty unit has nothing to match
+                assert_eq!(storage.remove_where::<String, _>(|_| true), Ok(None));
+                assert_eq!(storage.swap_remove_where::<String, _>(|_| true), Ok(None));
+                assert_eq!(
+                    storage.remove_where::<u8, _>(|_| true),
+                    Err(ErrorDesc::NoAllocatedUnit)
+                );
+            }
+
+            #[test]
+            fn one_state() {
+                let storage = setup();
+                storage.insert(String::from("only")).unwrap();
+                assert_eq!(storage.state::<String>(), Ok(UnitState::Single));
+                assert_eq!(storage.remove_where::<String, _>(|x| x == "other"), Ok(None));
+                assert_eq!(storage.state::<String>(), Ok(UnitState::Single));
+                assert_eq!(
+                    storage.remove_where::<String, _>(|x| x == "only"),
+                    Ok(Some(String::from("only")))
+                );
+                assert_eq!(storage.state::<String>(), Ok(UnitState::Empty));
+
+                storage.insert(String::from("again")).unwrap();
+                assert_eq!(
+                    storage.swap_remove_where::<String, _>(|_| true),
+                    Ok(Some(String::from("again")))
+                );
+                assert_eq!(storage.state::<String>(), Ok(UnitState::Empty));
+            }
+
+            #[test]
+            fn emptying_the_unit() {
+                let storage = setup();
+                for _ in 0..2 {
+                    storage.remove_where::<usize, _>(|_| true).unwrap().unwrap();
+                    storage.swap_remove_where::<usize, _>(|_| true).unwrap().unwrap();
+                }
+                assert_eq!(storage.state::<usize>(), Ok(UnitState::Multiple(1)));
+                assert_eq!(storage.remove_where::<usize, _>(|_| true), Ok(Some(4)));
+                assert_eq!(storage.state::<usize>(), Ok(UnitState::Empty));
+                assert_eq!(storage.remove_where::<usize, _>(|_| true), Ok(None));
+                // The unit takes a single value again once emptied
+                storage.insert(6usize).unwrap();
+                assert_eq!(storage.state::<usize>(), Ok(UnitState::Single));
+            }
+        }
+    };
+}
+
+remove_where_tests!(dynamic, DynamicStorage);
+remove_where_tests!(mutex, MutexStorage);
+remove_where_tests!(rwlock, RwLockStorage);
+
+#[test]
+fn fails_while_borrowed() {
+    let storage = make_storage!(RwLockStorage: usize);
+    storage.insert_many(vec![1usize, 2]).unwrap();
+    let reading = storage.ind::<usize>(0).unwrap();
+    assert_eq!(
+        storage.remove_where::<usize, _>(|_| true),
+        Err(ErrorDesc::BorrowedIncompatibly)
+    );
+    drop(reading);
+    assert_eq!(storage.remove_where::<usize, _>(|_| true), Ok(Some(1)));
+}
+
+#[test]
+fn removed_values_are_finalized() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let mut storage = make_storage!(DynamicStorage: usize);
+    let finalized = Arc::new(AtomicUsize::new(0));
+    let counter = finalized.clone();
+    storage.on_remove::<usize, _>(move |x, _| {
+        counter.fetch_add(*x, Ordering::Relaxed);
+    });
+    storage.insert_many(vec![1usize, 20, 300]).unwrap();
+    storage.remove_where::<usize, _>(|x| *x == 20).unwrap();
+    storage.swap_remove_where::<usize, _>(|x| *x == 1).unwrap();
+    assert_eq!(finalized.load(Ordering::Relaxed), 21);
+}
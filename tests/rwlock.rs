@@ -1,4 +1,4 @@
-use restor::{ErrorDesc, RwLockStorage};
+use restor::{ErrorDesc, RwLockStorage, UnitError};
 
 #[test]
 fn instantiate() {
@@ -72,7 +72,7 @@ fn ind() {
     x.allocate_for::<usize>();
     x.insert(0usize).unwrap();
     x.insert(1usize).unwrap();
-    let y = x.ind::<usize>(0);
+    let _y = x.ind::<usize>(0);
     let indexed = x.ind::<usize>(0);
     assert!(indexed.is_ok());
     if let Ok(val) = indexed {
@@ -150,8 +150,8 @@ fn ind_mut() {
     }
 }
 mod concurrent {
-    use parking_lot::MappedRwLockReadGuard;
-    use restor::{ErrorDesc, RwLockStorage};
+
+    use restor::RwLockStorage;
     use std::sync::Arc;
     use std::thread::spawn;
     use std::time::Duration;
@@ -165,22 +165,22 @@ mod concurrent {
         x.insert(1usize).unwrap();
         let xc = x.clone();
         let t = spawn(move || {
-            let y = (&*xc).ind::<usize>(0);
+            let y = xc.ind::<usize>(0);
             assert!(y.is_ok());
             if let Ok(z) = y {
                 assert_eq!(*z, 0usize);
             }
         });
-        t.join();
+        t.join().unwrap();
         let xc = x.clone();
         let t = spawn(move || {
-            let y = (&*xc).ind::<usize>(1);
+            let y = xc.ind::<usize>(1);
             assert!(y.is_ok());
             if let Ok(z) = y {
                 assert_eq!(*z, 1usize);
             }
         });
-        t.join();
+        t.join().unwrap();
         let xc = x.clone();
         let t1 = spawn(move || {
             let y = xc.ind::<usize>(0);
@@ -198,8 +198,8 @@ mod concurrent {
                 assert_eq!(*nz, 1usize);
             }
         });
-        t1.join();
-        t2.join();
+        t1.join().unwrap();
+        t2.join().unwrap();
     }
 
     #[test]
@@ -214,14 +214,14 @@ mod concurrent {
             let y = xc.ind_mut::<usize>(0);
             y.map(|m| *m)
         });
-        t.join().unwrap();
+        t.join().unwrap().unwrap();
         let xc = x.clone();
         let t = spawn(move || {
             let y = xc.ind_mut::<usize>(1);
             y.map(|m| *m)
         });
-        t.join().unwrap();
-        let xc = <Arc<RwLockStorage> as Clone>::clone(&x);
+        t.join().unwrap().unwrap();
+        let xc = x.clone();
         let t1 = spawn(move || {
             let y = xc.ind_mut::<usize>(0);
             std::thread::sleep(Duration::from_millis(200));
@@ -236,3 +236,1631 @@ mod concurrent {
         assert!(t2.join().unwrap().is_err());
     }
 }
+
+#[test]
+fn map_in_place() {
+    let mut x = RwLockStorage::new();
+    x.allocate_for::<usize>();
+    x.allocate_for::<String>();
+    x.insert_many(vec![1usize, 2, 3]).unwrap();
+    x.insert(String::from("abc")).unwrap();
+    x.map_in_place::<usize, _>(|v| v * 2).unwrap();
+    x.map_in_place::<String, _>(|s| s + "def").unwrap();
+    assert_eq!(*x.ind_mut::<usize>(0).unwrap(), 2);
+    assert_eq!(*x.ind_mut::<usize>(1).unwrap(), 4);
+    assert_eq!(*x.ind_mut::<usize>(2).unwrap(), 6);
+    assert_eq!(&*x.get_mut::<String>().unwrap(), "abcdef");
+}
+
+#[test]
+fn map_in_place_panic() {
+    let mut x = RwLockStorage::new();
+    x.allocate_for::<String>();
+    x.insert_many(vec![
+        String::from("a"),
+        String::from("b"),
+        String::from("c"),
+    ])
+    .unwrap();
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        x.map_in_place::<String, _>(|s| {
+            if s == "b" {
+                panic!("Mapping failed");
+            }
+            s + "!"
+        })
+    }));
+    assert!(result.is_err());
+    assert_eq!(&*x.ind_mut::<String>(0).unwrap(), "a!");
+    assert_eq!(&*x.ind_mut::<String>(1).unwrap(), "c");
+    assert_eq!(
+        x.ind_mut::<String>(2).map(|_| ()),
+        Err(ErrorDesc::Unit(UnitError::OutOfBounds))
+    );
+}
+
+#[test]
+fn position() {
+    let mut x = RwLockStorage::new();
+    x.allocate_for::<usize>();
+    assert_eq!(x.position::<usize, _>(|v| *v == 3), Ok(None));
+    x.insert(3usize).unwrap();
+    assert_eq!(x.position::<usize, _>(|v| *v == 3), Ok(Some(0)));
+    x.insert_many(vec![4usize, 5, 6]).unwrap();
+    assert_eq!(x.position::<usize, _>(|v| *v == 6), Ok(Some(3)));
+    assert_eq!(x.position::<usize, _>(|v| *v == 7), Ok(None));
+    assert_eq!(
+        x.position::<isize, _>(|v| *v == 0),
+        Err(ErrorDesc::NoAllocatedUnit)
+    );
+    let _lock = x.ind_mut::<usize>(0).unwrap();
+    assert_eq!(
+        x.position::<usize, _>(|v| *v == 3),
+        Err(ErrorDesc::BorrowedIncompatibly)
+    );
+}
+
+#[test]
+fn find_clone() {
+    let mut x = RwLockStorage::new();
+    x.allocate_for::<String>();
+    assert_eq!(x.find_clone::<String, _>(|_| true), Ok(None));
+    x.insert(String::from("abc")).unwrap();
+    assert_eq!(
+        x.find_clone::<String, _>(|s| s.len() == 3),
+        Ok(Some(String::from("abc")))
+    );
+    x.insert_many(vec![String::from("de"), String::from("fg")])
+        .unwrap();
+    assert_eq!(
+        x.find_clone::<String, _>(|s| s.len() == 2),
+        Ok(Some(String::from("de")))
+    );
+    // No guard escapes, so the unit can be mutably borrowed right away
+    x.ind_mut::<String>(1).unwrap().push('!');
+}
+
+#[test]
+fn interleaved_lookups() {
+    use std::sync::Arc;
+    let mut x = RwLockStorage::new();
+    x.allocate_for::<usize>();
+    x.allocate_for::<isize>();
+    x.insert(1usize).unwrap();
+    x.insert(-1isize).unwrap();
+    let x = Arc::new(x);
+    let threads = (0..4)
+        .map(|i| {
+            let x = x.clone();
+            std::thread::spawn(move || {
+                for j in 0..1000 {
+                    if (i + j) % 2 == 0 {
+                        assert_eq!(*x.get::<usize>().unwrap(), 1);
+                    } else {
+                        assert_eq!(*x.get::<isize>().unwrap(), -1);
+                    }
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+}
+
+#[test]
+fn unchecked() {
+    let mut x = RwLockStorage::new();
+    x.allocate_for::<usize>();
+    x.insert_many(vec![1usize, 2, 3]).unwrap();
+    unsafe {
+        assert_eq!(*x.ind_unchecked::<usize>(1).unwrap(), 2);
+        assert_eq!(
+            x.ind_unchecked::<usize>(3).map(|_| ()),
+            Err(ErrorDesc::Unit(UnitError::OutOfBounds))
+        );
+        assert_eq!(
+            x.get_unchecked::<usize>().map(|_| ()),
+            Err(ErrorDesc::Unit(UnitError::IsNotOne))
+        );
+        let _lock = x.ind_mut::<usize>(0).unwrap();
+        assert_eq!(
+            x.ind_unchecked::<usize>(1).map(|_| ()),
+            Err(ErrorDesc::BorrowedIncompatibly)
+        );
+    }
+}
+
+#[test]
+#[should_panic(expected = "There is no unit allocated for isize")]
+#[cfg(debug_assertions)]
+fn unchecked_missing_unit() {
+    let x = RwLockStorage::new();
+    let _ = unsafe { x.get_unchecked::<isize>() };
+}
+
+#[test]
+fn named() {
+    let mut x = RwLockStorage::new();
+    x.allocate_named_for::<usize>("counter").unwrap();
+    x.allocate_named_for::<usize>("count").unwrap();
+    x.insert_boxed_named("counter", Box::new(5usize)).unwrap();
+    assert_eq!(*x.get::<usize>().unwrap(), 5);
+    let value = x.extract_any_named("count").unwrap();
+    assert_eq!(*value.downcast::<usize>().unwrap(), 5);
+    assert_eq!(
+        x.extract_any_named("missing").map(|_| ()),
+        Err(ErrorDesc::NoAllocatedUnit)
+    );
+}
+
+#[test]
+fn shared() {
+    use std::sync::Arc;
+    let mut x = RwLockStorage::new();
+    assert_eq!(
+        x.insert_shared(String::from("abc")),
+        Err((String::from("abc"), ErrorDesc::NoAllocatedUnit))
+    );
+    x.allocate_for::<Arc<String>>();
+    x.insert_shared(String::from("abc")).unwrap();
+    let first = x.get_shared::<String>().unwrap();
+    // No guard outlives the call
+    assert!(x.get_mut::<Arc<String>>().is_ok());
+    let second = x.get_shared::<String>().unwrap();
+    assert!(Arc::ptr_eq(&first, &second));
+    assert_eq!(&*first, "abc");
+    x.insert_shared(String::from("def")).unwrap();
+    assert_eq!(
+        x.get_shared::<String>(),
+        Err(ErrorDesc::Unit(UnitError::IsNotOne))
+    );
+}
+
+#[test]
+fn no_poisoning() {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+    let mut x = RwLockStorage::new();
+    x.allocate_for::<usize>();
+    x.insert_many(vec![1usize, 2]).unwrap();
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        x.map_in_place::<usize, _>(|x| {
+            if x == 2 {
+                panic!("Interrupted")
+            } else {
+                x * 10
+            }
+        })
+    }));
+    assert!(result.is_err());
+    assert_eq!(*x.ind::<usize>(0).unwrap(), 10);
+}
+
+#[test]
+fn summary() {
+    let mut x = RwLockStorage::new();
+    assert_eq!(x.summary().to_string(), "(no units)");
+    x.allocate_for::<usize>();
+    x.allocate_for::<String>();
+    x.allocate_for::<isize>();
+    x.allocate_for::<u8>();
+    x.insert_many(vec![1usize, 2, 3]).unwrap();
+    x.insert(String::from("abc")).unwrap();
+    x.insert(0u8).unwrap();
+    let read = x.get::<String>().unwrap();
+    let write = x.get_mut::<u8>().unwrap();
+    let summary = x.summary();
+    assert_eq!(
+        summary.to_string(),
+        "alloc::string::String: Single (borrowed), isize: Empty, u8: locked, usize: Multiple(3)"
+    );
+    assert_eq!(
+        summary.iter().map(|x| x.count()).collect::<Vec<_>>(),
+        vec![Some(1), Some(0), None, Some(3)]
+    );
+    let mut names = Vec::new();
+    for entry in &summary {
+        names.push(entry.name);
+    }
+    assert_eq!(names, ["alloc::string::String", "isize", "u8", "usize"]);
+    drop((read, write));
+    assert_eq!(
+        x.summary().to_string(),
+        "alloc::string::String: Single, isize: Empty, u8: Single, usize: Multiple(3)"
+    );
+}
+
+#[test]
+fn stats_into() {
+    use restor::{UnitState, UnitStats};
+    use std::any::TypeId;
+
+    let mut x = restor::make_storage!(RwLockStorage: usize, String);
+    x.insert_many(vec![1usize, 2, 3]).unwrap();
+    let mut buf = Vec::<UnitStats>::with_capacity(8);
+    let stale = x.stats();
+    buf.extend(stale.iter().cloned().chain(stale.iter().cloned()));
+    let allocation = buf.as_ptr();
+
+    x.stats_into(&mut buf);
+    assert_eq!(buf.as_ptr(), allocation);
+    assert_eq!(buf.len(), 2);
+    assert_eq!(buf, x.stats());
+    assert_eq!(buf[0].type_id, TypeId::of::<usize>());
+    assert_eq!(buf[0].state, Some(UnitState::Multiple(3)));
+    assert_eq!(buf[0].len, Some(3));
+    assert!(buf[0].capacity >= Some(3));
+    assert!(buf[0].approx_bytes >= Some(3 * std::mem::size_of::<usize>()));
+    assert!(!buf[0].locked);
+    assert_eq!(buf[1].type_name, "alloc::string::String");
+    assert_eq!(buf[1].len, Some(0));
+
+    {
+        let _read = x.ind::<usize>(0).unwrap();
+        x.stats_into(&mut buf);
+        assert_eq!(buf[0].len, Some(3));
+        assert!(buf[0].locked);
+    }
+    {
+        x.insert(String::from("abc")).unwrap();
+        let _write = x.get_mut::<String>().unwrap();
+        x.stats_into(&mut buf);
+        assert_eq!(buf[1].state, None);
+        assert_eq!(
+            (buf[1].len, buf[1].capacity, buf[1].approx_bytes),
+            (None, None, None)
+        );
+        assert!(buf[1].locked);
+    }
+
+    x.remove_types(&[TypeId::of::<usize>()]);
+    x.stats_into(&mut buf);
+    assert_eq!(buf.len(), 1);
+    assert_eq!(buf[0].type_id, TypeId::of::<String>());
+    assert_eq!(buf.as_ptr(), allocation);
+}
+
+#[test]
+fn insert_and_get_mut() {
+    use std::sync::{mpsc::channel, Arc};
+    use std::thread::spawn;
+
+    let mut x = RwLockStorage::new();
+    x.allocate_for::<String>();
+    x.allocate_for::<usize>();
+    assert_eq!(
+        x.insert_and_get_mut(0u8).err(),
+        Some((0u8, ErrorDesc::NoAllocatedUnit))
+    );
+    x.insert_many(vec![1usize, 2]).unwrap();
+    let mut lock = x.insert_and_get_mut(3usize).unwrap();
+    *lock = 30;
+    drop(lock);
+    assert_eq!(*x.ind::<usize>(2).unwrap(), 30);
+    assert_eq!(*x.ind::<usize>(0).unwrap(), 1);
+
+    let x = Arc::new(x);
+    let mut lock = x.insert_and_get_mut(String::new()).unwrap();
+    let (sender, receiver) = channel();
+    let xc = x.clone();
+    let t = spawn(move || {
+        sender.send(()).unwrap();
+        loop {
+            if let Ok(value) = xc.get::<String>() {
+                return value.clone();
+            }
+        }
+    });
+    receiver.recv().unwrap();
+    lock.push_str("configured");
+    drop(lock);
+    assert_eq!(t.join().unwrap(), "configured");
+}
+
+#[test]
+fn capacity() {
+    let mut x = RwLockStorage::new();
+    assert!(x.is_unit_map_empty());
+    x.allocate_for::<usize>();
+    x.allocate_for::<String>();
+    assert_eq!(x.unit_count(), 2);
+    assert!(!x.is_unit_map_empty());
+    assert_eq!(x.capacity::<u8>(), Err(ErrorDesc::NoAllocatedUnit));
+    assert_eq!(x.capacity::<usize>(), Ok(0));
+    x.insert(0usize).unwrap();
+    assert_eq!(x.capacity::<usize>(), Ok(1));
+    x.insert_many((1usize..100).collect()).unwrap();
+    let grown = x.capacity::<usize>().unwrap();
+    assert!(grown >= 100);
+    for _ in 0..90 {
+        x.extract::<usize>().unwrap();
+    }
+    assert_eq!(x.capacity::<usize>(), Ok(grown));
+    x.shrink_to_fit::<usize>().unwrap();
+    assert!(x.capacity::<usize>().unwrap() < grown);
+    let _lock = x.ind_mut::<usize>(0).unwrap();
+    assert_eq!(x.capacity::<usize>(), Err(ErrorDesc::BorrowedIncompatibly));
+}
+
+#[test]
+fn ind_multi() {
+    let mut x = RwLockStorage::new();
+    x.allocate_for::<usize>();
+    x.allocate_for::<String>();
+    assert_eq!(x.ind_multi::<u8>(&[0]), Err(ErrorDesc::NoAllocatedUnit));
+    x.insert(String::from("one")).unwrap();
+    assert_eq!(
+        x.ind_multi::<String>(&[0, 0]),
+        Ok(vec![String::from("one"), String::from("one")])
+    );
+    x.insert_many(vec![0usize, 10, 20, 30]).unwrap();
+    assert_eq!(x.ind_multi::<usize>(&[3, 0, 3, 1]), Ok(vec![30, 0, 30, 10]));
+    assert_eq!(x.ind_multi::<usize>(&[]), Ok(vec![]));
+    assert_eq!(
+        x.ind_multi::<usize>(&[1, 4, 2]),
+        Err(ErrorDesc::Unit(UnitError::OutOfBounds))
+    );
+    let mut called = false;
+    assert_eq!(
+        x.with_ind_multi::<usize, _, _>(&[2, 5], |_| called = true),
+        Err(ErrorDesc::Unit(UnitError::OutOfBounds))
+    );
+    assert!(!called);
+    assert_eq!(
+        x.with_ind_multi::<usize, _, _>(&[2, 1], |x| *x[0] - *x[1]),
+        Ok(10)
+    );
+    // The borrow is released afterwards
+    x.extract_many::<usize>().unwrap();
+}
+
+#[test]
+fn version() {
+    use std::sync::Arc;
+    use std::thread::spawn;
+
+    let mut x = RwLockStorage::new();
+    assert_eq!(x.version::<usize>(), Err(ErrorDesc::NoAllocatedUnit));
+    x.allocate_for::<usize>();
+    x.insert(0usize).unwrap();
+    let start = x.version::<usize>().unwrap();
+    let x = Arc::new(x);
+
+    // The version is bumped when the lock is acquired, not when it is released
+    let lock = x.get_mut::<usize>().unwrap();
+    assert_eq!(x.version::<usize>(), Ok(start + 1));
+    drop(lock);
+    assert_eq!(x.version::<usize>(), Ok(start + 1));
+    // Failing to borrow doesn't count
+    let lock = x.get::<usize>().unwrap();
+    assert!(x.get_mut::<usize>().is_err());
+    assert_eq!(x.version::<usize>(), Ok(start + 1));
+    drop(lock);
+
+    let threads = (0..4)
+        .map(|_| {
+            let xc = x.clone();
+            spawn(move || {
+                let mut written = 0;
+                while written < 100 {
+                    if let Ok(mut value) = xc.get_mut::<usize>() {
+                        *value += 1;
+                        written += 1;
+                    }
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    let mut last_seen = start + 1;
+    let mut previous = 0;
+    while previous < 400 {
+        if let Ok(Some((value, version))) = x.get_if_newer::<usize>(last_seen) {
+            assert!(version > last_seen);
+            assert!(*value >= previous);
+            previous = *value;
+            last_seen = version;
+        }
+    }
+    threads.into_iter().for_each(|t| t.join().unwrap());
+    assert_eq!(x.version::<usize>(), Ok(start + 401));
+    assert_eq!(last_seen, start + 401);
+    assert!(x.get_if_newer::<usize>(last_seen).unwrap().is_none());
+}
+
+#[test]
+fn insert_iter() {
+    let mut x = RwLockStorage::new();
+    assert_eq!(
+        x.insert_iter((0..3usize).map(|x| x * 2)),
+        Err((vec![0, 2, 4], ErrorDesc::NoAllocatedUnit))
+    );
+    x.allocate_for::<usize>();
+    x.allocate_for::<String>();
+    x.insert_iter(std::iter::empty::<usize>()).unwrap();
+    assert_eq!(x.capacity::<usize>(), Ok(0));
+    x.insert_iter([1usize]).unwrap();
+    x.insert_iter(2..4usize).unwrap();
+    x.insert_iter(vec![4usize, 5].into_iter().map(|x| x * 10))
+        .unwrap();
+    assert_eq!(
+        x.ind_multi::<usize>(&[0, 1, 2, 3, 4]),
+        Ok(vec![1, 2, 3, 40, 50])
+    );
+    x.insert_iter(["a", "b"].iter().map(|s| s.to_string()))
+        .unwrap();
+    let _lock = x.ind_mut::<String>(0).unwrap();
+    assert_eq!(
+        x.insert_iter(["c"].iter().map(|s| s.to_string())),
+        Err((vec![String::from("c")], ErrorDesc::BorrowedIncompatibly))
+    );
+}
+
+#[test]
+fn apply() {
+    use restor::StorageUnit;
+    use std::sync::Arc;
+    use std::thread::spawn;
+
+    let mut x = RwLockStorage::new();
+    x.allocate_for::<usize>();
+    let x = Arc::new(x);
+    let threads = (0..4)
+        .map(|_| {
+            let xc = x.clone();
+            spawn(move || {
+                let mut applied = 0;
+                while applied < 100 {
+                    let result = xc.apply(|unit: &mut StorageUnit<usize>| match unit {
+                        StorageUnit::Nope => unit.insert(1),
+                        StorageUnit::One(x) => *x += 1,
+                        StorageUnit::Many(_) => panic!("There should only be one value"),
+                    });
+                    if result.is_ok() {
+                        applied += 1;
+                    }
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    threads.into_iter().for_each(|t| t.join().unwrap());
+    assert_eq!(*x.get::<usize>().unwrap(), 400);
+    assert_eq!(
+        x.apply(|unit: &mut StorageUnit<isize>| unit.len()),
+        Err(ErrorDesc::NoAllocatedUnit)
+    );
+    let _lock = x.get::<usize>().unwrap();
+    assert_eq!(
+        x.apply(|unit: &mut StorageUnit<usize>| unit.len()),
+        Err(ErrorDesc::BorrowedIncompatibly)
+    );
+}
+
+#[test]
+fn read_mostly() {
+    use std::sync::Arc;
+    use std::thread::spawn;
+
+    let mut x = RwLockStorage::new();
+    x.allocate_for::<usize>();
+    x.allocate_read_mostly_for::<usize>();
+    x.allocate_read_mostly_for::<String>();
+    assert_eq!(x.load::<usize>(), Err(ErrorDesc::UnsupportedOperation));
+    assert_eq!(x.load::<isize>(), Err(ErrorDesc::NoAllocatedUnit));
+    assert_eq!(x.load::<String>(), Err(ErrorDesc::Unit(UnitError::IsNone)));
+    assert_eq!(x.store(String::from("a")), Ok(None));
+    assert_eq!(
+        x.get::<String>().err(),
+        Some(ErrorDesc::UnsupportedOperation)
+    );
+    assert_eq!(
+        x.insert(String::new()),
+        Err((String::new(), ErrorDesc::UnsupportedOperation))
+    );
+
+    let x = Arc::new(x);
+    let readers = (0..4)
+        .map(|_| {
+            let xc = x.clone();
+            spawn(move || loop {
+                let value = xc.load::<String>().unwrap();
+                assert!(value.chars().all(|c| c == 'a'));
+                if value.len() == 50 {
+                    break;
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    let first = x.load::<String>().unwrap();
+    for len in 2..=50 {
+        let previous = x.store("a".repeat(len)).unwrap().unwrap();
+        assert_eq!(previous.len(), len - 1);
+    }
+    readers.into_iter().for_each(|t| t.join().unwrap());
+    // Values handed out before being replaced are unaffected
+    assert_eq!(&*first, "a");
+}
+
+#[test]
+fn read_mostly_releases_replaced_values() {
+    use std::sync::Arc;
+
+    let mut x = RwLockStorage::new();
+    x.allocate_read_mostly_for::<String>();
+    x.store(String::from("a")).unwrap();
+    let first = Arc::downgrade(&x.load::<String>().unwrap());
+    let second = Arc::downgrade(&x.store(String::from("b")).unwrap().unwrap());
+    // Neither is read anymore, so the next `store` releases both
+    x.store(String::from("c")).unwrap();
+    assert!(first.upgrade().is_none());
+    assert!(second.upgrade().is_none());
+    assert_eq!(&*x.load::<String>().unwrap(), "c");
+
+    let held = x.load::<String>().unwrap();
+    let third = Arc::downgrade(&held);
+    x.store(String::from("d")).unwrap();
+    x.store(String::from("e")).unwrap();
+    // A value handed out stays alive for as long as it is held
+    assert_eq!(third.upgrade().as_deref().map(String::as_str), Some("c"));
+    drop(held);
+    assert!(third.upgrade().is_none());
+}
+
+#[test]
+fn extract_regardless_of_count() {
+    let mut x = RwLockStorage::new();
+    x.allocate_for::<usize>();
+    assert_eq!(x.extract_any_count::<usize>(), Ok(vec![]));
+    assert_eq!(
+        x.extract_one_loose::<usize>(),
+        Err(ErrorDesc::Unit(UnitError::IsNone))
+    );
+
+    x.insert(1usize).unwrap();
+    assert_eq!(x.extract_any_count::<usize>(), Ok(vec![1]));
+    x.insert(1usize).unwrap();
+    assert_eq!(x.extract_one_loose::<usize>(), Ok(1));
+    assert_eq!(x.extract_any_count::<usize>(), Ok(vec![]));
+
+    x.insert_many(vec![1usize, 2, 3]).unwrap();
+    assert_eq!(x.extract_one_loose::<usize>(), Ok(1));
+    assert_eq!(x.extract_any_count::<usize>(), Ok(vec![2, 3]));
+    assert_eq!(x.extract_any_count::<usize>(), Ok(vec![]));
+
+    assert_eq!(
+        x.extract_any_count::<isize>(),
+        Err(ErrorDesc::NoAllocatedUnit)
+    );
+}
+
+#[test]
+fn insert_indexed() {
+    let mut x = RwLockStorage::new();
+    assert_eq!(
+        x.insert_indexed(0usize),
+        Err((0usize, ErrorDesc::NoAllocatedUnit))
+    );
+    x.allocate_for::<usize>();
+    for value in 10..15usize {
+        let index = x.insert_indexed(value).unwrap();
+        assert_eq!(*x.ind_mut::<usize>(index).unwrap(), value);
+    }
+    x.extract_ind::<usize>(1).unwrap();
+    let index = x.insert_indexed(20usize).unwrap();
+    assert_eq!(index, 4);
+    assert_eq!(*x.ind_mut::<usize>(index).unwrap(), 20);
+}
+
+#[test]
+fn insert_indexed_concurrently() {
+    let x = std::sync::Arc::new(restor::make_storage!(RwLockStorage: usize));
+    let threads = (0..4usize)
+        .map(|thread| {
+            let x = x.clone();
+            std::thread::spawn(move || {
+                for i in 0..50 {
+                    let mut value = thread * 100 + i;
+                    // Units are only ever tried, so a contended insert is retried
+                    let index = loop {
+                        match x.insert_indexed(value) {
+                            Ok(index) => break index,
+                            Err((rejected, ErrorDesc::BorrowedIncompatibly)) => value = rejected,
+                            Err((_, e)) => panic!("{}", e),
+                        }
+                    };
+                    let found = loop {
+                        if let Ok(found) = x.ind::<usize>(index) {
+                            break *found;
+                        }
+                    };
+                    // Values are only ever appended, so nothing moves after insertion
+                    assert_eq!(found, value);
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    assert_eq!(x.extract_any_count::<usize>().unwrap().len(), 200);
+}
+
+#[test]
+fn compose_retries_on_change() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::mpsc::channel;
+
+    let x = std::sync::Arc::new(restor::make_storage!(RwLockStorage: usize));
+    x.insert(1usize).unwrap();
+    let (request, requests) = channel::<()>();
+    let (done, dones) = channel::<()>();
+    let writer = {
+        let x = x.clone();
+        std::thread::spawn(move || {
+            for () in requests {
+                *x.get_mut::<usize>().unwrap() += 100;
+                done.send(()).unwrap();
+            }
+        })
+    };
+
+    // The writer changes the value during the first call only
+    let calls = AtomicUsize::new(0);
+    x.compose(|value: &usize| {
+        if calls.fetch_add(1, Ordering::SeqCst) == 0 {
+            request.send(()).unwrap();
+            dones.recv().unwrap();
+        }
+        value * 2
+    })
+    .unwrap();
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+    assert_eq!(*x.get::<usize>().unwrap(), 202);
+
+    // The writer changes the value during every call
+    calls.store(0, Ordering::SeqCst);
+    assert_eq!(
+        x.compose(|value: &usize| {
+            calls.fetch_add(1, Ordering::SeqCst);
+            request.send(()).unwrap();
+            dones.recv().unwrap();
+            value * 2
+        }),
+        Err(ErrorDesc::Contended)
+    );
+    assert_eq!(calls.load(Ordering::SeqCst), restor::COMPOSE_ATTEMPTS);
+    assert_eq!(
+        *x.get::<usize>().unwrap(),
+        202 + 100 * restor::COMPOSE_ATTEMPTS
+    );
+
+    drop(request);
+    writer.join().unwrap();
+
+    assert_eq!(
+        x.compose(|value: &isize| *value),
+        Err(ErrorDesc::NoAllocatedUnit.context("reading the value to compose"))
+    );
+}
+
+#[test]
+fn try_get_outcomes() {
+    let mut x = RwLockStorage::new();
+    // No unit allocated
+    assert!(x.try_get::<usize>().unwrap().is_none());
+    assert!(x.try_ind::<usize>(0).unwrap().is_none());
+
+    // No values
+    x.allocate_for::<usize>();
+    assert!(x.try_get::<usize>().unwrap().is_none());
+    assert!(x.try_get_mut::<usize>().unwrap().is_none());
+    assert!(x.try_ind::<usize>(0).unwrap().is_none());
+    assert!(x.try_ind::<usize>(1).unwrap().is_none());
+
+    // A single value
+    x.insert(1usize).unwrap();
+    assert_eq!(*x.try_get::<usize>().unwrap().unwrap(), 1);
+    *x.try_get_mut::<usize>().unwrap().unwrap() += 1;
+    assert_eq!(*x.try_ind::<usize>(0).unwrap().unwrap(), 2);
+    assert!(x.try_ind::<usize>(1).unwrap().is_none());
+
+    // A list, even of one value
+    x.insert(3usize).unwrap();
+    x.extract::<usize>().unwrap();
+    let not_one = Some(ErrorDesc::Unit(UnitError::IsNotOne));
+    assert_eq!(x.try_get::<usize>().err(), not_one);
+    assert_eq!(x.try_get_mut::<usize>().err(), not_one);
+    assert_eq!(*x.try_ind::<usize>(0).unwrap().unwrap(), 3);
+    assert!(x.try_ind::<usize>(1).unwrap().is_none());
+    x.extract::<usize>().unwrap();
+    x.insert(4usize).unwrap();
+
+    // Borrowed incompatibly
+    {
+        let _lock = x.get_mut::<usize>().unwrap();
+        let borrowed = Some(ErrorDesc::BorrowedIncompatibly);
+        assert_eq!(x.try_get::<usize>().err(), borrowed);
+        assert_eq!(x.try_get_mut::<usize>().err(), borrowed);
+        assert_eq!(x.try_ind::<usize>(0).err(), borrowed);
+        assert_eq!(x.try_ind::<usize>(1).err(), borrowed);
+    }
+
+    // Read-mostly
+    x.allocate_read_mostly_for::<isize>();
+    let unsupported = Some(ErrorDesc::UnsupportedOperation);
+    assert_eq!(x.try_get::<isize>().err(), unsupported);
+    assert_eq!(x.try_get_mut::<isize>().err(), unsupported);
+    assert_eq!(x.try_ind::<isize>(0).err(), unsupported);
+}
+
+#[test]
+fn weak_handles_break_cycles() {
+    use restor::{StorageHandle, StorageUnit, StorageWeakHandle};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    #[derive(Debug)]
+    struct Subsystem {
+        storage: StorageWeakHandle,
+        dropped: Arc<AtomicBool>,
+    }
+
+    impl Drop for Subsystem {
+        fn drop(&mut self) {
+            self.dropped.store(true, Ordering::SeqCst);
+        }
+    }
+
+    let dropped = Arc::new(AtomicBool::new(false));
+    let mut storage = RwLockStorage::new();
+    storage.allocate_for::<usize>();
+    storage.allocate_for::<Subsystem>();
+    let storage = StorageHandle::new(storage);
+    storage.insert(1usize).unwrap();
+    storage
+        .insert(Subsystem {
+            storage: storage.downgrade(),
+            dropped: dropped.clone(),
+        })
+        .unwrap();
+
+    let weak = storage.get::<Subsystem>().unwrap().storage.clone();
+    assert_eq!(weak.with(|x: &StorageUnit<usize>| x.len()), Ok(1));
+    assert_eq!(
+        weak.with(|x: &StorageUnit<isize>| x.len()),
+        Err(ErrorDesc::NoAllocatedUnit)
+    );
+    let other = weak.upgrade().unwrap();
+    other.insert(2usize).unwrap();
+    drop(other);
+    assert_eq!(weak.with(|x: &StorageUnit<usize>| x.len()), Ok(2));
+
+    // The subsystem's handle doesn't keep the storage, and so itself, alive
+    drop(storage);
+    assert!(dropped.load(Ordering::SeqCst));
+    assert!(weak.upgrade().is_none());
+    assert_eq!(
+        weak.with(|x: &StorageUnit<usize>| x.len()),
+        Err(ErrorDesc::StorageDropped)
+    );
+}
+
+#[test]
+fn cloned_iter_chunked_with_writer() {
+    use std::sync::Arc;
+
+    let x = Arc::new(restor::make_storage!(RwLockStorage: usize));
+    x.insert_many((0..1000usize).collect()).unwrap();
+    let writer = {
+        let x = x.clone();
+        std::thread::spawn(move || {
+            for i in 0..500 {
+                // Contended writes are dropped, which is fine for this test
+                if i % 2 == 0 {
+                    let _ = x.extract_ind::<usize>(0);
+                } else {
+                    let _ = x.insert(1000 + i);
+                }
+            }
+        })
+    };
+    let mut seen = 0;
+    for chunk in x.cloned_iter_chunked::<usize>(7).unwrap() {
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                assert_eq!(e, ErrorDesc::BorrowedIncompatibly);
+                break;
+            }
+        };
+        assert!(!chunk.is_empty() && chunk.len() <= 7);
+        // Only values that were ever inserted are seen
+        assert!(chunk.iter().all(|&v| v < 1500));
+        seen += chunk.len();
+    }
+    writer.join().unwrap();
+    assert!(seen <= 1250);
+}
+
+#[test]
+fn retain_types_read_mostly() {
+    use std::any::TypeId;
+
+    let mut x = RwLockStorage::new();
+    x.allocate_for::<usize>();
+    x.allocate_read_mostly_for::<String>();
+    x.store(String::from("config")).unwrap();
+    x.insert(1usize).unwrap();
+
+    assert_eq!(
+        x.retain_types_named(|name| name != std::any::type_name::<String>()),
+        vec![TypeId::of::<String>()]
+    );
+    assert_eq!(x.load::<String>().err(), Some(ErrorDesc::NoAllocatedUnit));
+    assert_eq!(*x.get::<usize>().unwrap(), 1);
+
+    assert_eq!(x.retain_types(|_| false), vec![TypeId::of::<usize>()]);
+    assert_eq!(x.get::<usize>().err(), Some(ErrorDesc::NoAllocatedUnit));
+    x.allocate_for::<usize>();
+    x.insert(2usize).unwrap();
+    assert_eq!(*x.get::<usize>().unwrap(), 2);
+}
+
+#[test]
+fn ind_from_end() {
+    let x = restor::make_storage!(RwLockStorage: usize);
+    let out_of_bounds = Err(ErrorDesc::Unit(UnitError::OutOfBounds));
+    assert_eq!(x.ind_from_end::<usize>(0).map(|x| *x), out_of_bounds);
+    assert_eq!(x.ind_from_end_mut::<usize>(0).map(|x| *x), out_of_bounds);
+    x.insert(1usize).unwrap();
+    assert_eq!(x.ind_from_end::<usize>(0).map(|x| *x), Ok(1));
+    assert_eq!(x.ind_from_end::<usize>(1).map(|x| *x), out_of_bounds);
+    x.insert_many(vec![2usize, 3]).unwrap();
+    assert_eq!(x.ind_from_end::<usize>(0).map(|x| *x), Ok(3));
+    assert_eq!(x.ind_from_end::<usize>(2).map(|x| *x), Ok(1));
+    assert_eq!(x.ind_from_end::<usize>(3).map(|x| *x), out_of_bounds);
+    assert_eq!(
+        x.ind_from_end::<usize>(usize::MAX).map(|x| *x),
+        out_of_bounds
+    );
+    *x.ind_from_end_mut::<usize>(1).unwrap() = 20;
+    assert_eq!(x.ind::<usize>(1).map(|x| *x), Ok(20));
+    assert_eq!(x.ind_from_end_mut::<usize>(3).map(|x| *x), out_of_bounds);
+}
+
+#[test]
+fn ind_from_end_while_appending() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    const APPENDS: usize = 2000;
+    let x = std::sync::Arc::new(restor::make_storage!(RwLockStorage: usize));
+    // The number of values which are known to have been appended
+    let appended = std::sync::Arc::new(AtomicUsize::new(0));
+    let writer = {
+        let (x, appended) = (x.clone(), appended.clone());
+        std::thread::spawn(move || {
+            for mut value in 0..APPENDS {
+                // Units are only ever tried, so a contended insert is retried
+                while let Err((rejected, e)) = x.insert(value) {
+                    assert_eq!(e, ErrorDesc::BorrowedIncompatibly);
+                    value = rejected;
+                }
+                appended.store(value + 1, Ordering::SeqCst);
+            }
+        })
+    };
+    let mut last = None;
+    while last != Some(APPENDS - 1) {
+        let known = appended.load(Ordering::SeqCst);
+        let found = match x.ind_from_end::<usize>(0) {
+            Ok(found) => *found,
+            Err(ErrorDesc::BorrowedIncompatibly) => continue,
+            Err(e) => {
+                assert_eq!(e, ErrorDesc::Unit(UnitError::OutOfBounds));
+                assert_eq!(known, 0);
+                continue;
+            }
+        };
+        // The value `i` is only ever at index `i`, so anything older than what was
+        // known to be appended before looking would be a stale index
+        assert!(found + 1 >= known, "read {} after {} appends", found, known);
+        assert!(last <= Some(found));
+        last = Some(found);
+    }
+    writer.join().unwrap();
+}
+
+#[test]
+fn try_with_error_mapping() {
+    use restor::TryError;
+
+    #[derive(Debug, PartialEq)]
+    enum Error {
+        Storage(ErrorDesc),
+        Negative(isize),
+    }
+
+    impl From<TryError<Error>> for Error {
+        fn from(e: TryError<Error>) -> Self {
+            match e {
+                TryError::Storage(e) => Error::Storage(e),
+                TryError::User(e) => e,
+            }
+        }
+    }
+
+    fn check(x: &RwLockStorage) -> Result<usize, Error> {
+        let count = x.try_with(|&n: &isize| {
+            if n < 0 {
+                Err(Error::Negative(n))
+            } else {
+                Ok(n as usize)
+            }
+        })?;
+        x.try_for_each_mut(|s: &mut String| {
+            s.push('!');
+            Ok(())
+        })?;
+        Ok(count)
+    }
+
+    let x = restor::make_storage!(RwLockStorage: isize, String);
+    assert_eq!(
+        check(&x),
+        Err(Error::Storage(ErrorDesc::Unit(UnitError::IsNotOne)))
+    );
+    x.insert(-3isize).unwrap();
+    assert_eq!(check(&x), Err(Error::Negative(-3)));
+    *x.get_mut::<isize>().unwrap() = 3;
+    x.insert(String::from("a")).unwrap();
+    assert_eq!(check(&x), Ok(3));
+    assert_eq!(&*x.get::<String>().unwrap(), "a!");
+
+    // Errors from the storage convert into a `TryError` with `?`
+    let insert = || -> Result<(), TryError<()>> {
+        x.get_mut::<String>()?.push('?');
+        Ok(())
+    };
+    assert_eq!(insert(), Ok(()));
+    let _lock = x.get::<String>().unwrap();
+    assert_eq!(
+        insert(),
+        Err(TryError::Storage(ErrorDesc::BorrowedIncompatibly))
+    );
+}
+
+#[test]
+fn get_policy() {
+    use restor::GetPolicy;
+
+    let not_one = Err(ErrorDesc::Unit(UnitError::IsNotOne));
+    for policy in [GetPolicy::Strict, GetPolicy::FirstOnMany] {
+        let mut x = RwLockStorage::with_policy(policy);
+        x.allocate_for::<usize>();
+        assert_eq!(x.policy(), policy);
+        // Neither policy changes how a single value or no values are handled
+        assert_eq!(x.get::<usize>().map(|x| *x), not_one);
+        assert_eq!(x.get_mut::<usize>().map(|x| *x), not_one);
+        x.insert(1usize).unwrap();
+        assert_eq!(x.get::<usize>().map(|x| *x), Ok(1));
+        x.insert(2usize).unwrap();
+        match policy {
+            GetPolicy::Strict => {
+                assert_eq!(x.get::<usize>().map(|x| *x), not_one);
+                assert_eq!(x.get_mut::<usize>().map(|x| *x), not_one);
+                assert!(x.try_get::<usize>().is_err());
+            }
+            GetPolicy::FirstOnMany => {
+                *x.get_mut::<usize>().unwrap() += 10;
+                assert_eq!(x.get::<usize>().map(|x| *x), Ok(11));
+                assert_eq!(x.try_get::<usize>().unwrap().map(|x| *x), Some(11));
+                assert_eq!(x.ind::<usize>(1).map(|x| *x), Ok(2));
+            }
+        }
+        // The policy can be overridden per call
+        let other = match policy {
+            GetPolicy::Strict => GetPolicy::FirstOnMany,
+            GetPolicy::FirstOnMany => GetPolicy::Strict,
+        };
+        let expected = match other {
+            GetPolicy::Strict => not_one.clone(),
+            GetPolicy::FirstOnMany => Ok(1),
+        };
+        assert_eq!(x.get_with_policy::<usize>(other).map(|x| *x), expected);
+        assert_eq!(x.get_mut_with_policy::<usize>(other).map(|x| *x), expected);
+        x.extract_many::<usize>().unwrap();
+        assert_eq!(
+            x.get_with_policy::<usize>(GetPolicy::FirstOnMany)
+                .map(|x| *x),
+            not_one
+        );
+    }
+}
+
+#[test]
+fn extract_many_into() {
+    let x = restor::make_storage!(RwLockStorage: usize);
+    let mut buf = vec![0usize];
+    assert_eq!(x.extract_many_into(&mut buf), Ok(0));
+    x.insert(1usize).unwrap();
+    assert_eq!(x.extract_many_into(&mut buf), Ok(1));
+    x.insert_many(vec![2usize, 3, 4]).unwrap();
+    assert_eq!(x.extract_many_into(&mut buf), Ok(3));
+    assert_eq!(buf, vec![0, 1, 2, 3, 4]);
+    // The unit is left with no values
+    assert_eq!(x.extract_many_into(&mut buf), Ok(0));
+    assert_eq!(buf.len(), 5);
+    assert_eq!(x.extract_any_count::<usize>(), Ok(vec![]));
+    assert_eq!(
+        x.extract_many_into(&mut Vec::<String>::new()),
+        Err(ErrorDesc::NoAllocatedUnit)
+    );
+}
+
+#[test]
+fn swap_backing_vec() {
+    let mut x = RwLockStorage::new();
+    x.allocate_for_with_vec::<usize>(Vec::with_capacity(32));
+    assert_eq!(x.capacity::<usize>(), Ok(32));
+    let mut spare = Vec::<usize>::with_capacity(32);
+    for frame in 0..4usize {
+        x.insert(frame).unwrap();
+        x.insert(frame + 1).unwrap();
+        let mut values = x.swap_backing_vec(spare).unwrap();
+        assert_eq!(values, vec![frame, frame + 1]);
+        assert_eq!(values.capacity(), 32);
+        values.clear();
+        spare = values;
+    }
+    // A single value in the new buffer is read as one
+    x.swap_backing_vec(vec![7usize]).unwrap();
+    assert_eq!(x.extract::<usize>(), Ok(7));
+    assert_eq!(x.capacity::<usize>(), Ok(1));
+    assert_eq!(
+        x.swap_backing_vec(Vec::<String>::new()),
+        Err(ErrorDesc::NoAllocatedUnit)
+    );
+}
+
+#[test]
+fn type_erased_values_cross_threads() {
+    use std::any::Any;
+    use std::sync::mpsc::channel;
+
+    let mut x = RwLockStorage::new();
+    x.allocate_named_for::<String>("input").unwrap();
+    x.allocate_named_for::<usize>("output").unwrap();
+    let x = std::sync::Arc::new(x);
+    x.insert(String::from("abc")).unwrap();
+
+    let (send, receive) = channel::<Box<dyn Any + Send>>();
+    let worker = {
+        let x = x.clone();
+        std::thread::spawn(move || {
+            let value = receive.recv().unwrap();
+            let len = value.downcast::<String>().unwrap().len();
+            x.insert_boxed_named("output", Box::new(len)).unwrap();
+        })
+    };
+    send.send(x.extract_any_named("input").unwrap()).unwrap();
+    worker.join().unwrap();
+    assert_eq!(*x.get::<usize>().unwrap(), 3);
+    assert_eq!(
+        x.extract_any_named("input").err(),
+        Some(ErrorDesc::Unit(UnitError::IsNotOne))
+    );
+}
+
+#[test]
+fn get_disjoint_ind_mut() {
+    let x = restor::make_storage!(RwLockStorage: String);
+    x.insert_many(vec!["a".to_string(), "b".to_string(), "c".to_string()])
+        .unwrap();
+    {
+        let [mut first, mut last] = x.get_disjoint_ind_mut::<String, 2>([0, 2]).unwrap();
+        std::mem::swap(&mut *first, &mut *last);
+        drop(first);
+        // The unit stays borrowed until every guard is dropped
+        assert_eq!(
+            x.ind_mut::<String>(1).err(),
+            Some(ErrorDesc::BorrowedIncompatibly)
+        );
+        last.push('!');
+    }
+    assert_eq!(
+        x.extract_many::<String>().map(Vec::from),
+        Ok(vec!["c".to_string(), "b".to_string(), "a!".to_string()])
+    );
+
+    x.insert(String::from("only")).unwrap();
+    let [mut only] = x.get_disjoint_ind_mut::<String, 1>([0]).unwrap();
+    only.push('!');
+    drop(only);
+    assert_eq!(x.extract::<String>(), Ok(String::from("only!")));
+}
+
+#[test]
+fn get_disjoint_ind_mut_errors() {
+    let x = restor::make_storage!(RwLockStorage: usize);
+    x.insert_many(vec![1usize, 2, 3]).unwrap();
+    assert_eq!(
+        x.get_disjoint_ind_mut::<usize, 3>([0, 2, 0]).err(),
+        Some(ErrorDesc::Unit(UnitError::DuplicateIndex))
+    );
+    assert_eq!(
+        x.get_disjoint_ind_mut::<usize, 2>([1, 3]).err(),
+        Some(ErrorDesc::Unit(UnitError::OutOfBounds))
+    );
+    assert_eq!(
+        x.get_disjoint_ind_mut::<String, 1>([0]).err(),
+        Some(ErrorDesc::NoAllocatedUnit)
+    );
+    {
+        let _guard = x.ind_mut::<usize>(0).unwrap();
+        assert_eq!(
+            x.get_disjoint_ind_mut::<usize, 2>([1, 2]).err(),
+            Some(ErrorDesc::BorrowedIncompatibly)
+        );
+    }
+    // A failed call leaves the unit unborrowed
+    assert_eq!(x.ind_multi::<usize>(&[0, 1, 2]), Ok(vec![1, 2, 3]));
+}
+
+#[test]
+fn poll_get() {
+    use restor::PollResult;
+
+    let x = restor::make_storage!(RwLockStorage: usize);
+    x.insert(1usize).unwrap();
+    let read = x.get::<usize>().unwrap();
+    // Readers only hold up writers
+    assert!(x.poll_get::<usize>().is_ready());
+    let token = match x.poll_get_mut::<usize>() {
+        PollResult::Pending(token) => token,
+        other => panic!("Expected a token, got {:?}", other),
+    };
+    assert!(!token.is_ready());
+    drop(read);
+    assert!(token.is_ready());
+
+    let write = x.get_mut::<usize>().unwrap();
+    let token = match x.poll_get::<usize>() {
+        PollResult::Pending(token) => token,
+        other => panic!("Expected a token, got {:?}", other),
+    };
+    assert!(!token.is_ready());
+    drop(write);
+    assert!(token.is_ready());
+    assert_eq!(x.poll_get::<usize>().ready().map(|x| *x), Some(1));
+}
+
+#[test]
+fn warm_capacity() {
+    let x = restor::make_storage!(RwLockStorage: u64);
+    x.warm_capacity::<u64>(1 << 20).unwrap();
+    let capacity = x.capacity::<u64>().unwrap();
+    assert!(capacity >= 1 << 20);
+    x.insert_iter(0..1u64 << 20).unwrap();
+    assert_eq!(x.capacity::<u64>(), Ok(capacity));
+    x.prefault::<u64>().unwrap();
+    assert_eq!(
+        x.run_for::<u64, bool, _>(|x| Some(x.unwrap().iter().copied().eq(0..1 << 20))),
+        Some(true)
+    );
+    assert_eq!(x.prefault::<String>(), Err(ErrorDesc::NoAllocatedUnit));
+}
+
+#[test]
+fn get_or_recover_races() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Barrier;
+
+    let x = restor::make_storage!(RwLockStorage: usize);
+    let recovered = AtomicUsize::new(0);
+    let barrier = Barrier::new(8);
+    std::thread::scope(|s| {
+        for i in 0..8usize {
+            let (x, recovered, barrier) = (&x, &recovered, &barrier);
+            s.spawn(move || {
+                barrier.wait();
+                loop {
+                    match x.get_or_recover::<usize, _>(|_| {
+                        recovered.fetch_add(1, Ordering::Relaxed);
+                        Some(i)
+                    }) {
+                        Ok(value) => break assert!(*value < 8),
+                        Err(ErrorDesc::BorrowedIncompatibly) => std::thread::yield_now(),
+                        Err(e) => panic!("Recovering failed with {:?}", e),
+                    }
+                }
+            });
+        }
+    });
+    // However many threads recovered, only one value was inserted
+    assert!(recovered.load(Ordering::Relaxed) >= 1);
+    assert_eq!(x.state::<usize>(), Ok(restor::UnitState::Single));
+}
+
+#[test]
+fn bounded_producers_never_exceed_cap() {
+    use restor::OverflowPolicy;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    const CAP: usize = 16;
+    for policy in [
+        OverflowPolicy::Reject,
+        OverflowPolicy::DropOldest,
+        OverflowPolicy::DropNewest,
+    ] {
+        let x = restor::make_storage!(RwLockStorage: usize);
+        let left_out = AtomicUsize::new(0);
+        let done = AtomicBool::new(false);
+        std::thread::scope(|s| {
+            let producers: Vec<_> = (0..4usize)
+                .map(|_| {
+                    let (x, left_out) = (&x, &left_out);
+                    s.spawn(move || {
+                        for i in 0..500usize {
+                            let mut value = i;
+                            loop {
+                                match x.insert_bounded(value, CAP, policy) {
+                                    Ok(None) => break,
+                                    Ok(Some(_)) | Err((_, ErrorDesc::Unit(UnitError::Full))) => {
+                                        left_out.fetch_add(1, Ordering::Relaxed);
+                                        break;
+                                    }
+                                    Err((v, ErrorDesc::BorrowedIncompatibly)) => {
+                                        value = v;
+                                        std::thread::yield_now();
+                                    }
+                                    Err((_, e)) => panic!("Inserting failed with {:?}", e),
+                                }
+                            }
+                        }
+                    })
+                })
+                .collect();
+            let (x, done) = (&x, &done);
+            s.spawn(move || {
+                while !done.load(Ordering::Relaxed) {
+                    if let Ok(state) = x.state::<usize>() {
+                        assert!(state.count() <= CAP);
+                    }
+                }
+            });
+            for producer in producers {
+                producer.join().unwrap();
+            }
+            done.store(true, Ordering::Relaxed);
+        });
+        assert_eq!(x.state::<usize>().map(|state| state.count()), Ok(CAP));
+        assert_eq!(left_out.load(Ordering::Relaxed), 4 * 500 - CAP);
+    }
+}
+
+#[test]
+fn on_remove_only_blocks_its_own_thread() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Barrier};
+
+    let barrier = Arc::new(Barrier::new(2));
+    let mut x = restor::make_storage!(RwLockStorage: usize, String);
+    let in_finalizer = barrier.clone();
+    let first = AtomicBool::new(true);
+    x.on_remove::<String, _>(move |_, storage| {
+        assert_eq!(
+            storage.insert(0usize).map_err(|(_, e)| e),
+            Err(ErrorDesc::BorrowedIncompatibly)
+        );
+        if first.swap(false, Ordering::Relaxed) {
+            // Another thread inserts while this finalizer runs
+            in_finalizer.wait();
+            in_finalizer.wait();
+        }
+    });
+    x.insert(String::from("a")).unwrap();
+    std::thread::scope(|s| {
+        let (x, barrier) = (&x, &barrier);
+        s.spawn(move || {
+            barrier.wait();
+            x.insert(1usize).unwrap();
+            barrier.wait();
+        });
+        assert_eq!(x.extract::<String>().as_deref(), Ok("a"));
+    });
+    assert_eq!(x.extract::<usize>(), Ok(1));
+}
+
+#[test]
+fn transaction_is_seen_whole() {
+    #[derive(Debug, PartialEq)]
+    struct Position(usize);
+    #[derive(Debug, PartialEq)]
+    struct Velocity(usize);
+
+    let storage = restor::make_storage!(RwLockStorage: Position, Velocity);
+    std::thread::scope(|s| {
+        let storage = &storage;
+        s.spawn(move || {
+            for i in 0..200 {
+                let mut txn = storage.transaction();
+                txn.insert(Position(i)).insert(Velocity(i));
+                // Retry for as long as the reader holds either unit
+                while let Err((rejected, errors)) = txn.commit() {
+                    assert!(errors
+                        .iter()
+                        .all(|(_, e)| *e.root() == ErrorDesc::BorrowedIncompatibly));
+                    txn = rejected;
+                }
+            }
+        });
+        loop {
+            let (positions, velocities) =
+                match (storage.state::<Position>(), storage.state::<Velocity>()) {
+                    (Ok(p), Ok(v)) => (p.count(), v.count()),
+                    _ => continue,
+                };
+            // Positions are always inserted along with their velocities
+            assert!(positions <= velocities);
+            if positions == 200 {
+                break;
+            }
+        }
+    });
+    assert_eq!(storage.state::<Velocity>().map(|s| s.count()), Ok(200));
+}
+
+#[test]
+fn cursor_follows_concurrent_appends() {
+    let storage = restor::make_storage!(RwLockStorage: usize);
+    storage.insert_many((0..1000usize).collect()).unwrap();
+    let mut cursor = storage.cursor::<usize>().unwrap();
+    let mut read = Vec::new();
+    std::thread::scope(|s| {
+        let writer = s.spawn(|| {
+            for i in 1000..2000usize {
+                while storage.insert(i).is_err() {}
+            }
+        });
+        // Reads a chunk at a time, releasing the unit in between
+        while read.len() < 2000 {
+            if let Ok(chunk) = cursor.next_chunk(&storage, 64) {
+                read.extend(chunk);
+            }
+        }
+        writer.join().unwrap();
+    });
+    assert_eq!(read, (0..2000).collect::<Vec<_>>());
+}
+
+#[test]
+fn assert_allocated_from_other_threads() {
+    let storage = std::sync::Arc::new(restor::make_storage!(RwLockStorage: usize, String));
+    let handles = (0..4)
+        .map(|_| {
+            let storage = storage.clone();
+            std::thread::spawn(move || {
+                assert_eq!(storage.assert_allocated::<(String, usize)>(), Ok(()));
+                storage
+                    .assert_allocated::<(usize, u8)>()
+                    .unwrap_err()
+                    .names()
+                    .to_vec()
+            })
+        })
+        .collect::<Vec<_>>();
+    for handle in handles {
+        assert_eq!(handle.join().unwrap(), vec!["u8"]);
+    }
+}
+
+#[test]
+fn scope_chunks_mut_visits_every_value_once() {
+    use std::num::NonZeroUsize;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    let storage = restor::make_storage!(RwLockStorage: usize);
+    storage.insert_many((0..10_007usize).collect()).unwrap();
+    let chunks = Mutex::new(Vec::new());
+    let threads = Mutex::new(std::collections::HashSet::new());
+    storage
+        .scope_chunks_mut_on(
+            NonZeroUsize::new(4).unwrap(),
+            100,
+            |i, chunk: &mut [usize]| {
+                chunks.lock().unwrap().push((i, chunk[0], chunk.len()));
+                threads.lock().unwrap().insert(std::thread::current().id());
+                chunk.iter_mut().for_each(|x| *x += 1);
+            },
+        )
+        .unwrap();
+    let mut chunks = chunks.into_inner().unwrap();
+    chunks.sort();
+    assert_eq!(chunks.len(), 101);
+    for (n, &(i, first, len)) in chunks.iter().enumerate() {
+        assert_eq!((i, first), (n, n * 100));
+        assert_eq!(len, if n == 100 { 7 } else { 100 });
+    }
+    assert!(threads.into_inner().unwrap().len() <= 4);
+    // Every value was incremented exactly once
+    let mut values = storage.cloned_iter::<usize>().unwrap();
+    assert!((1..=10_007).all(|x| values.next() == Some(x)));
+
+    // The borrow is held until every chunk is done
+    let visited = AtomicUsize::new(0);
+    storage
+        .scope_chunks_mut(7, |_, chunk: &mut [usize]| {
+            assert_eq!(
+                storage.ind::<usize>(0).err(),
+                Some(ErrorDesc::BorrowedIncompatibly)
+            );
+            visited.fetch_add(chunk.len(), Ordering::Relaxed);
+        })
+        .unwrap();
+    assert_eq!(visited.into_inner(), 10_007);
+}
+
+#[test]
+fn scope_chunks_mut_small_units() {
+    use std::num::NonZeroUsize;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let storage = restor::make_storage!(RwLockStorage: usize, u8);
+    let calls = AtomicUsize::new(0);
+    storage
+        .scope_chunks_mut(4, |_, _: &mut [usize]| {
+            calls.fetch_add(1, Ordering::Relaxed);
+        })
+        .unwrap();
+    assert_eq!(calls.load(Ordering::Relaxed), 0);
+
+    // A single chunk runs on the calling thread
+    storage.insert(5usize).unwrap();
+    let caller = std::thread::current().id();
+    storage
+        .scope_chunks_mut_on(
+            NonZeroUsize::new(8).unwrap(),
+            4,
+            |i, chunk: &mut [usize]| {
+                assert_eq!((i, std::thread::current().id()), (0, caller));
+                chunk[0] *= 3;
+            },
+        )
+        .unwrap();
+    assert_eq!(*storage.get::<usize>().unwrap(), 15);
+
+    assert_eq!(
+        storage.scope_chunks_mut(4, |_, _: &mut [u16]| ()),
+        Err(ErrorDesc::NoAllocatedUnit)
+    );
+}
+
+#[test]
+fn scope_chunks_mut_resumes_worker_panics() {
+    use std::num::NonZeroUsize;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    let mut storage = RwLockStorage::new();
+    storage.allocate_for::<usize>();
+    storage.insert_many((0..1000usize).collect()).unwrap();
+    let payload = catch_unwind(AssertUnwindSafe(|| {
+        storage.scope_chunks_mut_on(NonZeroUsize::new(4).unwrap(), 10, |i, _: &mut [usize]| {
+            if i == 42 {
+                std::panic::panic_any(i);
+            }
+        })
+    }))
+    .unwrap_err();
+    // The payload of the worker is resumed as it is
+    assert_eq!(payload.downcast_ref::<usize>(), Some(&42));
+    // The unit was released
+    assert_eq!(*storage.ind::<usize>(999).unwrap(), 999);
+}
+
+/// Runs 8 readers which each hold the unit for a millisecond at a time, along
+/// with a writer which writes at most once a millisecond, and returns how many
+/// times the writer and the readers got the unit.
+fn writes_among_readers(fairness: restor::Fairness) -> (usize, usize) {
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    let mut storage = RwLockStorage::new();
+    storage.allocate_for_with_fairness::<usize>(fairness);
+    storage.insert(0usize).unwrap();
+    let storage = Arc::new(storage);
+    let done = Arc::new(AtomicBool::new(false));
+    let reads = Arc::new(AtomicUsize::new(0));
+    let readers = (0..8)
+        .map(|i| {
+            let storage = storage.clone();
+            let done = done.clone();
+            let reads = reads.clone();
+            thread::spawn(move || {
+                // Staggered, so that the readers overlap one another
+                thread::sleep(Duration::from_micros(125 * i));
+                while !done.load(Ordering::Acquire) {
+                    match storage.get::<usize>() {
+                        Ok(value) => {
+                            thread::sleep(Duration::from_millis(1));
+                            drop(value);
+                            reads.fetch_add(1, Ordering::Relaxed);
+                        }
+                        Err(_) => thread::sleep(Duration::from_micros(50)),
+                    }
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    thread::sleep(Duration::from_millis(20));
+    let start = Instant::now();
+    let mut writes = 0;
+    while start.elapsed() < Duration::from_millis(300) {
+        match storage.get_mut::<usize>() {
+            Ok(mut value) => {
+                *value += 1;
+                writes += 1;
+                drop(value);
+                thread::sleep(Duration::from_millis(1));
+            }
+            Err(_) => thread::sleep(Duration::from_micros(100)),
+        }
+    }
+    done.store(true, Ordering::Release);
+    readers
+        .into_iter()
+        .for_each(|reader| reader.join().unwrap());
+    // The last write may have failed, and be holding off readers still
+    assert_eq!(*storage.get_mut::<usize>().unwrap(), writes);
+    (writes, reads.load(Ordering::Relaxed))
+}
+
+#[test]
+fn writer_priority_keeps_writers_from_starving() {
+    use restor::Fairness;
+
+    let (starved, _) = writes_among_readers(Fairness::ReaderPriority);
+    let (favored, reads) = writes_among_readers(Fairness::WriterPriority);
+    // The writer gets the unit at least once every 15 milliseconds or so,
+    // while the readers still get it in between
+    assert!(favored >= 20, "{} writes with writer priority", favored);
+    assert!(reads >= 20, "{} reads with writer priority", reads);
+    assert!(
+        starved * 4 < favored,
+        "{} writes with reader priority, {} with writer priority",
+        starved,
+        favored
+    );
+}
+
+#[test]
+fn fairness_is_kept_per_unit() {
+    use restor::Fairness;
+
+    let mut storage = RwLockStorage::new();
+    storage.allocate_for_with_fairness::<usize>(Fairness::WriterPriority);
+    storage.allocate_for::<isize>();
+    // Allocating again keeps the unit as it was
+    storage.allocate_for_with_fairness::<isize>(Fairness::WriterPriority);
+    storage.allocate_for::<usize>();
+    assert_eq!(storage.fairness::<usize>(), Ok(Fairness::WriterPriority));
+    assert_eq!(storage.fairness::<isize>(), Ok(Fairness::ReaderPriority));
+    assert_eq!(storage.fairness::<u8>(), Err(ErrorDesc::NoAllocatedUnit));
+
+    storage.insert(0usize).unwrap();
+    storage.insert(0isize).unwrap();
+    let usize_read = storage.get::<usize>().unwrap();
+    let isize_read = storage.get::<isize>().unwrap();
+    assert!(storage.get_mut::<usize>().is_err());
+    assert!(storage.get_mut::<isize>().is_err());
+    // Only the unit favoring writers turns new readers away
+    assert_eq!(
+        storage.get::<usize>().err(),
+        Some(ErrorDesc::BorrowedIncompatibly)
+    );
+    assert!(storage.get::<isize>().is_ok());
+    drop((usize_read, isize_read));
+    *storage.get_mut::<usize>().unwrap() += 1;
+    assert_eq!(*storage.get::<usize>().unwrap(), 1);
+}
@@ -0,0 +1,284 @@
+#![cfg(feature = "serde")]
+//! Pins the serialized form of the introspection of a storage, which is what
+//! metrics systems end up depending on. `serde_json` isn't a dependency, so
+//! the values are written out by a small JSON serializer.
+use restor::{make_storage, DynamicStorage, UnitState};
+use serde::ser::{self, Impossible, Serialize};
+use std::fmt::{self, Display};
+
+#[derive(Debug)]
+struct Error(String);
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl ser::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Error(msg.to_string())
+    }
+}
+
+fn to_json<T: Serialize>(value: &T) -> String {
+    let mut out = String::new();
+    value.serialize(Json(&mut out)).unwrap();
+    out
+}
+
+struct Json<'a>(&'a mut String);
+
+/// Writes the elements of a list or the fields of a struct
+struct Compound<'a> {
+    out: &'a mut String,
+    first: bool,
+    close: &'static str,
+}
+
+impl Compound<'_> {
+    fn separate(&mut self) {
+        if !self.first {
+            self.out.push(',');
+        }
+        self.first = false;
+    }
+}
+
+impl<'a> Json<'a> {
+    fn open(self, open: &str, close: &'static str) -> Compound<'a> {
+        self.0.push_str(open);
+        Compound {
+            out: self.0,
+            first: true,
+            close,
+        }
+    }
+
+    fn write<T: Display>(self, value: T) -> Result<(), Error> {
+        self.0.push_str(&value.to_string());
+        Ok(())
+    }
+
+    fn unsupported(self) -> Result<(), Error> {
+        Err(Error("unsupported".into()))
+    }
+}
+
+impl<'a> ser::Serializer for Json<'a> {
+    type Ok = ();
+    type Error = Error;
+    type SerializeSeq = Compound<'a>;
+    type SerializeTuple = Impossible<(), Error>;
+    type SerializeTupleStruct = Impossible<(), Error>;
+    type SerializeTupleVariant = Impossible<(), Error>;
+    type SerializeMap = Impossible<(), Error>;
+    type SerializeStruct = Compound<'a>;
+    type SerializeStructVariant = Impossible<(), Error>;
+
+    fn serialize_bool(self, v: bool) -> Result<(), Error> {
+        self.write(v)
+    }
+    fn serialize_i8(self, v: i8) -> Result<(), Error> {
+        self.write(v)
+    }
+    fn serialize_i16(self, v: i16) -> Result<(), Error> {
+        self.write(v)
+    }
+    fn serialize_i32(self, v: i32) -> Result<(), Error> {
+        self.write(v)
+    }
+    fn serialize_i64(self, v: i64) -> Result<(), Error> {
+        self.write(v)
+    }
+    fn serialize_u8(self, v: u8) -> Result<(), Error> {
+        self.write(v)
+    }
+    fn serialize_u16(self, v: u16) -> Result<(), Error> {
+        self.write(v)
+    }
+    fn serialize_u32(self, v: u32) -> Result<(), Error> {
+        self.write(v)
+    }
+    fn serialize_u64(self, v: u64) -> Result<(), Error> {
+        self.write(v)
+    }
+    fn serialize_f32(self, v: f32) -> Result<(), Error> {
+        self.write(v)
+    }
+    fn serialize_f64(self, v: f64) -> Result<(), Error> {
+        self.write(v)
+    }
+    fn serialize_char(self, v: char) -> Result<(), Error> {
+        self.serialize_str(&v.to_string())
+    }
+    fn serialize_str(self, v: &str) -> Result<(), Error> {
+        self.write(format_args!("{:?}", v))
+    }
+    fn serialize_bytes(self, _: &[u8]) -> Result<(), Error> {
+        self.unsupported()
+    }
+    fn serialize_none(self) -> Result<(), Error> {
+        self.write("null")
+    }
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<(), Error> {
+        value.serialize(self)
+    }
+    fn serialize_unit(self) -> Result<(), Error> {
+        self.write("null")
+    }
+    fn serialize_unit_struct(self, _: &'static str) -> Result<(), Error> {
+        self.write("null")
+    }
+    fn serialize_unit_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        variant: &'static str,
+    ) -> Result<(), Error> {
+        self.serialize_str(variant)
+    }
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        value.serialize(self)
+    }
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _: &'static str,
+        _: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.0.push_str(&format!("{{{:?}:", variant));
+        value.serialize(Json(self.0))?;
+        self.0.push('}');
+        Ok(())
+    }
+    fn serialize_seq(self, _: Option<usize>) -> Result<Compound<'a>, Error> {
+        Ok(self.open("[", "]"))
+    }
+    fn serialize_tuple(self, _: usize) -> Result<Self::SerializeTuple, Error> {
+        Err(Error("unsupported".into()))
+    }
+    fn serialize_tuple_struct(
+        self,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeTupleStruct, Error> {
+        Err(Error("unsupported".into()))
+    }
+    fn serialize_tuple_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeTupleVariant, Error> {
+        Err(Error("unsupported".into()))
+    }
+    fn serialize_map(self, _: Option<usize>) -> Result<Self::SerializeMap, Error> {
+        Err(Error("unsupported".into()))
+    }
+    fn serialize_struct(self, _: &'static str, _: usize) -> Result<Compound<'a>, Error> {
+        Ok(self.open("{", "}"))
+    }
+    fn serialize_struct_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeStructVariant, Error> {
+        Err(Error("unsupported".into()))
+    }
+}
+
+impl ser::SerializeSeq for Compound<'_> {
+    type Ok = ();
+    type Error = Error;
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        self.separate();
+        value.serialize(Json(self.out))
+    }
+    fn end(self) -> Result<(), Error> {
+        self.out.push_str(self.close);
+        Ok(())
+    }
+}
+
+impl ser::SerializeStruct for Compound<'_> {
+    type Ok = ();
+    type Error = Error;
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.separate();
+        self.out.push_str(&format!("{:?}:", key));
+        value.serialize(Json(self.out))
+    }
+    fn end(self) -> Result<(), Error> {
+        self.out.push_str(self.close);
+        Ok(())
+    }
+}
+
+#[test]
+fn unit_state() {
+    assert_eq!(to_json(&UnitState::Empty), r#""Empty""#);
+    assert_eq!(to_json(&UnitState::Multiple(3)), r#"{"Multiple":3}"#);
+}
+
+#[test]
+fn summary() {
+    let storage = make_storage!(DynamicStorage: usize, isize);
+    storage.insert(1usize).unwrap();
+    let _lock = storage.get_mut::<usize>().unwrap();
+    assert_eq!(
+        to_json(&storage.summary()),
+        concat!(
+            r#"[{"name":"isize","state":"Empty","borrowed":false},"#,
+            r#"{"name":"usize","state":null,"borrowed":true}]"#
+        )
+    );
+}
+
+#[test]
+fn stats() {
+    let storage = make_storage!(DynamicStorage: u32, String);
+    storage.insert_many(vec![1u32, 2]).unwrap();
+    storage.insert(String::new()).unwrap();
+    let _lock = storage.get_mut::<String>().unwrap();
+    let bytes = std::mem::size_of::<restor::StorageUnit<u32>>() + 2 * 4;
+    assert_eq!(
+        to_json(&storage.stats()),
+        format!(
+            concat!(
+                r#"[{{"type_name":"u32","state":{{"Multiple":2}},"len":2,"capacity":2,"#,
+                r#""approx_bytes":{},"locked":false}},"#,
+                r#"{{"type_name":"alloc::string::String","state":null,"len":null,"#,
+                r#""capacity":null,"approx_bytes":null,"locked":true}}]"#
+            ),
+            bytes
+        )
+    );
+}
+
+#[test]
+fn capacity_profile() {
+    let storage = make_storage!(DynamicStorage: u32, String);
+    storage.insert_many(vec![1u32, 2]).unwrap();
+    assert_eq!(
+        to_json(&storage.capacity_profile()),
+        concat!(
+            r#"{"units":[{"name":"u32","len":2,"capacity":2},"#,
+            r#"{"name":"alloc::string::String","len":0,"capacity":0}]}"#
+        )
+    );
+}
@@ -0,0 +1,110 @@
+use restor::{
+    make_storage, DynamicStorage, ErrorDesc, MutexStorage, RwLockStorage, SingletonStorage,
+    UnitError, UnitState,
+};
+
+macro_rules! singleton_tests {
+    ($name:ident, $storage:ty) => {
+        mod $name {
+            use super::*;
+
+            #[derive(Debug, PartialEq)]
+            struct Config(u32);
+
+            // `MutexStorage` only lends values mutably, so values are read
+            // through `get_mut` to test every storage the same way
+
+            #[test]
+            fn starts_empty() {
+                let mut storage = SingletonStorage::<$storage>::default();
+                assert!(!storage.contains::<Config>());
+                assert!(storage.get_mut::<Config>().is_err());
+                assert_eq!(storage.remove::<Config>(), None);
+            }
+
+            #[test]
+            fn insert_replaces_and_returns_the_old_value() {
+                let mut storage = SingletonStorage::<$storage>::default();
+                assert_eq!(storage.insert(Config(1)), None);
+                assert_eq!(storage.insert(Config(2)), Some(Config(1)));
+                assert_eq!(storage.insert(Config(3)), Some(Config(2)));
+                assert_eq!(storage.get_mut::<Config>().unwrap().0, 3);
+
+                // Other types are independent
+                assert_eq!(storage.insert(7usize), None);
+                assert_eq!(storage.get_mut::<Config>().unwrap().0, 3);
+                assert_eq!(*storage.get_mut::<usize>().unwrap(), 7);
+
+                // Units never hold more than one value
+                let storage = storage.into_inner();
+                assert_eq!(storage.state::<Config>(), Ok(UnitState::Single));
+                assert_eq!(storage.state::<usize>(), Ok(UnitState::Single));
+            }
+
+            #[test]
+            fn get_mut_changes_the_value() {
+                let mut storage = SingletonStorage::<$storage>::default();
+                storage.insert(Config(1));
+                storage.get_mut::<Config>().unwrap().0 += 10;
+                assert_eq!(storage.insert(Config(0)), Some(Config(11)));
+            }
+
+            #[test]
+            fn remove_empties_the_unit() {
+                let mut storage = SingletonStorage::<$storage>::default();
+                storage.insert(Config(1));
+                assert!(storage.contains::<Config>());
+                assert_eq!(storage.remove::<Config>(), Some(Config(1)));
+                assert!(!storage.contains::<Config>());
+                assert_eq!(
+                    storage.get_mut::<Config>().err(),
+                    Some(ErrorDesc::Unit(UnitError::IsNotOne))
+                );
+                assert_eq!(storage.remove::<Config>(), None);
+                assert_eq!(storage.insert(Config(2)), None);
+                assert!(storage.contains::<Config>());
+            }
+
+            #[test]
+            fn converts_from_and_to_storages() {
+                let storage = make_storage!($storage: Config, usize);
+                storage.insert(Config(1)).unwrap();
+                storage.insert_many(vec![1usize, 2]).unwrap();
+                let (storage, error) = SingletonStorage::from_storage(storage).err().unwrap();
+                assert_eq!(error.root(), &ErrorDesc::Unit(UnitError::IsNotOne));
+                assert_eq!(
+                    std::error::Error::source(&error).map(ToString::to_string),
+                    Some(UnitError::IsNotOne.to_string())
+                );
+                // The storage is handed back as it was
+                assert_eq!(storage.state::<usize>(), Ok(UnitState::Multiple(2)));
+
+                storage.extract_ind::<usize>(1).unwrap();
+                let mut singleton = SingletonStorage::from_storage(storage).ok().unwrap();
+                assert_eq!(singleton.insert(Config(2)), Some(Config(1)));
+                assert_eq!(singleton.insert(3usize), Some(1));
+                let storage: $storage = singleton.into();
+                assert_eq!(storage.extract::<usize>(), Ok(3));
+            }
+        }
+    };
+}
+
+singleton_tests!(dynamic, DynamicStorage);
+singleton_tests!(mutex, MutexStorage);
+singleton_tests!(rwlock, RwLockStorage);
+
+#[test]
+fn replaced_values_are_finalized() {
+    use std::sync::{Arc, Mutex};
+
+    let finalized = Arc::new(Mutex::new(Vec::new()));
+    let mut storage = make_storage!(DynamicStorage: String);
+    let log = finalized.clone();
+    storage.on_remove::<String, _>(move |value, _| log.lock().unwrap().push(value.clone()));
+    let mut storage = SingletonStorage::from_storage(storage).ok().unwrap();
+    storage.insert(String::from("first"));
+    storage.insert(String::from("second"));
+    assert_eq!(storage.remove::<String>().as_deref(), Some("second"));
+    assert_eq!(*finalized.lock().unwrap(), vec!["first", "second"]);
+}
@@ -0,0 +1,182 @@
+//! Drives random sequences of operations against every storage and against a
+//! model of a unit, checking that the two never disagree. The model is the
+//! specification of how a unit moves between `Empty`, `Single` and `Multiple`:
+//!  - `insert` into `Empty` gives `Single`, any other insertion gives `Multiple`,
+//!    except that inserting no values leaves the unit as it was
+//!  - Extracting the last value gives `Empty`, but a `Multiple` never becomes
+//!    `Single`
+//!  - Values keep the order they were inserted in
+use restor::{
+    DynamicResult, DynamicStorage, ErrorDesc, MutexStorage, RwLockStorage, StorageUnit, UnitError,
+    UnitState,
+};
+
+/// A xorshift generator, so that every run checks the same sequences
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+#[derive(Debug)]
+enum Op {
+    Insert(usize),
+    InsertMany(Vec<usize>),
+    InsertIter(Vec<usize>),
+    Extract,
+    ExtractInd(usize),
+    ExtractMany,
+}
+
+impl Op {
+    fn random(rng: &mut Rng, next: &mut usize) -> Self {
+        let mut values = |rng: &mut Rng| {
+            (0..rng.below(4))
+                .map(|_| {
+                    *next += 1;
+                    *next
+                })
+                .collect()
+        };
+        match rng.below(6) {
+            0 => {
+                *next += 1;
+                Op::Insert(*next)
+            }
+            1 => Op::InsertMany(values(rng)),
+            2 => Op::InsertIter(values(rng)),
+            3 => Op::Extract,
+            4 => Op::ExtractInd(rng.below(5)),
+            _ => Op::ExtractMany,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Model {
+    values: Vec<usize>,
+    many: bool,
+}
+
+impl Model {
+    fn state(&self) -> UnitState {
+        match self.values.len() {
+            0 => UnitState::Empty,
+            1 if !self.many => UnitState::Single,
+            len => UnitState::Multiple(len),
+        }
+    }
+
+    fn insert(&mut self, new: usize) {
+        self.many = !self.values.is_empty();
+        self.values.push(new);
+    }
+
+    fn insert_many(&mut self, new: &[usize]) {
+        if !new.is_empty() {
+            self.many = true;
+            self.values.extend_from_slice(new);
+        }
+    }
+
+    fn remove(&mut self, ind: usize) -> usize {
+        let value = self.values.remove(ind);
+        if self.values.is_empty() {
+            self.many = false;
+        }
+        value
+    }
+
+    fn ind_error(&self, ind: usize) -> ErrorDesc {
+        ErrorDesc::Unit(match self.state() {
+            UnitState::Multiple(_) => UnitError::OutOfBounds,
+            UnitState::Empty if ind == 0 => UnitError::IsNone,
+            _ => UnitError::IsNotMany,
+        })
+    }
+
+    /// Applies `op`, returning what the storage is expected to return.
+    fn apply(&mut self, op: &Op) -> DynamicResult<Vec<usize>> {
+        match op {
+            Op::Insert(x) => self.insert(*x),
+            Op::InsertMany(x) | Op::InsertIter(x) => self.insert_many(x),
+            Op::Extract => {
+                return match self.state() {
+                    UnitState::Empty => Err(ErrorDesc::Unit(UnitError::IsNotOne)),
+                    _ => Ok(vec![self.remove(0)]),
+                }
+            }
+            Op::ExtractInd(ind) => {
+                return if *ind < self.values.len() {
+                    Ok(vec![self.remove(*ind)])
+                } else {
+                    Err(self.ind_error(*ind))
+                }
+            }
+            Op::ExtractMany => {
+                return match self.state() {
+                    UnitState::Multiple(_) => {
+                        self.many = false;
+                        Ok(self.values.drain(..).collect())
+                    }
+                    _ => Err(ErrorDesc::Unit(UnitError::IsNotMany)),
+                }
+            }
+        }
+        Ok(Vec::new())
+    }
+}
+
+macro_rules! state_machine {
+    ($name:ident, $storage:ty) => {
+        #[test]
+        fn $name() {
+            let mut rng = Rng(0x2545_F491_4F6C_DD1D);
+            let mut next = 0;
+            for _ in 0..200 {
+                let mut storage = <$storage>::new();
+                storage.allocate_for::<usize>();
+                let mut model = Model::default();
+                for step in 0..40 {
+                    let op = Op::random(&mut rng, &mut next);
+                    let expected = model.apply(&op);
+                    let actual = match &op {
+                        Op::Insert(x) => storage.insert(*x).map(|_| Vec::new()).map_err(|e| e.1),
+                        Op::InsertMany(x) => storage
+                            .insert_many(x.clone())
+                            .map(|_| Vec::new())
+                            .map_err(|e| e.1),
+                        Op::InsertIter(x) => storage
+                            .insert_iter(x.iter().cloned())
+                            .map(|_| Vec::new())
+                            .map_err(|e| e.1),
+                        Op::Extract => storage.extract::<usize>().map(|x| vec![x]),
+                        Op::ExtractInd(ind) => storage.extract_ind::<usize>(*ind).map(|x| vec![x]),
+                        Op::ExtractMany => storage.extract_many::<usize>().map(Vec::from),
+                    };
+                    assert_eq!(actual, expected, "step {} applying {:?}", step, op);
+                    assert_eq!(storage.state::<usize>(), Ok(model.state()), "step {}", step);
+                    assert_eq!(
+                        storage.apply(|unit: &mut StorageUnit<usize>| unit.as_slice().to_vec()),
+                        Ok(model.values.clone()),
+                        "step {}",
+                        step
+                    );
+                }
+            }
+        }
+    };
+}
+
+state_machine!(dynamic, DynamicStorage);
+state_machine!(mutex, MutexStorage);
+state_machine!(rwlock, RwLockStorage);
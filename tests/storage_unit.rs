@@ -0,0 +1,131 @@
+use restor::{ErrorDesc, StorageUnit, UnitError, UnitState};
+
+#[test]
+fn empty() {
+    let unit = StorageUnit::<usize>::new();
+    assert!(unit.is_empty());
+    assert_eq!(unit.len(), 0);
+    assert_eq!(unit.capacity(), 0);
+    assert_eq!(unit.get(0), None);
+    assert_eq!(unit.ind(0), Err(ErrorDesc::Unit(UnitError::IsNone)));
+    assert_eq!(unit.ind(1), Err(ErrorDesc::Unit(UnitError::IsNotMany)));
+    assert_eq!(unit.iter().next(), None);
+    assert_eq!(format!("{:?}", unit), "Nope");
+}
+
+#[test]
+fn one() {
+    let mut unit = StorageUnit::from(5usize);
+    assert_eq!(unit.len(), 1);
+    assert_eq!(unit.capacity(), 1);
+    assert_eq!(unit.get(0), Some(&5));
+    assert_eq!(unit.get(1), None);
+    assert_eq!(unit.ind(1), Err(ErrorDesc::Unit(UnitError::IsNotMany)));
+    *unit.get_mut(0).unwrap() += 1;
+    assert_eq!(unit.iter().collect::<Vec<_>>(), vec![&6]);
+    assert_eq!(format!("{:?}", unit), "One(6)");
+}
+
+#[test]
+fn many() {
+    let mut unit = StorageUnit::from(Vec::with_capacity(8));
+    unit.extend(vec![1usize, 2, 3]);
+    assert_eq!(unit.len(), 3);
+    assert!(unit.capacity() >= 8);
+    assert_eq!(unit.ind(2), Ok(&3));
+    assert_eq!(unit.ind(3), Err(ErrorDesc::Unit(UnitError::OutOfBounds)));
+    for x in &mut unit {
+        *x *= 2;
+    }
+    assert_eq!((&unit).into_iter().sum::<usize>(), 12);
+    assert_eq!(format!("{:?}", unit), "Many([2, 4, 6])");
+    assert_eq!(unit.into_iter().collect::<Vec<_>>(), vec![2, 4, 6]);
+}
+
+#[test]
+fn transitions() {
+    let mut unit = StorageUnit::default();
+    unit.insert(1usize);
+    assert!(unit.one().is_ok());
+    unit.insert(2);
+    assert_eq!(unit.many(), Ok(&[1, 2][..]));
+    assert_eq!(unit.extract_ind(0), Ok(1));
+    assert_eq!(
+        unit.extract_ind(1),
+        Err(ErrorDesc::Unit(UnitError::OutOfBounds))
+    );
+    assert_eq!(unit.extract_many(), Ok(vec![2]));
+    assert!(unit.is_empty());
+
+    unit.extend(Vec::new());
+    assert!(unit.is_empty());
+    unit.extend(vec![3]);
+    assert_eq!(unit.many(), Ok(&[3][..]));
+
+    let mut unit = StorageUnit::from(1usize);
+    unit.extend(vec![2, 3]);
+    assert_eq!(unit.as_slice(), &[1, 2, 3]);
+    assert_eq!(unit.into_iter().collect::<Vec<_>>(), vec![1, 2, 3]);
+
+    let mut unit = StorageUnit::from(1usize);
+    assert_eq!(unit.extract_ind(0), Ok(1));
+    assert_eq!(unit.extract_ind(0), Err(ErrorDesc::Unit(UnitError::IsNone)));
+}
+
+#[test]
+fn second_insert_allocates_once() {
+    let mut unit = StorageUnit::new();
+    unit.insert(1usize);
+    assert_eq!(unit.capacity(), 1);
+    unit.insert(2);
+    assert_eq!(unit.capacity(), 2);
+    assert_eq!(unit.as_slice(), &[1, 2]);
+    unit.insert(3);
+    assert_eq!(unit.as_slice(), &[1, 2, 3]);
+}
+
+#[test]
+fn insertion_order() {
+    let mut unit = StorageUnit::new();
+    unit.insert(1usize);
+    unit.insert(2);
+    assert_eq!(unit.as_slice(), &[1, 2]);
+    unit.insert_many(vec![3, 4]);
+    unit.insert(5);
+    assert_eq!(unit.extract_ind(0), Ok(1));
+    assert_eq!(unit.extract_ind(2), Ok(4));
+    assert_eq!(unit.as_slice(), &[2, 3, 5]);
+    assert_eq!(unit.extract_many_boxed().map(Vec::from), Ok(vec![2, 3, 5]));
+    unit.insert(6);
+    unit.insert(7);
+    assert_eq!(unit.as_slice(), &[6, 7]);
+}
+
+#[test]
+fn warm_capacity() {
+    // Only a list has room to warm
+    let mut unit = StorageUnit::<usize>::new();
+    unit.warm_capacity(4096);
+    assert_eq!(unit.state(), UnitState::Empty);
+    assert_eq!(unit.capacity(), 0);
+
+    unit.extend(0..2usize);
+    unit.warm_capacity(4096);
+    let capacity = unit.capacity();
+    assert!(capacity >= 4098);
+    unit.extend(2..4098usize);
+    assert_eq!(unit.capacity(), capacity);
+
+    unit.warm_capacity(100_000);
+    unit.prefault();
+    assert!(unit.capacity() >= 104_098);
+    assert_eq!(unit.len(), 4098);
+    assert!(unit.iter().copied().eq(0..4098));
+
+    let mut unit = StorageUnit::from(String::from("abc"));
+    unit.prefault();
+    unit.warm_capacity(16);
+    assert_eq!(unit.state(), UnitState::Single);
+    assert_eq!(unit.one().map(String::as_str), Ok("abc"));
+    assert_eq!(unit.capacity(), 1);
+}
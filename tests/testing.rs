@@ -0,0 +1,157 @@
+#![cfg(feature = "testing")]
+use restor::testing::{AccessKind, RecordingStorage};
+use restor::{make_storage, DynamicStorage, ErrorDesc, MutexStorage, RwLockStorage};
+
+#[derive(Debug, PartialEq)]
+struct Config(u32);
+#[derive(Debug, PartialEq)]
+struct Physics(u32);
+#[derive(Debug, PartialEq)]
+struct Audio(u32);
+
+fn physics_step(storage: &RecordingStorage<RwLockStorage>) -> Result<(), ErrorDesc> {
+    let config = storage.get::<Config>()?;
+    let mut physics = storage.get_mut::<Physics>()?;
+    physics.0 += config.0;
+    Ok(())
+}
+
+fn setup() -> RecordingStorage<RwLockStorage> {
+    let storage = make_storage!(RwLockStorage: Config, Physics, Audio);
+    storage.insert(Config(3)).unwrap();
+    storage.insert(Physics(0)).unwrap();
+    storage.insert(Audio(0)).unwrap();
+    RecordingStorage::new(storage)
+}
+
+#[test]
+fn records_what_a_system_touches() {
+    let storage = setup();
+    physics_step(&storage).unwrap();
+
+    let recorder = storage.recorder();
+    assert!(recorder.read::<Config>());
+    assert!(!recorder.wrote::<Config>());
+    assert!(recorder.wrote::<Physics>());
+    assert!(!recorder.read::<Audio>() && !recorder.wrote::<Audio>());
+    assert_eq!(
+        recorder.touched(),
+        vec![
+            std::any::type_name::<Config>(),
+            std::any::type_name::<Physics>()
+        ]
+    );
+
+    let accesses = recorder.accesses();
+    assert_eq!(accesses.len(), 2);
+    assert_eq!(accesses[0].op, "get");
+    assert_eq!(accesses[0].kind, AccessKind::Read);
+    assert_eq!(accesses[1].op, "get_mut");
+    assert_eq!(accesses[1].kind, AccessKind::Write);
+    assert!(accesses.iter().all(|access| access.outcome.is_ok()));
+
+    // The storage itself isn't recorded
+    assert_eq!(*storage.storage().get::<Physics>().unwrap(), Physics(3));
+    assert_eq!(recorder.accesses().len(), 2);
+
+    recorder.clear();
+    assert!(recorder.accesses().is_empty());
+}
+
+#[test]
+fn records_failures() {
+    let storage = setup();
+    assert!(storage.ind::<Audio>(4).is_err());
+    {
+        let _lock = storage.storage().get_mut::<Audio>().unwrap();
+        assert_eq!(
+            storage.get::<Audio>().err(),
+            Some(ErrorDesc::BorrowedIncompatibly)
+        );
+    }
+    let accesses = storage.recorder().accesses_of::<Audio>();
+    assert_eq!(accesses.len(), 2);
+    assert!(accesses.iter().all(|access| access.outcome.is_err()));
+    assert!(storage.recorder().read::<Audio>());
+}
+
+#[test]
+fn fail_next_injects_one_error() {
+    let storage = setup();
+    storage.fail_next::<Physics>(ErrorDesc::BorrowedIncompatibly);
+    assert_eq!(physics_step(&storage), Err(ErrorDesc::BorrowedIncompatibly));
+
+    // The failed step never reached the storage
+    assert_eq!(*storage.storage().get::<Physics>().unwrap(), Physics(0));
+    let physics = storage.recorder().accesses_of::<Physics>();
+    assert_eq!(physics.len(), 1);
+    assert_eq!(physics[0].outcome, Err(ErrorDesc::BorrowedIncompatibly));
+
+    // Only the next access fails
+    physics_step(&storage).unwrap();
+    assert_eq!(*storage.storage().get::<Physics>().unwrap(), Physics(3));
+}
+
+#[test]
+fn fail_next_matches_the_type() {
+    let storage = setup();
+    storage.fail_next::<Audio>(ErrorDesc::Contended);
+    storage.fail_next::<Physics>(ErrorDesc::BorrowedIncompatibly);
+    storage.fail_next::<Physics>(ErrorDesc::Contended);
+
+    assert_eq!(physics_step(&storage), Err(ErrorDesc::BorrowedIncompatibly));
+    assert_eq!(physics_step(&storage), Err(ErrorDesc::Contended));
+    physics_step(&storage).unwrap();
+    assert_eq!(storage.extract::<Audio>(), Err(ErrorDesc::Contended));
+    assert_eq!(storage.extract::<Audio>(), Ok(Audio(0)));
+}
+
+#[test]
+fn injected_inserts_hand_data_back() {
+    let storage = RecordingStorage::new(make_storage!(DynamicStorage: usize));
+    storage.fail_next::<usize>(ErrorDesc::BorrowedIncompatibly);
+    assert_eq!(
+        storage.insert_many(vec![1usize, 2]),
+        Err((vec![1, 2], ErrorDesc::BorrowedIncompatibly))
+    );
+    storage.insert_many(vec![1usize, 2, 3]).unwrap();
+    assert_eq!(storage.extract_ind::<usize>(1), Ok(2));
+    assert_eq!(*storage.ind::<usize>(1).unwrap(), 3);
+    *storage.ind_mut::<usize>(0).unwrap() += 10;
+    assert_eq!(
+        storage.extract_many::<usize>().map(Vec::from),
+        Ok(vec![11, 3])
+    );
+
+    let ops: Vec<_> = storage
+        .recorder()
+        .accesses()
+        .into_iter()
+        .map(|access| (access.op, access.outcome.is_ok()))
+        .collect();
+    assert_eq!(
+        ops,
+        vec![
+            ("insert_many", false),
+            ("insert_many", true),
+            ("extract_ind", true),
+            ("ind", true),
+            ("ind_mut", true),
+            ("extract_many", true),
+        ]
+    );
+}
+
+#[test]
+fn wraps_mutex_storage() {
+    let mut storage = RecordingStorage::new(MutexStorage::new());
+    storage.storage_mut().allocate_for::<Config>();
+    storage.insert(Config(1)).unwrap();
+    storage.get_mut::<Config>().unwrap().0 += 1;
+    storage.fail_next::<Config>(ErrorDesc::Contended);
+    assert_eq!(
+        storage.get_mut::<Config>().err(),
+        Some(ErrorDesc::Contended)
+    );
+    assert_eq!(storage.into_inner().extract::<Config>(), Ok(Config(2)));
+}
@@ -0,0 +1,140 @@
+use restor::backend::{RefCellUnit, StorageUnit};
+use restor::{DynamicStorage, ErrorDesc, MutexStorage, RwLockStorage, UnitError};
+use std::any::TypeId;
+
+macro_rules! token_tests {
+    ($name:ident, $storage:ty) => {
+        mod $name {
+            use super::*;
+
+            #[test]
+            fn tokens_reach_their_units() {
+                let mut storage = <$storage>::new();
+                let numbers = storage.register_token::<usize>();
+                let names = storage.register_token::<String>();
+                storage.insert_many(vec![1usize, 2, 3]).unwrap();
+                storage.insert(String::from("a")).unwrap();
+
+                *storage.ind_mut_by_token(numbers, 1).unwrap() += 10;
+                storage.get_mut_by_token(names).unwrap().push('b');
+                assert_eq!(*storage.ind_mut_by_token(numbers, 1).unwrap(), 12);
+                assert_eq!(&*storage.get_mut_by_token(names).unwrap(), "ab");
+                assert_eq!(
+                    storage.get_mut_by_token(numbers).err(),
+                    Some(ErrorDesc::Unit(UnitError::IsNotOne))
+                );
+                assert_eq!(
+                    storage.ind_mut_by_token(numbers, 3).err(),
+                    Some(ErrorDesc::Unit(UnitError::OutOfBounds))
+                );
+            }
+
+            #[test]
+            fn registering_again_keeps_the_unit() {
+                let mut storage = <$storage>::new();
+                let first = storage.register_token::<usize>();
+                storage.insert(5usize).unwrap();
+                let second = storage.register_token::<usize>();
+                assert_eq!(*storage.get_mut_by_token(first).unwrap(), 5);
+                assert_eq!(*storage.get_mut_by_token(second).unwrap(), 5);
+                assert_eq!(storage.unit_count(), 1);
+            }
+
+            #[test]
+            fn tokens_survive_removing_other_units() {
+                let mut storage = <$storage>::new();
+                let bytes = storage.register_token::<u8>();
+                let numbers = storage.register_token::<usize>();
+                storage.insert(7usize).unwrap();
+
+                // Moves the unit for `usize` to where the unit for `u8` was
+                storage.remove_types(&[TypeId::of::<u8>()]);
+                assert_eq!(*storage.get_mut_by_token(numbers).unwrap(), 7);
+                assert_eq!(
+                    storage.get_mut_by_token(bytes).err(),
+                    Some(ErrorDesc::NoAllocatedUnit)
+                );
+
+                // A unit allocated again gets a new place, and the old token still finds it
+                storage.allocate_all::<(u8,)>();
+                storage.insert(1u8).unwrap();
+                assert_eq!(*storage.get_mut_by_token(bytes).unwrap(), 1);
+                let bytes = storage.register_token::<u8>();
+                assert_eq!(*storage.get_mut_by_token(bytes).unwrap(), 1);
+            }
+
+            #[test]
+            #[cfg(debug_assertions)]
+            #[should_panic(expected = "used on a storage other than the one which registered it")]
+            fn tokens_from_other_storages_are_caught() {
+                let mut registered = <$storage>::new();
+                let token = registered.register_token::<usize>();
+                let mut other = <$storage>::new();
+                other.register_token::<usize>();
+                other.insert(0usize).unwrap();
+                let _ = other.get_mut_by_token(token);
+            }
+
+            #[test]
+            #[cfg(not(debug_assertions))]
+            fn tokens_from_other_storages_are_looked_up_by_type() {
+                let mut registered = <$storage>::new();
+                let token = registered.register_token::<usize>();
+                let mut other = <$storage>::new();
+                other.register_token::<u8>();
+                other.register_token::<usize>();
+                other.insert(3u8).unwrap();
+                other.insert(4usize).unwrap();
+                assert_eq!(*other.get_mut_by_token(token).unwrap(), 4);
+            }
+        }
+    };
+}
+
+token_tests!(dynamic, DynamicStorage);
+token_tests!(mutex, MutexStorage);
+token_tests!(rwlock, RwLockStorage);
+
+#[test]
+fn immutable_token_accessors() {
+    let mut storage = RwLockStorage::new();
+    let token = storage.register_token::<usize>();
+    storage.insert_many(vec![4usize, 5]).unwrap();
+    let first = storage.ind_by_token(token, 0).unwrap();
+    let second = storage.ind_by_token(token, 1).unwrap();
+    assert_eq!((*first, *second), (4, 5));
+    assert_eq!(
+        storage.get_by_token(token).err(),
+        Some(ErrorDesc::Unit(UnitError::IsNotOne))
+    );
+    drop((first, second));
+    storage.extract_many::<usize>().unwrap();
+    storage.insert(6usize).unwrap();
+    assert_eq!(*storage.get_by_token(token).unwrap(), 6);
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "used on a storage other than the one which registered it")]
+fn tokens_are_not_shared_with_forks() {
+    let mut storage = DynamicStorage::new();
+    storage.allocate_clonable_for::<usize>();
+    let token = storage.register_token::<usize>();
+    storage.insert(1usize).unwrap();
+    let forked = storage.fork().unwrap();
+    assert_eq!(*storage.get_by_token(token).unwrap(), 1);
+    let _ = forked.get_by_token(token);
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "The unit for usize stores u8")]
+fn tokens_check_the_unit_they_reach() {
+    let mut storage = DynamicStorage::new();
+    let token = storage.register_token::<usize>();
+    storage.insert_unit_under(
+        TypeId::of::<usize>(),
+        Box::new(RefCellUnit::new(StorageUnit::<u8>::new())),
+    );
+    let _ = storage.get_by_token(token);
+}
@@ -0,0 +1,132 @@
+#![cfg(feature = "tracing")]
+use restor::{make_storage, set_hold_warning, DynamicStorage, RwLockStorage};
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Level, Metadata, Subscriber};
+
+/// Records every event as its level followed by its fields.
+#[derive(Clone, Default)]
+struct Recorder {
+    events: Arc<Mutex<Vec<String>>>,
+}
+
+struct FieldWriter(String);
+
+impl Visit for FieldWriter {
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.0.push_str(&format!(" {}={:?}", field.name(), value));
+    }
+}
+
+impl Subscriber for Recorder {
+    fn enabled(&self, _: &Metadata) -> bool {
+        true
+    }
+    fn new_span(&self, _: &Attributes) -> Id {
+        Id::from_u64(1)
+    }
+    fn record(&self, _: &Id, _: &Record) {}
+    fn record_follows_from(&self, _: &Id, _: &Id) {}
+    fn event(&self, event: &Event) {
+        let mut writer = FieldWriter(event.metadata().level().to_string());
+        event.record(&mut writer);
+        self.events.lock().unwrap().push(writer.0);
+    }
+    fn enter(&self, _: &Id) {}
+    fn exit(&self, _: &Id) {}
+}
+
+/// Keeps the tests from seeing the threshold set by `hold_warning`
+static SERIAL: Mutex<()> = Mutex::new(());
+
+fn record<F: FnOnce()>(f: F) -> Vec<String> {
+    let _serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+    let recorder = Recorder::default();
+    tracing::subscriber::with_default(recorder.clone(), f);
+    let events = recorder.events.lock().unwrap().clone();
+    events
+}
+
+#[test]
+fn borrow_conflict() {
+    let storage = make_storage!(DynamicStorage: usize);
+    storage.insert(0usize).unwrap();
+    let events = record(|| {
+        let _lock = storage.get::<usize>().unwrap();
+        assert!(storage.get_mut::<usize>().is_err());
+    });
+    assert_eq!(
+        events,
+        vec![
+            format!("{} message=ok op=\"get\" type_name=\"usize\"", Level::TRACE),
+            format!(
+                "{} message=borrow conflict op=\"get_mut\" type_name=\"usize\" error=the unit is borrowed incompatibly",
+                Level::WARN
+            ),
+        ]
+    );
+}
+
+#[test]
+fn operations() {
+    let storage = make_storage!(RwLockStorage: usize);
+    let events = record(|| {
+        storage.insert(0usize).unwrap();
+        drop(storage.ind::<usize>(0).unwrap());
+        storage.extract::<usize>().unwrap();
+        storage.insert(0isize).unwrap_err();
+    });
+    assert_eq!(events.len(), 4);
+    assert!(events[0].contains("op=\"insert\""));
+    assert!(events[1].contains("op=\"ind\""));
+    assert!(events[2].contains("op=\"extract\""));
+    assert!(events[3].starts_with(&Level::DEBUG.to_string()));
+    assert!(events[3].contains("type_name=\"isize\""));
+    assert!(events[3].contains("error=there is no unit allocated for the type"));
+}
+
+#[test]
+fn hold_warning() {
+    let storage = make_storage!(RwLockStorage: usize, String);
+    storage.insert(0usize).unwrap();
+    storage.insert(String::new()).unwrap();
+    let events = record(|| {
+        set_hold_warning(Some(Duration::from_millis(20)));
+        let guard = storage.get_mut::<usize>().unwrap();
+        drop(storage.get::<String>().unwrap());
+        std::thread::sleep(Duration::from_millis(40));
+        drop(guard);
+        set_hold_warning(None);
+        let guard = storage.get::<String>().unwrap();
+        std::thread::sleep(Duration::from_millis(40));
+        drop(guard);
+    });
+    let warnings: Vec<_> = events
+        .iter()
+        .filter(|e| e.contains("held for too long"))
+        .collect();
+    assert_eq!(warnings.len(), 1, "{:?}", events);
+    assert!(warnings[0].starts_with(&Level::WARN.to_string()));
+    assert!(warnings[0].contains("kind=\"write\" type_name=\"usize\""));
+}
+
+#[test]
+fn skipped_name() {
+    let mut storage = DynamicStorage::new();
+    storage.allocate_named_for::<String>("usize").unwrap();
+    let events = record(|| storage.allocate_for::<usize>());
+    assert_eq!(
+        events,
+        vec![format!(
+            "{} message=name already taken by another unit type_name=\"usize\"",
+            Level::WARN
+        )]
+    );
+    // The name still refers to the other type
+    storage.insert(String::from("a")).unwrap();
+    let named = storage.extract_any_named("usize").unwrap();
+    assert!(named.is::<String>());
+}
@@ -1,4 +1,4 @@
-use restor::{DynamicStorage, ErrorDesc};
+use restor::{DynamicStorage, ErrorDesc, UnitError, UnitState};
 
 #[test]
 fn instantiate() {
@@ -72,7 +72,7 @@ fn ind() {
     x.allocate_for::<usize>();
     x.insert(0usize).unwrap();
     x.insert(1usize).unwrap();
-    let y = x.ind::<usize>(0);
+    let _y = x.ind::<usize>(0);
     let indexed = x.ind::<usize>(0);
     assert!(indexed.is_ok());
     if let Ok(val) = indexed {
@@ -149,3 +149,1631 @@ fn ind_mut() {
         }
     }
 }
+
+#[test]
+fn map_in_place() {
+    let mut x = DynamicStorage::new();
+    x.allocate_for::<usize>();
+    x.allocate_for::<String>();
+    x.insert_many(vec![1usize, 2, 3]).unwrap();
+    x.insert(String::from("abc")).unwrap();
+    x.map_in_place::<usize, _>(|v| v * 2).unwrap();
+    x.map_in_place::<String, _>(|s| s + "def").unwrap();
+    assert_eq!(*x.ind_mut::<usize>(0).unwrap(), 2);
+    assert_eq!(*x.ind_mut::<usize>(1).unwrap(), 4);
+    assert_eq!(*x.ind_mut::<usize>(2).unwrap(), 6);
+    assert_eq!(&*x.get_mut::<String>().unwrap(), "abcdef");
+}
+
+#[test]
+fn map_in_place_panic() {
+    let mut x = DynamicStorage::new();
+    x.allocate_for::<String>();
+    x.insert_many(vec![
+        String::from("a"),
+        String::from("b"),
+        String::from("c"),
+    ])
+    .unwrap();
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        x.map_in_place::<String, _>(|s| {
+            if s == "b" {
+                panic!("Mapping failed");
+            }
+            s + "!"
+        })
+    }));
+    assert!(result.is_err());
+    assert_eq!(&*x.ind_mut::<String>(0).unwrap(), "a!");
+    assert_eq!(&*x.ind_mut::<String>(1).unwrap(), "c");
+    assert_eq!(
+        x.ind_mut::<String>(2).map(|_| ()),
+        Err(ErrorDesc::Unit(UnitError::OutOfBounds))
+    );
+}
+
+#[test]
+fn position() {
+    let mut x = DynamicStorage::new();
+    x.allocate_for::<usize>();
+    assert_eq!(x.position::<usize, _>(|v| *v == 3), Ok(None));
+    x.insert(3usize).unwrap();
+    assert_eq!(x.position::<usize, _>(|v| *v == 3), Ok(Some(0)));
+    x.insert_many(vec![4usize, 5, 6]).unwrap();
+    assert_eq!(x.position::<usize, _>(|v| *v == 6), Ok(Some(3)));
+    assert_eq!(x.position::<usize, _>(|v| *v == 7), Ok(None));
+    assert_eq!(
+        x.position::<isize, _>(|v| *v == 0),
+        Err(ErrorDesc::NoAllocatedUnit)
+    );
+    let _lock = x.ind_mut::<usize>(0).unwrap();
+    assert_eq!(
+        x.position::<usize, _>(|v| *v == 3),
+        Err(ErrorDesc::BorrowedIncompatibly)
+    );
+}
+
+#[test]
+fn find_clone() {
+    let mut x = DynamicStorage::new();
+    x.allocate_for::<String>();
+    assert_eq!(x.find_clone::<String, _>(|_| true), Ok(None));
+    x.insert(String::from("abc")).unwrap();
+    assert_eq!(
+        x.find_clone::<String, _>(|s| s.len() == 3),
+        Ok(Some(String::from("abc")))
+    );
+    x.insert_many(vec![String::from("de"), String::from("fg")])
+        .unwrap();
+    assert_eq!(
+        x.find_clone::<String, _>(|s| s.len() == 2),
+        Ok(Some(String::from("de")))
+    );
+    // No guard escapes, so the unit can be mutably borrowed right away
+    x.ind_mut::<String>(1).unwrap().push('!');
+}
+
+#[test]
+fn error_context() {
+    use std::error::Error;
+    let mut x = DynamicStorage::new();
+    x.allocate_for::<usize>();
+    let err = x
+        .get::<usize>()
+        .map(|_| ())
+        .map_err(|e| e.context("reading").context("frame update"))
+        .unwrap_err();
+    assert_eq!(err.root(), &ErrorDesc::Unit(UnitError::IsNotOne));
+    assert_eq!(
+        err.to_string(),
+        "frame update: reading: the unit does not contain exactly one value"
+    );
+    let source = err.source().unwrap();
+    assert_eq!(
+        source.to_string(),
+        "reading: the unit does not contain exactly one value"
+    );
+    assert!(source.source().unwrap().source().is_none());
+    if let ErrorDesc::Context { op, .. } = err {
+        assert_eq!(op, "frame update");
+    } else {
+        panic!();
+    }
+}
+
+#[test]
+fn interleaved_lookups() {
+    let mut x = DynamicStorage::new();
+    x.allocate_for::<usize>();
+    x.allocate_for::<isize>();
+    x.insert(1usize).unwrap();
+    x.insert(-1isize).unwrap();
+    for _ in 0..4 {
+        assert_eq!(*x.get::<usize>().unwrap(), 1);
+        assert_eq!(*x.get::<usize>().unwrap(), 1);
+        assert_eq!(*x.get::<isize>().unwrap(), -1);
+    }
+    // Allocating may move the units around, which must not leave a stale cache behind
+    x.allocate_for::<u8>();
+    x.allocate_for::<u16>();
+    x.allocate_for::<u32>();
+    x.allocate_for::<u64>();
+    x.allocate_for::<i8>();
+    x.allocate_for::<i16>();
+    x.allocate_for::<i32>();
+    x.allocate_for::<i64>();
+    assert_eq!(*x.get::<isize>().unwrap(), -1);
+    assert_eq!(*x.get::<usize>().unwrap(), 1);
+    assert_eq!(
+        x.get::<u8>().map(|_| ()),
+        Err(ErrorDesc::Unit(UnitError::IsNotOne))
+    );
+}
+
+#[test]
+fn unchecked() {
+    let mut x = DynamicStorage::new();
+    x.allocate_for::<String>();
+    x.insert(String::from("abc")).unwrap();
+    unsafe {
+        assert_eq!(&*x.get_unchecked::<String>().unwrap(), "abc");
+        assert_eq!(&*x.ind_unchecked::<String>(0).unwrap(), "abc");
+        let _lock = x.get_mut::<String>().unwrap();
+        assert_eq!(
+            x.get_unchecked::<String>().map(|_| ()),
+            Err(ErrorDesc::BorrowedIncompatibly)
+        );
+    }
+}
+
+#[test]
+fn unchecked_follows_policy() {
+    use restor::GetPolicy;
+    let mut x = DynamicStorage::with_policy(GetPolicy::FirstOnMany);
+    x.allocate_for::<usize>();
+    x.insert_many(vec![1usize, 2]).unwrap();
+    unsafe {
+        assert_eq!(*x.get_unchecked::<usize>().unwrap(), 1);
+    }
+    let mut x = DynamicStorage::new();
+    x.allocate_for::<usize>();
+    x.insert_many(vec![1usize, 2]).unwrap();
+    unsafe {
+        assert_eq!(
+            x.get_unchecked::<usize>().map(|_| ()),
+            Err(ErrorDesc::Unit(UnitError::IsNotOne))
+        );
+    }
+}
+
+#[test]
+fn named() {
+    let mut x = DynamicStorage::new();
+    x.allocate_for::<usize>();
+    x.allocate_named_for::<String>("config").unwrap();
+    assert_eq!(
+        x.allocate_named_for::<usize>("config"),
+        Err(ErrorDesc::NameCollision)
+    );
+    assert!(x.has_unit_named("config"));
+    assert!(x.has_unit_named(std::any::type_name::<String>()));
+    assert!(x.has_unit_named("usize"));
+    assert!(!x.has_unit_named("isize"));
+
+    x.insert_boxed_named("config", Box::new(String::from("abc")))
+        .unwrap();
+    x.insert_boxed_named("usize", Box::new(vec![1usize, 2]))
+        .unwrap();
+    let (rejected, err) = x
+        .insert_boxed_named("config", Box::new(0usize))
+        .unwrap_err();
+    assert_eq!(err, ErrorDesc::NoMatchingType);
+    assert_eq!(*rejected.downcast::<usize>().unwrap(), 0);
+    assert_eq!(
+        x.insert_boxed_named("isize", Box::new(0isize))
+            .unwrap_err()
+            .1,
+        ErrorDesc::NoAllocatedUnit
+    );
+
+    assert_eq!(&*x.get::<String>().unwrap(), "abc");
+    assert_eq!(*x.ind::<usize>(1).unwrap(), 2);
+    let value = x.extract_any_named("config").unwrap();
+    assert_eq!(*value.downcast::<String>().unwrap(), "abc");
+    assert!(x.get::<String>().is_err());
+}
+
+#[test]
+fn shared() {
+    use std::sync::Arc;
+    let mut x = DynamicStorage::new();
+    assert_eq!(
+        x.insert_shared(String::from("abc")),
+        Err((String::from("abc"), ErrorDesc::NoAllocatedUnit))
+    );
+    x.allocate_for::<Arc<String>>();
+    x.insert_shared(String::from("abc")).unwrap();
+    let first = x.get_shared::<String>().unwrap();
+    // No guard outlives the call
+    assert!(x.get_mut::<Arc<String>>().is_ok());
+    let second = x.get_shared::<String>().unwrap();
+    assert!(Arc::ptr_eq(&first, &second));
+    assert_eq!(&*first, "abc");
+    x.insert_shared(String::from("def")).unwrap();
+    assert_eq!(
+        x.get_shared::<String>(),
+        Err(ErrorDesc::Unit(UnitError::IsNotOne))
+    );
+}
+
+#[test]
+fn ind_multi() {
+    let mut x = DynamicStorage::new();
+    x.allocate_for::<usize>();
+    x.allocate_for::<String>();
+    assert_eq!(x.ind_multi::<u8>(&[0]), Err(ErrorDesc::NoAllocatedUnit));
+    x.insert(String::from("one")).unwrap();
+    assert_eq!(
+        x.ind_multi::<String>(&[0, 0]),
+        Ok(vec![String::from("one"), String::from("one")])
+    );
+    x.insert_many(vec![0usize, 10, 20, 30]).unwrap();
+    assert_eq!(x.ind_multi::<usize>(&[3, 0, 3, 1]), Ok(vec![30, 0, 30, 10]));
+    assert_eq!(x.ind_multi::<usize>(&[]), Ok(vec![]));
+    assert_eq!(
+        x.ind_multi::<usize>(&[1, 4, 2]),
+        Err(ErrorDesc::Unit(UnitError::OutOfBounds))
+    );
+    let mut called = false;
+    assert_eq!(
+        x.with_ind_multi::<usize, _, _>(&[2, 5], |_| called = true),
+        Err(ErrorDesc::Unit(UnitError::OutOfBounds))
+    );
+    assert!(!called);
+    assert_eq!(
+        x.with_ind_multi::<usize, _, _>(&[2, 1], |x| *x[0] - *x[1]),
+        Ok(10)
+    );
+    // The borrow is released afterwards
+    x.extract_many::<usize>().unwrap();
+}
+
+#[test]
+fn insert_iter() {
+    let mut x = DynamicStorage::new();
+    assert_eq!(
+        x.insert_iter((0..3usize).map(|x| x * 2)),
+        Err((vec![0, 2, 4], ErrorDesc::NoAllocatedUnit))
+    );
+    x.allocate_for::<usize>();
+    x.allocate_for::<String>();
+    x.insert_iter(std::iter::empty::<usize>()).unwrap();
+    assert_eq!(x.capacity::<usize>(), Ok(0));
+    x.insert_iter([1usize]).unwrap();
+    x.insert_iter(2..4usize).unwrap();
+    x.insert_iter(vec![4usize, 5].into_iter().map(|x| x * 10))
+        .unwrap();
+    assert_eq!(
+        x.ind_multi::<usize>(&[0, 1, 2, 3, 4]),
+        Ok(vec![1, 2, 3, 40, 50])
+    );
+    x.insert_iter(["a", "b"].iter().map(|s| s.to_string()))
+        .unwrap();
+    let _lock = x.ind_mut::<String>(0).unwrap();
+    assert_eq!(
+        x.insert_iter(["c"].iter().map(|s| s.to_string())),
+        Err((vec![String::from("c")], ErrorDesc::BorrowedIncompatibly))
+    );
+}
+
+#[test]
+fn extract_regardless_of_count() {
+    let mut x = DynamicStorage::new();
+    x.allocate_for::<usize>();
+    assert_eq!(x.extract_any_count::<usize>(), Ok(vec![]));
+    assert_eq!(
+        x.extract_one_loose::<usize>(),
+        Err(ErrorDesc::Unit(UnitError::IsNone))
+    );
+
+    x.insert(1usize).unwrap();
+    assert_eq!(x.extract_any_count::<usize>(), Ok(vec![1]));
+    x.insert(1usize).unwrap();
+    assert_eq!(x.extract_one_loose::<usize>(), Ok(1));
+    assert_eq!(x.extract_any_count::<usize>(), Ok(vec![]));
+
+    x.insert_many(vec![1usize, 2, 3]).unwrap();
+    assert_eq!(x.extract_one_loose::<usize>(), Ok(1));
+    assert_eq!(x.extract_any_count::<usize>(), Ok(vec![2, 3]));
+    assert_eq!(x.extract_any_count::<usize>(), Ok(vec![]));
+
+    assert_eq!(
+        x.extract_any_count::<isize>(),
+        Err(ErrorDesc::NoAllocatedUnit)
+    );
+}
+
+#[test]
+fn insert_indexed() {
+    let mut x = DynamicStorage::new();
+    assert_eq!(
+        x.insert_indexed(0usize),
+        Err((0usize, ErrorDesc::NoAllocatedUnit))
+    );
+    x.allocate_for::<usize>();
+    for value in 10..15usize {
+        let index = x.insert_indexed(value).unwrap();
+        assert_eq!(*x.ind_mut::<usize>(index).unwrap(), value);
+    }
+    x.extract_ind::<usize>(1).unwrap();
+    let index = x.insert_indexed(20usize).unwrap();
+    assert_eq!(index, 4);
+    assert_eq!(*x.ind_mut::<usize>(index).unwrap(), 20);
+}
+
+#[test]
+fn compose() {
+    let x = restor::make_storage!(DynamicStorage: usize);
+    let err = x.compose(|value: &usize| value + 1).unwrap_err();
+    assert_eq!(err.root(), &ErrorDesc::Unit(UnitError::IsNotOne));
+    assert_eq!(
+        err.to_string(),
+        "reading the value to compose: the unit does not contain exactly one value"
+    );
+    assert_eq!(
+        std::error::Error::source(&err).map(ToString::to_string),
+        Some(ErrorDesc::Unit(UnitError::IsNotOne).to_string())
+    );
+    x.insert(1usize).unwrap();
+    x.compose(|value: &usize| value + 1).unwrap();
+    assert_eq!(*x.get::<usize>().unwrap(), 2);
+
+    // Changing the value from within `f` is seen as contention
+    assert_eq!(
+        x.compose(|value: &usize| {
+            *x.get_mut::<usize>().unwrap() += 1;
+            *value
+        }),
+        Err(ErrorDesc::Contended)
+    );
+    assert_eq!(*x.get::<usize>().unwrap(), 2 + restor::COMPOSE_ATTEMPTS);
+}
+
+#[test]
+fn try_get_outcomes() {
+    let mut x = DynamicStorage::new();
+    // No unit allocated
+    assert!(x.try_get::<usize>().unwrap().is_none());
+    assert!(x.try_ind::<usize>(0).unwrap().is_none());
+
+    // No values
+    x.allocate_for::<usize>();
+    assert!(x.try_get::<usize>().unwrap().is_none());
+    assert!(x.try_get_mut::<usize>().unwrap().is_none());
+    assert!(x.try_ind::<usize>(0).unwrap().is_none());
+    assert!(x.try_ind::<usize>(1).unwrap().is_none());
+
+    // A single value
+    x.insert(1usize).unwrap();
+    assert_eq!(*x.try_get::<usize>().unwrap().unwrap(), 1);
+    *x.try_get_mut::<usize>().unwrap().unwrap() += 1;
+    assert_eq!(*x.try_ind::<usize>(0).unwrap().unwrap(), 2);
+    assert!(x.try_ind::<usize>(1).unwrap().is_none());
+
+    // A list, even of one value
+    x.insert(3usize).unwrap();
+    x.extract::<usize>().unwrap();
+    let not_one = Some(ErrorDesc::Unit(UnitError::IsNotOne));
+    assert_eq!(x.try_get::<usize>().err(), not_one);
+    assert_eq!(x.try_get_mut::<usize>().err(), not_one);
+    assert_eq!(*x.try_ind::<usize>(0).unwrap().unwrap(), 3);
+    assert!(x.try_ind::<usize>(1).unwrap().is_none());
+    x.extract::<usize>().unwrap();
+    x.insert(4usize).unwrap();
+
+    // Borrowed incompatibly
+    {
+        let _lock = x.get_mut::<usize>().unwrap();
+        let borrowed = Some(ErrorDesc::BorrowedIncompatibly);
+        assert_eq!(x.try_get::<usize>().err(), borrowed);
+        assert_eq!(x.try_get_mut::<usize>().err(), borrowed);
+        assert_eq!(x.try_ind::<usize>(0).err(), borrowed);
+        assert_eq!(x.try_ind::<usize>(1).err(), borrowed);
+    }
+}
+
+#[test]
+fn cloned_iter_chunked_with_changes() {
+    let x = restor::make_storage!(DynamicStorage: usize);
+    assert_eq!(x.cloned_iter::<usize>().unwrap().count(), 0);
+    assert!(x.cloned_iter_chunked::<usize>(3).unwrap().next().is_none());
+    assert_eq!(
+        x.cloned_iter_chunked::<isize>(3).err(),
+        Some(ErrorDesc::NoAllocatedUnit)
+    );
+
+    x.insert(0usize).unwrap();
+    assert_eq!(
+        x.cloned_iter::<usize>().unwrap().collect::<Vec<_>>(),
+        vec![0]
+    );
+    x.insert_many((1..10usize).collect()).unwrap();
+
+    let mut chunks = x.cloned_iter_chunked::<usize>(3).unwrap();
+    assert_eq!(chunks.next(), Some(Ok(vec![0, 1, 2])));
+    // Removing a value that was already seen makes the next one be skipped
+    x.extract_ind::<usize>(0).unwrap();
+    assert_eq!(chunks.next(), Some(Ok(vec![4, 5, 6])));
+    // Inserting before the position makes a value be seen twice
+    x.apply(|unit: &mut restor::StorageUnit<usize>| unit.many_mut().unwrap().insert(0, 100))
+        .unwrap();
+    assert_eq!(chunks.next(), Some(Ok(vec![6, 7, 8])));
+    // A borrowed unit ends the iteration with an error
+    let lock = x.ind_mut::<usize>(0).unwrap();
+    assert_eq!(chunks.next(), Some(Err(ErrorDesc::BorrowedIncompatibly)));
+    assert_eq!(chunks.next(), None);
+    drop(lock);
+
+    // Removing the remaining values ends the iteration early
+    let mut chunks = x.cloned_iter_chunked::<usize>(4).unwrap();
+    assert_eq!(chunks.next(), Some(Ok(vec![100, 1, 2, 3])));
+    x.extract_many::<usize>().unwrap();
+    assert_eq!(chunks.next(), None);
+}
+
+#[test]
+fn retain_types() {
+    use std::any::TypeId;
+
+    let mut x = restor::make_storage!(DynamicStorage: usize, isize, String, u8);
+    x.allocate_named_for::<u8>("byte").unwrap();
+    x.insert_many(vec![1usize, 2]).unwrap();
+    x.insert(-1isize).unwrap();
+    x.insert(String::from("abc")).unwrap();
+    x.insert(3u8).unwrap();
+
+    let mut removed = x.retain_types(|id| id != TypeId::of::<String>() && id != TypeId::of::<u8>());
+    removed.sort();
+    let mut expected = vec![TypeId::of::<String>(), TypeId::of::<u8>()];
+    expected.sort();
+    assert_eq!(removed, expected);
+    assert_eq!(x.unit_count(), 2);
+    assert_eq!(x.get::<String>().err(), Some(ErrorDesc::NoAllocatedUnit));
+    assert_eq!(x.extract::<u8>().err(), Some(ErrorDesc::NoAllocatedUnit));
+    assert!(!x.has_unit_named("byte"));
+    assert_eq!(x.ind_multi::<usize>(&[0, 1]), Ok(vec![1, 2]));
+    assert_eq!(*x.get::<isize>().unwrap(), -1);
+
+    assert_eq!(
+        x.retain_types_named(|name| name != "isize"),
+        vec![TypeId::of::<isize>()]
+    );
+    assert_eq!(x.get::<isize>().err(), Some(ErrorDesc::NoAllocatedUnit));
+
+    x.remove_types(&[TypeId::of::<usize>(), TypeId::of::<String>()]);
+    assert!(x.is_unit_map_empty());
+    assert_eq!(x.get::<usize>().err(), Some(ErrorDesc::NoAllocatedUnit));
+
+    // A removed type can be allocated again, starting out empty
+    x.allocate_for::<usize>();
+    assert_eq!(x.extract_any_count::<usize>(), Ok(vec![]));
+}
+
+#[test]
+fn ind_from_end() {
+    let x = restor::make_storage!(DynamicStorage: usize);
+    let out_of_bounds = Err(ErrorDesc::Unit(UnitError::OutOfBounds));
+    assert_eq!(x.ind_from_end::<usize>(0).map(|x| *x), out_of_bounds);
+    assert_eq!(x.ind_from_end_mut::<usize>(0).map(|x| *x), out_of_bounds);
+    x.insert(1usize).unwrap();
+    assert_eq!(x.ind_from_end::<usize>(0).map(|x| *x), Ok(1));
+    assert_eq!(x.ind_from_end::<usize>(1).map(|x| *x), out_of_bounds);
+    x.insert_many(vec![2usize, 3]).unwrap();
+    assert_eq!(x.ind_from_end::<usize>(0).map(|x| *x), Ok(3));
+    assert_eq!(x.ind_from_end::<usize>(2).map(|x| *x), Ok(1));
+    assert_eq!(x.ind_from_end::<usize>(3).map(|x| *x), out_of_bounds);
+    assert_eq!(
+        x.ind_from_end::<usize>(usize::MAX).map(|x| *x),
+        out_of_bounds
+    );
+    *x.ind_from_end_mut::<usize>(1).unwrap() = 20;
+    assert_eq!(x.ind::<usize>(1).map(|x| *x), Ok(20));
+    assert_eq!(x.ind_from_end_mut::<usize>(3).map(|x| *x), out_of_bounds);
+}
+
+#[test]
+fn try_for_each_mut_stops_early() {
+    use restor::TryError;
+
+    let x = restor::make_storage!(DynamicStorage: usize);
+    x.insert_many(vec![1usize, 2, 3, 4]).unwrap();
+    let mut seen = Vec::new();
+    let result = x.try_for_each_mut(|value: &mut usize| {
+        seen.push(*value);
+        if *value == 2 {
+            return Err("two");
+        }
+        *value *= 10;
+        Ok(())
+    });
+    assert_eq!(result, Err(TryError::User("two")));
+    assert_eq!(seen, vec![1, 2]);
+    // The unit was released, and only the values before the error were changed
+    assert_eq!(
+        x.run_for::<usize, Vec<usize>, _>(|x| Some(x.unwrap().to_vec())),
+        Some(vec![10, 2, 3, 4])
+    );
+    assert!(x.get_mut::<usize>().is_err());
+    assert!(x.ind_mut::<usize>(0).is_ok());
+
+    let mut count = 0;
+    assert_eq!(
+        x.try_for_each(|_: &usize| {
+            count += 1;
+            Ok::<_, ()>(())
+        }),
+        Ok(())
+    );
+    assert_eq!(count, 4);
+
+    // Storage errors are kept apart from those of the closure
+    {
+        let _lock = x.ind::<usize>(0).unwrap();
+        let result = x.try_for_each_mut(|_: &mut usize| Err("unreachable"));
+        assert_eq!(
+            result,
+            Err(TryError::Storage(ErrorDesc::BorrowedIncompatibly))
+        );
+    }
+    assert_eq!(
+        x.try_with(|_: &usize| Err::<(), _>("unreachable")),
+        Err(TryError::Storage(ErrorDesc::Unit(UnitError::IsNotOne)))
+    );
+    assert_eq!(
+        x.try_with(|_: &String| Ok::<_, ()>(())),
+        Err(TryError::Storage(ErrorDesc::NoAllocatedUnit))
+    );
+}
+
+#[test]
+fn get_policy() {
+    use restor::GetPolicy;
+
+    let not_one = Err(ErrorDesc::Unit(UnitError::IsNotOne));
+    for policy in [GetPolicy::Strict, GetPolicy::FirstOnMany] {
+        let mut x = DynamicStorage::with_policy(policy);
+        x.allocate_for::<usize>();
+        assert_eq!(x.policy(), policy);
+        // Neither policy changes how a single value or no values are handled
+        assert_eq!(x.get::<usize>().map(|x| *x), not_one);
+        assert_eq!(x.get_mut::<usize>().map(|x| *x), not_one);
+        x.insert(1usize).unwrap();
+        assert_eq!(x.get::<usize>().map(|x| *x), Ok(1));
+        x.insert(2usize).unwrap();
+        match policy {
+            GetPolicy::Strict => {
+                assert_eq!(x.get::<usize>().map(|x| *x), not_one);
+                assert_eq!(x.get_mut::<usize>().map(|x| *x), not_one);
+                assert!(x.try_get::<usize>().is_err());
+            }
+            GetPolicy::FirstOnMany => {
+                *x.get_mut::<usize>().unwrap() += 10;
+                assert_eq!(x.get::<usize>().map(|x| *x), Ok(11));
+                assert_eq!(x.try_get::<usize>().unwrap().map(|x| *x), Some(11));
+                assert_eq!(x.ind::<usize>(1).map(|x| *x), Ok(2));
+            }
+        }
+        // The policy can be overridden per call
+        let other = match policy {
+            GetPolicy::Strict => GetPolicy::FirstOnMany,
+            GetPolicy::FirstOnMany => GetPolicy::Strict,
+        };
+        let expected = match other {
+            GetPolicy::Strict => not_one.clone(),
+            GetPolicy::FirstOnMany => Ok(1),
+        };
+        assert_eq!(x.get_with_policy::<usize>(other).map(|x| *x), expected);
+        assert_eq!(x.get_mut_with_policy::<usize>(other).map(|x| *x), expected);
+        x.extract_many::<usize>().unwrap();
+        assert_eq!(
+            x.get_with_policy::<usize>(GetPolicy::FirstOnMany)
+                .map(|x| *x),
+            not_one
+        );
+    }
+}
+
+#[test]
+fn extract_many_into() {
+    let x = restor::make_storage!(DynamicStorage: usize);
+    let mut buf = vec![0usize];
+    assert_eq!(x.extract_many_into(&mut buf), Ok(0));
+    x.insert(1usize).unwrap();
+    assert_eq!(x.extract_many_into(&mut buf), Ok(1));
+    x.insert_many(vec![2usize, 3, 4]).unwrap();
+    assert_eq!(x.extract_many_into(&mut buf), Ok(3));
+    assert_eq!(buf, vec![0, 1, 2, 3, 4]);
+    // The unit is left with no values
+    assert_eq!(x.extract_many_into(&mut buf), Ok(0));
+    assert_eq!(buf.len(), 5);
+    assert_eq!(x.extract_any_count::<usize>(), Ok(vec![]));
+    assert_eq!(
+        x.extract_many_into(&mut Vec::<String>::new()),
+        Err(ErrorDesc::NoAllocatedUnit)
+    );
+}
+
+#[test]
+fn units_keep_allocation_order() {
+    use std::any::TypeId;
+
+    let mut x = restor::make_storage!(DynamicStorage: u8, String, usize, isize);
+    assert_eq!(
+        x.retain_types(|id| id == TypeId::of::<isize>()),
+        vec![
+            TypeId::of::<u8>(),
+            TypeId::of::<String>(),
+            TypeId::of::<usize>(),
+        ]
+    );
+
+    // Removing a unit keeps the others in order, and a reallocated unit goes last
+    x.allocate_for::<u8>();
+    x.allocate_for::<String>();
+    x.allocate_for::<usize>();
+    x.remove_types(&[TypeId::of::<u8>()]);
+    x.allocate_for::<u8>();
+    assert_eq!(
+        x.retain_types_named(|_| false),
+        vec![
+            TypeId::of::<isize>(),
+            TypeId::of::<String>(),
+            TypeId::of::<usize>(),
+            TypeId::of::<u8>(),
+        ]
+    );
+}
+
+#[test]
+fn values_keep_insertion_order() {
+    let mut x = DynamicStorage::new();
+    x.allocate_for::<usize>();
+    x.insert(1usize).unwrap();
+    x.insert(2usize).unwrap();
+    x.insert_many(vec![3usize, 4, 5]).unwrap();
+    x.insert(6usize).unwrap();
+    assert_eq!(
+        x.ind_multi::<usize>(&[0, 1, 2, 3, 4, 5]),
+        Ok(vec![1, 2, 3, 4, 5, 6])
+    );
+
+    assert_eq!(x.extract_ind::<usize>(1), Ok(2));
+    assert_eq!(x.extract_ind::<usize>(3), Ok(5));
+    let mut buf = Vec::<usize>::new();
+    assert_eq!(x.extract_many_into(&mut buf), Ok(4));
+    assert_eq!(buf, vec![1, 3, 4, 6]);
+
+    x.insert(7usize).unwrap();
+    x.insert(8usize).unwrap();
+    assert_eq!(x.swap_backing_vec(vec![9usize, 10]), Ok(vec![7, 8]));
+    x.insert(11usize).unwrap();
+    assert_eq!(
+        x.extract_many::<usize>().map(Vec::from),
+        Ok(vec![9, 10, 11])
+    );
+}
+
+#[test]
+fn allocated_buffer_is_kept() {
+    let mut x = DynamicStorage::new();
+    x.allocate_for_with_vec::<usize>(Vec::with_capacity(16));
+    assert_eq!(x.state::<usize>(), Ok(UnitState::Empty));
+    assert_eq!(x.capacity::<usize>(), Ok(16));
+
+    // A single value is still `Single`
+    x.insert(1usize).unwrap();
+    assert_eq!(x.state::<usize>(), Ok(UnitState::Single));
+    assert_eq!(*x.get::<usize>().unwrap(), 1);
+    assert_eq!(x.capacity::<usize>(), Ok(16));
+
+    // And the buffer is kept through `Many` and back
+    x.insert(2usize).unwrap();
+    assert_eq!(x.state::<usize>(), Ok(UnitState::Multiple(2)));
+    assert_eq!(x.capacity::<usize>(), Ok(16));
+    assert_eq!(x.extract_ind::<usize>(1), Ok(2));
+    assert_eq!(x.state::<usize>(), Ok(UnitState::Multiple(1)));
+    assert_eq!(x.extract_ind::<usize>(0), Ok(1));
+    assert_eq!(x.state::<usize>(), Ok(UnitState::Empty));
+    assert_eq!(x.capacity::<usize>(), Ok(16));
+
+    x.insert_many(vec![3usize]).unwrap();
+    assert_eq!(x.state::<usize>(), Ok(UnitState::Multiple(1)));
+    let mut buf = Vec::<usize>::new();
+    assert_eq!(x.extract_many_into(&mut buf), Ok(1));
+    assert_eq!(x.capacity::<usize>(), Ok(16));
+
+    x.insert(4usize).unwrap();
+    x.shrink_to_fit::<usize>().unwrap();
+    assert_eq!(x.capacity::<usize>(), Ok(1));
+    assert_eq!(*x.get::<usize>().unwrap(), 4);
+}
+
+#[test]
+fn swap_backing_vec() {
+    let mut x = DynamicStorage::new();
+    x.allocate_for_with_vec::<usize>(Vec::with_capacity(32));
+    assert_eq!(x.capacity::<usize>(), Ok(32));
+    let mut spare = Vec::<usize>::with_capacity(32);
+    for frame in 0..4usize {
+        x.insert(frame).unwrap();
+        x.insert(frame + 1).unwrap();
+        let mut values = x.swap_backing_vec(spare).unwrap();
+        assert_eq!(values, vec![frame, frame + 1]);
+        assert_eq!(values.capacity(), 32);
+        values.clear();
+        spare = values;
+    }
+    // A single value in the new buffer is read as one
+    x.swap_backing_vec(vec![7usize]).unwrap();
+    assert_eq!(x.extract::<usize>(), Ok(7));
+    assert_eq!(x.capacity::<usize>(), Ok(1));
+    assert_eq!(
+        x.swap_backing_vec(Vec::<String>::new()),
+        Err(ErrorDesc::NoAllocatedUnit)
+    );
+}
+
+#[test]
+fn corrupted_unit_map() {
+    use restor::backend::{RefCellUnit, StorageUnit};
+    use restor::IntegrityError;
+    use std::any::TypeId;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    let mut x = DynamicStorage::new();
+    x.allocate_for::<String>();
+    x.insert_unit_under(
+        TypeId::of::<usize>(),
+        Box::new(RefCellUnit::new(StorageUnit::<String>::new())),
+    );
+    assert_eq!(
+        x.validate(),
+        Err(vec![IntegrityError {
+            key: TypeId::of::<usize>(),
+            found: TypeId::of::<String>(),
+            type_name: std::any::type_name::<String>(),
+        }])
+    );
+
+    // The accessors fail a debug assertion rather than reading the unit as `usize`s
+    let result = catch_unwind(AssertUnwindSafe(|| x.insert(1usize).map_err(|(_, e)| e)));
+    if cfg!(debug_assertions) {
+        assert!(result.is_err());
+    } else {
+        assert_eq!(result.unwrap(), Err(ErrorDesc::NoMatchingType));
+    }
+    let result = catch_unwind(AssertUnwindSafe(|| x.get::<usize>().map(|x| *x)));
+    if cfg!(debug_assertions) {
+        assert!(result.is_err());
+    } else {
+        assert_eq!(result.unwrap(), Err(ErrorDesc::NoMatchingType));
+    }
+
+    // The other units are unaffected
+    x.insert(String::from("abc")).unwrap();
+    assert_eq!(&*x.get::<String>().unwrap(), "abc");
+    x.insert_unit_under(
+        TypeId::of::<usize>(),
+        Box::new(RefCellUnit::new(StorageUnit::<usize>::new())),
+    );
+    assert_eq!(x.validate(), Ok(()));
+}
+
+#[test]
+fn fork_reads_values_at_fork_time() {
+    let mut x = DynamicStorage::new();
+    x.allocate_clonable_for::<usize>();
+    x.allocate_clonable_for::<String>();
+    x.insert_many(vec![1usize, 2, 3]).unwrap();
+    x.insert(String::from("abc")).unwrap();
+    let fork = x.fork().unwrap();
+
+    // Changes to the parent after forking aren't seen by the fork
+    *x.ind_mut::<usize>(0).unwrap() = 10;
+    x.get_mut::<String>().unwrap().push_str("def");
+    x.insert(4usize).unwrap();
+    assert_eq!(
+        fork.run_for::<usize, _, _>(|x| x.ok().map(<[usize]>::to_vec)),
+        Some(vec![1, 2, 3])
+    );
+    assert_eq!(&*fork.get::<String>().unwrap(), "abc");
+    assert!(fork.has_unit_named("usize"));
+}
+
+#[test]
+fn fork_writes_are_isolated() {
+    let mut x = DynamicStorage::new();
+    x.allocate_clonable_for::<usize>();
+    x.insert_many(vec![1usize, 2, 3]).unwrap();
+    let fork = x.fork().unwrap();
+    let nested = fork.fork().unwrap();
+
+    *fork.ind_mut::<usize>(1).unwrap() = 20;
+    assert_eq!(fork.extract_ind::<usize>(0), Ok(1));
+    fork.insert(4usize).unwrap();
+    assert_eq!(
+        fork.run_for::<usize, _, _>(|x| x.ok().map(<[usize]>::to_vec)),
+        Some(vec![20, 3, 4])
+    );
+
+    // Neither the parent nor the fork of the fork are affected
+    assert_eq!(
+        x.run_for::<usize, _, _>(|x| x.ok().map(<[usize]>::to_vec)),
+        Some(vec![1, 2, 3])
+    );
+    assert_eq!(
+        nested.run_for::<usize, _, _>(|x| x.ok().map(<[usize]>::to_vec)),
+        Some(vec![1, 2, 3])
+    );
+    assert_eq!(x.extract_many::<usize>().map(Vec::from), Ok(vec![1, 2, 3]));
+    assert_eq!(nested.state::<usize>(), Ok(restor::UnitState::Multiple(3)));
+}
+
+#[test]
+fn fork_requires_clonable_units() {
+    let mut x = DynamicStorage::new();
+    x.allocate_clonable_for::<usize>();
+    x.insert(1usize).unwrap();
+    {
+        let _guard = x.get_mut::<usize>().unwrap();
+        assert_eq!(x.fork().err(), Some(ErrorDesc::BorrowedIncompatibly));
+    }
+    assert!(x.fork().is_ok());
+    x.allocate_for::<String>();
+    assert_eq!(x.fork().err(), Some(ErrorDesc::UnsupportedOperation));
+}
+
+#[test]
+fn get_disjoint_ind_mut() {
+    let x = restor::make_storage!(DynamicStorage: String);
+    x.insert_many(vec!["a".to_string(), "b".to_string(), "c".to_string()])
+        .unwrap();
+    {
+        let [mut first, mut last] = x.get_disjoint_ind_mut::<String, 2>([0, 2]).unwrap();
+        std::mem::swap(&mut *first, &mut *last);
+        drop(first);
+        // The unit stays borrowed until every guard is dropped
+        assert_eq!(
+            x.ind_mut::<String>(1).err(),
+            Some(ErrorDesc::BorrowedIncompatibly)
+        );
+        last.push('!');
+    }
+    assert_eq!(
+        x.extract_many::<String>().map(Vec::from),
+        Ok(vec!["c".to_string(), "b".to_string(), "a!".to_string()])
+    );
+
+    x.insert(String::from("only")).unwrap();
+    let [mut only] = x.get_disjoint_ind_mut::<String, 1>([0]).unwrap();
+    only.push('!');
+    drop(only);
+    assert_eq!(x.extract::<String>(), Ok(String::from("only!")));
+}
+
+#[test]
+fn get_disjoint_ind_mut_errors() {
+    let x = restor::make_storage!(DynamicStorage: usize);
+    x.insert_many(vec![1usize, 2, 3]).unwrap();
+    assert_eq!(
+        x.get_disjoint_ind_mut::<usize, 3>([0, 2, 0]).err(),
+        Some(ErrorDesc::Unit(UnitError::DuplicateIndex))
+    );
+    assert_eq!(
+        x.get_disjoint_ind_mut::<usize, 2>([1, 3]).err(),
+        Some(ErrorDesc::Unit(UnitError::OutOfBounds))
+    );
+    assert_eq!(
+        x.get_disjoint_ind_mut::<String, 1>([0]).err(),
+        Some(ErrorDesc::NoAllocatedUnit)
+    );
+    {
+        let _guard = x.ind_mut::<usize>(0).unwrap();
+        assert_eq!(
+            x.get_disjoint_ind_mut::<usize, 2>([1, 2]).err(),
+            Some(ErrorDesc::BorrowedIncompatibly)
+        );
+    }
+    // A failed call leaves the unit unborrowed
+    assert_eq!(x.ind_multi::<usize>(&[0, 1, 2]), Ok(vec![1, 2, 3]));
+}
+
+#[test]
+fn poll_get() {
+    use restor::PollResult;
+
+    let x = restor::make_storage!(DynamicStorage: usize);
+    x.insert(1usize).unwrap();
+    let lock = x.get_mut::<usize>().unwrap();
+    let token = match x.poll_get::<usize>() {
+        PollResult::Pending(token) => token,
+        other => panic!("Expected a token, got {:?}", other),
+    };
+    assert!(!token.is_ready());
+    drop(lock);
+    assert!(token.is_ready());
+    assert_eq!(x.poll_get::<usize>().ready().map(|x| *x), Some(1));
+
+    let read = x.get::<usize>().unwrap();
+    assert!(x.poll_get::<usize>().is_ready());
+    let token = match x.poll_get_mut::<usize>() {
+        PollResult::Pending(token) => token,
+        other => panic!("Expected a token, got {:?}", other),
+    };
+    assert!(!token.is_ready());
+    drop(read);
+    assert!(token.is_ready());
+    *x.poll_get_mut::<usize>().ready().unwrap() += 1;
+    assert_eq!(x.extract::<usize>(), Ok(2));
+
+    assert!(matches!(
+        x.poll_get::<usize>(),
+        PollResult::Err(ErrorDesc::Unit(UnitError::IsNotOne))
+    ));
+    assert!(matches!(
+        x.poll_get_mut::<String>(),
+        PollResult::Err(ErrorDesc::NoAllocatedUnit)
+    ));
+}
+
+#[test]
+fn warm_capacity() {
+    let x = restor::make_storage!(DynamicStorage: u64);
+    x.warm_capacity::<u64>(1 << 20).unwrap();
+    let capacity = x.capacity::<u64>().unwrap();
+    assert!(capacity >= 1 << 20);
+    x.insert_iter(0..1u64 << 20).unwrap();
+    assert_eq!(x.capacity::<u64>(), Ok(capacity));
+    x.prefault::<u64>().unwrap();
+    assert_eq!(
+        x.run_for::<u64, bool, _>(|x| Some(x.unwrap().iter().copied().eq(0..1 << 20))),
+        Some(true)
+    );
+    assert_eq!(x.prefault::<String>(), Err(ErrorDesc::NoAllocatedUnit));
+}
+
+#[test]
+fn get_or_recover() {
+    use std::cell::Cell;
+
+    let x = restor::make_storage!(DynamicStorage: usize);
+    let calls = Cell::new(0);
+    let recover = |value: Option<usize>| {
+        let calls = &calls;
+        move |e: ErrorDesc| {
+            calls.set(calls.get() + 1);
+            assert_eq!(e, ErrorDesc::Unit(UnitError::IsNotOne));
+            value
+        }
+    };
+
+    // Declining to recover keeps the unit empty
+    assert_eq!(
+        x.get_or_recover::<usize, _>(recover(None)).err(),
+        Some(ErrorDesc::Unit(UnitError::IsNotOne))
+    );
+    assert_eq!(x.state::<usize>(), Ok(restor::UnitState::Empty));
+
+    assert_eq!(*x.get_or_recover::<usize, _>(recover(Some(1))).unwrap(), 1);
+    assert_eq!(*x.get_or_recover::<usize, _>(recover(Some(2))).unwrap(), 1);
+    assert_eq!(calls.get(), 2);
+
+    // Borrow conflicts and lists are returned as they are
+    {
+        let _lock = x.get_mut::<usize>().unwrap();
+        assert_eq!(
+            x.get_or_recover::<usize, _>(recover(Some(3))).err(),
+            Some(ErrorDesc::BorrowedIncompatibly)
+        );
+    }
+    x.insert(4usize).unwrap();
+    assert_eq!(
+        x.get_or_recover::<usize, _>(recover(Some(5))).err(),
+        Some(ErrorDesc::Unit(UnitError::IsNotOne))
+    );
+    assert_eq!(calls.get(), 2);
+    assert_eq!(x.extract_many::<usize>().map(Vec::from), Ok(vec![1, 4]));
+
+    // A missing unit is reported to `recover`, but can't be allocated here
+    let mut reported = None;
+    assert_eq!(
+        x.get_or_recover::<String, _>(|e| {
+            reported = Some(e);
+            Some(String::new())
+        })
+        .err(),
+        Some(ErrorDesc::NoAllocatedUnit)
+    );
+    assert_eq!(reported, Some(ErrorDesc::NoAllocatedUnit));
+
+    // The steps after recovering report which of them failed
+    let mut held = None;
+    let err = x
+        .get_or_recover::<usize, _>(|_| {
+            held = Some(x.insert_and_get_mut(6usize).unwrap());
+            Some(7)
+        })
+        .err()
+        .unwrap();
+    assert_eq!(
+        err,
+        ErrorDesc::BorrowedIncompatibly.context("inserting the recovered value")
+    );
+    assert_eq!(err.root(), &ErrorDesc::BorrowedIncompatibly);
+    drop(held);
+    x.extract::<usize>().unwrap();
+    let err = x
+        .get_or_recover::<usize, _>(|_| {
+            x.insert_many(vec![8usize, 9]).unwrap();
+            Some(10)
+        })
+        .err()
+        .unwrap();
+    assert_eq!(
+        err.to_string(),
+        "borrowing the recovered value: the unit does not contain exactly one value"
+    );
+    assert_eq!(err.root(), &ErrorDesc::Unit(UnitError::IsNotOne));
+    assert_eq!(x.extract_many::<usize>().map(Vec::from), Ok(vec![8, 9]));
+}
+
+#[test]
+fn take_dirty() {
+    let mut x = DynamicStorage::new();
+    x.allocate_tracked_for::<usize>();
+    x.allocate_for::<String>();
+    assert_eq!(x.take_dirty::<usize>(), Ok(vec![]));
+
+    // Inserting marks the new values
+    x.insert(0usize).unwrap();
+    assert_eq!(x.take_dirty::<usize>(), Ok(vec![0]));
+    *x.get_mut::<usize>().unwrap() += 1;
+    assert_eq!(x.take_dirty::<usize>(), Ok(vec![0]));
+    x.insert_many((1..100).collect::<Vec<usize>>()).unwrap();
+    assert_eq!(x.take_dirty::<usize>(), Ok((1..100).collect()));
+
+    // Only values borrowed mutably are marked
+    let _ = x.ind::<usize>(3).unwrap();
+    drop(x.ind_mut::<usize>(5).unwrap());
+    drop(x.ind_mut::<usize>(70).unwrap());
+    drop(x.ind_from_end_mut::<usize>(0).unwrap());
+    assert!(x.ind_mut::<usize>(100).is_err());
+    assert_eq!(x.take_dirty::<usize>(), Ok(vec![5, 70, 99]));
+
+    // Removing a value moves the marks after it, across words too
+    drop(x.ind_mut::<usize>(10).unwrap());
+    drop(x.ind_mut::<usize>(64).unwrap());
+    drop(x.ind_mut::<usize>(65).unwrap());
+    x.extract_ind::<usize>(10).unwrap();
+    assert_eq!(x.take_dirty::<usize>(), Ok(vec![63, 64]));
+    drop(x.ind_mut::<usize>(0).unwrap());
+    drop(x.ind_mut::<usize>(1).unwrap());
+    assert_eq!(x.extract::<usize>(), Ok(1));
+    assert_eq!(x.take_dirty::<usize>(), Ok(vec![0]));
+    assert!(x.extract_ind::<usize>(1000).is_err());
+    assert_eq!(x.take_dirty::<usize>(), Ok(vec![]));
+
+    // Borrowing the whole unit marks every value
+    x.apply::<usize, _, _>(|_| ()).unwrap();
+    drop(x.get_disjoint_ind_mut::<usize, 2>([0, 1]).unwrap());
+    assert_eq!(x.take_dirty::<usize>(), Ok((0..98).collect()));
+
+    // Not while a value could still be changed
+    {
+        let _lock = x.ind_mut::<usize>(4).unwrap();
+        assert_eq!(
+            x.take_dirty::<usize>(),
+            Err(ErrorDesc::BorrowedIncompatibly)
+        );
+    }
+    assert_eq!(x.take_dirty::<usize>(), Ok(vec![4]));
+
+    drop(x.ind_mut::<usize>(4).unwrap());
+    x.extract_many::<usize>().unwrap();
+    assert_eq!(x.take_dirty::<usize>(), Ok(vec![]));
+
+    // Other units don't keep track of this
+    x.insert(String::new()).unwrap();
+    assert_eq!(
+        x.take_dirty::<String>(),
+        Err(ErrorDesc::UnsupportedOperation)
+    );
+    assert_eq!(x.take_dirty::<u8>(), Err(ErrorDesc::NoAllocatedUnit));
+}
+
+#[test]
+fn insert_bounded() {
+    use restor::OverflowPolicy;
+    let x = restor::make_storage!(DynamicStorage: usize);
+    let full = |v| Err((v, ErrorDesc::Unit(UnitError::Full)));
+
+    // Up to the cap, every policy inserts
+    for (i, policy) in [
+        OverflowPolicy::Reject,
+        OverflowPolicy::DropOldest,
+        OverflowPolicy::DropNewest,
+    ]
+    .iter()
+    .enumerate()
+    {
+        assert_eq!(x.insert_bounded(i, 3, *policy), Ok(None));
+    }
+
+    // At len == cap, none of them do
+    assert_eq!(x.insert_bounded(3usize, 3, OverflowPolicy::Reject), full(3));
+    assert_eq!(
+        x.insert_bounded(4usize, 3, OverflowPolicy::DropNewest),
+        Ok(Some(4))
+    );
+    assert_eq!(
+        x.insert_bounded(5usize, 3, OverflowPolicy::DropOldest),
+        Ok(Some(0))
+    );
+    assert_eq!(x.extract_any_count::<usize>(), Ok(vec![1, 2, 5]));
+
+    // A cap of zero or one
+    assert_eq!(x.insert_bounded(0usize, 0, OverflowPolicy::Reject), full(0));
+    assert_eq!(
+        x.insert_bounded(0usize, 0, OverflowPolicy::DropOldest),
+        Ok(Some(0))
+    );
+    assert_eq!(
+        x.insert_bounded(1usize, 1, OverflowPolicy::DropOldest),
+        Ok(None)
+    );
+    assert_eq!(
+        x.insert_bounded(2usize, 1, OverflowPolicy::DropOldest),
+        Ok(Some(1))
+    );
+    assert_eq!(*x.get::<usize>().unwrap(), 2);
+
+    // A unit filled past the cap by other means is brought back down to it
+    x.insert_many(vec![3usize, 4, 5]).unwrap();
+    assert_eq!(
+        x.insert_bounded(6usize, 2, OverflowPolicy::DropOldest),
+        Ok(Some(2))
+    );
+    assert_eq!(x.extract_any_count::<usize>(), Ok(vec![5, 6]));
+
+    // Rejected while borrowed
+    x.insert(0usize).unwrap();
+    let _lock = x.get::<usize>().unwrap();
+    assert_eq!(
+        x.insert_bounded(1usize, 3, OverflowPolicy::DropOldest),
+        Err((1, ErrorDesc::BorrowedIncompatibly))
+    );
+}
+
+#[test]
+fn insert_many_bounded() {
+    use restor::OverflowPolicy;
+    let x = restor::make_storage!(DynamicStorage: usize);
+
+    // Exactly filling the unit leaves nothing out
+    assert_eq!(
+        x.insert_many_bounded(vec![0usize, 1], 3, OverflowPolicy::Reject),
+        Ok(vec![])
+    );
+    assert_eq!(
+        x.insert_many_bounded(vec![2usize], 3, OverflowPolicy::Reject),
+        Ok(vec![])
+    );
+    assert_eq!(
+        x.insert_many_bounded(vec![3usize], 3, OverflowPolicy::Reject),
+        Err((vec![3], ErrorDesc::Unit(UnitError::Full)))
+    );
+    assert_eq!(
+        x.insert_many_bounded(Vec::<usize>::new(), 3, OverflowPolicy::Reject),
+        Ok(vec![])
+    );
+
+    // Reject is all or nothing
+    x.extract::<usize>().unwrap();
+    assert_eq!(
+        x.insert_many_bounded(vec![3usize, 4], 3, OverflowPolicy::Reject),
+        Err((vec![3, 4], ErrorDesc::Unit(UnitError::Full)))
+    );
+    assert_eq!(x.state::<usize>(), Ok(restor::UnitState::Multiple(2)));
+
+    // DropNewest keeps the first of the new values which fit
+    assert_eq!(
+        x.insert_many_bounded(vec![3usize, 4], 3, OverflowPolicy::DropNewest),
+        Ok(vec![4])
+    );
+
+    // DropOldest drops the existing values first, then the oldest new ones
+    assert_eq!(
+        x.insert_many_bounded(vec![5usize, 6], 3, OverflowPolicy::DropOldest),
+        Ok(vec![1, 2])
+    );
+    assert_eq!(
+        x.insert_many_bounded(vec![7usize, 8, 9, 10, 11], 3, OverflowPolicy::DropOldest),
+        Ok(vec![3, 5, 6, 7, 8])
+    );
+    assert_eq!(x.extract_any_count::<usize>(), Ok(vec![9, 10, 11]));
+}
+
+#[test]
+fn on_remove_runs_once_per_value() {
+    use restor::OverflowPolicy;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Debug, PartialEq)]
+    struct Res(u32);
+    #[derive(Debug)]
+    struct Device(&'static str);
+
+    let finalized = Arc::new(Mutex::new(Vec::new()));
+    let mut x = DynamicStorage::new();
+    x.allocate_for::<Device>();
+    x.allocate_for::<Res>();
+    x.insert(Device("gpu")).unwrap();
+    let log = finalized.clone();
+    x.on_remove::<Res, _>(move |res, storage| {
+        // Siblings, and the other values of the same type, can be read
+        assert_eq!(storage.get::<Device>().unwrap().0, "gpu");
+        assert!(storage.state::<Res>().is_ok());
+        log.lock().unwrap().push(res.0);
+        res.0 += 1000;
+    });
+    let take = || std::mem::take(&mut *finalized.lock().unwrap());
+
+    x.insert_many((0..4).map(Res).collect()).unwrap();
+    assert_eq!(x.extract::<Res>(), Ok(Res(1000)));
+    assert_eq!(x.extract_ind::<Res>(1), Ok(Res(1002)));
+    assert_eq!(x.extract_one_loose::<Res>(), Ok(Res(1001)));
+    assert_eq!(x.extract_any_count::<Res>(), Ok(vec![Res(1003)]));
+    assert_eq!(take(), vec![0, 2, 1, 3]);
+
+    x.insert_many(vec![Res(4), Res(5)]).unwrap();
+    assert_eq!(x.extract_many::<Res>().unwrap().len(), 2);
+    x.insert(Res(6)).unwrap();
+    let mut buf = vec![Res(99)];
+    assert_eq!(x.extract_many_into(&mut buf), Ok(1));
+    assert_eq!(buf, vec![Res(99), Res(1006)]);
+    x.insert(Res(7)).unwrap();
+    assert!(x.extract_any_named(std::any::type_name::<Res>()).is_ok());
+    x.insert_many(vec![Res(8), Res(9)]).unwrap();
+    assert_eq!(x.swap_backing_vec(Vec::<Res>::new()).unwrap().len(), 2);
+    assert_eq!(take(), vec![4, 5, 6, 7, 8, 9]);
+
+    // Only the values displaced from the unit are finalized
+    x.insert_many(vec![Res(10), Res(11)]).unwrap();
+    assert_eq!(
+        x.insert_bounded(Res(12), 2, OverflowPolicy::DropOldest),
+        Ok(Some(Res(1010)))
+    );
+    assert_eq!(
+        x.insert_bounded(Res(13), 2, OverflowPolicy::DropNewest),
+        Ok(Some(Res(13)))
+    );
+    assert_eq!(
+        x.insert_many_bounded(
+            vec![Res(14), Res(15), Res(16)],
+            2,
+            OverflowPolicy::DropOldest
+        ),
+        Ok(vec![Res(1011), Res(1012), Res(14)])
+    );
+    assert_eq!(take(), vec![10, 11, 12]);
+
+    // Failed extractions finalize nothing
+    assert!(x.extract_ind::<Res>(5).is_err());
+    assert!(take().is_empty());
+
+    // Removing the unit, and dropping the storage
+    x.remove_types(&[std::any::TypeId::of::<Res>()]);
+    assert_eq!(take(), vec![15, 16]);
+    x.allocate_for::<Res>();
+    x.insert(Res(17)).unwrap();
+    drop(x);
+    assert!(take().is_empty());
+
+    let mut x = DynamicStorage::new();
+    x.allocate_for::<Res>();
+    x.allocate_for::<Device>();
+    let log = finalized.clone();
+    x.on_remove::<Res, _>(move |res, _| log.lock().unwrap().push(res.0));
+    x.insert_many(vec![Res(18), Res(19)]).unwrap();
+    x.retain_types(|id| id != std::any::TypeId::of::<Device>());
+    assert!(take().is_empty());
+    x.insert(Res(20)).unwrap();
+    drop(x);
+    assert_eq!(take(), vec![18, 19, 20]);
+}
+
+#[test]
+fn on_remove_is_read_only() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let mut x = DynamicStorage::new();
+    x.allocate_for::<usize>();
+    x.allocate_for::<String>();
+    x.insert(String::from("kept")).unwrap();
+    x.insert_many(vec![1usize, 2]).unwrap();
+    let checked = Arc::new(AtomicUsize::new(0));
+    let count = checked.clone();
+    x.on_remove::<usize, _>(move |_, storage| {
+        let borrowed = Some(ErrorDesc::BorrowedIncompatibly);
+        assert_eq!(storage.get_mut::<String>().err(), borrowed);
+        assert_eq!(storage.ind_mut::<usize>(0).err(), borrowed);
+        assert_eq!(storage.extract::<String>().err(), borrowed);
+        assert_eq!(storage.extract_ind::<usize>(0).err(), borrowed);
+        assert_eq!(storage.apply::<usize, _, _>(|_| ()).err(), borrowed);
+        assert_eq!(
+            storage.insert(0usize).map_err(|(_, e)| e),
+            Err(ErrorDesc::BorrowedIncompatibly)
+        );
+        assert_eq!(&*storage.ind::<String>(0).unwrap(), "kept");
+        assert!(storage.state::<usize>().is_ok());
+        count.fetch_add(1, Ordering::Relaxed);
+    });
+    assert_eq!(x.extract_ind::<usize>(0), Ok(1));
+    assert_eq!(checked.load(Ordering::Relaxed), 1);
+
+    // Once the finalizer is done, the storage can be changed again
+    *x.ind_mut::<usize>(0).unwrap() += 1;
+    x.insert(String::from("again")).unwrap();
+    assert_eq!(*x.ind::<usize>(0).unwrap(), 3);
+}
+
+#[test]
+fn transaction_aborts_without_changes() {
+    let storage = restor::make_storage!(DynamicStorage: usize, String, u8);
+    storage.insert(1usize).unwrap();
+    let mut txn = storage.transaction();
+    txn.insert(2usize)
+        .insert(String::from("name"))
+        .insert_many(vec![3usize, 4])
+        .insert(5u32)
+        .insert(6i64);
+    assert_eq!(txn.len(), 6);
+
+    let (mut txn, mut errors) = {
+        let _held = storage.get::<usize>().unwrap();
+        txn.commit().unwrap_err()
+    };
+    errors.sort_by_key(|(name, _)| *name);
+    let mut expected = vec![
+        (
+            std::any::type_name::<usize>(),
+            ErrorDesc::BorrowedIncompatibly,
+        ),
+        (std::any::type_name::<u32>(), ErrorDesc::NoAllocatedUnit),
+        (std::any::type_name::<i64>(), ErrorDesc::NoAllocatedUnit),
+    ];
+    expected.sort_by_key(|(name, _)| *name);
+    let roots: Vec<_> = errors
+        .iter()
+        .map(|(name, e)| (*name, e.root().clone()))
+        .collect();
+    assert_eq!(roots, expected);
+    assert!(errors.iter().all(|(_, e)| e
+        .to_string()
+        .starts_with("borrowing the unit to commit to: ")));
+
+    // Nothing was inserted, not even into the units which could be borrowed
+    assert_eq!(*storage.get::<usize>().unwrap(), 1);
+    assert!(storage.get::<String>().is_err());
+    assert!(storage.get::<u8>().is_err());
+
+    // Every staged value is handed back
+    assert_eq!(txn.len(), 6);
+    assert_eq!(txn.take::<usize>(), vec![2, 3, 4]);
+    assert_eq!(txn.take::<u32>(), vec![5]);
+    assert_eq!(txn.take::<i64>(), vec![6]);
+    assert!(txn.take::<u8>().is_empty());
+    txn.commit().unwrap();
+    assert_eq!(&*storage.get::<String>().unwrap(), "name");
+    assert_eq!(*storage.get::<usize>().unwrap(), 1);
+}
+
+#[test]
+fn transaction_commits_every_type() {
+    let mut storage = DynamicStorage::new();
+    storage.allocate_tracked_for::<usize>();
+    storage.allocate_for::<String>();
+    storage.insert(0usize).unwrap();
+    storage.take_dirty::<usize>().unwrap();
+
+    let mut txn = storage.transaction();
+    txn.insert_many(vec![1usize, 2]).insert(String::from("new"));
+    assert!(!txn.is_empty());
+    txn.commit().unwrap();
+    assert_eq!(storage.take_dirty::<usize>(), Ok(vec![1, 2]));
+    assert_eq!(
+        storage.extract_many::<usize>().map(Vec::from),
+        Ok(vec![0, 1, 2])
+    );
+    assert_eq!(storage.extract::<String>(), Ok(String::from("new")));
+}
+
+#[test]
+fn cursor_invalidation_rules() {
+    use restor::Cursor;
+
+    let mut storage = restor::make_storage!(DynamicStorage: usize);
+    let setup = |storage: &DynamicStorage| -> Cursor<usize> {
+        let _ = storage.extract_any_count::<usize>();
+        storage.insert_many((0..4usize).collect()).unwrap();
+        let mut cursor = storage.cursor::<usize>().unwrap();
+        assert_eq!(cursor.next_chunk(storage, 2), Ok(vec![0, 1]));
+        cursor
+    };
+
+    // Resumes after appending, changing values in place and capacity changes
+    let mut cursor = setup(&storage);
+    storage.insert(4usize).unwrap();
+    storage.insert_many(vec![5usize, 6]).unwrap();
+    *storage.ind_mut::<usize>(2).unwrap() += 10;
+    storage.map_in_place::<usize, _>(|x| x * 2).unwrap();
+    storage.warm_capacity::<usize>(16).unwrap();
+    storage.shrink_to_fit::<usize>().unwrap();
+    assert_eq!(cursor.next_chunk(&storage, 3), Ok(vec![24, 6, 8]));
+    assert_eq!(cursor.position(), 5);
+    let mut seen = 0;
+    assert_eq!(cursor.visit_chunk(&storage, 10, |_| seen += 1), Ok(2));
+    assert_eq!((seen, cursor.position()), (2, 7));
+    assert_eq!(cursor.next_chunk(&storage, 10), Ok(vec![]));
+
+    // Every way of removing values invalidates
+    type Removal = Box<dyn Fn(&DynamicStorage)>;
+    let removals: Vec<(&str, Removal)> = vec![
+        ("extract", Box::new(|s| drop(s.extract::<usize>()))),
+        (
+            "extract_ind after",
+            Box::new(|s| drop(s.extract_ind::<usize>(3))),
+        ),
+        (
+            "extract_one_loose",
+            Box::new(|s| drop(s.extract_one_loose::<usize>())),
+        ),
+        (
+            "extract_any_named",
+            Box::new(|s| drop(s.extract_any_named("usize"))),
+        ),
+        (
+            "extract_many",
+            Box::new(|s| drop(s.extract_many::<usize>())),
+        ),
+        (
+            "extract_any_count",
+            Box::new(|s| drop(s.extract_any_count::<usize>())),
+        ),
+        (
+            "swap_backing_vec",
+            Box::new(|s| drop(s.swap_backing_vec(vec![0usize; 8]))),
+        ),
+        (
+            "insert_bounded",
+            Box::new(|s| drop(s.insert_bounded(9usize, 4, restor::OverflowPolicy::DropOldest))),
+        ),
+        ("apply", Box::new(|s| drop(s.apply::<usize, _, _>(|_| ())))),
+    ];
+    for (name, remove) in removals {
+        let mut cursor = setup(&storage);
+        remove(&storage);
+        assert_eq!(
+            cursor.next_chunk(&storage, 1),
+            Err(ErrorDesc::Invalidated),
+            "{}",
+            name
+        );
+        // Until it is restarted
+        storage.insert(10usize).unwrap();
+        assert_eq!(cursor.next_chunk(&storage, 1), Err(ErrorDesc::Invalidated));
+        cursor.restart(&storage).unwrap();
+        assert_eq!(cursor.position(), 0);
+        assert!(cursor.next_chunk(&storage, 1).is_ok());
+    }
+
+    // A failed removal changes nothing
+    let mut cursor = setup(&storage);
+    assert!(storage.extract_ind::<usize>(10).is_err());
+    assert_eq!(cursor.next_chunk(&storage, 1), Ok(vec![2]));
+
+    // Reallocating the unit starts over with new values
+    let mut cursor = setup(&storage);
+    storage.remove_types(&[std::any::TypeId::of::<usize>()]);
+    assert_eq!(
+        cursor.next_chunk(&storage, 1),
+        Err(ErrorDesc::NoAllocatedUnit)
+    );
+    storage.allocate_for::<usize>();
+    storage.insert_many((0..4usize).collect()).unwrap();
+    assert_eq!(cursor.next_chunk(&storage, 1), Err(ErrorDesc::Invalidated));
+
+    // A cursor can't be made or moved while the unit is borrowed mutably
+    let mut cursor = setup(&storage);
+    let _lock = storage.ind_mut::<usize>(0).unwrap();
+    assert_eq!(
+        storage.cursor::<usize>().err(),
+        Some(ErrorDesc::BorrowedIncompatibly)
+    );
+    assert_eq!(
+        cursor.next_chunk(&storage, 1),
+        Err(ErrorDesc::BorrowedIncompatibly)
+    );
+}
+
+#[test]
+fn cursor_invalidated_by_a_panicking_mutation() {
+    let storage = restor::make_storage!(DynamicStorage: usize);
+    storage.insert_many(vec![1usize, 2, 3]).unwrap();
+    let mut cursor = storage.cursor::<usize>().unwrap();
+    assert_eq!(cursor.next_chunk(&storage, 1), Ok(vec![1]));
+    let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        storage.map_in_place::<usize, _>(|x| if x == 2 { panic!("losing a value") } else { x })
+    }));
+    assert_eq!(cursor.next_chunk(&storage, 1), Err(ErrorDesc::Invalidated));
+}
+
+#[test]
+fn assert_allocated_reports_missing_names() {
+    use restor::Sets;
+
+    struct Position;
+
+    let mut storage = DynamicStorage::new();
+    storage.allocate_all::<(usize, String)>();
+    assert_eq!(storage.assert_allocated::<(usize, String)>(), Ok(()));
+    assert_eq!(storage.assert_allocated::<(String,)>(), Ok(()));
+
+    // Only the missing types are reported, in the order of the set
+    let missing = storage
+        .assert_allocated::<Sets<((u8, usize), (Position, String, Vec<bool>))>>()
+        .unwrap_err();
+    let position = std::any::type_name::<Position>();
+    assert_eq!(missing.names(), &["u8", position, "alloc::vec::Vec<bool>"]);
+    assert_eq!(
+        missing.to_string(),
+        format!(
+            "No unit is allocated for `u8`, `{}`, `alloc::vec::Vec<bool>`",
+            position
+        )
+    );
+
+    storage.allocate_all::<(u8, Position, Vec<bool>)>();
+    storage.expect_allocated::<Sets<((u8, usize), (Position, String, Vec<bool>))>>();
+}
+
+#[test]
+#[should_panic(expected = "No unit is allocated for `isize`")]
+fn expect_allocated_panics_with_missing_names() {
+    let storage = restor::make_storage!(DynamicStorage: usize);
+    storage.expect_allocated::<(usize, isize)>();
+}
+
+#[test]
+fn extra_types_are_those_not_expected() {
+    use std::any::TypeId;
+
+    let mut storage = restor::make_storage!(DynamicStorage: usize, String, u8);
+    assert_eq!(
+        storage.extra_types(&[
+            TypeId::of::<u8>(),
+            TypeId::of::<usize>(),
+            TypeId::of::<bool>()
+        ]),
+        vec![TypeId::of::<String>()]
+    );
+    assert_eq!(
+        storage.extra_types(&[
+            TypeId::of::<usize>(),
+            TypeId::of::<String>(),
+            TypeId::of::<u8>()
+        ]),
+        vec![]
+    );
+    storage.remove_types(&[TypeId::of::<String>()]);
+    assert_eq!(
+        storage.extra_types(&[]),
+        vec![TypeId::of::<usize>(), TypeId::of::<u8>()]
+    );
+}
+
+#[test]
+#[cfg(not(any(feature = "instrument", feature = "tracing", feature = "poison")))]
+fn guards_are_no_larger_than_the_backend() {
+    use std::cell::{Ref, RefMut};
+    use std::mem::{size_of, size_of_val};
+
+    let x = restor::make_storage!(DynamicStorage: String);
+    x.insert(String::new()).unwrap();
+    let read = x.get::<String>().unwrap();
+    assert_eq!(size_of_val(&read), size_of::<Ref<String>>());
+    drop(read);
+    let write = x.get_mut::<String>().unwrap();
+    assert_eq!(size_of_val(&write), size_of::<RefMut<String>>());
+}
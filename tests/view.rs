@@ -0,0 +1,144 @@
+use restor::{
+    make_storage, DynamicStorage, ErrorDesc, MutexStorage, RwLockStorage, SharedView, StorageUnit,
+    UnitError,
+};
+use std::sync::Arc;
+
+macro_rules! view_tests {
+    ($name:ident, $storage:ty) => {
+        mod $name {
+            use super::*;
+
+            fn setup() -> $storage {
+                let storage = make_storage!($storage: usize, String);
+                storage.insert_many(vec![3usize, 1, 4, 1, 5]).unwrap();
+                storage
+            }
+
+            #[test]
+            fn closure_reads_match_the_storage() {
+                let storage = setup();
+                let view = storage.as_view();
+                let copy = view;
+
+                assert!(view.contains::<usize>());
+                assert!(!view.contains::<u8>());
+                assert_eq!(view.len::<usize>(), Ok(5));
+                assert_eq!(view.len::<String>(), Ok(0));
+                assert_eq!(view.is_empty::<String>(), Ok(true));
+                assert_eq!(view.is_empty::<usize>(), Ok(false));
+                assert_eq!(view.len::<u8>(), Err(ErrorDesc::NoAllocatedUnit));
+                assert_eq!(view.state::<usize>(), storage.state::<usize>());
+                assert_eq!(view.version::<usize>(), storage.version::<usize>());
+
+                let is_one = |x: &usize| *x == 1;
+                assert_eq!(view.position(is_one), storage.position(is_one));
+                assert_eq!(copy.find_clone(|x: &usize| *x > 3), Ok(Some(4)));
+                assert_eq!(
+                    view.cloned_iter::<usize>().map(|values| values.collect::<Vec<_>>()),
+                    storage.cloned_iter::<usize>().map(|values| values.collect::<Vec<_>>())
+                );
+                assert_eq!(view.ind_multi::<usize>(&[4, 0]), Ok(vec![5, 3]));
+                assert_eq!(
+                    view.with_ind_multi(&[1, 3], |x: &[&usize]| *x[0] + *x[1]),
+                    Ok(2)
+                );
+                assert_eq!(
+                    view.with(|unit: &StorageUnit<usize>| unit.len()),
+                    Ok(5)
+                );
+
+                let mut sum = 0;
+                view.try_for_each(|x: &usize| {
+                    sum += x;
+                    Ok::<_, ()>(())
+                })
+                .unwrap();
+                assert_eq!(sum, 14);
+                assert_eq!(
+                    view.try_with(|x: &usize| Ok::<_, ()>(*x)),
+                    storage.try_with(|x: &usize| Ok::<_, ()>(*x))
+                );
+            }
+
+            #[test]
+            fn view_sees_later_changes() {
+                let storage = setup();
+                let view = storage.as_view();
+                storage.extract_many::<usize>().unwrap();
+                storage.insert(String::from("changed")).unwrap();
+                assert_eq!(view.len::<usize>(), Ok(0));
+                assert_eq!(
+                    view.try_with(|x: &String| Ok::<_, ()>(x.len())),
+                    Ok(7)
+                );
+            }
+        }
+    };
+}
+
+view_tests!(dynamic, DynamicStorage);
+view_tests!(mutex, MutexStorage);
+view_tests!(rwlock, RwLockStorage);
+
+#[test]
+fn guard_reads_match_the_storage() {
+    let storage = make_storage!(RwLockStorage: usize, String);
+    storage.insert_many(vec![1usize, 2, 3]).unwrap();
+    storage.insert(String::from("one")).unwrap();
+    let view = storage.as_view();
+    assert_eq!(*view.ind::<usize>(1).unwrap(), 2);
+    assert_eq!(*view.ind_from_end::<usize>(0).unwrap(), 3);
+    assert_eq!(&*view.get::<String>().unwrap(), "one");
+    assert_eq!(
+        view.get::<usize>().err(),
+        Some(ErrorDesc::Unit(UnitError::IsNotOne))
+    );
+    assert_eq!(view.ind::<usize>(3).err(), storage.ind::<usize>(3).err());
+
+    // Borrows through the view hold the unit like those on the storage
+    let value = view.ind::<usize>(0).unwrap();
+    assert_eq!(
+        storage.ind_mut::<usize>(0).err(),
+        Some(ErrorDesc::BorrowedIncompatibly)
+    );
+    drop(value);
+    *storage.ind_mut::<usize>(0).unwrap() += 10;
+    assert_eq!(*view.ind::<usize>(0).unwrap(), 11);
+}
+
+#[test]
+fn dynamic_view_borrows_like_the_storage() {
+    let storage = make_storage!(DynamicStorage: usize);
+    storage.insert(1usize).unwrap();
+    let _lock = storage.get_mut::<usize>().unwrap();
+    assert_eq!(
+        storage.as_view().get::<usize>().err(),
+        Some(ErrorDesc::BorrowedIncompatibly)
+    );
+    assert_eq!(
+        storage.as_view().len::<usize>(),
+        Err(ErrorDesc::BorrowedIncompatibly)
+    );
+}
+
+#[test]
+fn shared_view_reads_from_other_threads() {
+    let storage = Arc::new(make_storage!(RwLockStorage: usize));
+    storage.insert_many((0..100usize).collect()).unwrap();
+    let view = SharedView::from(storage.clone());
+    let readers = (0..4)
+        .map(|i| {
+            let view = view.clone();
+            std::thread::spawn(move || *view.view().ind::<usize>(i * 10).unwrap())
+        })
+        .collect::<Vec<_>>();
+    let read = readers
+        .into_iter()
+        .map(|reader| reader.join().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(read, vec![0, 10, 20, 30]);
+
+    let view = SharedView::new(Arc::new(make_storage!(MutexStorage: usize)));
+    assert_eq!(view.view().len::<usize>(), Ok(0));
+}